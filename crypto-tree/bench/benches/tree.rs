@@ -0,0 +1,128 @@
+//! Throughput and latency of the core tree operations at 10k, 100k and 1M transactions.
+//!
+//! Run all of them with `cargo bench`, or one group or size with a filter,
+//! e.g. `cargo bench -- search/100000`.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use crypto_tree::{verify_proof, CryptoBinaryTree, Sha256Hasher, Transaction};
+
+const SIZES: [usize; 3] = [10_000, 100_000, 1_000_000];
+
+/// Sorted transactions between a small set of addresses, as on a real ledger
+fn transactions(n: usize) -> Vec<Transaction> {
+    (0..n)
+        .map(|i| Transaction {
+            id: format!("tx_{:07}", i),
+            from: format!("addr_{}", i % 97).into(),
+            to: format!("addr_{}", i % 89).into(),
+            amount: (i % 1_000) as u128 + 1,
+            timestamp: Some(1_700_000_000 + i as u64),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Ids spread over the whole key range in a fixed, cache-unfriendly order
+fn probe_ids(n: usize) -> Vec<String> {
+    (0..1_024).map(|i| format!("tx_{:07}", (i * 7_919) % n)).collect()
+}
+
+/// Sample count for a group, fewer for the slow 1M runs
+fn samples(n: usize) -> usize {
+    if n >= 1_000_000 {
+        10
+    } else {
+        30
+    }
+}
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+    for n in SIZES {
+        let txs = transactions(n);
+        group.throughput(Throughput::Elements(n as u64)).sample_size(samples(n));
+        // Keys arrive in a shuffled order so that rotations happen throughout
+        let mut shuffled = txs.clone();
+        shuffled.sort_by_key(|tx| tx.id.bytes().rev().collect::<Vec<_>>());
+        group.bench_with_input(BenchmarkId::new("one_by_one", n), &shuffled, |b, txs| {
+            b.iter_batched(
+                || txs.clone(),
+                |txs| {
+                    let mut tree = CryptoBinaryTree::new();
+                    for tx in txs {
+                        tree.insert(tx);
+                    }
+                    tree
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("batch", n), &shuffled, |b, txs| {
+            b.iter_batched(
+                || txs.clone(),
+                |txs| {
+                    let mut tree = CryptoBinaryTree::new();
+                    tree.insert_batch(txs);
+                    tree
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bulk_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bulk_load");
+    for n in SIZES {
+        let txs = transactions(n);
+        group.throughput(Throughput::Elements(n as u64)).sample_size(samples(n));
+        group.bench_with_input(BenchmarkId::new("from_sorted", n), &txs, |b, txs| {
+            b.iter_batched(|| txs.clone(), |txs| CryptoBinaryTree::from_sorted(txs).unwrap(), BatchSize::LargeInput)
+        });
+        group.bench_with_input(BenchmarkId::new("par_from_sorted", n), &txs, |b, txs| {
+            b.iter_batched(
+                || txs.clone(),
+                |txs| CryptoBinaryTree::par_from_sorted_with_hasher(txs, Sha256Hasher::new()).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Search, proof generation and proof verification against one prebuilt tree per size
+fn queries(c: &mut Criterion) {
+    for n in SIZES {
+        let tree = CryptoBinaryTree::from_sorted(transactions(n)).unwrap();
+        let ids = probe_ids(n);
+        let proofs: Vec<_> = ids
+            .iter()
+            .map(|id| (tree.search(id).unwrap().clone(), tree.get_proof_of_inclusion(id).unwrap()))
+            .collect();
+
+        let mut next = 0;
+        c.benchmark_group("search").bench_function(BenchmarkId::from_parameter(n), |b| {
+            b.iter(|| {
+                next = (next + 1) % ids.len();
+                black_box(tree.search(ids[next].as_str()))
+            })
+        });
+        c.benchmark_group("proof").bench_function(BenchmarkId::from_parameter(n), |b| {
+            b.iter(|| {
+                next = (next + 1) % ids.len();
+                black_box(tree.get_proof_of_inclusion(ids[next].as_str()))
+            })
+        });
+        c.benchmark_group("verify").bench_function(BenchmarkId::from_parameter(n), |b| {
+            b.iter(|| {
+                next = (next + 1) % proofs.len();
+                let (tx, proof) = &proofs[next];
+                assert!(verify_proof(tree.merkle_root(), tx, proof));
+            })
+        });
+    }
+}
+
+criterion_group!(benches, insert, bulk_load, queries);
+criterion_main!(benches);
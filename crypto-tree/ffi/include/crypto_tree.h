@@ -0,0 +1,109 @@
+/* Generated by cbindgen from crypto-tree/ffi/src/lib.rs. Do not edit by hand. */
+
+#ifndef CRYPTO_TREE_H
+#define CRYPTO_TREE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Result of a mutating call
+typedef enum CryptoTreeStatus {
+  CRYPTO_TREE_STATUS_OK = 0,
+  // A transaction with this id is already stored
+  CRYPTO_TREE_STATUS_DUPLICATE = 1,
+  // A pointer was null or a string was not valid UTF-8
+  CRYPTO_TREE_STATUS_INVALID_ARGUMENT = 2,
+  // The transaction JSON could not be parsed
+  CRYPTO_TREE_STATUS_INVALID_JSON = 3,
+  // The sender's balance does not cover the amount
+  CRYPTO_TREE_STATUS_INSUFFICIENT_BALANCE = 4,
+  // The transaction would take a balance out of range
+  CRYPTO_TREE_STATUS_BALANCE_OVERFLOW = 5,
+  // The tree's validator refused the transaction
+  CRYPTO_TREE_STATUS_REJECTED = 6,
+  // Signatures are required but the transaction carries none
+  CRYPTO_TREE_STATUS_UNSIGNED = 7,
+  // The transaction's signature does not verify
+  CRYPTO_TREE_STATUS_INVALID_SIGNATURE = 8,
+  // Content ids are required and the id is not the transaction's hash
+  CRYPTO_TREE_STATUS_ID_MISMATCH = 9,
+  // The transaction's data attachment exceeds the tree's limit
+  CRYPTO_TREE_STATUS_DATA_TOO_LARGE = 10,
+  // The transaction could not be encoded for hashing
+  CRYPTO_TREE_STATUS_SERIALIZATION_FAILED = 11,
+  // The tree is damaged or another internal operation failed
+  CRYPTO_TREE_STATUS_INTERNAL = 12,
+} CryptoTreeStatus;
+
+// Opaque tree handle, `CryptoTree` in C
+typedef struct CryptoTree CryptoTree;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates an empty tree. Release it with `crypto_tree_free`.
+CryptoTree *crypto_tree_new(void);
+
+// Releases a tree created by `crypto_tree_new`. Null is ignored.
+//
+// # Safety
+// `tree` must be null or a handle from `crypto_tree_new` not freed before.
+void crypto_tree_free(CryptoTree *tree);
+
+// Inserts a transaction given as JSON, e.g.
+// `{"id":"tx_1","from":"A","to":"B","amount":10,"timestamp":null}`.
+//
+// A rejected transaction is reported by the status naming the reason, e.g.
+// `Duplicate` or `DataTooLarge`, and leaves the tree unchanged.
+//
+// # Safety
+// `tree` must be a live handle and `tx_json` null or a NUL-terminated string.
+CryptoTreeStatus crypto_tree_insert(CryptoTree *tree, const char *tx_json);
+
+// Looks up a transaction, returning it as JSON or null if absent.
+//
+// # Safety
+// `tree` must be a live handle and `id` null or a NUL-terminated string.
+char *crypto_tree_search(const CryptoTree *tree, const char *id);
+
+// Builds a self-contained inclusion proof for `id` as JSON, null if absent.
+//
+// # Safety
+// `tree` must be a live handle and `id` null or a NUL-terminated string.
+char *crypto_tree_proof(const CryptoTree *tree, const char *id);
+
+// Checks a proof from `crypto_tree_proof` against a trusted Merkle root.
+//
+// Needs no tree; returns false for malformed input.
+//
+// # Safety
+// Both arguments must be null or NUL-terminated strings.
+bool crypto_tree_verify_proof(const char *root, const char *proof_json);
+
+// Returns the current Merkle root as a hex string (`"0"` when empty).
+//
+// # Safety
+// `tree` must be null or a live handle.
+char *crypto_tree_root(const CryptoTree *tree);
+
+// Returns the number of stored transactions, 0 for a null handle.
+//
+// # Safety
+// `tree` must be null or a live handle.
+size_t crypto_tree_len(const CryptoTree *tree);
+
+// Releases a string returned by this library. Null is ignored.
+//
+// # Safety
+// `s` must be null or a string returned by this library not freed before.
+void crypto_tree_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CRYPTO_TREE_H */
@@ -0,0 +1,294 @@
+//! C ABI for `crypto_tree`.
+//!
+//! Trees are opaque handles owned by the caller and released with
+//! `crypto_tree_free`. Transactions and proofs cross the boundary as
+//! NUL-terminated UTF-8 JSON; every string returned by this library must be
+//! released with `crypto_tree_string_free`. Functions never unwind into C:
+//! invalid arguments are reported through [`CryptoTreeStatus`] or a null
+//! return. `include/crypto_tree.h` is generated from this file by cbindgen.
+
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+use crypto_tree::{CryptoBinaryTree, CryptoTreeError, Proof, Transaction};
+
+/// Opaque tree handle, `CryptoTree` in C
+pub struct CryptoTreeHandle {
+    tree: CryptoBinaryTree,
+}
+
+/// Result of a mutating call
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum CryptoTreeStatus {
+    Ok = 0,
+    /// A transaction with this id is already stored
+    Duplicate = 1,
+    /// A pointer was null or a string was not valid UTF-8
+    InvalidArgument = 2,
+    /// The transaction JSON could not be parsed
+    InvalidJson = 3,
+    /// The sender's balance does not cover the amount
+    InsufficientBalance = 4,
+    /// The transaction would take a balance out of range
+    BalanceOverflow = 5,
+    /// The tree's validator refused the transaction
+    Rejected = 6,
+    /// Signatures are required but the transaction carries none
+    Unsigned = 7,
+    /// The transaction's signature does not verify
+    InvalidSignature = 8,
+    /// Content ids are required and the id is not the transaction's hash
+    IdMismatch = 9,
+    /// The transaction's data attachment exceeds the tree's limit
+    DataTooLarge = 10,
+    /// The transaction could not be encoded for hashing
+    SerializationFailed = 11,
+    /// The tree is damaged or another internal operation failed
+    Internal = 12,
+}
+
+impl From<&CryptoTreeError> for CryptoTreeStatus {
+    fn from(e: &CryptoTreeError) -> Self {
+        match e {
+            CryptoTreeError::DuplicateId(_) => CryptoTreeStatus::Duplicate,
+            CryptoTreeError::InsufficientBalance { .. } => CryptoTreeStatus::InsufficientBalance,
+            CryptoTreeError::BalanceOverflow { .. } => CryptoTreeStatus::BalanceOverflow,
+            CryptoTreeError::Rejected { .. } => CryptoTreeStatus::Rejected,
+            CryptoTreeError::Unsigned(_) => CryptoTreeStatus::Unsigned,
+            CryptoTreeError::InvalidSignature(_) => CryptoTreeStatus::InvalidSignature,
+            CryptoTreeError::IdMismatch { .. } => CryptoTreeStatus::IdMismatch,
+            CryptoTreeError::DataTooLarge { .. } => CryptoTreeStatus::DataTooLarge,
+            CryptoTreeError::SerializationFailed(_) => CryptoTreeStatus::SerializationFailed,
+            // Not produced by an insert
+            CryptoTreeError::NotFound(_)
+            | CryptoTreeError::KeyChanged(_)
+            | CryptoTreeError::UnsortedInput(_)
+            | CryptoTreeError::CorruptedNode { .. }
+            | CryptoTreeError::InvariantViolated { .. }
+            | CryptoTreeError::RootMismatch { .. }
+            | CryptoTreeError::SizeMismatch { .. }
+            | CryptoTreeError::MalformedState(_)
+            | CryptoTreeError::InvalidProof(_)
+            | CryptoTreeError::NotWitnessed(_)
+            | CryptoTreeError::UnknownVersion(_)
+            | CryptoTreeError::Snapshot(_)
+            | CryptoTreeError::Wal(_)
+            | CryptoTreeError::Storage(_) => CryptoTreeStatus::Internal,
+        }
+    }
+}
+
+/// Reads a borrowed C string, `None` if null or not UTF-8.
+///
+/// # Safety
+/// `s` must be null or point to a NUL-terminated string valid for `'a`.
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// Hands a string to C, null if it contains an interior NUL.
+fn into_c_string(s: String) -> *mut c_char {
+    CString::new(s).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Creates an empty tree. Release it with `crypto_tree_free`.
+#[no_mangle]
+pub extern "C" fn crypto_tree_new() -> *mut CryptoTreeHandle {
+    Box::into_raw(Box::new(CryptoTreeHandle {
+        tree: CryptoBinaryTree::new(),
+    }))
+}
+
+/// Releases a tree created by `crypto_tree_new`. Null is ignored.
+///
+/// # Safety
+/// `tree` must be null or a handle from `crypto_tree_new` not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_tree_free(tree: *mut CryptoTreeHandle) {
+    if !tree.is_null() {
+        drop(Box::from_raw(tree));
+    }
+}
+
+/// Inserts a transaction given as JSON, e.g.
+/// `{"id":"tx_1","from":"A","to":"B","amount":10,"timestamp":null}`.
+///
+/// A rejected transaction is reported by the status naming the reason, e.g.
+/// `Duplicate` or `DataTooLarge`, and leaves the tree unchanged.
+///
+/// # Safety
+/// `tree` must be a live handle and `tx_json` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_tree_insert(tree: *mut CryptoTreeHandle, tx_json: *const c_char) -> CryptoTreeStatus {
+    let (Some(handle), Some(json)) = (tree.as_mut(), str_arg(tx_json)) else {
+        return CryptoTreeStatus::InvalidArgument;
+    };
+    let Ok(tx) = serde_json::from_str::<Transaction>(json) else {
+        return CryptoTreeStatus::InvalidJson;
+    };
+    match handle.tree.try_insert(tx) {
+        Ok(()) => CryptoTreeStatus::Ok,
+        Err(e) => CryptoTreeStatus::from(&e),
+    }
+}
+
+/// Looks up a transaction, returning it as JSON or null if absent.
+///
+/// # Safety
+/// `tree` must be a live handle and `id` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_tree_search(tree: *const CryptoTreeHandle, id: *const c_char) -> *mut c_char {
+    let (Some(handle), Some(id)) = (tree.as_ref(), str_arg(id)) else {
+        return ptr::null_mut();
+    };
+    handle
+        .tree
+        .search(id)
+        .and_then(|tx| serde_json::to_string(tx).ok())
+        .map_or(ptr::null_mut(), into_c_string)
+}
+
+/// Builds a self-contained inclusion proof for `id` as JSON, null if absent.
+///
+/// # Safety
+/// `tree` must be a live handle and `id` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_tree_proof(tree: *const CryptoTreeHandle, id: *const c_char) -> *mut c_char {
+    let (Some(handle), Some(id)) = (tree.as_ref(), str_arg(id)) else {
+        return ptr::null_mut();
+    };
+    handle
+        .tree
+        .get_proof(id)
+        .and_then(|proof| serde_json::to_string(&proof).ok())
+        .map_or(ptr::null_mut(), into_c_string)
+}
+
+/// Checks a proof from `crypto_tree_proof` against a trusted Merkle root.
+///
+/// Needs no tree; returns false for malformed input.
+///
+/// # Safety
+/// Both arguments must be null or NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_tree_verify_proof(root: *const c_char, proof_json: *const c_char) -> bool {
+    let (Some(root), Some(json)) = (str_arg(root), str_arg(proof_json)) else {
+        return false;
+    };
+    serde_json::from_str::<Proof>(json).is_ok_and(|proof| proof.root == root && proof.verify())
+}
+
+/// Returns the current Merkle root as a hex string (`"0"` when empty).
+///
+/// # Safety
+/// `tree` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_tree_root(tree: *const CryptoTreeHandle) -> *mut c_char {
+    tree.as_ref()
+        .map_or(ptr::null_mut(), |handle| into_c_string(handle.tree.merkle_root().to_string()))
+}
+
+/// Returns the number of stored transactions, 0 for a null handle.
+///
+/// # Safety
+/// `tree` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_tree_len(tree: *const CryptoTreeHandle) -> usize {
+    tree.as_ref().map_or(0, |handle| handle.tree.len())
+}
+
+/// Releases a string returned by this library. Null is ignored.
+///
+/// # Safety
+/// `s` must be null or a string returned by this library not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn crypto_tree_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    unsafe fn take(s: *mut c_char) -> String {
+        assert!(!s.is_null());
+        let owned = CStr::from_ptr(s).to_str().unwrap().to_string();
+        crypto_tree_string_free(s);
+        owned
+    }
+
+    #[test]
+    fn test_round_trip_through_c_abi() {
+        unsafe {
+            let tree = crypto_tree_new();
+            for i in 0..20 {
+                let json = format!(r#"{{"id":"tx_{:02}","from":"A","to":"B","amount":{},"timestamp":null}}"#, i, i);
+                assert_eq!(crypto_tree_insert(tree, c(&json).as_ptr()), CryptoTreeStatus::Ok);
+            }
+            let dup = c(r#"{"id":"tx_00","from":"A","to":"B","amount":1,"timestamp":null}"#);
+            assert_eq!(crypto_tree_insert(tree, dup.as_ptr()), CryptoTreeStatus::Duplicate);
+            assert_eq!(crypto_tree_len(tree), 20);
+
+            let found = take(crypto_tree_search(tree, c("tx_07").as_ptr()));
+            assert!(found.contains(r#""amount":7"#));
+            assert!(crypto_tree_search(tree, c("missing").as_ptr()).is_null());
+
+            let root = take(crypto_tree_root(tree));
+            let proof = take(crypto_tree_proof(tree, c("tx_07").as_ptr()));
+            assert!(crypto_tree_verify_proof(c(&root).as_ptr(), c(&proof).as_ptr()));
+            assert!(!crypto_tree_verify_proof(c(&"0".repeat(64)).as_ptr(), c(&proof).as_ptr()));
+            assert!(!crypto_tree_verify_proof(c(&root).as_ptr(), c("{}").as_ptr()));
+
+            crypto_tree_free(tree);
+        }
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        unsafe {
+            let tree = crypto_tree_new();
+            assert_eq!(crypto_tree_insert(tree, ptr::null()), CryptoTreeStatus::InvalidArgument);
+            assert_eq!(crypto_tree_insert(ptr::null_mut(), c("{}").as_ptr()), CryptoTreeStatus::InvalidArgument);
+            assert_eq!(crypto_tree_insert(tree, c("not json").as_ptr()), CryptoTreeStatus::InvalidJson);
+            assert!(crypto_tree_search(tree, ptr::null()).is_null());
+            assert!(crypto_tree_proof(ptr::null(), c("tx").as_ptr()).is_null());
+            assert!(!crypto_tree_verify_proof(ptr::null(), ptr::null()));
+            assert_eq!(take(crypto_tree_root(tree)), "0");
+            assert_eq!(crypto_tree_len(ptr::null()), 0);
+            crypto_tree_string_free(ptr::null_mut());
+            crypto_tree_free(tree);
+            crypto_tree_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_rejections_report_their_reason() {
+        unsafe {
+            let tree = crypto_tree_new();
+            (*tree).tree.set_max_data_size(Some(2));
+            let big = c(r#"{"id":"tx_1","from":"A","to":"B","amount":1,"timestamp":null,"data":"0a0b0c"}"#);
+            assert_eq!(crypto_tree_insert(tree, big.as_ptr()), CryptoTreeStatus::DataTooLarge);
+            assert_eq!(crypto_tree_len(tree), 0);
+            crypto_tree_free(tree);
+        }
+        let overdraw = CryptoTreeError::InsufficientBalance {
+            id: "tx_1".into(),
+            sender: "A".into(),
+            balance: 0,
+            amount: 1,
+        };
+        assert_eq!(CryptoTreeStatus::from(&overdraw), CryptoTreeStatus::InsufficientBalance);
+        assert_eq!(CryptoTreeStatus::from(&CryptoTreeError::Unsigned("tx_1".into())), CryptoTreeStatus::Unsigned);
+        assert_eq!(CryptoTreeStatus::from(&CryptoTreeError::Storage("gone".into())), CryptoTreeStatus::Internal);
+    }
+}
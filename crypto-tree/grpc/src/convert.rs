@@ -0,0 +1,108 @@
+//! Conversions between the core types and their protobuf messages.
+
+use crypto_tree::{Proof, ProofStep, Side, Transaction};
+use tonic::Status;
+
+use crate::pb;
+
+impl From<Transaction> for pb::Transaction {
+    fn from(tx: Transaction) -> Self {
+        pb::Transaction {
+            id: tx.id,
+            from: tx.from.into(),
+            to: tx.to.into(),
+            amount: tx.amount.to_string(),
+            timestamp: tx.timestamp,
+            metadata: tx.metadata.into_iter().map(|(k, v)| (k, v.to_string())).collect(),
+            data: tx.data,
+            signature: tx.signature,
+            public_key: tx.public_key,
+        }
+    }
+}
+
+impl TryFrom<pb::Transaction> for Transaction {
+    type Error = Status;
+
+    fn try_from(tx: pb::Transaction) -> Result<Self, Status> {
+        let amount = tx
+            .amount
+            .parse()
+            .map_err(|_| Status::invalid_argument(format!("transaction {}: amount {:?} is not an unsigned integer", tx.id, tx.amount)))?;
+        let metadata = tx
+            .metadata
+            .into_iter()
+            .map(|(k, v)| {
+                serde_json::from_str(&v)
+                    .map(|v| (k.clone(), v))
+                    .map_err(|e| Status::invalid_argument(format!("transaction {}: metadata {}: {}", tx.id, k, e)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Transaction {
+            id: tx.id,
+            from: tx.from.into(),
+            to: tx.to.into(),
+            amount,
+            timestamp: tx.timestamp,
+            metadata,
+            data: tx.data,
+            signature: tx.signature,
+            public_key: tx.public_key,
+        })
+    }
+}
+
+impl From<ProofStep> for pb::ProofStep {
+    fn from(step: ProofStep) -> Self {
+        let side = match step.side {
+            Side::Left => pb::Side::Left,
+            Side::Right => pb::Side::Right,
+        };
+        pb::ProofStep {
+            side: side.into(),
+            hash: step.hash,
+            height: step.height,
+            size: step.size as u64,
+            transaction: step.transaction.map(Into::into),
+        }
+    }
+}
+
+impl From<Proof> for pb::Proof {
+    fn from(proof: Proof) -> Self {
+        pb::Proof {
+            transaction: Some(proof.transaction.into()),
+            root: proof.root,
+            height: proof.height,
+            steps: proof.steps.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_round_trip() {
+        let mut tx = Transaction {
+            id: "tx_1".to_string(),
+            from: "A".into(),
+            to: "B".into(),
+            amount: u128::MAX,
+            timestamp: Some(1_700_000_000),
+            data: vec![1, 2, 3],
+            ..Default::default()
+        };
+        tx.metadata.insert("fee".to_string(), serde_json::json!(5));
+        let message = pb::Transaction::from(tx.clone());
+        assert_eq!(message.amount, u128::MAX.to_string());
+        assert_eq!(Transaction::try_from(message).unwrap(), tx);
+
+        let bad = pb::Transaction {
+            amount: "-1".to_string(),
+            ..Default::default()
+        };
+        assert!(Transaction::try_from(bad).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! gRPC service for a shared [`TransactionTree`], generated from
+//! `proto/crypto_tree.proto` with tonic.
+
+use std::pin::Pin;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crypto_tree::{CryptoTreeError, Transaction, TransactionTree};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+mod convert;
+
+/// Generated protobuf messages and service traits
+pub mod pb {
+    tonic::include_proto!("cryptotree.v1");
+}
+
+pub use pb::crypto_tree_server::CryptoTreeServer;
+
+/// Updates buffered per subscriber before the slowest ones start missing some
+const UPDATE_BUFFER: usize = 1024;
+
+/// Serves a tree shared between concurrent calls; lookups run in parallel,
+/// inserts take the write lock.
+pub struct CryptoTreeService {
+    tree: Arc<RwLock<TransactionTree>>,
+    updates: broadcast::Sender<pb::Update>,
+}
+
+impl CryptoTreeService {
+    pub fn new(tree: TransactionTree) -> Self {
+        let (updates, _) = broadcast::channel(UPDATE_BUFFER);
+        CryptoTreeService {
+            tree: Arc::new(RwLock::new(tree)),
+            updates,
+        }
+    }
+
+    /// Wraps the service for `tonic::transport::Server::add_service`.
+    pub fn into_server(self) -> CryptoTreeServer<Self> {
+        CryptoTreeServer::new(self)
+    }
+
+    fn publish(&self, ids: Vec<String>, tree: &TransactionTree) {
+        // No subscribers is not an error
+        let _ = self.updates.send(pb::Update {
+            ids,
+            root: tree.merkle_root().to_string(),
+            size: tree.len() as u64,
+        });
+    }
+}
+
+fn status(e: CryptoTreeError) -> Status {
+    match e {
+        CryptoTreeError::DuplicateId(_) => Status::already_exists(e.to_string()),
+        CryptoTreeError::NotFound(_) => Status::not_found(e.to_string()),
+        _ => Status::failed_precondition(e.to_string()),
+    }
+}
+
+fn not_found(id: &str) -> Status {
+    Status::not_found(format!("transaction {} not found", id))
+}
+
+#[tonic::async_trait]
+impl pb::crypto_tree_server::CryptoTree for CryptoTreeService {
+    async fn insert(&self, request: Request<pb::InsertRequest>) -> Result<Response<pb::InsertResponse>, Status> {
+        let tx: Transaction = request
+            .into_inner()
+            .transaction
+            .ok_or_else(|| Status::invalid_argument("missing transaction"))?
+            .try_into()?;
+        let id = tx.id.clone();
+        let mut tree = self.tree.write().unwrap_or_else(PoisonError::into_inner);
+        tree.try_insert(tx).map_err(status)?;
+        self.publish(vec![id], &tree);
+        Ok(Response::new(pb::InsertResponse {
+            root: tree.merkle_root().to_string(),
+        }))
+    }
+
+    async fn batch_insert(
+        &self,
+        request: Request<pb::BatchInsertRequest>,
+    ) -> Result<Response<pb::BatchInsertResponse>, Status> {
+        let transactions = request
+            .into_inner()
+            .transactions
+            .into_iter()
+            .map(Transaction::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut tree = self.tree.write().unwrap_or_else(PoisonError::into_inner);
+        let ids: Vec<String> = transactions.iter().map(|tx| tx.id.clone()).collect();
+        let result = tree.insert_batch(transactions);
+        if result.inserted > 0 {
+            let skipped: Vec<&String> = result.duplicates.iter().chain(result.failed.iter().map(|(id, _)| id)).collect();
+            let inserted = ids.into_iter().filter(|id| !skipped.contains(&id)).collect();
+            self.publish(inserted, &tree);
+        }
+        Ok(Response::new(pb::BatchInsertResponse {
+            inserted: result.inserted as u64,
+            failed: result
+                .failed
+                .into_iter()
+                .map(|(id, e)| pb::Failure { id, reason: e.to_string() })
+                .collect(),
+            duplicates: result.duplicates,
+            root: tree.merkle_root().to_string(),
+        }))
+    }
+
+    async fn search(&self, request: Request<pb::SearchRequest>) -> Result<Response<pb::Transaction>, Status> {
+        let id = request.into_inner().id;
+        let tree = self.tree.read().unwrap_or_else(PoisonError::into_inner);
+        let tx = tree.search(id.as_str()).ok_or_else(|| not_found(&id))?;
+        Ok(Response::new(tx.clone().into()))
+    }
+
+    async fn get_proof(&self, request: Request<pb::GetProofRequest>) -> Result<Response<pb::Proof>, Status> {
+        let id = request.into_inner().id;
+        let tree = self.tree.read().unwrap_or_else(PoisonError::into_inner);
+        let proof = tree.get_proof(id.as_str()).ok_or_else(|| not_found(&id))?;
+        Ok(Response::new(proof.into()))
+    }
+
+    async fn get_root(&self, _request: Request<pb::GetRootRequest>) -> Result<Response<pb::RootResponse>, Status> {
+        let tree = self.tree.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(Response::new(pb::RootResponse {
+            root: tree.merkle_root().to_string(),
+            size: tree.len() as u64,
+        }))
+    }
+
+    type StreamUpdatesStream = Pin<Box<dyn Stream<Item = Result<pb::Update, Status>> + Send>>;
+
+    async fn stream_updates(
+        &self,
+        _request: Request<pb::StreamUpdatesRequest>,
+    ) -> Result<Response<Self::StreamUpdatesStream>, Status> {
+        // A subscriber that falls more than UPDATE_BUFFER behind is told so and disconnected
+        let updates = BroadcastStream::new(self.updates.subscribe())
+            .map(|update| update.map_err(|e| Status::data_loss(e.to_string())));
+        Ok(Response::new(Box::pin(updates)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pb::crypto_tree_server::CryptoTree;
+    use super::*;
+
+    fn tx(id: &str) -> pb::Transaction {
+        pb::Transaction {
+            id: id.to_string(),
+            from: "A".to_string(),
+            to: "B".to_string(),
+            amount: "10".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_insert_search_proof() {
+        let service = CryptoTreeService::new(TransactionTree::new());
+        let mut updates = service.stream_updates(Request::new(pb::StreamUpdatesRequest {})).await.unwrap().into_inner();
+
+        let inserted = service
+            .insert(Request::new(pb::InsertRequest { transaction: Some(tx("tx_1")) }))
+            .await
+            .unwrap()
+            .into_inner();
+        let update = updates.next().await.unwrap().unwrap();
+        assert_eq!(update.ids, ["tx_1"]);
+        assert_eq!(update.root, inserted.root);
+
+        let duplicate = service
+            .insert(Request::new(pb::InsertRequest { transaction: Some(tx("tx_1")) }))
+            .await
+            .unwrap_err();
+        assert_eq!(duplicate.code(), tonic::Code::AlreadyExists);
+
+        let batch = service
+            .batch_insert(Request::new(pb::BatchInsertRequest {
+                transactions: vec![tx("tx_2"), tx("tx_1"), tx("tx_3")],
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(batch.inserted, 2);
+        assert_eq!(batch.duplicates, ["tx_1"]);
+        assert_eq!(updates.next().await.unwrap().unwrap().ids, ["tx_2", "tx_3"]);
+
+        let found = service.search(Request::new(pb::SearchRequest { id: "tx_2".to_string() })).await.unwrap();
+        assert_eq!(found.into_inner().amount, "10");
+        let missing = service.search(Request::new(pb::SearchRequest { id: "nope".to_string() })).await.unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+
+        let proof = service.get_proof(Request::new(pb::GetProofRequest { id: "tx_3".to_string() })).await.unwrap();
+        assert_eq!(proof.into_inner().root, batch.root);
+    }
+}
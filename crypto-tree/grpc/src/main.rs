@@ -0,0 +1,25 @@
+//! Serves a tree over gRPC.
+//!
+//! Usage: `crypto-tree-grpc [SNAPSHOT]`. The listen address is taken from
+//! `CRYPTO_TREE_GRPC_ADDR` (default `127.0.0.1:50051`); a snapshot, if given
+//! and present, is loaded at startup.
+
+use crypto_tree::TransactionTree;
+use crypto_tree_grpc::CryptoTreeService;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let tree = match std::env::args().nth(1) {
+        Some(path) if std::path::Path::new(&path).exists() => TransactionTree::load(&path)?,
+        _ => TransactionTree::new(),
+    };
+    let addr = std::env::var("CRYPTO_TREE_GRPC_ADDR")
+        .unwrap_or_else(|_| "127.0.0.1:50051".to_string())
+        .parse()?;
+    eprintln!("listening on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(CryptoTreeService::new(tree).into_server())
+        .serve(addr)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,289 @@
+//! Native Node.js bindings mirroring `CryptoTreeWasm`.
+//!
+//! Values cross the boundary as plain JS objects built from the same serde
+//! representation the WASM package uses, but without the WASM memory copy;
+//! snapshots are exchanged as `Buffer`s.
+
+use crypto_tree::{CryptoBinaryTree, ProofStep, Sha256Hasher, Transaction};
+use napi::bindgen_prelude::{BigInt, Buffer};
+use napi::{Error, Result};
+use napi_derive::napi;
+use serde_json::Value;
+
+fn invalid(what: &str, e: impl std::fmt::Display) -> Error {
+    Error::from_reason(format!("invalid {}: {}", what, e))
+}
+
+fn to_js<T: serde::Serialize + ?Sized>(value: &T) -> Result<Value> {
+    serde_json::to_value(value).map_err(|e| Error::from_reason(format!("could not convert result for JS: {}", e)))
+}
+
+/// Summary returned by `stats`
+#[napi(object)]
+pub struct TreeStats {
+    pub size: i64,
+    pub height: i32,
+    pub root: String,
+    /// Edges on the longest root-to-leaf path, 0 for an empty tree
+    pub max_depth: i64,
+}
+
+#[napi(js_name = "CryptoTree")]
+pub struct CryptoTreeNative {
+    tree: CryptoBinaryTree,
+}
+
+#[napi]
+impl CryptoTreeNative {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self {
+            tree: CryptoBinaryTree::new(),
+        }
+    }
+
+    /// Restores a tree from `exportState` output, rehashing every node.
+    #[napi(factory)]
+    pub fn from_state(bytes: Buffer) -> Result<Self> {
+        let tree = CryptoBinaryTree::read_snapshot(&mut &bytes[..], Sha256Hasher::default())
+            .map_err(|e| invalid("state", e))?;
+        Ok(Self { tree })
+    }
+
+    /// Serializes the tree as a snapshot.
+    #[napi]
+    pub fn export_state(&self) -> Result<Buffer> {
+        let mut bytes = Vec::new();
+        self.tree
+            .write_snapshot(&mut bytes)
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+        Ok(bytes.into())
+    }
+
+    #[napi]
+    pub fn insert(&mut self, id: String, from: String, to: String, amount: BigInt, timestamp: Option<BigInt>) -> Result<bool> {
+        let (negative, amount, lossless) = amount.get_u128();
+        if negative || !lossless {
+            return Err(invalid("amount", "must be a non-negative 128-bit integer"));
+        }
+        let timestamp = match timestamp.map(|t| t.get_u64()) {
+            None => None,
+            Some((false, t, true)) => Some(t),
+            Some(_) => return Err(invalid("timestamp", "must be a non-negative 64-bit integer")),
+        };
+        Ok(self.tree.insert(Transaction {
+            id,
+            from: from.into(),
+            to: to.into(),
+            amount,
+            timestamp,
+            ..Default::default()
+        }))
+    }
+
+    /// Inserts a `{id, from, to, amount, timestamp}` object.
+    ///
+    /// Returns `false` for a duplicate id and throws if the object is malformed.
+    #[napi]
+    pub fn insert_tx(&mut self, tx: Value) -> Result<bool> {
+        let tx: Transaction = serde_json::from_value(tx).map_err(|e| invalid("transaction", e))?;
+        Ok(self.tree.insert(tx))
+    }
+
+    /// Removes a transaction, returning it or `undefined` if the id is unknown.
+    #[napi]
+    pub fn remove(&mut self, id: String) -> Result<Option<Value>> {
+        self.tree.remove(&id).map(|tx| to_js(&tx)).transpose()
+    }
+
+    #[napi]
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Replaces the transaction stored under `id` with `tx`, returning the new Merkle root.
+    #[napi]
+    pub fn update(&mut self, id: String, tx: Value) -> Result<String> {
+        let tx: Transaction = serde_json::from_value(tx).map_err(|e| invalid("transaction", e))?;
+        self.tree
+            .update(&id, |stored| *stored = tx)
+            .map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    #[napi]
+    pub fn search(&self, id: String) -> Result<Option<Value>> {
+        self.tree.search(&id).map(to_js).transpose()
+    }
+
+    #[napi]
+    pub fn get_proof_of_inclusion(&self, id: String) -> Result<Option<Value>> {
+        self.tree.get_proof_of_inclusion(&id).map(|proof| to_js(&proof)).transpose()
+    }
+
+    /// Like `getProofOfInclusion`, bundled with the transaction, root and height.
+    #[napi]
+    pub fn get_proof(&self, id: String) -> Result<Option<Value>> {
+        self.tree.get_proof(&id).map(|proof| to_js(&proof)).transpose()
+    }
+
+    /// Checks a proof from `getProofOfInclusion` against a Merkle root,
+    /// without holding the tree. Malformed inputs yield `false`.
+    #[napi]
+    pub fn verify_proof(root: String, transaction: Value, proof: Value) -> bool {
+        let (Ok(transaction), Ok(proof)) = (
+            serde_json::from_value::<Transaction>(transaction),
+            serde_json::from_value::<Vec<ProofStep>>(proof),
+        ) else {
+            return false;
+        };
+        crypto_tree::verify_proof(&root, &transaction, &proof)
+    }
+
+    /// Returns up to `limit` transactions in id order, skipping the first `offset`.
+    #[napi]
+    pub fn list(&self, offset: u32, limit: u32) -> Result<Value> {
+        let offset = offset as usize;
+        let end = offset.saturating_add(limit as usize).min(self.tree.len());
+        let page: Vec<&Transaction> = (offset..end).filter_map(|k| self.tree.select(k)).collect();
+        to_js(&page)
+    }
+
+    /// Returns the transactions with ids in `startId..=endId`, in id order.
+    #[napi]
+    pub fn list_range(&self, start_id: String, end_id: String) -> Result<Value> {
+        let page: Vec<&Transaction> = (self.tree.rank(start_id.as_str())..)
+            .map_while(|k| self.tree.select(k))
+            .take_while(|tx| tx.id <= end_id)
+            .collect();
+        to_js(&page)
+    }
+
+    #[napi]
+    pub fn verify_integrity(&self) -> bool {
+        self.tree.verify_integrity()
+    }
+
+    #[napi]
+    pub fn merkle_root(&self) -> String {
+        self.tree.merkle_root().to_string()
+    }
+
+    #[napi]
+    pub fn len(&self) -> Result<u32> {
+        u32::try_from(self.tree.len()).map_err(|_| Error::from_reason("tree holds more than 2^32 - 1 transactions"))
+    }
+
+    #[napi]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    #[napi]
+    pub fn height(&self) -> i32 {
+        self.tree.height()
+    }
+
+    #[napi]
+    pub fn stats(&self) -> Result<TreeStats> {
+        Ok(TreeStats {
+            size: i64::from(self.len()?),
+            height: self.tree.height(),
+            root: self.tree.merkle_root().to_string(),
+            max_depth: i64::from((self.tree.height() - 1).max(0)),
+        })
+    }
+}
+
+impl Default for CryptoTreeNative {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crypto_tree_testkit::sample_tx;
+    use serde_json::json;
+
+    use super::*;
+
+    fn tx_js(id: &str, amount: u128) -> Value {
+        to_js(&sample_tx(id, amount)).unwrap()
+    }
+
+    fn ids(page: Value) -> Vec<String> {
+        let page: Vec<Transaction> = serde_json::from_value(page).unwrap();
+        page.into_iter().map(|tx| tx.id).collect()
+    }
+
+    #[test]
+    fn test_insert_and_insert_tx() {
+        let mut tree = CryptoTreeNative::new();
+        let big = BigInt::from(u128::MAX);
+        assert!(tree.insert("tx_1".into(), "Alice".into(), "Bob".into(), big, Some(BigInt::from(1_700_000_000u64))).unwrap());
+        assert!(!tree.insert("tx_1".into(), "Alice".into(), "Bob".into(), BigInt::from(1u64), None).unwrap());
+        assert!(tree.insert("tx_2".into(), "Alice".into(), "Bob".into(), BigInt::from(-1i64), None).is_err());
+        let stored = tree.tree.search("tx_1").unwrap();
+        assert_eq!((stored.amount, stored.timestamp), (u128::MAX, Some(1_700_000_000)));
+
+        assert!(tree.insert_tx(tx_js("tx_2", 10)).unwrap());
+        assert!(!tree.insert_tx(tx_js("tx_2", 10)).unwrap());
+        assert!(tree.insert_tx(json!({ "id": "tx_3" })).is_err());
+        assert_eq!(tree.len().unwrap(), 2);
+        assert_eq!(tree.search("tx_2".into()).unwrap(), Some(tx_js("tx_2", 10)));
+    }
+
+    #[test]
+    fn test_update_and_remove() {
+        let mut tree = CryptoTreeNative::new();
+        tree.insert_tx(tx_js("tx_1", 10)).unwrap();
+        tree.insert_tx(tx_js("tx_2", 20)).unwrap();
+
+        let root = tree.update("tx_1".into(), tx_js("tx_1", 15)).unwrap();
+        assert_eq!(root, tree.merkle_root());
+        assert!(tree.update("tx_9".into(), tx_js("tx_9", 1)).is_err());
+        assert!(tree.update("tx_1".into(), tx_js("tx_2", 1)).is_err());
+        assert!(tree.update("tx_1".into(), json!(null)).is_err());
+        assert_eq!(tree.merkle_root(), root);
+
+        assert_eq!(tree.remove("tx_1".into()).unwrap(), Some(tx_js("tx_1", 15)));
+        assert_eq!(tree.remove("tx_1".into()).unwrap(), None);
+        assert_eq!(tree.len().unwrap(), 1);
+        tree.clear();
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_list_and_list_range() {
+        let mut tree = CryptoTreeNative::new();
+        for i in 1..=25 {
+            tree.insert_tx(tx_js(&format!("tx_{:03}", i), 10)).unwrap();
+        }
+        assert_eq!(ids(tree.list(0, 3).unwrap()), ["tx_001", "tx_002", "tx_003"]);
+        assert_eq!(ids(tree.list(23, 10).unwrap()), ["tx_024", "tx_025"]);
+        assert!(ids(tree.list(25, 10).unwrap()).is_empty());
+        assert!(ids(tree.list(u32::MAX, u32::MAX).unwrap()).is_empty());
+
+        // Bounds need not be stored ids, and both ends are included
+        assert_eq!(ids(tree.list_range("tx_010".into(), "tx_012".into()).unwrap()), ["tx_010", "tx_011", "tx_012"]);
+        assert_eq!(ids(tree.list_range("tx_0235".into(), "zzz".into()).unwrap()), ["tx_024", "tx_025"]);
+        assert!(ids(tree.list_range("tx_012".into(), "tx_010".into()).unwrap()).is_empty());
+        assert!(ids(CryptoTreeNative::new().list_range(String::new(), "zzz".into()).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_stats() {
+        let empty = CryptoTreeNative::new().stats().unwrap();
+        assert_eq!((empty.size, empty.height, empty.max_depth), (0, 0, 0));
+        assert_eq!(empty.root, CryptoTreeNative::new().merkle_root());
+
+        // 7 sorted inserts settle into a perfect tree of 3 levels
+        let mut tree = CryptoTreeNative::new();
+        for i in 1..=7 {
+            tree.insert_tx(tx_js(&format!("tx_{}", i), 10)).unwrap();
+        }
+        let stats = tree.stats().unwrap();
+        assert_eq!((stats.size, stats.height, stats.max_depth), (7, 3, 2));
+        assert_eq!(stats.root, tree.merkle_root());
+    }
+}
@@ -0,0 +1,141 @@
+//! Poseidon node hashing over the BN254 scalar field.
+//!
+//! [`PoseidonHasher`] is a [`TreeHasher`] whose digests are field elements,
+//! using the circom-compatible parameters of `light-poseidon`, so inclusion
+//! proofs can be re-checked inside Groth16/PLONK circuits over BN254.
+//!
+//! Byte strings, including the canonical node encoding the tree hashes, map
+//! to field elements with [`bytes_to_field_elements`]: big-endian chunks of
+//! 31 bytes, each below the field modulus. [`hash_field_elements`] absorbs
+//! them into a running state seeded with the byte length, at most
+//! [`RATE`] elements per permutation.
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use crypto_tree::{encode_canonical, EncodingError, HashFormat, TreeHasher};
+use light_poseidon::{Poseidon, PoseidonHasher as _};
+use serde::Serialize;
+
+/// Bytes packed into one field element; 31 bytes always fit below the modulus
+pub const CHUNK_BYTES: usize = 31;
+
+/// Field elements absorbed per permutation, next to the running state
+pub const RATE: usize = 11;
+
+/// Splits `data` into big-endian field elements of [`CHUNK_BYTES`] bytes each;
+/// the last element holds the remainder.
+pub fn bytes_to_field_elements(data: &[u8]) -> Vec<Fr> {
+    data.chunks(CHUNK_BYTES).map(Fr::from_be_bytes_mod_order).collect()
+}
+
+/// Field-element encoding of a payload: its canonical binary encoding (see
+/// [`encode_canonical`]), packed with [`bytes_to_field_elements`].
+pub fn transaction_field_elements<T: Serialize + ?Sized>(transaction: &T) -> Result<Vec<Fr>, EncodingError> {
+    Ok(bytes_to_field_elements(&encode_canonical(transaction)?))
+}
+
+/// Hashes field elements that encode `byte_len` bytes.
+///
+/// The state starts as `byte_len`, then absorbs the elements [`RATE`] at a
+/// time as `state = Poseidon(state, e_1, .., e_k)`; with no elements the
+/// result is `Poseidon(byte_len)`.
+pub fn hash_field_elements(byte_len: u64, elements: &[Fr]) -> Fr {
+    let mut state = Fr::from(byte_len);
+    if elements.is_empty() {
+        return permute(&[state]);
+    }
+    for chunk in elements.chunks(RATE) {
+        let mut inputs = Vec::with_capacity(chunk.len() + 1);
+        inputs.push(state);
+        inputs.extend_from_slice(chunk);
+        state = permute(&inputs);
+    }
+    state
+}
+
+fn permute(inputs: &[Fr]) -> Fr {
+    Poseidon::<Fr>::new_circom(inputs.len())
+        .and_then(|mut poseidon| poseidon.hash(inputs))
+        .expect("1 to 12 inputs are supported by the circom parameters")
+}
+
+/// Renders a field element as 64 lowercase hex digits, big-endian.
+pub fn field_element_to_hex(element: &Fr) -> String {
+    element.into_bigint().to_bytes_be().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A [`TreeHasher`] computing every node hash with Poseidon over BN254
+#[derive(Clone, Debug, Default)]
+pub struct PoseidonHasher {
+    format: HashFormat,
+}
+
+impl PoseidonHasher {
+    pub fn new() -> Self {
+        Self::with_format(HashFormat::default())
+    }
+
+    /// Creates a hasher using a specific node layout.
+    pub fn with_format(format: HashFormat) -> Self {
+        Self { format }
+    }
+}
+
+impl TreeHasher for PoseidonHasher {
+    fn hash(&self, data: &[u8]) -> String {
+        field_element_to_hex(&hash_field_elements(data.len() as u64, &bytes_to_field_elements(data)))
+    }
+
+    fn format(&self) -> HashFormat {
+        self.format
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto_tree::{verify_proof, verify_proof_with, CryptoBinaryTree};
+    use crypto_tree_testkit::sample_tx;
+
+    #[test]
+    fn test_matches_circom_poseidon() {
+        // circomlib Poseidon([1, 2])
+        let hash = Poseidon::<Fr>::new_circom(2).unwrap().hash(&[Fr::from(1u64), Fr::from(2u64)]).unwrap();
+        assert_eq!(
+            field_element_to_hex(&hash),
+            "115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a"
+        );
+    }
+
+    #[test]
+    fn test_packing() {
+        let data: Vec<u8> = (0..70).collect();
+        let elements = bytes_to_field_elements(&data);
+        assert_eq!(elements.len(), 3);
+        assert_eq!(elements[2], Fr::from(0x3e3f_4041_4243_4445u64));
+        assert_eq!(transaction_field_elements(&sample_tx("tx_1", 10)).unwrap().len(), {
+            let bytes = encode_canonical(&sample_tx("tx_1", 10)).unwrap();
+            bytes.len().div_ceil(CHUNK_BYTES)
+        });
+
+        // The length seed tells apart inputs that pack to the same elements
+        let hasher = PoseidonHasher::new();
+        assert_ne!(hasher.hash(&[0, 1]), hasher.hash(&[1]));
+        assert_ne!(hasher.hash(b""), hasher.hash(&[0]));
+    }
+
+    #[test]
+    fn test_poseidon_tree() {
+        let mut tree = CryptoBinaryTree::with_hasher(PoseidonHasher::new());
+        for i in 1..=12 {
+            tree.insert(sample_tx(&format!("tx_{:02}", i), 10));
+        }
+        assert!(tree.verify_integrity());
+        assert_eq!(tree.merkle_root().len(), 64);
+
+        let tx = tree.search("tx_05").unwrap();
+        let proof = tree.get_proof_of_inclusion("tx_05").unwrap();
+        assert!(verify_proof_with(tree.hasher(), tree.merkle_root(), tx, &proof));
+        assert!(!verify_proof(tree.merkle_root(), tx, &proof));
+    }
+}
@@ -0,0 +1,341 @@
+//! [RocksDB](https://rocksdb.org) storage for the `crypto-tree` Merkle AVL
+//! tree, aimed at ledgers larger than memory.
+//!
+//! [`RocksStore`] implements [`TreeStore`] with one column family each for
+//! nodes, committed roots and two secondary indexes. Node writes are buffered
+//! in a `WriteBatch` that `commit_root` writes together with the new root, so
+//! an [`AsyncCryptoTree::insert_batch`] lands atomically or not at all.
+//!
+//! [`AsyncCryptoTree::insert_batch`]: crypto_tree::AsyncCryptoTree::insert_batch
+
+use core::fmt;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use crypto_tree::{LedgerEntry, StoredNode, TreeKey, TreeStore};
+use rocksdb::{
+    ColumnFamily, ColumnFamilyDescriptor, DBCompactionStyle, DBCompressionType, Direction, IteratorMode, Options,
+    WriteBatch, WriteOptions, DB,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Column family holding JSON-encoded nodes by hash
+pub const NODES_CF: &str = "nodes";
+/// Column family journaling committed roots by sequence number
+pub const ROOTS_CF: &str = "roots";
+/// Column family indexing transaction ids by sender and recipient
+pub const ADDRESS_CF: &str = "by_address";
+/// Column family indexing transaction ids by timestamp
+pub const TIME_CF: &str = "by_time";
+
+const COLUMN_FAMILIES: [&str; 4] = [NODES_CF, ROOTS_CF, ADDRESS_CF, TIME_CF];
+
+/// Failure of a [`RocksStore`]
+#[derive(Debug)]
+pub enum RocksStoreError {
+    /// RocksDB could not read or write
+    Rocks(rocksdb::Error),
+    /// A stored node could not be encoded or decoded
+    Encoding(serde_json::Error),
+    /// A journaled root or index entry is not valid
+    Malformed(&'static str),
+}
+
+impl fmt::Display for RocksStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RocksStoreError::Rocks(e) => write!(f, "rocksdb: {}", e),
+            RocksStoreError::Encoding(e) => write!(f, "node encoding: {}", e),
+            RocksStoreError::Malformed(what) => write!(f, "malformed {}", what),
+        }
+    }
+}
+
+impl std::error::Error for RocksStoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RocksStoreError::Rocks(e) => Some(e),
+            RocksStoreError::Encoding(e) => Some(e),
+            RocksStoreError::Malformed(_) => None,
+        }
+    }
+}
+
+impl From<rocksdb::Error> for RocksStoreError {
+    fn from(e: rocksdb::Error) -> Self {
+        RocksStoreError::Rocks(e)
+    }
+}
+
+impl From<serde_json::Error> for RocksStoreError {
+    fn from(e: serde_json::Error) -> Self {
+        RocksStoreError::Encoding(e)
+    }
+}
+
+/// How a [`RocksStore`] is tuned; applies to every column family
+#[derive(Debug, Clone)]
+pub struct StoreOptions {
+    /// Defaults to level compaction, suited to the mostly-appended nodes
+    pub compaction: DBCompactionStyle,
+    /// Leave compaction to `RocksStore::compact`, e.g. during bulk loads
+    pub disable_auto_compaction: bool,
+    pub compression: DBCompressionType,
+    /// Sync the write-ahead log on every commit; on by default
+    pub sync: bool,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        StoreOptions {
+            compaction: DBCompactionStyle::Level,
+            disable_auto_compaction: false,
+            compression: DBCompressionType::Lz4,
+            sync: true,
+        }
+    }
+}
+
+impl StoreOptions {
+    fn rocks(&self) -> Options {
+        let mut options = Options::default();
+        options.set_compaction_style(self.compaction);
+        options.set_disable_auto_compactions(self.disable_auto_compaction);
+        options.set_compression_type(self.compression);
+        options
+    }
+}
+
+/// Writes buffered until the next commit
+#[derive(Default)]
+struct Pending {
+    batch: WriteBatch,
+    /// Encoded nodes in `batch`, which reads must see before the commit
+    nodes: HashMap<String, Vec<u8>>,
+}
+
+/// A [`TreeStore`] in a RocksDB database
+///
+/// Besides nodes and roots it indexes every stored transaction by sender,
+/// recipient and timestamp; see [`ids_by_address`](Self::ids_by_address) and
+/// [`ids_between`](Self::ids_between). RocksDB is called on the polling
+/// task: single-key reads and one batch write per commit, which return
+/// quickly but do block.
+pub struct RocksStore<T> {
+    db: DB,
+    pending: Mutex<Pending>,
+    /// Sequence number of the next journaled root
+    next_root: AtomicU64,
+    sync: bool,
+    _payload: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for RocksStore<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RocksStore").field("path", &self.db.path()).finish_non_exhaustive()
+    }
+}
+
+impl<T> RocksStore<T> {
+    /// Opens or creates the database at `path` with default options.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RocksStoreError> {
+        Self::open_with(path, StoreOptions::default())
+    }
+
+    pub fn open_with(path: impl AsRef<Path>, options: StoreOptions) -> Result<Self, RocksStoreError> {
+        let mut db_options = options.rocks();
+        db_options.create_if_missing(true);
+        db_options.create_missing_column_families(true);
+        let families = COLUMN_FAMILIES.map(|name| ColumnFamilyDescriptor::new(name, options.rocks()));
+        let db = DB::open_cf_descriptors(&db_options, path, families)?;
+        let next_root = match db.iterator_cf(cf(&db, ROOTS_CF), IteratorMode::End).next() {
+            Some(entry) => decode_root(entry?)?.0 + 1,
+            None => 0,
+        };
+        Ok(RocksStore {
+            db,
+            pending: Mutex::new(Pending::default()),
+            next_root: AtomicU64::new(next_root),
+            sync: options.sync,
+            _payload: PhantomData,
+        })
+    }
+
+    pub fn db(&self) -> &DB {
+        &self.db
+    }
+
+    /// Compacts every column family now, e.g. after a bulk load with
+    /// automatic compaction disabled.
+    pub fn compact(&self) {
+        for name in COLUMN_FAMILIES {
+            self.db.compact_range_cf(cf(&self.db, name), None::<&[u8]>, None::<&[u8]>);
+        }
+    }
+
+    /// Every committed root with its sequence number, oldest first; `None`
+    /// records a commit of the empty tree.
+    pub fn root_history(&self) -> Result<Vec<(u64, Option<String>)>, RocksStoreError> {
+        self.db
+            .iterator_cf(cf(&self.db, ROOTS_CF), IteratorMode::Start)
+            .map(|entry| decode_root(entry?))
+            .collect()
+    }
+
+    /// Ids of the committed transactions sent or received by `address`, in id order
+    pub fn ids_by_address(&self, address: &str) -> Result<Vec<String>, RocksStoreError> {
+        let mut prefix = address.as_bytes().to_vec();
+        prefix.push(0);
+        self.scan(ADDRESS_CF, &prefix, |key| key.starts_with(&prefix))
+    }
+
+    /// Ids of the committed transactions with a timestamp in `start..=end`,
+    /// by timestamp and then id
+    pub fn ids_between(&self, start: u64, end: u64) -> Result<Vec<String>, RocksStoreError> {
+        let from = start.to_be_bytes();
+        self.scan(TIME_CF, &from, |key| key[..8] <= end.to_be_bytes()[..])
+    }
+
+    /// Ids in the index `name` from key `from` on, for as long as `within` holds
+    fn scan(&self, name: &str, from: &[u8], within: impl Fn(&[u8]) -> bool) -> Result<Vec<String>, RocksStoreError> {
+        let mut ids = Vec::new();
+        for entry in self.db.iterator_cf(cf(&self.db, name), IteratorMode::From(from, Direction::Forward)) {
+            let (key, _) = entry?;
+            if !within(&key) {
+                break;
+            }
+            let id = match name {
+                TIME_CF => key.get(8..),
+                _ => key.iter().position(|&b| b == 0).map(|at| &key[at + 1..]),
+            };
+            let id = id.ok_or(RocksStoreError::Malformed("index entry"))?;
+            ids.push(String::from_utf8(id.to_vec()).map_err(|_| RocksStoreError::Malformed("index entry"))?);
+        }
+        Ok(ids)
+    }
+}
+
+fn cf<'a>(db: &'a DB, name: &str) -> &'a ColumnFamily {
+    db.cf_handle(name).expect("column families are created on open")
+}
+
+fn decode_root((key, value): (Box<[u8]>, Box<[u8]>)) -> Result<(u64, Option<String>), RocksStoreError> {
+    let seq = <[u8; 8]>::try_from(&*key).map_err(|_| RocksStoreError::Malformed("root journal"))?;
+    let root = match &*value {
+        [] => None,
+        hash => Some(String::from_utf8(hash.to_vec()).map_err(|_| RocksStoreError::Malformed("root journal"))?),
+    };
+    Ok((u64::from_be_bytes(seq), root))
+}
+
+/// Id of a payload as text, as the tree reports it in errors
+fn id_of<T: TreeKey>(transaction: &T) -> Result<String, RocksStoreError> {
+    Ok(match serde_json::to_value(transaction.key())? {
+        serde_json::Value::String(s) => s,
+        value => value.to_string(),
+    })
+}
+
+impl<T: LedgerEntry + Serialize + DeserializeOwned + Send + Sync> TreeStore<T> for RocksStore<T> {
+    type Error = RocksStoreError;
+
+    async fn get_node(&self, hash: &str) -> Result<Option<StoredNode<T>>, RocksStoreError> {
+        let pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner).nodes.get(hash).cloned();
+        let bytes = match pending {
+            Some(bytes) => bytes,
+            None => match self.db.get_cf(cf(&self.db, NODES_CF), hash)? {
+                Some(bytes) => bytes,
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(serde_json::from_slice(&bytes)?))
+    }
+
+    async fn put_node(&self, hash: &str, node: &StoredNode<T>) -> Result<(), RocksStoreError> {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        // Nodes are content-addressed, so one already stored is identical
+        if pending.nodes.contains_key(hash) || self.db.get_pinned_cf(cf(&self.db, NODES_CF), hash)?.is_some() {
+            return Ok(());
+        }
+        let bytes = serde_json::to_vec(node)?;
+        let tx = &node.transaction;
+        let id = id_of(tx)?;
+        // Rewritten path nodes repeat these entries, which is harmless
+        for address in [tx.sender(), tx.recipient()] {
+            pending
+                .batch
+                .put_cf(cf(&self.db, ADDRESS_CF), [address.as_bytes(), &[0][..], id.as_bytes()].concat(), b"");
+        }
+        if let Some(timestamp) = tx.timestamp() {
+            pending
+                .batch
+                .put_cf(cf(&self.db, TIME_CF), [&timestamp.to_be_bytes()[..], id.as_bytes()].concat(), b"");
+        }
+        pending.batch.put_cf(cf(&self.db, NODES_CF), hash, &bytes);
+        pending.nodes.insert(hash.to_string(), bytes);
+        Ok(())
+    }
+
+    async fn commit_root(&self, root: Option<&str>) -> Result<(), RocksStoreError> {
+        // Buffered nodes are dropped if the write fails, as the tree returns
+        // to its last committed root
+        let Pending { mut batch, .. } = core::mem::take(&mut *self.pending.lock().unwrap_or_else(PoisonError::into_inner));
+        let seq = self.next_root.fetch_add(1, Ordering::SeqCst);
+        batch.put_cf(cf(&self.db, ROOTS_CF), seq.to_be_bytes(), root.unwrap_or("").as_bytes());
+        let mut options = WriteOptions::default();
+        options.set_sync(self.sync);
+        self.db.write_opt(batch, &options)?;
+        Ok(())
+    }
+
+    async fn load_root(&self) -> Result<Option<String>, RocksStoreError> {
+        match self.db.iterator_cf(cf(&self.db, ROOTS_CF), IteratorMode::End).next() {
+            Some(entry) => Ok(decode_root(entry?)?.1),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crypto_tree::{verify_proof, AsyncCryptoTree, CryptoBinaryTree, Sha256Hasher, Transaction};
+    use crypto_tree_testkit::sample_tx;
+    use pollster::block_on;
+
+    use super::*;
+
+    #[test]
+    fn test_batches_commit_atomically_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let batch: Vec<_> = (0..50)
+            .map(|i| Transaction {
+                from: if i % 2 == 0 { "Alice" } else { "Carol" }.into(),
+                timestamp: Some(1_000 + i),
+                ..sample_tx(&format!("tx_{:02}", i), 10)
+            })
+            .collect();
+        let mut reference = CryptoBinaryTree::new();
+        reference.insert_batch(batch.clone());
+        {
+            let store = RocksStore::open(dir.path()).unwrap();
+            let mut tree = block_on(AsyncCryptoTree::open(store, Sha256Hasher::new())).unwrap();
+            assert_eq!(block_on(tree.insert_batch(batch)).unwrap().inserted, 50);
+            assert_eq!(tree.store().root_history().unwrap().len(), 1);
+            assert_eq!(tree.merkle_root(), reference.merkle_root());
+        }
+
+        let store = RocksStore::<Transaction>::open(dir.path()).unwrap();
+        assert_eq!(store.ids_by_address("Alice").unwrap().len(), 25);
+        assert_eq!(store.ids_by_address("Bob").unwrap().len(), 50);
+        assert_eq!(store.ids_between(1_010, 1_012).unwrap(), ["tx_10", "tx_11", "tx_12"]);
+        let tree = block_on(AsyncCryptoTree::open(store, Sha256Hasher::new())).unwrap();
+        assert_eq!(tree.merkle_root(), reference.merkle_root());
+        let tx = block_on(tree.search("tx_07")).unwrap().unwrap();
+        let proof = block_on(tree.get_proof_of_inclusion("tx_07")).unwrap().unwrap();
+        assert!(verify_proof(tree.merkle_root(), &tx, &proof));
+    }
+}
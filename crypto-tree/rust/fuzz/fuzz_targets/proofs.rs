@@ -0,0 +1,32 @@
+//! Proof decoders and verifiers: malformed proofs are rejected, never a panic.
+
+#![no_main]
+
+use crypto_tree::{
+    verify_absence_proof, verify_multi_proof, verify_range_proof, AbsenceProof, MultiProof, Proof, RangeProof,
+    Transaction,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(proof) = Proof::<Transaction>::from_bytes(data) {
+        let _ = proof.verify();
+        let _ = proof.to_bytes();
+    }
+    if let Ok(text) = core::str::from_utf8(data) {
+        let _ = Proof::<Transaction>::from_base64(text).map(|p| p.verify());
+        let _ = Proof::<Transaction>::from_hex(text).map(|p| p.verify());
+    }
+    if let Ok(proof) = serde_json::from_slice::<Proof>(data) {
+        let _ = proof.verify();
+    }
+    if let Ok(proof) = serde_json::from_slice::<AbsenceProof<Transaction>>(data) {
+        let _ = verify_absence_proof("0", &proof);
+    }
+    if let Ok((transactions, proof)) = serde_json::from_slice::<(Vec<Transaction>, MultiProof<Transaction>)>(data) {
+        let _ = verify_multi_proof("0", &transactions, &proof);
+    }
+    if let Ok(proof) = serde_json::from_slice::<RangeProof<Transaction>>(data) {
+        let _ = verify_range_proof("0", &proof);
+    }
+});
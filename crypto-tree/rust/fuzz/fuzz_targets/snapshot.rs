@@ -0,0 +1,16 @@
+//! Binary snapshots and CBOR exports: any input loads or fails cleanly, and
+//! whatever loads passes the integrity check.
+
+#![no_main]
+
+use crypto_tree::{Sha256Hasher, TransactionTree};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(tree) = TransactionTree::read_snapshot(&mut &data[..], Sha256Hasher::new()) {
+        assert!(tree.check_integrity().is_ok());
+    }
+    if let Ok(tree) = TransactionTree::from_cbor(data) {
+        assert!(tree.check_integrity().is_ok());
+    }
+});
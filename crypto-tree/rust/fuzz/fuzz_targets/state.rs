@@ -0,0 +1,26 @@
+//! JSON tree states, verified and unverified.
+//!
+//! A verified load must pass the integrity check; an unverified one may hold
+//! wrong hashes but must still be safe to query and walk.
+
+#![no_main]
+
+use crypto_tree::{Sha256Hasher, TransactionTree, TreeState};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(tree) = serde_json::from_slice::<TransactionTree>(data) {
+        assert!(tree.check_integrity().is_ok());
+    }
+    let Ok(state) = serde_json::from_slice::<TreeState>(data) else {
+        return;
+    };
+    if let Ok(tree) = state.into_tree(Sha256Hasher::new(), false) {
+        let _ = tree.check_integrity();
+        if let Some(first) = tree.first() {
+            let _ = tree.get_proof(first.id.as_str());
+        }
+        let _ = tree.get_proof_of_absence("tx_fuzz");
+        let _ = tree.clone().into_iter().count();
+    }
+});
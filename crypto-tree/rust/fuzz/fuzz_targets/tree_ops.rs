@@ -0,0 +1,31 @@
+//! Random operation sequences against eager and lazily hashed trees.
+//!
+//! After every step the tree must be a balanced search tree holding what the
+//! model holds; at the end both trees must agree on the root and pass the
+//! integrity check.
+
+#![no_main]
+
+use crypto_tree::TransactionTree;
+use crypto_tree_testkit::{check_shape, contents, Model, Op};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|ops: Vec<Op>| {
+    let mut eager = TransactionTree::new();
+    let mut lazy = TransactionTree::new();
+    lazy.set_lazy_hashing(true);
+    let mut model = Model::new();
+
+    for op in &ops {
+        op.apply(&mut eager);
+        op.apply(&mut lazy);
+        op.apply_model(&mut model);
+        if let Err(violation) = check_shape(&eager) {
+            panic!("after {:?}: {}", op, violation);
+        }
+    }
+
+    assert_eq!(contents(&eager), model.into_values().collect::<Vec<_>>());
+    assert!(eager.check_integrity().is_ok());
+    assert_eq!(lazy.flush_hashes(), eager.merkle_root());
+});
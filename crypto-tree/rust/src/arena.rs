@@ -0,0 +1,286 @@
+//! Paged, copy-on-write node storage.
+//!
+//! Nodes live in fixed-size pages and refer to their children by [`NodeId`].
+//! The arena never has holes: freeing a node moves the last one into its
+//! slot, so iterating, cloning, serializing or dropping a tree is a flat walk
+//! over the pages and never recurses.
+//!
+//! Pages and the page table are reference-counted. Cloning an arena only
+//! shares them; the first write to a shared page copies that page and the
+//! page table, so a frozen copy costs O(1) to take and a write after it
+//! copies the pages on its path rather than the whole tree.
+
+use alloc::sync::Arc;
+use core::ops::{Index, IndexMut};
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::CryptoTreeNode;
+
+/// Index of a node in its tree's arena.
+///
+/// Ids are only meaningful for the tree they came from and may change when
+/// a node is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub(crate) fn new(index: usize) -> Self {
+        NodeId(u32::try_from(index).expect("a tree holds at most u32::MAX nodes"))
+    }
+
+    /// Position of the node in the arena
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Running totals of the restructuring work done on an arena
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Work {
+    pub(crate) rotations: u64,
+    pub(crate) hashes: u64,
+}
+
+impl Work {
+    /// Work done since `earlier` was read
+    pub(crate) fn since(self, earlier: Work) -> Work {
+        Work {
+            rotations: self.rotations - earlier.rotations,
+            hashes: self.hashes - earlier.hashes,
+        }
+    }
+}
+
+/// Nodes per page; a power of two so ids split with a shift and a mask
+const PAGE_BITS: u32 = 6;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+type Page<T> = Vec<CryptoTreeNode<T>>;
+
+/// Page and slot of a node index
+fn locate(index: usize) -> (usize, usize) {
+    (index >> PAGE_BITS, index & (PAGE_SIZE - 1))
+}
+
+/// The nodes of one tree; clones share pages until either side writes
+#[derive(Debug)]
+pub(crate) struct Arena<T> {
+    /// Every page is full except possibly the last, which is never empty
+    pages: Arc<Vec<Arc<Page<T>>>>,
+    len: usize,
+    /// Rotations and node rehashes performed so far, reported to observers
+    pub(crate) work: Work,
+}
+
+impl<T> Clone for Arena<T> {
+    fn clone(&self) -> Self {
+        Arena {
+            pages: Arc::clone(&self.pages),
+            len: self.len,
+            work: self.work,
+        }
+    }
+}
+
+impl<T> Arena<T> {
+    pub(crate) fn new() -> Self {
+        Arena {
+            pages: Arc::new(Vec::new()),
+            len: 0,
+            work: Work::default(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn get(&self, id: NodeId) -> Option<&CryptoTreeNode<T>> {
+        let (page, slot) = locate(id.index());
+        self.pages.get(page)?.get(slot)
+    }
+
+    pub(crate) fn last_id(&self) -> Option<NodeId> {
+        self.len.checked_sub(1).map(NodeId::new)
+    }
+
+    /// Nodes in id order
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &CryptoTreeNode<T>> {
+        self.pages.iter().flat_map(|page| page.iter())
+    }
+
+    /// Nodes for rayon to split across threads, a page at a time
+    #[cfg(feature = "parallel")]
+    pub(crate) fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = &CryptoTreeNode<T>>
+    where
+        T: Send + Sync,
+    {
+        use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+        self.pages.as_slice().par_iter().flat_map_iter(|page| page.iter())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pages = Arc::new(Vec::new());
+        self.len = 0;
+    }
+
+    /// Height of a possibly empty subtree
+    pub(crate) fn height(&self, id: Option<NodeId>) -> i32 {
+        id.map_or(0, |id| self[id].height)
+    }
+
+    /// Node count of a possibly empty subtree
+    pub(crate) fn size(&self, id: Option<NodeId>) -> usize {
+        id.map_or(0, |id| self[id].size)
+    }
+
+    /// Stored hash of a possibly empty subtree, `None` for an empty one
+    pub(crate) fn hash(&self, id: Option<NodeId>) -> Option<&str> {
+        id.map(|id| self[id].hash.as_str())
+    }
+}
+
+impl<T: Clone> Arena<T> {
+    pub(crate) fn from_vec(nodes: Vec<CryptoTreeNode<T>>) -> Self {
+        let len = nodes.len();
+        let mut pages = Vec::with_capacity(len.div_ceil(PAGE_SIZE));
+        let mut nodes = nodes.into_iter();
+        while nodes.len() > 0 {
+            pages.push(Arc::new(nodes.by_ref().take(PAGE_SIZE).collect()));
+        }
+        Arena {
+            pages: Arc::new(pages),
+            len,
+            work: Work::default(),
+        }
+    }
+
+    /// The page at `page` for writing, copying it and the page table if shared
+    fn page_mut(&mut self, page: usize) -> &mut Page<T> {
+        Arc::make_mut(&mut Arc::make_mut(&mut self.pages)[page])
+    }
+
+    pub(crate) fn push(&mut self, node: CryptoTreeNode<T>) -> NodeId {
+        let id = NodeId::new(self.len);
+        let (page, _) = locate(self.len);
+        if page == self.pages.len() {
+            Arc::make_mut(&mut self.pages).push(Arc::new(Vec::with_capacity(PAGE_SIZE)));
+        }
+        self.page_mut(page).push(node);
+        self.len += 1;
+        id
+    }
+
+    /// Removes an unlinked node; the last node takes over its id.
+    ///
+    /// The caller must repoint the link to the moved node, see
+    /// [`Arena::last_id`].
+    pub(crate) fn swap_remove(&mut self, id: NodeId) -> CryptoTreeNode<T> {
+        let (last_page, _) = locate(self.len - 1);
+        let last = self.page_mut(last_page).pop().expect("pages are never empty");
+        if self.pages[last_page].is_empty() {
+            Arc::make_mut(&mut self.pages).pop();
+        }
+        self.len -= 1;
+        if id.index() == self.len {
+            return last;
+        }
+        core::mem::replace(&mut self[id], last)
+    }
+
+    /// Exchanges the payloads of two distinct nodes.
+    pub(crate) fn swap_transactions(&mut self, a: NodeId, b: NodeId) {
+        let (low, high) = (a.index().min(b.index()), a.index().max(b.index()));
+        let ((low_page, low_slot), (high_page, high_slot)) = (locate(low), locate(high));
+        if low_page == high_page {
+            let (head, tail) = self.page_mut(low_page).split_at_mut(high_slot);
+            core::mem::swap(&mut head[low_slot].transaction, &mut tail[0].transaction);
+        } else {
+            let pages = Arc::make_mut(&mut self.pages);
+            let (head, tail) = pages.split_at_mut(high_page);
+            let (low, high) = (Arc::make_mut(&mut head[low_page]), Arc::make_mut(&mut tail[0]));
+            core::mem::swap(&mut low[low_slot].transaction, &mut high[high_slot].transaction);
+        }
+    }
+
+    /// Nodes in id order for writing, copying shared pages
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut CryptoTreeNode<T>> {
+        Arc::make_mut(&mut self.pages)
+            .iter_mut()
+            .flat_map(|page| Arc::make_mut(page).iter_mut())
+    }
+
+    /// The nodes in id order, copying only pages still shared
+    pub(crate) fn into_vec(self) -> Vec<CryptoTreeNode<T>> {
+        let mut nodes = Vec::with_capacity(self.len);
+        for page in Arc::unwrap_or_clone(self.pages) {
+            nodes.extend(Arc::unwrap_or_clone(page));
+        }
+        nodes
+    }
+}
+
+impl<T> Index<NodeId> for Arena<T> {
+    type Output = CryptoTreeNode<T>;
+
+    fn index(&self, id: NodeId) -> &CryptoTreeNode<T> {
+        let (page, slot) = locate(id.index());
+        &self.pages[page][slot]
+    }
+}
+
+impl<T: Clone> IndexMut<NodeId> for Arena<T> {
+    fn index_mut(&mut self, id: NodeId) -> &mut CryptoTreeNode<T> {
+        let (page, slot) = locate(id.index());
+        &mut self.page_mut(page)[slot]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Transaction;
+
+    fn arena(n: usize) -> Arena<Transaction> {
+        let nodes = (0..n)
+            .map(|i| {
+                CryptoTreeNode::unhashed(Transaction {
+                    id: format!("tx_{}", i),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        Arena::from_vec(nodes)
+    }
+
+    #[test]
+    fn test_clones_share_untouched_pages() {
+        let original = arena(3 * PAGE_SIZE);
+        let mut copy = original.clone();
+        copy[NodeId::new(PAGE_SIZE + 1)].transaction.amount = 7;
+
+        assert_eq!(original[NodeId::new(PAGE_SIZE + 1)].transaction.amount, 0);
+        assert_eq!(copy[NodeId::new(PAGE_SIZE + 1)].transaction.amount, 7);
+        assert!(!Arc::ptr_eq(&original.pages, &copy.pages));
+        assert!(!Arc::ptr_eq(&original.pages[1], &copy.pages[1]));
+        assert!(Arc::ptr_eq(&original.pages[0], &copy.pages[0]) && Arc::ptr_eq(&original.pages[2], &copy.pages[2]));
+    }
+
+    #[test]
+    fn test_swap_remove_across_pages() {
+        let mut nodes = arena(PAGE_SIZE + 1);
+        let removed = nodes.swap_remove(NodeId::new(3));
+        assert_eq!(removed.transaction.id, "tx_3");
+        assert_eq!(nodes[NodeId::new(3)].transaction.id, format!("tx_{}", PAGE_SIZE));
+        assert_eq!((nodes.len(), nodes.pages.len()), (PAGE_SIZE, 1));
+
+        nodes.swap_transactions(NodeId::new(0), NodeId::new(PAGE_SIZE - 1));
+        assert_eq!(nodes[NodeId::new(0)].transaction.id, format!("tx_{}", PAGE_SIZE - 1));
+        let ids: Vec<_> = nodes.into_vec().into_iter().map(|n| n.transaction.id).collect();
+        assert_eq!(ids.len(), PAGE_SIZE);
+        assert_eq!(ids[PAGE_SIZE - 1], "tx_0");
+    }
+}
@@ -0,0 +1,287 @@
+//! Tamper-evident record of the operations applied to a tree.
+//!
+//! Once enabled with [`CryptoBinaryTree::enable_audit_log`], every call that
+//! changes the tree appends an [`AuditEntry`] naming the actor set with
+//! `set_audit_actor`, the time, the operation and the ids it touched, with
+//! the Merkle roots before and after. Each entry commits to the hash of the
+//! one before, so [`verify_audit_log`] shows that no entry was altered,
+//! dropped or reordered and that the recorded operations lead from the first
+//! root to the last.
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::{encode_canonical, CryptoBinaryTree, Result, TreeHasher, TreeKey};
+
+/// The kind of call an [`AuditEntry`] records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditOp {
+    Insert,
+    InsertBatch,
+    Update,
+    Remove,
+    Retain,
+    Clear,
+    Merge,
+    ApplyDelta,
+    Rollback,
+    Rebuild,
+}
+
+/// One mutating call in an [`AuditLog`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, starting at 0
+    pub seq: u64,
+    /// Who made the call, as last set with `set_audit_actor`
+    pub actor: String,
+    /// Unix timestamp of the call, never decreasing; 0 without the `std` feature
+    pub timestamp: u64,
+    pub operation: AuditOp,
+    /// Ids of the transactions inserted, updated or removed; empty for
+    /// `Clear`, `ApplyDelta` and `Rollback`, which replace the tree wholesale
+    pub keys: Vec<String>,
+    pub pre_root: String,
+    pub post_root: String,
+    /// Hash of the previous entry, `"0"` for the first
+    pub prev_hash: String,
+    /// Hash of the other fields of this entry
+    pub hash: String,
+}
+
+/// The fields an entry's hash covers
+#[derive(Serialize)]
+struct EntryBody<'a> {
+    seq: u64,
+    actor: &'a str,
+    timestamp: u64,
+    operation: AuditOp,
+    keys: &'a [String],
+    pre_root: &'a str,
+    post_root: &'a str,
+    prev_hash: &'a str,
+}
+
+impl AuditEntry {
+    /// Hashes the canonical encoding of every field but `hash` with `hasher`.
+    pub fn compute_hash<H: TreeHasher>(&self, hasher: &H) -> String {
+        let body = EntryBody {
+            seq: self.seq,
+            actor: &self.actor,
+            timestamp: self.timestamp,
+            operation: self.operation,
+            keys: &self.keys,
+            pre_root: &self.pre_root,
+            post_root: &self.post_root,
+            prev_hash: &self.prev_hash,
+        };
+        hasher.hash(&encode_canonical(&body).expect("audit entries always encode"))
+    }
+}
+
+/// Every mutating call on a tree since its audit log was enabled, oldest first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditLog {
+    entries: Vec<AuditEntry>,
+    actor: String,
+    /// Root after the latest entry, or at enable time
+    root: String,
+}
+
+impl AuditLog {
+    pub(crate) fn record<H: TreeHasher>(&mut self, operation: AuditOp, keys: Vec<String>, root: String, hasher: &H) {
+        let last = self.entries.last();
+        #[cfg(feature = "std")]
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        #[cfg(not(feature = "std"))]
+        let now = 0;
+        let mut entry = AuditEntry {
+            seq: last.map_or(0, |e| e.seq + 1),
+            actor: self.actor.clone(),
+            // A clock stepped back must not unsort the log
+            timestamp: last.map_or(now, |e| e.timestamp.max(now)),
+            operation,
+            keys,
+            pre_root: core::mem::replace(&mut self.root, root.clone()),
+            post_root: root,
+            prev_hash: last.map_or("0", |e| &e.hash).to_string(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash(hasher);
+        self.entries.push(entry);
+    }
+
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    /// Hash of the latest entry, `"0"` while the log is empty
+    pub fn head_hash(&self) -> &str {
+        self.entries.last().map_or("0", |e| &e.hash)
+    }
+
+    /// The actor the next entries are recorded under
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries as a JSON array.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&self.entries)?)
+    }
+
+    /// The entries as newline-delimited JSON, one entry per line.
+    pub fn to_ndjson(&self) -> Result<String> {
+        let mut out = String::new();
+        for entry in &self.entries {
+            out.push_str(&serde_json::to_string(entry)?);
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+/// Checks that `entries` form a log from its first entry: numbered in order,
+/// each hashing to its `hash` under `hasher`, naming the hash of the one
+/// before and starting from the root the one before ended at. Returns the
+/// hash of the last entry, or fails with the `seq` of the first that does not
+/// link up.
+pub fn verify_audit_log<H: TreeHasher>(entries: &[AuditEntry], hasher: &H) -> core::result::Result<String, u64> {
+    let mut prev: Option<&AuditEntry> = None;
+    for (seq, entry) in entries.iter().enumerate() {
+        let linked = match prev {
+            Some(prev) => entry.prev_hash == prev.hash && entry.pre_root == prev.post_root,
+            None => entry.prev_hash == "0",
+        };
+        if entry.seq != seq as u64 || !linked || entry.hash != entry.compute_hash(hasher) {
+            return Err(seq as u64);
+        }
+        prev = Some(entry);
+    }
+    Ok(prev.map_or("0", |e| &e.hash).to_string())
+}
+
+impl<T: TreeKey, H> CryptoBinaryTree<T, H> {
+    /// The recorded calls, `None` unless `enable_audit_log` was called
+    pub fn audit_log(&self) -> Option<&AuditLog> {
+        self.audit.as_ref()
+    }
+
+    /// Stops auditing and returns the log so far.
+    pub fn take_audit_log(&mut self) -> Option<AuditLog> {
+        self.audit.take()
+    }
+
+    /// Records the calls that follow under `actor`. Does nothing unless the
+    /// audit log is enabled.
+    pub fn set_audit_actor(&mut self, actor: impl Into<String>) {
+        if let Some(audit) = self.audit.as_mut() {
+            audit.actor = actor.into();
+        }
+    }
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Starts recording every mutating call as `actor`, from the current
+    /// root. Does nothing if already enabled.
+    ///
+    /// Inserts, batch inserts, updates, removals, `retain`, `clear`, `merge`,
+    /// `apply_delta` and rollbacks that change the tree each add one entry;
+    /// calls that fail or change nothing add none. Every entry needs its
+    /// post-root, so with lazy hashing each audited call flushes. Entries are
+    /// hashed with the tree's hasher. The log is cloned with the tree but not
+    /// kept in snapshots; export it with `to_json` or `to_ndjson`.
+    pub fn enable_audit_log(&mut self, actor: impl Into<String>) {
+        if self.audit.is_none() {
+            self.audit = Some(AuditLog {
+                entries: Vec::new(),
+                actor: actor.into(),
+                root: self.flush_hashes().to_string(),
+            });
+        }
+    }
+
+    /// Appends an entry for a call that changed the tree, if auditing.
+    pub(crate) fn _audit(&mut self, operation: AuditOp, keys: Vec<String>) {
+        if self.audit.is_none() {
+            return;
+        }
+        let root = self.flush_hashes().to_string();
+        if let Some(audit) = self.audit.as_mut() {
+            audit.record(operation, keys, root, &self.hasher);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MergePolicy, Sha256Hasher};
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_audit_log_chains_operations() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.insert(sample_tx("tx_a", 1));
+        let start = tree.merkle_root().to_string();
+        tree.enable_audit_log("alice");
+        tree.set_lazy_hashing(true);
+
+        tree.insert(sample_tx("tx_b", 2));
+        assert!(!tree.insert(sample_tx("tx_b", 3)));
+        tree.insert_batch(vec![sample_tx("tx_c", 1), sample_tx("tx_d", 1)]);
+        tree.set_audit_actor("bob");
+        tree.update("tx_a", |tx| tx.amount = 10).unwrap();
+        tree.remove("tx_b");
+        assert!(tree.remove("tx_b").is_none());
+        tree.retain(|tx| tx.id != "tx_c");
+        let mut other = CryptoBinaryTree::new();
+        other.insert(sample_tx("tx_e", 5));
+        tree.merge(other, MergePolicy::KeepExisting).unwrap();
+        tree.clear();
+
+        let log = tree.audit_log().unwrap();
+        let ops: Vec<_> = log.entries().iter().map(|e| e.operation).collect();
+        assert_eq!(
+            ops,
+            [
+                AuditOp::Insert,
+                AuditOp::InsertBatch,
+                AuditOp::Update,
+                AuditOp::Remove,
+                AuditOp::Retain,
+                AuditOp::Merge,
+                AuditOp::Clear,
+            ]
+        );
+        let entries = log.entries();
+        assert_eq!(entries[0].pre_root, start);
+        assert_eq!((entries[0].actor.as_str(), entries[2].actor.as_str()), ("alice", "bob"));
+        assert_eq!(entries[1].keys, ["tx_c", "tx_d"]);
+        assert_eq!(entries[6].post_root, "0");
+
+        let hasher = Sha256Hasher::new();
+        assert_eq!(verify_audit_log(entries, &hasher).unwrap(), log.head_hash());
+        let exported: Vec<AuditEntry> = log.to_ndjson().unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(exported, entries);
+        let array: Vec<AuditEntry> = serde_json::from_str(&log.to_json().unwrap()).unwrap();
+        assert_eq!(array, entries);
+
+        let mut forged = entries.to_vec();
+        forged[3].keys = vec!["tx_z".to_string()];
+        assert_eq!(verify_audit_log(&forged, &hasher), Err(3));
+        forged.remove(3);
+        assert_eq!(verify_audit_log(&forged, &hasher), Err(3));
+        let mut swapped = entries.to_vec();
+        swapped.swap(1, 2);
+        assert_eq!(verify_audit_log(&swapped, &hasher), Err(1));
+    }
+}
@@ -0,0 +1,178 @@
+//! `crypto-tree` command-line tool, built with the `cli` feature.
+//!
+//! The tree lives in a snapshot file (`--tree`, default `crypto-tree.snap`)
+//! that is loaded, and for mutating commands rewritten, on every invocation.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use crypto_tree::{Proof, Transaction, TransactionTree};
+
+const USAGE: &str = "\
+Usage: crypto-tree [--tree PATH] <COMMAND>
+
+Commands:
+  insert [FILE]                 Insert transactions (JSON array or NDJSON) from FILE or stdin
+  search <ID>                   Print a transaction as JSON
+  proof <ID>                    Print a self-contained inclusion proof as JSON
+  verify-proof [FILE] [--root ROOT]
+                                Check a proof from FILE or stdin, optionally against a trusted root
+  root                          Print the Merkle root
+  export [FILE]                 Write all transactions as NDJSON to FILE or stdout
+  import [FILE]                 Replace the tree with transactions from FILE or stdin
+  verify                        Recompute every hash and check the tree's shape
+
+Options:
+  --tree PATH                   Snapshot file holding the tree [default: crypto-tree.snap]
+  -h, --help                    Print this help
+";
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Runs one command; `Ok(false)` reports a negative verification result.
+fn run(args: Vec<String>) -> Result<bool, String> {
+    let mut tree_path = "crypto-tree.snap".to_string();
+    let mut root = None;
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tree" => tree_path = args.next().ok_or("--tree needs a path")?,
+            "--root" => root = Some(args.next().ok_or("--root needs a hash")?),
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return Ok(true);
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let Some((command, rest)) = positional.split_first() else {
+        return Err(format!("missing command\n\n{}", USAGE));
+    };
+    let tree_path = Path::new(&tree_path);
+    let operand = |name: &str| rest.first().cloned().ok_or(format!("{} needs {}", command, name));
+
+    match command.as_str() {
+        "insert" => {
+            let mut tree = load_or_new(tree_path)?;
+            let result = tree.insert_batch(parse_transactions(&read_input(rest.first())?)?);
+            tree.save(tree_path).map_err(|e| e.to_string())?;
+            println!("inserted {}, duplicates {}", result.inserted, result.duplicates.len());
+            for (id, error) in &result.failed {
+                eprintln!("rejected {}: {}", id, error);
+            }
+            Ok(result.failed.is_empty())
+        }
+        "search" => {
+            let tree = load(tree_path)?;
+            let id = operand("an id")?;
+            let tx = tree.search(id.as_str()).ok_or(format!("transaction {} not found", id))?;
+            println!("{}", to_json(tx)?);
+            Ok(true)
+        }
+        "proof" => {
+            let tree = load(tree_path)?;
+            let id = operand("an id")?;
+            let proof = tree.get_proof(id.as_str()).ok_or(format!("transaction {} not found", id))?;
+            println!("{}", to_json(&proof)?);
+            Ok(true)
+        }
+        "verify-proof" => {
+            let proof: Proof = serde_json::from_str(&read_input(rest.first())?).map_err(|e| format!("invalid proof: {}", e))?;
+            let valid = root.as_ref().is_none_or(|root| *root == proof.root) && proof.verify();
+            println!("{}", if valid { "valid" } else { "invalid" });
+            Ok(valid)
+        }
+        "root" => {
+            println!("{}", load(tree_path)?.merkle_root());
+            Ok(true)
+        }
+        "export" => {
+            let mut out = String::new();
+            for tx in load(tree_path)? {
+                out.push_str(&to_json(&tx)?);
+                out.push('\n');
+            }
+            match rest.first() {
+                Some(file) => fs::write(file, out).map_err(|e| format!("{}: {}", file, e))?,
+                None => io::stdout().write_all(out.as_bytes()).map_err(|e| e.to_string())?,
+            }
+            Ok(true)
+        }
+        "import" => {
+            let mut transactions = parse_transactions(&read_input(rest.first())?)?;
+            transactions.sort_by(|a, b| a.id.cmp(&b.id));
+            let tree = TransactionTree::from_sorted(transactions).map_err(|e| e.to_string())?;
+            tree.save(tree_path).map_err(|e| e.to_string())?;
+            println!("imported {}, root {}", tree.len(), tree.merkle_root());
+            Ok(true)
+        }
+        "verify" => match TransactionTree::load(tree_path).map_err(|e| e.to_string()).and_then(|tree| {
+            tree.check_integrity().map_err(|e| e.to_string())?;
+            tree.check_invariants().map_err(|e| e.to_string())?;
+            Ok(tree)
+        }) {
+            Ok(tree) => {
+                println!("ok: {} transactions, root {}", tree.len(), tree.merkle_root());
+                Ok(true)
+            }
+            Err(e) => {
+                println!("corrupted: {}", e);
+                Ok(false)
+            }
+        },
+        other => Err(format!("unknown command {}\n\n{}", other, USAGE)),
+    }
+}
+
+fn load(path: &Path) -> Result<TransactionTree, String> {
+    TransactionTree::load(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn load_or_new(path: &Path) -> Result<TransactionTree, String> {
+    if path.exists() {
+        load(path)
+    } else {
+        Ok(TransactionTree::new())
+    }
+}
+
+/// Reads `file`, or stdin when it is absent or `-`.
+fn read_input(file: Option<&String>) -> Result<String, String> {
+    match file.map(String::as_str) {
+        Some(file) if file != "-" => fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e)),
+        _ => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input).map_err(|e| e.to_string())?;
+            Ok(input)
+        }
+    }
+}
+
+/// Parses a JSON array of transactions, or one JSON transaction per line.
+fn parse_transactions(input: &str) -> Result<Vec<Transaction>, String> {
+    if input.trim_start().starts_with('[') {
+        return serde_json::from_str(input).map_err(|e| format!("invalid transactions: {}", e));
+    }
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| serde_json::from_str(line).map_err(|e| format!("line {}: {}", n + 1, e)))
+        .collect()
+}
+
+fn to_json<T: serde::Serialize + ?Sized>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| e.to_string())
+}
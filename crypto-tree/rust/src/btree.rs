@@ -0,0 +1,496 @@
+//! Merkle B-tree: many transactions per node for shallow trees.
+//!
+//! A [`MerkleBTree`] keeps up to `fanout - 1` transactions in each node, in
+//! id order, with a child between every two of them. On huge datasets this
+//! gives trees a fraction as tall as the AVL tree and walks over contiguous
+//! nodes, at the cost of proofs that carry a whole node per level. It offers
+//! the same operations as [`CryptoBinaryTree`](crate::CryptoBinaryTree),
+//! hashed with any [`TreeHasher`], with proofs checked by
+//! [`verify_btree_proof`].
+
+use core::borrow::Borrow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::{encoding, encode_canonical, key_string, CryptoTreeError, Result, Sha256Hasher, Transaction, TreeHasher, TreeKey};
+
+/// Fanout of `MerkleBTree::new`
+pub const DEFAULT_FANOUT: usize = 16;
+
+/// Upper bound on the height of a B-tree that fits in memory; verifiers
+/// reject longer proofs before hashing
+const MAX_BTREE_HEIGHT: usize = 64;
+
+/// The fields a node hash covers
+#[derive(Serialize)]
+struct NodeData<'a, T> {
+    transactions: &'a [&'a T],
+    children: &'a [&'a str],
+    size: u64,
+}
+
+fn node_hash<T: Serialize, H: TreeHasher>(hasher: &H, transactions: &[&T], children: &[&str], size: usize) -> Result<String> {
+    let bytes = encode_canonical(&NodeData {
+        transactions,
+        children,
+        size: size as u64,
+    })?;
+    match hasher.salt() {
+        Some(salt) => Ok(hasher.hash(&encoding::salt_node(salt, &bytes))),
+        None => Ok(hasher.hash(&bytes)),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BNode<T> {
+    transactions: Vec<T>,
+    /// Empty for leaves, otherwise one more than `transactions`
+    children: Vec<BNode<T>>,
+    /// Transactions in this subtree
+    size: usize,
+    hash: String,
+}
+
+impl<T: TreeKey + Serialize> BNode<T> {
+    fn leaf() -> Self {
+        BNode {
+            transactions: Vec::new(),
+            children: Vec::new(),
+            size: 0,
+            hash: String::new(),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn find<Q>(&self, key: &Q) -> core::result::Result<usize, usize>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.transactions.binary_search_by(|t| t.key().borrow().cmp(key))
+    }
+
+    /// Recomputes the size and hash from the transactions and children.
+    fn rehash<H: TreeHasher>(&mut self, hasher: &H) {
+        self.size = self.transactions.len() + self.children.iter().map(|c| c.size).sum::<usize>();
+        let transactions: Vec<&T> = self.transactions.iter().collect();
+        let children: Vec<&str> = self.children.iter().map(|c| c.hash.as_str()).collect();
+        self.hash = node_hash(hasher, &transactions, &children, self.size).expect("stored transactions are checked to encode");
+    }
+
+    /// Inserts a transaction whose key is not stored yet, returning the
+    /// median and new right sibling if the node had to split.
+    fn insert<H: TreeHasher>(&mut self, transaction: T, max: usize, hasher: &H) -> Option<(T, BNode<T>)> {
+        let i = self.find(transaction.key()).expect_err("key is not stored");
+        if self.is_leaf() {
+            self.transactions.insert(i, transaction);
+        } else if let Some((median, right)) = self.children[i].insert(transaction, max, hasher) {
+            self.transactions.insert(i, median);
+            self.children.insert(i + 1, right);
+        }
+        if self.transactions.len() <= max {
+            self.rehash(hasher);
+            return None;
+        }
+        let mid = self.transactions.len() / 2;
+        let mut right = BNode {
+            transactions: self.transactions.split_off(mid + 1),
+            children: if self.is_leaf() { Vec::new() } else { self.children.split_off(mid + 1) },
+            size: 0,
+            hash: String::new(),
+        };
+        let median = self.transactions.pop().expect("an overfull node has a median");
+        self.rehash(hasher);
+        right.rehash(hasher);
+        Some((median, right))
+    }
+
+    /// Removes the transaction with `key` from this subtree, leaving this
+    /// node possibly below `min` transactions for the parent to fix.
+    fn remove<Q, H>(&mut self, key: &Q, min: usize, hasher: &H) -> Option<T>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+        H: TreeHasher,
+    {
+        let removed = match (self.find(key), self.is_leaf()) {
+            (Ok(i), true) => self.transactions.remove(i),
+            (Ok(i), false) => {
+                let predecessor = self.children[i].pop_last(min, hasher);
+                let removed = core::mem::replace(&mut self.transactions[i], predecessor);
+                self._fix_child(i, min, hasher);
+                removed
+            }
+            (Err(_), true) => return None,
+            (Err(i), false) => {
+                let removed = self.children[i].remove(key, min, hasher)?;
+                self._fix_child(i, min, hasher);
+                removed
+            }
+        };
+        self.rehash(hasher);
+        Some(removed)
+    }
+
+    /// Removes the largest transaction of this non-empty subtree.
+    fn pop_last<H: TreeHasher>(&mut self, min: usize, hasher: &H) -> T {
+        let last = if self.is_leaf() {
+            self.transactions.pop().expect("nodes on a removal path are not empty")
+        } else {
+            let i = self.children.len() - 1;
+            let last = self.children[i].pop_last(min, hasher);
+            self._fix_child(i, min, hasher);
+            last
+        };
+        self.rehash(hasher);
+        last
+    }
+
+    /// Refills child `i` from a sibling, or merges it into one, once it
+    /// holds fewer than `min` transactions.
+    fn _fix_child<H: TreeHasher>(&mut self, i: usize, min: usize, hasher: &H) {
+        if self.children[i].transactions.len() >= min {
+            return;
+        }
+        if i > 0 && self.children[i - 1].transactions.len() > min {
+            let left = &mut self.children[i - 1];
+            let moved = left.transactions.pop().expect("left sibling has spare transactions");
+            let moved_child = left.children.pop();
+            left.rehash(hasher);
+            let separator = core::mem::replace(&mut self.transactions[i - 1], moved);
+            let child = &mut self.children[i];
+            child.transactions.insert(0, separator);
+            if let Some(moved_child) = moved_child {
+                child.children.insert(0, moved_child);
+            }
+            child.rehash(hasher);
+        } else if i + 1 < self.children.len() && self.children[i + 1].transactions.len() > min {
+            let right = &mut self.children[i + 1];
+            let moved = right.transactions.remove(0);
+            let moved_child = (!right.is_leaf()).then(|| right.children.remove(0));
+            right.rehash(hasher);
+            let separator = core::mem::replace(&mut self.transactions[i], moved);
+            let child = &mut self.children[i];
+            child.transactions.push(separator);
+            child.children.extend(moved_child);
+            child.rehash(hasher);
+        } else {
+            // Merge the child with a neighbour and the separator between them
+            let left = if i > 0 { i - 1 } else { i };
+            let right = self.children.remove(left + 1);
+            let separator = self.transactions.remove(left);
+            let merged = &mut self.children[left];
+            merged.transactions.push(separator);
+            merged.transactions.extend(right.transactions);
+            merged.children.extend(right.children);
+            merged.rehash(hasher);
+        }
+    }
+
+    fn check<H: TreeHasher>(&self, hasher: &H) -> bool {
+        if !self.children.iter().all(|c| c.check(hasher)) {
+            return false;
+        }
+        let transactions: Vec<&T> = self.transactions.iter().collect();
+        let children: Vec<&str> = self.children.iter().map(|c| c.hash.as_str()).collect();
+        let size = self.transactions.len() + self.children.iter().map(|c| c.size).sum::<usize>();
+        size == self.size && node_hash(hasher, &transactions, &children, size).is_ok_and(|hash| hash == self.hash)
+    }
+}
+
+/// One node on the path of a [`MerkleBTree`] proof
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BTreeProofStep<T = Transaction> {
+    /// The node's transactions; in the last step, all but the proven one
+    pub transactions: Vec<T>,
+    /// Hashes of the node's children; except in the last step, all but the
+    /// one on the path
+    pub children: Vec<String>,
+    /// Position of the proven transaction in the last step, or of the child
+    /// on the path in the others
+    pub index: usize,
+    /// Subtree size of the node
+    pub size: usize,
+}
+
+/// Verifies a proof produced by `MerkleBTree::get_proof_of_inclusion`, for
+/// trees hashed with SHA-256; see [`verify_btree_proof_with`].
+pub fn verify_btree_proof<T: Serialize>(root: &str, transaction: &T, proof: &[BTreeProofStep<T>]) -> bool {
+    verify_btree_proof_with(&Sha256Hasher::default(), root, transaction, proof)
+}
+
+/// Like [`verify_btree_proof`], for trees built with a custom hasher.
+///
+/// The node holding `transaction` is rehashed with it at its position, then
+/// each node above it with the recomputed hash at its child's position; the
+/// proof is valid when the last hash equals `root`.
+pub fn verify_btree_proof_with<T: Serialize, H: TreeHasher>(hasher: &H, root: &str, transaction: &T, proof: &[BTreeProofStep<T>]) -> bool {
+    let Some((target, ancestors)) = proof.split_last() else {
+        return false;
+    };
+    if proof.len() > MAX_BTREE_HEIGHT || target.index > target.transactions.len() {
+        return false;
+    }
+    let mut transactions: Vec<&T> = target.transactions.iter().collect();
+    transactions.insert(target.index, transaction);
+    let children: Vec<&str> = target.children.iter().map(String::as_str).collect();
+    let Ok(mut current) = node_hash(hasher, &transactions, &children, target.size) else {
+        return false;
+    };
+    for step in ancestors.iter().rev() {
+        if step.index > step.children.len() {
+            return false;
+        }
+        let transactions: Vec<&T> = step.transactions.iter().collect();
+        let mut children: Vec<&str> = step.children.iter().map(String::as_str).collect();
+        children.insert(step.index, &current);
+        let Ok(hash) = node_hash(hasher, &transactions, &children, step.size) else {
+            return false;
+        };
+        current = hash;
+    }
+    current == root
+}
+
+/// A Merkle tree of transactions kept in B-tree nodes
+///
+/// Every node but the root holds between `fanout / 2 - 1` and `fanout - 1`
+/// transactions and all leaves are at the same depth. Inserts and removals
+/// rehash one node per level, each over all its transactions.
+#[derive(Debug, Clone)]
+pub struct MerkleBTree<T: TreeKey = Transaction, H = Sha256Hasher> {
+    root: Option<BNode<T>>,
+    hasher: H,
+    fanout: usize,
+}
+
+impl<T: TreeKey + Serialize> MerkleBTree<T> {
+    /// Creates an empty tree with `DEFAULT_FANOUT`, hashed with SHA-256.
+    pub fn new() -> Self {
+        Self::with_hasher(Sha256Hasher::default())
+    }
+}
+
+impl<T: TreeKey + Serialize> Default for MerkleBTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: TreeKey + Serialize, H: TreeHasher> MerkleBTree<T, H> {
+    /// Creates an empty tree with `DEFAULT_FANOUT` whose nodes are hashed with `hasher`.
+    pub fn with_hasher(hasher: H) -> Self {
+        Self::with_fanout(DEFAULT_FANOUT, hasher)
+    }
+
+    /// Creates an empty tree with up to `fanout` children per node, at least 4.
+    pub fn with_fanout(fanout: usize, hasher: H) -> Self {
+        MerkleBTree {
+            root: None,
+            hasher,
+            fanout: fanout.max(4),
+        }
+    }
+
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    pub fn fanout(&self) -> usize {
+        self.fanout
+    }
+
+    /// Inserts a transaction, returning `false` if it was rejected.
+    pub fn insert(&mut self, transaction: T) -> bool {
+        self.try_insert(transaction).is_ok()
+    }
+
+    /// Inserts a transaction, splitting full nodes on the way back up.
+    ///
+    /// Fails with `DuplicateId` if the id is already stored and with
+    /// `SerializationFailed` if the payload cannot be encoded for hashing.
+    pub fn try_insert(&mut self, transaction: T) -> Result<()> {
+        if self.search(transaction.key()).is_some() {
+            return Err(CryptoTreeError::DuplicateId(key_string(transaction.key())));
+        }
+        encode_canonical(&transaction)?;
+        let root = self.root.get_or_insert_with(BNode::leaf);
+        if let Some((median, right)) = root.insert(transaction, self.fanout - 1, &self.hasher) {
+            let left = core::mem::replace(root, BNode::leaf());
+            root.transactions.push(median);
+            root.children = vec![left, right];
+            root.rehash(&self.hasher);
+        }
+        Ok(())
+    }
+
+    /// Removes the transaction with the given id, returning it if it was present.
+    pub fn remove<Q>(&mut self, tx_id: &Q) -> Option<T>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let root = self.root.as_mut()?;
+        let removed = root.remove(tx_id, self.fanout / 2 - 1, &self.hasher)?;
+        if root.transactions.is_empty() {
+            self.root = root.children.pop();
+        }
+        Some(removed)
+    }
+
+    pub fn search<Q>(&self, tx_id: &Q) -> Option<&T>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut current = self.root.as_ref()?;
+        loop {
+            match current.find(tx_id) {
+                Ok(i) => return Some(&current.transactions[i]),
+                Err(i) => current = current.children.get(i)?,
+            }
+        }
+    }
+
+    /// The root hash, `"0"` while the tree is empty
+    pub fn merkle_root(&self) -> &str {
+        self.root.as_ref().map_or("0", |r| &r.hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |r| r.size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Number of node levels, 0 for an empty tree
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut current = self.root.as_ref();
+        while let Some(node) = current {
+            height += 1;
+            current = node.children.first();
+        }
+        height
+    }
+
+    /// Builds a proof that `tx_id` is stored, one step per node from the
+    /// root down to the one holding it.
+    pub fn get_proof_of_inclusion<Q>(&self, tx_id: &Q) -> Option<Vec<BTreeProofStep<T>>>
+    where
+        T: Clone,
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut proof = Vec::new();
+        let mut current = self.root.as_ref()?;
+        loop {
+            let hashes = current.children.iter().map(|c| c.hash.clone());
+            match current.find(tx_id) {
+                Ok(i) => {
+                    let transactions = current.transactions.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, t)| t.clone());
+                    proof.push(BTreeProofStep {
+                        transactions: transactions.collect(),
+                        children: hashes.collect(),
+                        index: i,
+                        size: current.size,
+                    });
+                    return Some(proof);
+                }
+                Err(i) => {
+                    let next = current.children.get(i)?;
+                    proof.push(BTreeProofStep {
+                        transactions: current.transactions.clone(),
+                        children: hashes.enumerate().filter(|&(j, _)| j != i).map(|(_, h)| h).collect(),
+                        index: i,
+                        size: current.size,
+                    });
+                    current = next;
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if every stored hash and subtree size matches.
+    pub fn verify_integrity(&self) -> bool {
+        self.root.as_ref().is_none_or(|r| r.check(&self.hasher))
+    }
+
+    /// Transactions in ascending id order
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        // Each entry is a node and the index of the next transaction to yield
+        let mut stack: Vec<(&BNode<T>, usize)> = Vec::new();
+        let mut descend = self.root.as_ref();
+        core::iter::from_fn(move || {
+            while let Some(node) = descend.take() {
+                stack.push((node, 0));
+                descend = node.children.first();
+            }
+            let (node, i) = stack.pop()?;
+            if i + 1 < node.transactions.len() {
+                stack.push((node, i + 1));
+            }
+            descend = node.children.get(i + 1);
+            node.transactions.get(i)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_btree_proofs_and_removal() {
+        let mut tree: MerkleBTree = MerkleBTree::with_fanout(4, Sha256Hasher::default());
+        // A scattered insertion order exercises splits on both sides
+        let ids: Vec<String> = (0..300).map(|i| format!("tx_{:04}", i * 7919 % 300)).collect();
+        for id in &ids {
+            assert!(tree.insert(sample_tx(id, 10)));
+        }
+        assert!(matches!(tree.try_insert(sample_tx("tx_0001", 1)), Err(CryptoTreeError::DuplicateId(_))));
+        assert_eq!(tree.len(), 300);
+        assert!(tree.height() <= 8 && tree.verify_integrity());
+        assert!(tree.iter().map(|tx| tx.id.clone()).eq((0..300).map(|i| format!("tx_{:04}", i))));
+
+        let mut wide: MerkleBTree = MerkleBTree::new();
+        for id in &ids {
+            wide.insert(sample_tx(id, 10));
+        }
+        assert!(wide.height() < tree.height());
+
+        let root = tree.merkle_root().to_string();
+        for id in ["tx_0000", "tx_0150", "tx_0299"] {
+            let tx = tree.search(id).unwrap();
+            let proof = tree.get_proof_of_inclusion(id).unwrap();
+            assert!(proof.len() <= tree.height());
+            assert!(verify_btree_proof(&root, tx, &proof));
+            assert!(!verify_btree_proof(&root, &sample_tx(id, 11), &proof));
+        }
+        assert!(tree.get_proof_of_inclusion("tx_9999").is_none());
+
+        for id in ids.iter().step_by(2) {
+            assert_eq!(tree.remove(id.as_str()).unwrap().id, *id);
+            assert!(tree.verify_integrity());
+        }
+        assert!(tree.remove("tx_9999").is_none());
+        assert_eq!(tree.len(), 150);
+        let mut rebuilt: MerkleBTree = MerkleBTree::with_fanout(4, Sha256Hasher::default());
+        for id in ids.iter().skip(1).step_by(2) {
+            rebuilt.insert(sample_tx(id, 10));
+        }
+        assert!(tree.iter().eq(rebuilt.iter()));
+        for id in ids.iter().skip(1).step_by(2) {
+            assert!(tree.remove(id.as_str()).is_some());
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.merkle_root(), "0");
+    }
+}
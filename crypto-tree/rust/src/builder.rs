@@ -0,0 +1,153 @@
+use core::fmt;
+
+use serde::Serialize;
+
+use crate::prelude::*;
+use crate::{CryptoBinaryTree, HashAlgorithm, HashFormat, RuntimeHasher, TreeKey};
+
+/// Configures a tree whose hashing is chosen at runtime.
+///
+/// ```
+/// use crypto_tree::{CryptoBinaryTree, HashAlgorithm, RuntimeHasher, Transaction, TreeBuilder};
+///
+/// let tree: CryptoBinaryTree<Transaction, RuntimeHasher> =
+///     TreeBuilder::new().hash_algorithm(HashAlgorithm::Sha256).build();
+/// assert!(tree.is_empty());
+/// ```
+#[derive(Clone, Default)]
+pub struct TreeBuilder {
+    algorithm: HashAlgorithm,
+    format: HashFormat,
+    key: Option<[u8; 32]>,
+    salt: Option<Vec<u8>>,
+}
+
+impl TreeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hash function for node hashes, SHA-256 by default.
+    pub fn hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Byte layout of the hashed node data, `HashFormat::default()` by default.
+    pub fn hash_format(mut self, format: HashFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Secret key every node hash is computed under (see
+    /// [`RuntimeHasher::keyed`]); it is held by the hasher only and never
+    /// serialized, so a tree must be rebuilt or reloaded with the same key.
+    pub fn hash_key(mut self, key: [u8; 32]) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Public salt or domain string mixed into every node hash, so the same
+    /// data yields different roots in independent deployments and proofs
+    /// cannot be replayed across them. Snapshots record that a tree is salted.
+    pub fn salt(mut self, salt: impl Into<Vec<u8>>) -> Self {
+        self.salt = Some(salt.into());
+        self
+    }
+
+    /// The hasher this builder configures.
+    pub fn hasher(&self) -> RuntimeHasher {
+        let hasher = match self.key {
+            Some(key) => RuntimeHasher::keyed(self.algorithm, self.format, key),
+            None => RuntimeHasher::new(self.algorithm, self.format),
+        };
+        match &self.salt {
+            Some(salt) => hasher.with_salt(salt.clone()),
+            None => hasher,
+        }
+    }
+
+    pub fn build<T: TreeKey + Serialize + Clone>(self) -> CryptoBinaryTree<T, RuntimeHasher> {
+        CryptoBinaryTree::with_hasher(self.hasher())
+    }
+}
+
+impl fmt::Debug for TreeBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the key
+        f.debug_struct("TreeBuilder")
+            .field("algorithm", &self.algorithm)
+            .field("format", &self.format)
+            .field("keyed", &self.key.is_some())
+            .field("salt", &self.salt)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Transaction;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_default_matches_sha256_tree() {
+        let mut built: CryptoBinaryTree<Transaction, RuntimeHasher> = TreeBuilder::new().build();
+        let mut plain = CryptoBinaryTree::new();
+        for i in 1..=10 {
+            built.insert(sample_tx(&format!("tx_{:02}", i), 10));
+            plain.insert(sample_tx(&format!("tx_{:02}", i), 10));
+        }
+        assert_eq!(built.hasher().algorithm(), HashAlgorithm::Sha256);
+        assert_eq!(built.merkle_root(), plain.merkle_root());
+    }
+
+    #[test]
+    fn test_salted_trees_differ() {
+        let build = |salt: &str| {
+            let mut tree: CryptoBinaryTree<Transaction, RuntimeHasher> = TreeBuilder::new().salt(salt).build();
+            for i in 1..=10 {
+                tree.insert(sample_tx(&format!("tx_{:02}", i), 10));
+            }
+            tree
+        };
+        let (mainnet, testnet) = (build("mainnet"), build("testnet"));
+        assert_ne!(mainnet.merkle_root(), testnet.merkle_root());
+        assert!(mainnet.verify_integrity());
+
+        let tx = mainnet.search("tx_04").unwrap();
+        let proof = mainnet.get_proof_of_inclusion("tx_04").unwrap();
+        assert!(crate::verify_proof_with(mainnet.hasher(), mainnet.merkle_root(), tx, &proof));
+        // A proof from one deployment does not replay in another
+        assert!(!crate::verify_proof_with(testnet.hasher(), mainnet.merkle_root(), tx, &proof));
+        assert!(!crate::verify_proof(mainnet.merkle_root(), tx, &proof));
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_blake3_tree() {
+        use crate::{verify_proof, verify_proof_with, Blake3Hasher, TreeHasher};
+
+        let mut tree: CryptoBinaryTree<Transaction, RuntimeHasher> =
+            TreeBuilder::new().hash_algorithm(HashAlgorithm::Blake3).build();
+        for i in 1..=20 {
+            tree.insert(sample_tx(&format!("tx_{:02}", i), 10));
+        }
+        assert!(tree.verify_integrity());
+        assert_eq!(tree.merkle_root().len(), 64);
+
+        let tx = tree.search("tx_11").unwrap();
+        let proof = tree.get_proof_of_inclusion("tx_11").unwrap();
+        assert!(verify_proof_with(tree.hasher(), tree.merkle_root(), tx, &proof));
+        assert!(verify_proof_with(&Blake3Hasher::new(), tree.merkle_root(), tx, &proof));
+        assert!(!verify_proof(tree.merkle_root(), tx, &proof));
+
+        let keyed = Blake3Hasher::keyed([7; 32]);
+        let built = TreeBuilder::new().hash_algorithm(HashAlgorithm::Blake3).hash_key([7; 32]);
+        assert_eq!(built.hasher().hash(b"abc"), keyed.hash(b"abc"));
+        assert!(!format!("{:?}", built).contains("7, 7"));
+        assert!(!verify_proof_with(&keyed, tree.merkle_root(), tx, &proof));
+        assert_ne!(keyed.hash(b"abc"), Blake3Hasher::new().hash(b"abc"));
+        assert!(!format!("{:?}", keyed).contains("7, 7"));
+    }
+}
@@ -0,0 +1,89 @@
+//! Compact CBOR export/import for trees and proofs (`cbor` feature).
+//!
+//! CBOR is self-describing, so every serde-compatible type of the crate
+//! (`CryptoBinaryTree`, `ProofStep`, `AbsenceProof`, ...) round-trips through
+//! it exactly, usually at a fraction of the size of the JSON encoding.
+
+use core::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{CryptoBinaryTree, TreeHasher, TreeKey};
+
+/// Error raised while encoding or decoding CBOR
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CborError(String);
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CBOR error: {}", self.0)
+    }
+}
+
+impl core::error::Error for CborError {}
+
+/// Encodes any serializable value (tree, proof, transaction) as CBOR.
+pub fn to_vec<S: Serialize + ?Sized>(value: &S) -> Result<Vec<u8>, CborError> {
+    let mut out = Vec::new();
+    ciborium::into_writer(value, &mut out).map_err(|e| CborError(e.to_string()))?;
+    Ok(out)
+}
+
+/// Decodes a value previously encoded with [`to_vec`].
+pub fn from_slice<D: DeserializeOwned>(bytes: &[u8]) -> Result<D, CborError> {
+    ciborium::from_reader(bytes).map_err(|e| CborError(e.to_string()))
+}
+
+impl<T, H> CryptoBinaryTree<T, H>
+where
+    T: TreeKey + Serialize + DeserializeOwned + Clone,
+    H: TreeHasher + Default,
+{
+    /// Exports the tree, including node hashes, as CBOR.
+    pub fn to_cbor(&self) -> Result<Vec<u8>, CborError> {
+        to_vec(self)
+    }
+
+    /// Imports a tree exported with [`CryptoBinaryTree::to_cbor`], re-verifying its integrity.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, CborError> {
+        from_slice(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_proof, AbsenceProof, ProofStep, TransactionTree};
+    use crate::test_util::build_tree;
+
+    #[test]
+    fn test_tree_round_trip_preserves_root() {
+        let tree = build_tree(500);
+        let bytes = tree.to_cbor().unwrap();
+        assert!(bytes.len() < serde_json::to_vec(&tree).unwrap().len());
+
+        let restored = TransactionTree::from_cbor(&bytes).unwrap();
+        assert_eq!(restored.merkle_root(), tree.merkle_root());
+        assert_eq!(restored.len(), 500);
+        assert!(restored.verify_integrity());
+    }
+
+    #[test]
+    fn test_proof_round_trip() {
+        let tree = build_tree(100);
+        let proof = tree.get_proof_of_inclusion("tx_042").unwrap();
+        let decoded: Vec<ProofStep> = from_slice(&to_vec(&proof).unwrap()).unwrap();
+        let tx = tree.search("tx_042").unwrap();
+        assert!(verify_proof(tree.merkle_root(), tx, &decoded));
+
+        let absence = tree.get_proof_of_absence("tx_9999").unwrap();
+        let decoded: AbsenceProof = from_slice(&to_vec(&absence).unwrap()).unwrap();
+        assert!(crate::verify_absence_proof(tree.merkle_root(), &decoded));
+    }
+
+    #[test]
+    fn test_rejects_garbage() {
+        assert!(TransactionTree::from_cbor(&[0xff, 0x00, 0x13]).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! Checkpoints of a [`LoggedTree`].
+//!
+//! A checkpoint is a snapshot taken at a segment boundary, named after the
+//! root it holds. With a [`CheckpointPolicy`] the log takes them on its own,
+//! every so many operations or seconds, and keeps the newest few together
+//! with the segments written since the oldest of them. Any root reached in
+//! that window can then be restored: [`LoggedTree::restore_at`] loads the
+//! matching checkpoint or replays forward from the oldest one until the root
+//! comes up.
+
+use std::path::Path;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::wal::{list, load_checkpoint, replay, restore};
+use crate::{AtRestCipher, CryptoBinaryTree, LoggedTree, Result, TreeHasher, TreeKey, WalError};
+
+/// When a [`LoggedTree`] checkpoints by itself and how many checkpoints it keeps
+///
+/// Both triggers are checked after each logged operation, so an idle log
+/// does not checkpoint however long `every` has passed. The default takes no
+/// automatic checkpoints and keeps one, like `compact_to_snapshot`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointPolicy {
+    /// Checkpoint once this many operations were logged since the last one
+    pub every_ops: Option<u64>,
+    /// Checkpoint once this much time passed since the last one
+    pub every: Option<Duration>,
+    /// Checkpoints to keep, at least one
+    pub keep: usize,
+}
+
+impl Default for CheckpointPolicy {
+    fn default() -> Self {
+        CheckpointPolicy {
+            every_ops: None,
+            every: None,
+            keep: 1,
+        }
+    }
+}
+
+/// A snapshot in the log directory
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Segment the snapshot is followed by
+    pub seq: u64,
+    /// Merkle root of the snapshotted tree
+    pub root: String,
+}
+
+impl<T, H> LoggedTree<T, H>
+where
+    T: TreeKey + Serialize + DeserializeOwned + Clone,
+    T::Key: DeserializeOwned,
+    H: TreeHasher,
+{
+    /// Snapshots the tree now, then drops the checkpoints and segments
+    /// beyond what the policy keeps.
+    pub fn checkpoint(&mut self) -> Result<Checkpoint> {
+        let checkpoint = self._write_checkpoint()?;
+        self._prune(self.options.checkpoints.keep)?;
+        Ok(checkpoint)
+    }
+
+    /// The retained checkpoints, oldest first
+    pub fn checkpoints(&self) -> Result<Vec<Checkpoint>> {
+        Ok(list(&self.dir).map_err(WalError::from)?.1)
+    }
+
+    pub(crate) fn _checkpoint_due(&self) -> bool {
+        let policy = &self.options.checkpoints;
+        policy.every_ops.is_some_and(|n| self.pending_ops >= n)
+            || policy.every.zip(self.checkpointed).is_some_and(|(every, at)| at.elapsed() >= every)
+    }
+
+    /// The tree as of the last operation logged in `dir`.
+    ///
+    /// Unlike [`open_with`](Self::open_with) this only reads the log: a torn
+    /// final record is skipped but left in place, and no segment is created.
+    pub fn restore_latest<P: AsRef<Path>>(dir: P, hasher: H) -> Result<CryptoBinaryTree<T, H>> {
+        Ok(restore(dir.as_ref(), hasher, false, None)?.0)
+    }
+
+    /// Like [`restore_latest`](Self::restore_latest), for a log written with
+    /// [`open_encrypted`](Self::open_encrypted).
+    pub fn restore_latest_encrypted<P: AsRef<Path>>(dir: P, hasher: H, cipher: &dyn AtRestCipher) -> Result<CryptoBinaryTree<T, H>> {
+        Ok(restore(dir.as_ref(), hasher, false, Some(cipher))?.0)
+    }
+
+    /// The tree as it was when its Merkle root was `root`.
+    ///
+    /// Reads a checkpoint with that root if one is kept, and otherwise
+    /// replays the log from the oldest kept checkpoint, stopping at the first
+    /// operation that produced `root`. Roots from before the oldest
+    /// checkpoint have been pruned and fail with `WalError::UnknownRoot`.
+    pub fn restore_at<P: AsRef<Path>>(dir: P, root: &str, hasher: H) -> Result<CryptoBinaryTree<T, H>> {
+        Self::_restore_at(dir.as_ref(), root, hasher, None)
+    }
+
+    /// Like [`restore_at`](Self::restore_at), for a log written with
+    /// [`open_encrypted`](Self::open_encrypted).
+    pub fn restore_at_encrypted<P: AsRef<Path>>(dir: P, root: &str, hasher: H, cipher: &dyn AtRestCipher) -> Result<CryptoBinaryTree<T, H>> {
+        Self::_restore_at(dir.as_ref(), root, hasher, Some(cipher))
+    }
+
+    fn _restore_at(dir: &Path, root: &str, hasher: H, cipher: Option<&dyn AtRestCipher>) -> Result<CryptoBinaryTree<T, H>> {
+        let (segments, checkpoints) = list(dir).map_err(WalError::from)?;
+        if let Some(checkpoint) = checkpoints.iter().rev().find(|c| c.root == root) {
+            return load_checkpoint(dir, checkpoint, hasher, cipher);
+        }
+        let start = checkpoints.first().map_or(0, |c| c.seq);
+        let mut tree = match checkpoints.first() {
+            Some(checkpoint) => load_checkpoint(dir, checkpoint, hasher, cipher)?,
+            None => CryptoBinaryTree::with_hasher(hasher),
+        };
+        if tree.merkle_root() == root {
+            return Ok(tree);
+        }
+        let replayed: Vec<u64> = segments.into_iter().filter(|&seq| seq >= start).collect();
+        for (i, &seq) in replayed.iter().enumerate() {
+            if replay(&mut tree, dir, seq, i + 1 == replayed.len(), false, cipher, &mut |t| t.merkle_root() == root)? {
+                return Ok(tree);
+            }
+        }
+        Err(WalError::UnknownRoot(root.to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{Sha256Hasher, Transaction, WalOptions};
+
+    use super::*;
+    use crate::test_util::{sample_tx, temp_path};
+
+    #[test]
+    fn test_checkpoints_by_op_count() {
+        let dir = temp_path("checkpoint_ops");
+        let options = WalOptions {
+            sync: false,
+            checkpoints: CheckpointPolicy {
+                every_ops: Some(10),
+                keep: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut log: LoggedTree = LoggedTree::open_with(&dir, Sha256Hasher::new(), options).unwrap();
+        let mut roots = Vec::new();
+        for i in 0..45 {
+            log.try_insert(sample_tx(&format!("tx_{:02}", i), 10)).unwrap();
+            roots.push(log.tree().merkle_root().to_string());
+        }
+        let checkpoints = log.checkpoints().unwrap();
+        assert_eq!(checkpoints.len(), 2);
+        assert_eq!(checkpoints[0].root, roots[29]);
+        assert_eq!(checkpoints[1].root, roots[39]);
+        drop(log);
+
+        let latest = LoggedTree::<Transaction>::restore_latest(&dir, Sha256Hasher::new()).unwrap();
+        assert_eq!(latest.merkle_root(), roots[44]);
+        // A kept checkpoint, a root between checkpoints and one in the tail
+        for i in [29, 33, 42] {
+            let tree = LoggedTree::<Transaction>::restore_at(&dir, &roots[i], Sha256Hasher::new()).unwrap();
+            assert_eq!(tree.len(), i + 1);
+            assert_eq!(tree.merkle_root(), roots[i]);
+        }
+        // Pruned along with the first checkpoint
+        assert!(matches!(
+            LoggedTree::<Transaction>::restore_at(&dir, &roots[15], Sha256Hasher::new()),
+            Err(crate::CryptoTreeError::Wal(WalError::UnknownRoot(_)))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_without_checkpoints() {
+        let dir = temp_path("checkpoint_none");
+        let mut log: LoggedTree = LoggedTree::open(&dir).unwrap();
+        let empty = log.tree().merkle_root().to_string();
+        log.try_insert(sample_tx("tx_a", 1)).unwrap();
+        let first = log.tree().merkle_root().to_string();
+        log.try_insert(sample_tx("tx_b", 2)).unwrap();
+        assert!(log.checkpoints().unwrap().is_empty());
+        drop(log);
+
+        let tree = LoggedTree::<Transaction>::restore_at(&dir, &first, Sha256Hasher::new()).unwrap();
+        assert_eq!(tree.len(), 1);
+        assert!(LoggedTree::<Transaction>::restore_at(&dir, &empty, Sha256Hasher::new()).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+//! Incremental snapshots for replicas.
+//!
+//! Node hashes cover whole subtrees, so a subtree whose root hash a replica
+//! already holds is one it already has in full. [`CryptoBinaryTree::diff_snapshot`]
+//! therefore exports only the nodes whose hashes the replica's version lacks,
+//! which for a few changes is about `log n` nodes each, and
+//! [`CryptoBinaryTree::apply_delta`] rebuilds the new version from them and
+//! the replica's own nodes.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::arena::Arena;
+use crate::prelude::*;
+use crate::{AuditOp, CryptoBinaryTree, CryptoTreeError, CryptoTreeNode, NodeId, Result, Transaction, TreeHasher, TreeKey, MAX_TREE_HEIGHT};
+
+/// A node of a [`TreeDelta`], linked to its children by hash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeltaNode<T = Transaction> {
+    pub hash: String,
+    pub transaction: T,
+    pub height: i32,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+/// The nodes that turn the version at `base_root` into the one at `root`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeDelta<T = Transaction> {
+    pub base_root: String,
+    pub root: String,
+    /// Nodes missing from the base version, children before their parents
+    pub nodes: Vec<DeltaNode<T>>,
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Exports the nodes of this tree that `since` lacks.
+    ///
+    /// `since` is the version a replica holds, typically a
+    /// [`snapshot`](Self::snapshot) taken when its root was current. Finding
+    /// the shared subtrees reads every hash of `since`; the delta itself only
+    /// holds the nodes that were added or rehashed since.
+    pub fn diff_snapshot(&self, since: &CryptoBinaryTree<T, H>) -> TreeDelta<T> {
+        self._assert_hashed();
+        since._assert_hashed();
+        let mut known = BTreeSet::new();
+        let mut stack: Vec<NodeId> = since.root.into_iter().collect();
+        while let Some(id) = stack.pop() {
+            let n = &since.nodes[id];
+            known.insert(n.hash.as_str());
+            stack.extend(n.left);
+            stack.extend(n.right);
+        }
+
+        let mut nodes = Vec::new();
+        if let Some(root) = self.root {
+            self._collect_delta(root, &known, &mut nodes);
+        }
+        TreeDelta {
+            base_root: since.merkle_root.clone(),
+            root: self.merkle_root.clone(),
+            nodes,
+        }
+    }
+
+    /// Appends the nodes under `id` whose hashes are not `known`, in post-order.
+    fn _collect_delta(&self, id: NodeId, known: &BTreeSet<&str>, out: &mut Vec<DeltaNode<T>>) {
+        let n = &self.nodes[id];
+        if known.contains(n.hash.as_str()) {
+            return;
+        }
+        for child in [n.left, n.right].into_iter().flatten() {
+            self._collect_delta(child, known, out);
+        }
+        out.push(DeltaNode {
+            hash: n.hash.clone(),
+            transaction: n.transaction.clone(),
+            height: n.height,
+            left: self.nodes.hash(n.left).map(str::to_string),
+            right: self.nodes.hash(n.right).map(str::to_string),
+        });
+    }
+
+    /// Moves this tree to the version described by `delta` and returns its root.
+    ///
+    /// The tree must be at `delta.base_root`. Every node taken from the delta
+    /// is rehashed and the result must be a valid AVL search tree, so a
+    /// damaged or forged delta fails with `MalformedState`, `CorruptedNode` or
+    /// `InvariantViolated` and leaves the tree unchanged. Like a snapshot, a
+    /// delta bypasses the validator, policies and ledger rules; the index, if
+    /// enabled, is rebuilt.
+    pub fn apply_delta(&mut self, delta: TreeDelta<T>) -> Result<String> {
+        self.flush_hashes();
+        if self.merkle_root != delta.base_root {
+            return Err(CryptoTreeError::MalformedState(format!(
+                "delta applies to root {}, tree is at {}",
+                delta.base_root, self.merkle_root
+            )));
+        }
+        let mut existing = BTreeMap::new();
+        let mut stack: Vec<NodeId> = self.root.into_iter().collect();
+        while let Some(id) = stack.pop() {
+            let n = &self.nodes[id];
+            existing.insert(n.hash.clone(), id);
+            stack.extend(n.left);
+            stack.extend(n.right);
+        }
+        let mut incoming: BTreeMap<String, DeltaNode<T>> = BTreeMap::new();
+        for node in delta.nodes {
+            incoming.insert(node.hash.clone(), node);
+        }
+
+        let mut rebuild = Rebuild {
+            tree: self,
+            existing,
+            incoming,
+            nodes: Vec::new(),
+        };
+        let root = match delta.root.as_str() {
+            "0" => None,
+            hash => Some(rebuild.place(hash, 1)?),
+        };
+        if let Some(hash) = rebuild.incoming.keys().next() {
+            return Err(CryptoTreeError::MalformedState(format!("delta node {} is not reachable", hash)));
+        }
+        let nodes = Arena::from_vec(rebuild.nodes);
+        Self::_check_shape(&nodes, root, None, None)?;
+
+        self.root = root;
+        self.nodes = nodes;
+        self._intern_all();
+        self._reindex();
+        self._update_merkle_root();
+        self._audit(AuditOp::ApplyDelta, Vec::new());
+        Ok(self.merkle_root.clone())
+    }
+}
+
+/// State of `apply_delta` while it assembles the new node arena
+struct Rebuild<'a, T: TreeKey, H> {
+    tree: &'a CryptoBinaryTree<T, H>,
+    /// Subtrees of the current version by root hash
+    existing: BTreeMap<String, NodeId>,
+    /// Delta nodes not placed yet
+    incoming: BTreeMap<String, DeltaNode<T>>,
+    nodes: Vec<CryptoTreeNode<T>>,
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> Rebuild<'_, T, H> {
+    /// Places the subtree with root `hash`, from the delta or the current
+    /// version, and returns its new id.
+    fn place(&mut self, hash: &str, depth: i32) -> Result<NodeId> {
+        if depth > MAX_TREE_HEIGHT {
+            return Err(CryptoTreeError::MalformedState(format!("delta nested deeper than {}", MAX_TREE_HEIGHT)));
+        }
+        if let Some(node) = self.incoming.remove(hash) {
+            let left = node.left.as_deref().map(|h| self.place(h, depth + 1)).transpose()?;
+            let right = node.right.as_deref().map(|h| self.place(h, depth + 1)).transpose()?;
+            let size = 1 + [left, right].into_iter().flatten().map(|c| self.nodes[c.index()].size).sum::<usize>();
+            let computed = crate::encoding::hash_node(
+                &self.tree.hasher,
+                &node.transaction,
+                node.left.as_deref(),
+                node.right.as_deref(),
+                node.height,
+                size,
+            )?;
+            if computed != node.hash {
+                return Err(CryptoTreeError::CorruptedNode {
+                    id: crate::key_string(node.transaction.key()),
+                });
+            }
+            return Ok(self.push(CryptoTreeNode {
+                transaction: node.transaction,
+                left,
+                right,
+                height: node.height,
+                size,
+                hash: node.hash,
+                digest: None,
+            }));
+        }
+        match self.existing.get(hash) {
+            Some(&id) => Ok(self.copy(id)),
+            None => Err(CryptoTreeError::MalformedState(format!(
+                "node {} is neither in the delta nor in the tree",
+                hash
+            ))),
+        }
+    }
+
+    /// Copies a subtree of the current version.
+    fn copy(&mut self, id: NodeId) -> NodeId {
+        let n = &self.tree.nodes[id];
+        let left = n.left.map(|l| self.copy(l));
+        let right = n.right.map(|r| self.copy(r));
+        let n = &self.tree.nodes[id];
+        self.push(CryptoTreeNode {
+            transaction: n.transaction.clone(),
+            left,
+            right,
+            height: n.height,
+            size: n.size,
+            hash: n.hash.clone(),
+            digest: n.digest,
+        })
+    }
+
+    fn push(&mut self, node: CryptoTreeNode<T>) -> NodeId {
+        self.nodes.push(node);
+        NodeId::new(self.nodes.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CryptoTreeError;
+    use crate::test_util::{build_tree, sample_tx};
+
+    #[test]
+    fn test_delta_syncs_replica() {
+        let mut primary = build_tree(1000);
+        let mut replica = build_tree(1000);
+        let base = primary.snapshot();
+
+        primary.insert(sample_tx("tx_2000", 5));
+        primary.remove("tx_100");
+        primary.update("tx_500", |tx| tx.amount = 99).unwrap();
+        let delta = primary.diff_snapshot(&base);
+        assert!(delta.nodes.len() < 40, "delta holds {} nodes", delta.nodes.len());
+
+        let root = replica.apply_delta(delta.clone()).unwrap();
+        assert_eq!(root, primary.merkle_root());
+        assert_eq!(replica.len(), 1000);
+        assert_eq!(replica.search("tx_500").unwrap().amount, 99);
+        assert!(replica.search("tx_100").is_none());
+        assert!(replica.verify_integrity() && replica.verify_invariants());
+
+        // Applied twice, the base root no longer matches
+        assert!(matches!(replica.apply_delta(delta), Err(CryptoTreeError::MalformedState(_))));
+        assert!(primary.diff_snapshot(&primary.snapshot()).nodes.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_forged_delta() {
+        let mut primary = build_tree(50);
+        let mut replica = build_tree(50);
+        let base = primary.snapshot();
+        primary.insert(sample_tx("tx_9999", 5));
+        let mut delta = primary.diff_snapshot(&base);
+        let root = replica.merkle_root().to_string();
+
+        delta.nodes[0].transaction.amount = 1_000_000;
+        assert!(matches!(replica.apply_delta(delta), Err(CryptoTreeError::CorruptedNode { .. })));
+        assert_eq!(replica.merkle_root(), root);
+        assert!(replica.verify_integrity());
+    }
+}
@@ -0,0 +1,195 @@
+//! Transaction-level differences between two trees.
+//!
+//! [`CryptoBinaryTree::diff`] walks both trees in key order at once. Whenever
+//! the next pending subtrees on both sides have the same hash they hold the
+//! same transactions and are skipped whole, so trees that share most of
+//! their structure, such as two versions of one ledger, are compared in
+//! about O(changes · log n) instead of O(n).
+
+use core::cmp::Ordering;
+
+use crate::prelude::*;
+use crate::{CryptoBinaryTree, NodeId, TreeKey};
+
+/// What changed from one tree to another, each list in key order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeDiff<'a, T> {
+    /// Transactions only the other tree holds
+    pub added: Vec<&'a T>,
+    /// Transactions only this tree holds
+    pub removed: Vec<&'a T>,
+    /// Transactions both hold under the same key but with different
+    /// contents, as `(this tree's, other tree's)`
+    pub modified: Vec<(&'a T, &'a T)>,
+}
+
+impl<T> TreeDiff<'_, T> {
+    /// Returns `true` if the trees hold the same transactions
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+
+    /// Number of changed keys
+    pub fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.modified.len()
+    }
+}
+
+/// An in-order position in a tree: the top of the stack is visited next
+enum Pending {
+    /// A whole subtree, not expanded yet
+    Subtree(NodeId),
+    /// A single node whose left subtree is already behind
+    Node(NodeId),
+}
+
+struct Cursor<'a, T: TreeKey, H> {
+    tree: &'a CryptoBinaryTree<T, H>,
+    stack: Vec<Pending>,
+}
+
+impl<'a, T: TreeKey, H> Cursor<'a, T, H> {
+    fn new(tree: &'a CryptoBinaryTree<T, H>) -> Self {
+        Cursor {
+            tree,
+            stack: tree.root.map(Pending::Subtree).into_iter().collect(),
+        }
+    }
+
+    /// Replaces the subtree on top with its right subtree, root and left subtree.
+    fn expand(&mut self, id: NodeId) {
+        let n = &self.tree.nodes[id];
+        self.stack.pop();
+        self.stack.extend(n.right.map(Pending::Subtree));
+        self.stack.push(Pending::Node(id));
+        self.stack.extend(n.left.map(Pending::Subtree));
+    }
+
+    /// Expands subtrees until a single node is on top, and takes it.
+    fn next_node(&mut self) -> Option<&'a T> {
+        loop {
+            match *self.stack.last()? {
+                Pending::Subtree(id) => self.expand(id),
+                Pending::Node(id) => {
+                    self.stack.pop();
+                    return Some(&self.tree.nodes[id].transaction);
+                }
+            }
+        }
+    }
+
+    fn peek_node(&mut self) -> Option<&'a T> {
+        loop {
+            match *self.stack.last()? {
+                Pending::Subtree(id) => self.expand(id),
+                Pending::Node(id) => return Some(&self.tree.nodes[id].transaction),
+            }
+        }
+    }
+}
+
+impl<T: TreeKey + PartialEq, H> CryptoBinaryTree<T, H> {
+    /// Lists the transactions added, removed and modified going from this
+    /// tree to `other`.
+    ///
+    /// Identical subtrees are recognised by their hashes, so both trees must
+    /// use the same hasher, salt and key for the walk to skip anything; with
+    /// different ones the result is the same but every node is visited.
+    /// Panics if either tree has pending hashes, see `flush_hashes`.
+    pub fn diff<'a>(&'a self, other: &'a CryptoBinaryTree<T, H>) -> TreeDiff<'a, T> {
+        self._assert_hashed();
+        other._assert_hashed();
+        let mut diff = TreeDiff {
+            added: Vec::new(),
+            removed: Vec::new(),
+            modified: Vec::new(),
+        };
+        let (mut ours, mut theirs) = (Cursor::new(self), Cursor::new(other));
+        loop {
+            match (ours.stack.last(), theirs.stack.last()) {
+                (None, None) => break,
+                (Some(&Pending::Subtree(a)), Some(&Pending::Subtree(b))) => {
+                    let (na, nb) = (&self.nodes[a], &other.nodes[b]);
+                    if na.hash == nb.hash {
+                        ours.stack.pop();
+                        theirs.stack.pop();
+                    } else if na.size >= nb.size {
+                        ours.expand(a);
+                    } else {
+                        theirs.expand(b);
+                    }
+                }
+                _ => {
+                    let (Some(a), Some(b)) = (ours.peek_node(), theirs.peek_node()) else {
+                        diff.removed.extend(core::iter::from_fn(|| ours.next_node()));
+                        diff.added.extend(core::iter::from_fn(|| theirs.next_node()));
+                        break;
+                    };
+                    match a.key().cmp(b.key()) {
+                        Ordering::Less => diff.removed.extend(ours.next_node()),
+                        Ordering::Greater => diff.added.extend(theirs.next_node()),
+                        Ordering::Equal => {
+                            ours.stack.pop();
+                            theirs.stack.pop();
+                            if a != b {
+                                diff.modified.push((a, b));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CryptoBinaryTree, Transaction};
+    use crate::test_util::{build_tree, sample_tx};
+
+    fn ids<'a>(txs: &[&'a Transaction]) -> Vec<&'a str> {
+        txs.iter().map(|tx| tx.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_diff_versions() {
+        let before = build_tree(1000);
+        let mut after = before.clone();
+        after.insert(sample_tx("tx_5000", 1));
+        after.remove("tx_100");
+        after.update("tx_500", |tx| tx.amount = 99).unwrap();
+
+        let diff = before.diff(&after);
+        assert_eq!(ids(&diff.added), ["tx_5000"]);
+        assert_eq!(ids(&diff.removed), ["tx_100"]);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!((diff.modified[0].0.amount, diff.modified[0].1.amount), (500, 99));
+        assert_eq!(diff.len(), 3);
+
+        let back = after.diff(&before);
+        assert_eq!(ids(&back.added), ["tx_100"]);
+        assert_eq!(ids(&back.removed), ["tx_5000"]);
+        assert!(before.diff(&before.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_diff_unrelated_shapes() {
+        // Same contents, built in different orders, so shapes differ
+        let forward = build_tree(300);
+        let mut backward = CryptoBinaryTree::new();
+        for tx in forward.iter().collect::<Vec<_>>().into_iter().rev() {
+            backward.insert(tx.clone());
+        }
+        assert!(forward.diff(&backward).is_empty());
+
+        let empty = CryptoBinaryTree::new();
+        assert_eq!(forward.diff(&empty).removed.len(), 300);
+        assert_eq!(empty.diff(&forward).added.len(), 300);
+        let mut odd = build_tree(599);
+        odd.retain(|tx| tx.amount % 2 == 1);
+        let diff = forward.diff(&odd);
+        assert_eq!((diff.removed.len(), diff.added.len(), diff.modified.len()), (150, 150, 0));
+        assert!(diff.removed.iter().all(|tx| tx.id.ends_with(['0', '2', '4', '6', '8'])));
+    }
+}
@@ -0,0 +1,622 @@
+use core::fmt;
+
+use serde::{ser, Serialize};
+
+use crate::prelude::*;
+use crate::TreeHasher;
+
+/// Version of the byte layout that is hashed for every node
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum HashFormat {
+    /// Legacy layout: the `serde_json` encoding of the node fields
+    JsonV0,
+    /// Canonical binary layout: length-prefixed fields and fixed-width integers
+    BinaryV1,
+    /// `BinaryV1` plus the subtree size, so order statistics are tamper-evident
+    BinaryV2,
+    /// `BinaryV2` behind a domain byte, as in RFC 6962: `leaf_prefix` for
+    /// nodes without children, `internal_prefix` for all others, so a leaf
+    /// encoding can never equal an internal one
+    BinaryV3 { leaf_prefix: u8, internal_prefix: u8 },
+}
+
+impl HashFormat {
+    /// `BinaryV3` with the RFC 6962 prefixes `0x00` (leaf) and `0x01` (internal)
+    pub const RFC6962: HashFormat = HashFormat::BinaryV3 {
+        leaf_prefix: 0x00,
+        internal_prefix: 0x01,
+    };
+}
+
+impl Default for HashFormat {
+    fn default() -> Self {
+        HashFormat::RFC6962
+    }
+}
+
+/// Version byte prefixed to every `BinaryV1` node encoding
+const BINARY_V1_TAG: u8 = 0x01;
+
+/// Version byte prefixed to every `BinaryV2` node encoding
+const BINARY_V2_TAG: u8 = 0x02;
+
+/// Version byte following the domain byte of every `BinaryV3` node encoding
+const BINARY_V3_TAG: u8 = 0x03;
+
+/// Error raised when a value cannot be canonically encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodingError(String);
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "canonical encoding failed: {}", self.0)
+    }
+}
+
+impl core::error::Error for EncodingError {}
+
+impl ser::Error for EncodingError {
+    fn custom<M: fmt::Display>(msg: M) -> Self {
+        EncodingError(msg.to_string())
+    }
+}
+
+/// Encodes a value into the canonical binary form used for hashing.
+///
+/// - integers use their fixed width, big-endian; floats use their IEEE bits
+/// - strings, byte arrays, sequences and maps are prefixed with a `u64` length
+/// - `None` is `0x00`, `Some(v)` is `0x01` followed by `v`
+/// - struct fields and map entries are sorted by name/encoded key, so
+///   declaration order and map iteration order never affect the result
+/// - enum variants are encoded by name
+pub fn encode_canonical<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, EncodingError> {
+    let mut out = Vec::new();
+    value.serialize(CanonicalEncoder { out: &mut out })?;
+    Ok(out)
+}
+
+/// Encodes the fields of a node in the `BinaryV1` layout.
+pub(crate) fn encode_node_v1<T: Serialize + ?Sized>(
+    transaction: &T,
+    left_hash: &str,
+    right_hash: &str,
+    height: i32,
+) -> Result<Vec<u8>, EncodingError> {
+    encode_node(BINARY_V1_TAG, transaction, left_hash, right_hash, height)
+}
+
+/// Encodes the fields of a node in the `BinaryV2` layout.
+pub(crate) fn encode_node_v2<T: Serialize + ?Sized>(
+    transaction: &T,
+    left_hash: &str,
+    right_hash: &str,
+    height: i32,
+    size: u64,
+) -> Result<Vec<u8>, EncodingError> {
+    let mut out = encode_node(BINARY_V2_TAG, transaction, left_hash, right_hash, height)?;
+    out.extend_from_slice(&size.to_be_bytes());
+    Ok(out)
+}
+
+/// Encodes the fields of a node in the `BinaryV3` layout, behind `domain`.
+pub(crate) fn encode_node_v3<T: Serialize + ?Sized>(
+    domain: u8,
+    transaction: &T,
+    left_hash: &str,
+    right_hash: &str,
+    height: i32,
+    size: u64,
+) -> Result<Vec<u8>, EncodingError> {
+    let mut out = vec![domain];
+    out.append(&mut encode_node(BINARY_V3_TAG, transaction, left_hash, right_hash, height)?);
+    out.extend_from_slice(&size.to_be_bytes());
+    Ok(out)
+}
+
+/// Hash of an AVL node over its payload, child hashes (`None` for an empty
+/// child), height and subtree size, as the tree and proof verifiers compute it.
+pub(crate) fn hash_node<T: Serialize, H: TreeHasher>(
+    hasher: &H,
+    transaction: &T,
+    left_hash: Option<&str>,
+    right_hash: Option<&str>,
+    height: i32,
+    size: usize,
+) -> crate::Result<String> {
+    let bytes = encode_node_in(hasher.format(), transaction, left_hash, right_hash, height, size)?;
+    match hasher.salt() {
+        Some(salt) => Ok(hasher.hash(&salt_node(salt, &bytes))),
+        None => Ok(hasher.hash(&bytes)),
+    }
+}
+
+/// Encodes the node fields in the given hash format.
+///
+/// The subtree `size` is only committed by `HashFormat::BinaryV2` and later.
+pub(crate) fn encode_node_in<T: Serialize>(
+    format: HashFormat,
+    transaction: &T,
+    left_hash: Option<&str>,
+    right_hash: Option<&str>,
+    height: i32,
+    size: usize,
+) -> crate::Result<Vec<u8>> {
+    let node_data = NodeData {
+        transaction,
+        left_hash: left_hash.unwrap_or("0"),
+        right_hash: right_hash.unwrap_or("0"),
+        height,
+    };
+
+    Ok(match format {
+        HashFormat::JsonV0 => serde_json::to_vec(&node_data)?,
+        HashFormat::BinaryV1 => encode_node_v1(transaction, node_data.left_hash, node_data.right_hash, height)?,
+        HashFormat::BinaryV2 => encode_node_v2(transaction, node_data.left_hash, node_data.right_hash, height, size as u64)?,
+        HashFormat::BinaryV3 { leaf_prefix, internal_prefix } => {
+            // "0" marks an empty child, here and in proofs
+            let is_leaf = node_data.left_hash == "0" && node_data.right_hash == "0";
+            let domain = if is_leaf { leaf_prefix } else { internal_prefix };
+            encode_node_v3(domain, transaction, node_data.left_hash, node_data.right_hash, height, size as u64)?
+        }
+    })
+}
+
+/// Node fields hashed under the legacy `HashFormat::JsonV0` layout
+#[derive(Serialize, Debug)]
+struct NodeData<'a, T> {
+    transaction: &'a T,
+    left_hash: &'a str,
+    right_hash: &'a str,
+    height: i32,
+}
+
+/// Prefixes an encoded node with a length-prefixed per-tree salt.
+pub(crate) fn salt_node(salt: &[u8], node: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 + salt.len() + node.len());
+    write_bytes(&mut out, salt);
+    out.extend_from_slice(node);
+    out
+}
+
+fn encode_node<T: Serialize + ?Sized>(
+    tag: u8,
+    transaction: &T,
+    left_hash: &str,
+    right_hash: &str,
+    height: i32,
+) -> Result<Vec<u8>, EncodingError> {
+    let payload = encode_canonical(transaction)?;
+    let mut out = Vec::with_capacity(payload.len() + left_hash.len() + right_hash.len() + 37);
+    out.push(tag);
+    write_bytes(&mut out, &payload);
+    write_bytes(&mut out, left_hash.as_bytes());
+    write_bytes(&mut out, right_hash.as_bytes());
+    out.extend_from_slice(&height.to_be_bytes());
+    Ok(out)
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u64).to_be_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_len(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+struct CanonicalEncoder<'a> {
+    out: &'a mut Vec<u8>,
+}
+
+impl<'a> ser::Serializer for CanonicalEncoder<'a> {
+    type Ok = ();
+    type Error = EncodingError;
+    type SerializeSeq = SeqEncoder<'a>;
+    type SerializeTuple = TupleEncoder<'a>;
+    type SerializeTupleStruct = TupleEncoder<'a>;
+    type SerializeTupleVariant = TupleEncoder<'a>;
+    type SerializeMap = MapEncoder<'a>;
+    type SerializeStruct = StructEncoder<'a>;
+    type SerializeStructVariant = StructEncoder<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), EncodingError> {
+        self.out.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), EncodingError> {
+        self.out.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&v.to_bits().to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&v.to_bits().to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), EncodingError> {
+        self.out.extend_from_slice(&(v as u32).to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), EncodingError> {
+        write_bytes(self.out, v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), EncodingError> {
+        write_bytes(self.out, v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), EncodingError> {
+        self.out.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<V: Serialize + ?Sized>(self, value: &V) -> Result<(), EncodingError> {
+        self.out.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), EncodingError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), EncodingError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<(), EncodingError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<V: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &V,
+    ) -> Result<(), EncodingError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<V: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &V,
+    ) -> Result<(), EncodingError> {
+        write_bytes(self.out, variant.as_bytes());
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SeqEncoder<'a>, EncodingError> {
+        Ok(SeqEncoder {
+            out: self.out,
+            items: Vec::new(),
+            count: 0,
+        })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<TupleEncoder<'a>, EncodingError> {
+        Ok(TupleEncoder { out: self.out })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<TupleEncoder<'a>, EncodingError> {
+        Ok(TupleEncoder { out: self.out })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<TupleEncoder<'a>, EncodingError> {
+        write_bytes(self.out, variant.as_bytes());
+        Ok(TupleEncoder { out: self.out })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapEncoder<'a>, EncodingError> {
+        Ok(MapEncoder {
+            out: self.out,
+            entries: Vec::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<StructEncoder<'a>, EncodingError> {
+        Ok(StructEncoder {
+            out: self.out,
+            fields: Vec::new(),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<StructEncoder<'a>, EncodingError> {
+        write_bytes(self.out, variant.as_bytes());
+        Ok(StructEncoder {
+            out: self.out,
+            fields: Vec::new(),
+        })
+    }
+}
+
+struct SeqEncoder<'a> {
+    out: &'a mut Vec<u8>,
+    items: Vec<u8>,
+    count: usize,
+}
+
+impl ser::SerializeSeq for SeqEncoder<'_> {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_element<V: Serialize + ?Sized>(&mut self, value: &V) -> Result<(), EncodingError> {
+        self.count += 1;
+        value.serialize(CanonicalEncoder { out: &mut self.items })
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        write_len(self.out, self.count);
+        self.out.extend_from_slice(&self.items);
+        Ok(())
+    }
+}
+
+/// Fixed-length compounds need no length prefix
+struct TupleEncoder<'a> {
+    out: &'a mut Vec<u8>,
+}
+
+impl TupleEncoder<'_> {
+    fn element<V: Serialize + ?Sized>(&mut self, value: &V) -> Result<(), EncodingError> {
+        value.serialize(CanonicalEncoder { out: self.out })
+    }
+}
+
+impl ser::SerializeTuple for TupleEncoder<'_> {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_element<V: Serialize + ?Sized>(&mut self, value: &V) -> Result<(), EncodingError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for TupleEncoder<'_> {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_field<V: Serialize + ?Sized>(&mut self, value: &V) -> Result<(), EncodingError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for TupleEncoder<'_> {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_field<V: Serialize + ?Sized>(&mut self, value: &V) -> Result<(), EncodingError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        Ok(())
+    }
+}
+
+struct MapEncoder<'a> {
+    out: &'a mut Vec<u8>,
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    key: Option<Vec<u8>>,
+}
+
+impl ser::SerializeMap for MapEncoder<'_> {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_key<K: Serialize + ?Sized>(&mut self, key: &K) -> Result<(), EncodingError> {
+        self.key = Some(encode_canonical(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<V: Serialize + ?Sized>(&mut self, value: &V) -> Result<(), EncodingError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| EncodingError("map value without a key".to_string()))?;
+        self.entries.push((key, encode_canonical(value)?));
+        Ok(())
+    }
+
+    fn end(mut self) -> Result<(), EncodingError> {
+        self.entries.sort();
+        write_len(self.out, self.entries.len());
+        for (key, value) in &self.entries {
+            self.out.extend_from_slice(key);
+            self.out.extend_from_slice(value);
+        }
+        Ok(())
+    }
+}
+
+struct StructEncoder<'a> {
+    out: &'a mut Vec<u8>,
+    fields: Vec<(&'static str, Vec<u8>)>,
+}
+
+impl StructEncoder<'_> {
+    fn field<V: Serialize + ?Sized>(&mut self, key: &'static str, value: &V) -> Result<(), EncodingError> {
+        self.fields.push((key, encode_canonical(value)?));
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), EncodingError> {
+        self.fields.sort_by(|a, b| a.0.cmp(b.0));
+        write_len(self.out, self.fields.len());
+        for (name, value) in &self.fields {
+            write_bytes(self.out, name.as_bytes());
+            self.out.extend_from_slice(value);
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for StructEncoder<'_> {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_field<V: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &V,
+    ) -> Result<(), EncodingError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for StructEncoder<'_> {
+    type Ok = ();
+    type Error = EncodingError;
+
+    fn serialize_field<V: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &V,
+    ) -> Result<(), EncodingError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), EncodingError> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Forward {
+        a: u32,
+        b: String,
+    }
+
+    #[derive(Serialize)]
+    struct Reversed {
+        b: String,
+        a: u32,
+    }
+
+    #[test]
+    fn test_field_order_independent() {
+        let forward = Forward { a: 7, b: "x".to_string() };
+        let reversed = Reversed { b: "x".to_string(), a: 7 };
+        assert_eq!(encode_canonical(&forward).unwrap(), encode_canonical(&reversed).unwrap());
+    }
+
+    #[test]
+    fn test_length_prefix_disambiguates() {
+        let first = encode_canonical(&("ab", "c")).unwrap();
+        let second = encode_canonical(&("a", "bc")).unwrap();
+        assert_ne!(first, second);
+        assert_ne!(encode_canonical(&None::<u64>).unwrap(), encode_canonical(&Some(0u64)).unwrap());
+    }
+
+    #[test]
+    fn test_fixed_width_integers() {
+        assert_eq!(encode_canonical(&1u64).unwrap(), vec![0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(encode_canonical(&-1i32).unwrap(), vec![0xff; 4]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_map_order_independent() {
+        use std::collections::{BTreeMap, HashMap};
+
+        let hashed: HashMap<_, _> = (0..32u32).map(|i| (i.to_string(), i)).collect();
+        let sorted: BTreeMap<_, _> = (0..32u32).map(|i| (i.to_string(), i)).collect();
+        assert_eq!(encode_canonical(&hashed).unwrap(), encode_canonical(&sorted).unwrap());
+    }
+}
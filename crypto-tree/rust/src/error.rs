@@ -0,0 +1,197 @@
+use core::fmt;
+
+use crate::prelude::*;
+#[cfg(all(feature = "std", feature = "tree"))]
+use crate::{SnapshotError, WalError};
+use crate::EncodingError;
+
+/// Reason given by a validator for rejecting a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    message: String,
+}
+
+impl ValidationError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self { message: message.into() }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl core::error::Error for ValidationError {}
+
+/// Errors returned by the fallible `CryptoBinaryTree` API
+#[derive(Debug)]
+pub enum CryptoTreeError {
+    /// A transaction with this id is already stored
+    DuplicateId(String),
+    /// No transaction with this id is stored
+    NotFound(String),
+    /// An in-place update changed the id of the transaction it was applied to
+    KeyChanged(String),
+    /// Ledger rules rejected a transaction whose sender cannot cover its amount
+    InsufficientBalance { id: String, sender: String, balance: i128, amount: u128 },
+    /// Applying a transaction would take an indexed balance outside the `i128` range
+    BalanceOverflow { id: String, address: String },
+    /// The validator installed with `set_validator` refused a transaction
+    Rejected { id: String, reason: ValidationError },
+    /// Signatures are required but the transaction carries none
+    Unsigned(String),
+    /// The transaction's signature or public key does not verify
+    InvalidSignature(String),
+    /// Content ids are required but the id is not the transaction's content hash
+    IdMismatch { id: String, expected: String },
+    /// A transaction's `data` attachment exceeds the tree's size limit
+    DataTooLarge { id: String, size: usize, limit: usize },
+    /// Input expected in ascending id order was not; carries the first out-of-order id
+    UnsortedInput(String),
+    /// A payload could not be encoded for hashing or export
+    SerializationFailed(String),
+    /// A node's stored hash does not match its recomputed hash
+    CorruptedNode { id: String },
+    /// A node breaks a structural invariant that its hash does not cover
+    InvariantViolated { id: String, invariant: Invariant },
+    /// The stored Merkle root differs from the one recomputed from the nodes
+    RootMismatch { stored: String, computed: String },
+    /// The stored node count differs from the number of nodes found
+    SizeMismatch { stored: usize, found: usize },
+    /// The node links of a serialized tree state do not form a single tree
+    MalformedState(String),
+    /// A proof does not verify against the expected root
+    InvalidProof(String),
+    /// A query reaches a part of a `PartialTree` that no added proof reveals
+    NotWitnessed(String),
+    /// No retained version of the tree has this Merkle root
+    UnknownVersion(String),
+    /// Reading or writing a snapshot failed
+    #[cfg(all(feature = "std", feature = "tree"))]
+    Snapshot(SnapshotError),
+    /// Writing or replaying a write-ahead log failed
+    #[cfg(all(feature = "std", feature = "tree"))]
+    Wal(WalError),
+    /// A `TreeStore` failed, or lacks a node that a stored root refers to
+    Storage(String),
+}
+
+/// Structural property of a search tree checked by `check_invariants`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invariant {
+    /// The node's id is not strictly between those of its in-order neighbours
+    KeyOrder,
+    /// The stored height differs from the one implied by the children
+    Height { stored: i32, actual: i32 },
+    /// The heights of the two subtrees differ by more than one
+    Balance(i32),
+    /// The stored subtree size differs from the number of nodes below
+    Size { stored: usize, actual: usize },
+}
+
+impl fmt::Display for Invariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Invariant::KeyOrder => f.write_str("id out of order"),
+            Invariant::Height { stored, actual } => write!(f, "stored height {}, actual {}", stored, actual),
+            Invariant::Balance(factor) => write!(f, "balance factor {}", factor),
+            Invariant::Size { stored, actual } => write!(f, "stored size {}, actual {}", stored, actual),
+        }
+    }
+}
+
+impl fmt::Display for CryptoTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoTreeError::DuplicateId(id) => write!(f, "duplicate transaction id {}", id),
+            CryptoTreeError::NotFound(id) => write!(f, "transaction {} not found", id),
+            CryptoTreeError::KeyChanged(id) => write!(f, "update changed the id of transaction {}", id),
+            CryptoTreeError::InsufficientBalance { id, sender, balance, amount } => write!(
+                f,
+                "transaction {} sends {} but {} only holds {}",
+                id, amount, sender, balance
+            ),
+            CryptoTreeError::Rejected { id, reason } => write!(f, "transaction {} rejected: {}", id, reason),
+            CryptoTreeError::Unsigned(id) => write!(f, "transaction {} is not signed", id),
+            CryptoTreeError::InvalidSignature(id) => write!(f, "transaction {} has an invalid signature", id),
+            CryptoTreeError::IdMismatch { id, expected } => {
+                write!(f, "transaction id {} does not match content hash {}", id, expected)
+            }
+            CryptoTreeError::BalanceOverflow { id, address } => {
+                write!(f, "transaction {} overflows the balance of {}", id, address)
+            }
+            CryptoTreeError::DataTooLarge { id, size, limit } => {
+                write!(f, "transaction {} carries {} bytes of data, limit is {}", id, size, limit)
+            }
+            CryptoTreeError::UnsortedInput(id) => write!(f, "input not sorted by id at {}", id),
+            CryptoTreeError::SerializationFailed(msg) => write!(f, "serialization failed: {}", msg),
+            CryptoTreeError::CorruptedNode { id } => write!(f, "hash mismatch at transaction {}", id),
+            CryptoTreeError::InvariantViolated { id, invariant } => {
+                write!(f, "invariant violated at transaction {}: {}", id, invariant)
+            }
+            CryptoTreeError::RootMismatch { stored, computed } => {
+                write!(f, "merkle root mismatch: stored {}, computed {}", stored, computed)
+            }
+            CryptoTreeError::SizeMismatch { stored, found } => {
+                write!(f, "size mismatch: stored {}, found {} nodes", stored, found)
+            }
+            CryptoTreeError::MalformedState(reason) => write!(f, "malformed tree state: {}", reason),
+            CryptoTreeError::InvalidProof(reason) => write!(f, "invalid proof: {}", reason),
+            CryptoTreeError::NotWitnessed(what) => write!(f, "{} lies outside the proven part of the tree", what),
+            CryptoTreeError::UnknownVersion(root) => write!(f, "no retained version has root {}", root),
+            #[cfg(all(feature = "std", feature = "tree"))]
+            CryptoTreeError::Snapshot(e) => e.fmt(f),
+            #[cfg(all(feature = "std", feature = "tree"))]
+            CryptoTreeError::Wal(e) => e.fmt(f),
+            CryptoTreeError::Storage(reason) => write!(f, "storage failed: {}", reason),
+        }
+    }
+}
+
+impl core::error::Error for CryptoTreeError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(all(feature = "std", feature = "tree"))]
+            CryptoTreeError::Snapshot(e) => Some(e),
+            #[cfg(all(feature = "std", feature = "tree"))]
+            CryptoTreeError::Wal(e) => Some(e),
+            CryptoTreeError::Rejected { reason, .. } => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+impl From<EncodingError> for CryptoTreeError {
+    fn from(e: EncodingError) -> Self {
+        CryptoTreeError::SerializationFailed(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for CryptoTreeError {
+    fn from(e: serde_json::Error) -> Self {
+        CryptoTreeError::SerializationFailed(e.to_string())
+    }
+}
+
+#[cfg(all(feature = "std", feature = "tree"))]
+impl From<SnapshotError> for CryptoTreeError {
+    fn from(e: SnapshotError) -> Self {
+        CryptoTreeError::Snapshot(e)
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl From<crate::cbor::CborError> for CryptoTreeError {
+    fn from(e: crate::cbor::CborError) -> Self {
+        CryptoTreeError::SerializationFailed(e.to_string())
+    }
+}
+
+/// Convenience alias for results carrying a [`CryptoTreeError`]
+pub type Result<T, E = CryptoTreeError> = core::result::Result<T, E>;
@@ -0,0 +1,136 @@
+//! Frozen, copy-on-write views of a tree.
+//!
+//! [`CryptoBinaryTree::snapshot`] shares the tree's node pages instead of
+//! copying them, so it takes O(1) time. The tree keeps mutating; each write
+//! copies the shared pages it touches, and the snapshot goes on serving
+//! reads and proofs against the root it was taken at.
+
+use core::ops::Deref;
+
+use serde::Serialize;
+
+use crate::arena::Arena;
+use crate::intern::Interner;
+use crate::subscribe::Subscribers;
+use crate::prelude::*;
+use crate::{CryptoBinaryTree, NodeId, Sha256Hasher, Transaction, TreeHasher, TreeKey};
+
+/// A read-only view of a tree as it was when the snapshot was taken
+///
+/// Dereferences to the tree, so every `&self` method is available: lookups,
+/// iteration, proofs, integrity checks and serialization. Address and time
+/// queries and `balance` scan the nodes, as the secondary index is not
+/// carried over. Snapshots are `Send + Sync` for payloads and hashers that
+/// are, so one can be handed to reader threads while the tree keeps taking
+/// writes.
+#[derive(Debug, Clone)]
+pub struct TreeSnapshot<T: TreeKey = Transaction, H = Sha256Hasher> {
+    tree: CryptoBinaryTree<T, H>,
+}
+
+impl<T: TreeKey, H> Deref for TreeSnapshot<T, H> {
+    type Target = CryptoBinaryTree<T, H>;
+
+    fn deref(&self) -> &CryptoBinaryTree<T, H> {
+        &self.tree
+    }
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher + Clone> TreeSnapshot<T, H> {
+    /// Turns the snapshot into a tree of its own, e.g. to fork a ledger.
+    ///
+    /// The new tree starts without an index, validator, policies or observer.
+    pub fn into_tree(self) -> CryptoBinaryTree<T, H> {
+        let mut tree = self.tree;
+        tree.observer = None;
+        tree._intern_all();
+        tree
+    }
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher + Clone> CryptoBinaryTree<T, H> {
+    /// Takes a frozen view of the tree in O(1), sharing its nodes.
+    ///
+    /// Later writes to the tree copy the node pages they touch, about
+    /// `log n` pages of 64 nodes for the first write to each, and leave the
+    /// snapshot unchanged. The snapshot reports to the tree's observer.
+    /// Panics if hashes are pending, see `flush_hashes`.
+    pub fn snapshot(&self) -> TreeSnapshot<T, H> {
+        self._assert_hashed();
+        self._frozen(self.root, self.nodes.clone(), self.merkle_root.clone())
+    }
+
+    /// A read-only tree over `nodes`, which must be fully hashed.
+    pub(crate) fn _frozen(&self, root: Option<NodeId>, nodes: Arena<T>, merkle_root: String) -> TreeSnapshot<T, H> {
+        TreeSnapshot {
+            tree: CryptoBinaryTree {
+                root,
+                nodes,
+                merkle_root,
+                hasher: self.hasher.clone(),
+                index: None,
+                validator: None,
+                policies: Vec::new(),
+                interner: Interner::new(),
+                lazy_hashing: false,
+                observer: self.observer.clone(),
+                subscribers: Subscribers::new(),
+                versions: None,
+                audit: None,
+                #[cfg(feature = "std")]
+                history: None,
+                #[cfg(feature = "ed25519")]
+                root_signer: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{verify_proof, CryptoBinaryTree};
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_snapshot_is_unaffected_by_writes() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 0..500 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 10));
+        }
+        let snapshot = tree.snapshot();
+        let root = snapshot.merkle_root().to_string();
+        assert_eq!(root, tree.merkle_root());
+
+        for i in 0..100 {
+            tree.remove(format!("tx_{:03}", i).as_str());
+        }
+        tree.update("tx_250", |tx| tx.amount = 99).unwrap();
+        tree.insert(sample_tx("tx_999", 1));
+        assert_ne!(tree.merkle_root(), root);
+        assert!(tree.verify_integrity() && tree.verify_invariants());
+
+        assert_eq!(snapshot.merkle_root(), root);
+        assert_eq!(snapshot.len(), 500);
+        assert!(snapshot.verify_integrity() && snapshot.verify_invariants());
+        assert_eq!(snapshot.search("tx_250").unwrap().amount, 10);
+        assert!(snapshot.search("tx_999").is_none());
+        let tx = snapshot.search("tx_042").unwrap();
+        let proof = snapshot.get_proof_of_inclusion("tx_042").unwrap();
+        assert!(verify_proof(&root, tx, &proof));
+        assert_eq!(snapshot.transactions_from("Alice").len(), 500);
+    }
+
+    #[test]
+    fn test_snapshot_into_tree_forks() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 0..10 {
+            tree.insert(sample_tx(&format!("tx_{}", i), 10));
+        }
+        let mut fork = tree.snapshot().into_tree();
+        fork.insert(sample_tx("tx_fork", 1));
+        tree.remove("tx_0");
+        assert_eq!((tree.len(), fork.len()), (9, 11));
+        assert!(fork.search("tx_0").is_some() && tree.search("tx_fork").is_none());
+        assert!(tree.verify_integrity() && fork.verify_integrity());
+    }
+}
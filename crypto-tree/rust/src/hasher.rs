@@ -0,0 +1,663 @@
+use core::any::TypeId;
+use core::fmt;
+use core::marker::PhantomData;
+
+use sha2::digest::core_api::BlockSizeUser;
+use sha2::digest::Output;
+use sha2::{Digest, Sha256};
+
+use crate::prelude::*;
+use crate::HashFormat;
+
+/// Hash function used to commit tree nodes
+pub trait TreeHasher {
+    /// Hashes `data` and returns the digest as a lowercase hex string
+    fn hash(&self, data: &[u8]) -> String;
+
+    /// Byte layout of the node data fed into [`TreeHasher::hash`]
+    fn format(&self) -> HashFormat {
+        HashFormat::default()
+    }
+
+    /// Hash function this hasher implements, recorded in snapshot headers;
+    /// `None` for custom hashers
+    fn algorithm(&self) -> Option<HashAlgorithm> {
+        None
+    }
+
+    /// Public per-tree salt mixed into every node hash, so independent
+    /// deployments of the same data produce different roots
+    fn salt(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+/// A [`TreeHasher`] backed by any `sha2::Digest`-style hash function,
+/// e.g. `DigestHasher<sha2::Sha512>` or `DigestHasher<sha3::Sha3_256>`
+///
+/// With a key (see [`DigestHasher::with_key`]) every node hash is an HMAC.
+pub struct DigestHasher<D> {
+    format: HashFormat,
+    key: Option<Vec<u8>>,
+    salt: Option<Vec<u8>>,
+    _digest: PhantomData<fn() -> D>,
+}
+
+/// The default hasher: SHA-256
+pub type Sha256Hasher = DigestHasher<Sha256>;
+
+/// Keccak-256 as computed by the EVM's `keccak256`, so roots and node hashes
+/// can be checked on-chain. This is the pre-standard padding, not SHA3-256.
+#[cfg(feature = "keccak")]
+pub type Keccak256Hasher = DigestHasher<sha3::Keccak256>;
+
+impl<D> DigestHasher<D> {
+    pub fn new() -> Self {
+        Self::with_format(HashFormat::default())
+    }
+
+    /// Creates a hasher using a specific node layout, e.g. `HashFormat::JsonV0`
+    /// to keep reproducing roots computed by earlier releases.
+    pub fn with_format(format: HashFormat) -> Self {
+        Self {
+            format,
+            key: None,
+            salt: None,
+            _digest: PhantomData,
+        }
+    }
+
+    /// Computes every node hash as `HMAC-D(key, node)`, so only key holders
+    /// can build or check roots. The key is never serialized.
+    pub fn with_key(mut self, key: &[u8]) -> Self {
+        self.key = Some(key.to_vec());
+        self
+    }
+
+    /// Returns `true` if node hashes are keyed.
+    pub fn is_keyed(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Mixes `salt` into every node hash (see [`TreeHasher::salt`]).
+    pub fn with_salt(mut self, salt: impl Into<Vec<u8>>) -> Self {
+        self.salt = Some(salt.into());
+        self
+    }
+}
+
+impl<D> Default for DigestHasher<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> Clone for DigestHasher<D> {
+    fn clone(&self) -> Self {
+        Self {
+            format: self.format,
+            key: self.key.clone(),
+            salt: self.salt.clone(),
+            _digest: PhantomData,
+        }
+    }
+}
+
+impl<D> fmt::Debug for DigestHasher<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keyed = if self.is_keyed() { ", keyed" } else { "" };
+        let salted = if self.salt.is_some() { ", salted" } else { "" };
+        write!(f, "DigestHasher<{}, {:?}{}{}>", core::any::type_name::<D>(), self.format, keyed, salted)
+    }
+}
+
+/// HMAC (RFC 2104) over `D`.
+fn hmac<D: Digest + BlockSizeUser>(key: &[u8], data: &[u8]) -> Output<D> {
+    let mut block = if key.len() > D::block_size() {
+        D::digest(key).to_vec()
+    } else {
+        key.to_vec()
+    };
+    block.resize(D::block_size(), 0);
+
+    let mut inner = D::new();
+    inner.update(block.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+    inner.update(data);
+    let mut outer = D::new();
+    outer.update(block.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+    outer.update(inner.finalize());
+    outer.finalize()
+}
+
+impl<D: Digest + BlockSizeUser + 'static> TreeHasher for DigestHasher<D> {
+    fn hash(&self, data: &[u8]) -> String {
+        match &self.key {
+            Some(key) => to_hex(&hmac::<D>(key, data)),
+            None => to_hex(&D::digest(data)),
+        }
+    }
+
+    fn format(&self) -> HashFormat {
+        self.format
+    }
+
+    fn algorithm(&self) -> Option<HashAlgorithm> {
+        let digest = TypeId::of::<D>();
+        if digest == TypeId::of::<Sha256>() {
+            return Some(HashAlgorithm::Sha256);
+        }
+        #[cfg(feature = "keccak")]
+        if digest == TypeId::of::<sha3::Keccak256>() {
+            return Some(HashAlgorithm::Keccak256);
+        }
+        None
+    }
+
+    fn salt(&self) -> Option<&[u8]> {
+        self.salt.as_deref()
+    }
+}
+
+/// Bitcoin's double SHA-256 (`SHA256(SHA256(data))`), applied to the
+/// canonical binary node layout.
+///
+/// Digests are hex in internal byte order, as hashed into Bitcoin Merkle
+/// trees; txids and block hashes are usually displayed byte-reversed, see
+/// [`DoubleSha256Hasher::display_order`].
+#[derive(Clone, Default)]
+pub struct DoubleSha256Hasher {
+    format: HashFormat,
+    key: Option<Vec<u8>>,
+    salt: Option<Vec<u8>>,
+}
+
+impl DoubleSha256Hasher {
+    pub fn new() -> Self {
+        Self::with_format(HashFormat::default())
+    }
+
+    /// Creates a hasher using a specific node layout.
+    pub fn with_format(format: HashFormat) -> Self {
+        Self {
+            format,
+            key: None,
+            salt: None,
+        }
+    }
+
+    /// Computes every node hash as `SHA256(HMAC-SHA256(key, node))`. The key
+    /// is never serialized.
+    pub fn with_key(mut self, key: &[u8]) -> Self {
+        self.key = Some(key.to_vec());
+        self
+    }
+
+    /// Returns `true` if node hashes are keyed.
+    pub fn is_keyed(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Mixes `salt` into every node hash (see [`TreeHasher::salt`]).
+    pub fn with_salt(mut self, salt: impl Into<Vec<u8>>) -> Self {
+        self.salt = Some(salt.into());
+        self
+    }
+
+    /// Reverses the bytes of a hex digest, converting between internal and
+    /// display order (the conversion is its own inverse). Returns `None` if
+    /// `hash` is not valid hex.
+    pub fn display_order(hash: &str) -> Option<String> {
+        let mut bytes = from_hex(hash)?;
+        bytes.reverse();
+        Some(to_hex(&bytes))
+    }
+}
+
+impl fmt::Debug for DoubleSha256Hasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DoubleSha256Hasher")
+            .field("format", &self.format)
+            .field("keyed", &self.is_keyed())
+            .field("salt", &self.salt)
+            .finish()
+    }
+}
+
+impl TreeHasher for DoubleSha256Hasher {
+    fn hash(&self, data: &[u8]) -> String {
+        let first = match &self.key {
+            Some(key) => hmac::<Sha256>(key, data),
+            None => Sha256::digest(data),
+        };
+        to_hex(&Sha256::digest(first))
+    }
+
+    fn format(&self) -> HashFormat {
+        self.format
+    }
+
+    fn algorithm(&self) -> Option<HashAlgorithm> {
+        Some(HashAlgorithm::DoubleSha256)
+    }
+
+    fn salt(&self) -> Option<&[u8]> {
+        self.salt.as_deref()
+    }
+}
+
+/// A [`TreeHasher`] using BLAKE3, optionally in its keyed mode
+#[cfg(feature = "blake3")]
+#[derive(Clone, Default)]
+pub struct Blake3Hasher {
+    format: HashFormat,
+    key: Option<[u8; 32]>,
+    salt: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "blake3")]
+impl Blake3Hasher {
+    pub fn new() -> Self {
+        Self::with_format(HashFormat::default())
+    }
+
+    /// Creates a hasher using a specific node layout.
+    pub fn with_format(format: HashFormat) -> Self {
+        Self {
+            format,
+            key: None,
+            salt: None,
+        }
+    }
+
+    /// Creates a hasher computing every node hash as a BLAKE3 keyed hash
+    /// (a MAC) under `key`, so only key holders can produce or check roots.
+    pub fn keyed(key: [u8; 32]) -> Self {
+        Self::new().with_key(key)
+    }
+
+    /// Switches to keyed mode under `key`. The key is never serialized.
+    pub fn with_key(mut self, key: [u8; 32]) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Returns `true` if this hasher uses keyed mode.
+    pub fn is_keyed(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Mixes `salt` into every node hash (see [`TreeHasher::salt`]).
+    pub fn with_salt(mut self, salt: impl Into<Vec<u8>>) -> Self {
+        self.salt = Some(salt.into());
+        self
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl fmt::Debug for Blake3Hasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the key
+        f.debug_struct("Blake3Hasher")
+            .field("format", &self.format)
+            .field("keyed", &self.is_keyed())
+            .field("salt", &self.salt)
+            .finish()
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl TreeHasher for Blake3Hasher {
+    fn hash(&self, data: &[u8]) -> String {
+        let digest = match &self.key {
+            Some(key) => blake3::keyed_hash(key, data),
+            None => blake3::hash(data),
+        };
+        to_hex(digest.as_bytes())
+    }
+
+    fn format(&self) -> HashFormat {
+        self.format
+    }
+
+    fn algorithm(&self) -> Option<HashAlgorithm> {
+        Some(HashAlgorithm::Blake3)
+    }
+
+    fn salt(&self) -> Option<&[u8]> {
+        self.salt.as_deref()
+    }
+}
+
+/// Hash function of a [`RuntimeHasher`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    /// Bitcoin-style `SHA256(SHA256(data))`
+    DoubleSha256,
+    #[cfg(feature = "blake3")]
+    Blake3,
+    #[cfg(feature = "keccak")]
+    Keccak256,
+}
+
+/// A [`TreeHasher`] whose hash function is picked at runtime, e.g. from
+/// configuration; see [`TreeBuilder`](crate::TreeBuilder).
+#[derive(Clone, Debug)]
+pub enum RuntimeHasher {
+    Sha256(Sha256Hasher),
+    DoubleSha256(DoubleSha256Hasher),
+    #[cfg(feature = "blake3")]
+    Blake3(Blake3Hasher),
+    #[cfg(feature = "keccak")]
+    Keccak256(Keccak256Hasher),
+}
+
+impl RuntimeHasher {
+    pub fn new(algorithm: HashAlgorithm, format: HashFormat) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => RuntimeHasher::Sha256(Sha256Hasher::with_format(format)),
+            HashAlgorithm::DoubleSha256 => RuntimeHasher::DoubleSha256(DoubleSha256Hasher::with_format(format)),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => RuntimeHasher::Blake3(Blake3Hasher::with_format(format)),
+            #[cfg(feature = "keccak")]
+            HashAlgorithm::Keccak256 => RuntimeHasher::Keccak256(Keccak256Hasher::with_format(format)),
+        }
+    }
+
+    /// Like [`RuntimeHasher::new`], computing every node hash under `key`:
+    /// keyed BLAKE3 for `Blake3`, HMAC for the other algorithms.
+    pub fn keyed(algorithm: HashAlgorithm, format: HashFormat, key: [u8; 32]) -> Self {
+        match Self::new(algorithm, format) {
+            RuntimeHasher::Sha256(h) => RuntimeHasher::Sha256(h.with_key(&key)),
+            RuntimeHasher::DoubleSha256(h) => RuntimeHasher::DoubleSha256(h.with_key(&key)),
+            #[cfg(feature = "blake3")]
+            RuntimeHasher::Blake3(h) => RuntimeHasher::Blake3(h.with_key(key)),
+            #[cfg(feature = "keccak")]
+            RuntimeHasher::Keccak256(h) => RuntimeHasher::Keccak256(h.with_key(&key)),
+        }
+    }
+
+    /// Mixes `salt` into every node hash (see [`TreeHasher::salt`]).
+    pub fn with_salt(self, salt: impl Into<Vec<u8>>) -> Self {
+        match self {
+            RuntimeHasher::Sha256(h) => RuntimeHasher::Sha256(h.with_salt(salt)),
+            RuntimeHasher::DoubleSha256(h) => RuntimeHasher::DoubleSha256(h.with_salt(salt)),
+            #[cfg(feature = "blake3")]
+            RuntimeHasher::Blake3(h) => RuntimeHasher::Blake3(h.with_salt(salt)),
+            #[cfg(feature = "keccak")]
+            RuntimeHasher::Keccak256(h) => RuntimeHasher::Keccak256(h.with_salt(salt)),
+        }
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self {
+            RuntimeHasher::Sha256(_) => HashAlgorithm::Sha256,
+            RuntimeHasher::DoubleSha256(_) => HashAlgorithm::DoubleSha256,
+            #[cfg(feature = "blake3")]
+            RuntimeHasher::Blake3(_) => HashAlgorithm::Blake3,
+            #[cfg(feature = "keccak")]
+            RuntimeHasher::Keccak256(_) => HashAlgorithm::Keccak256,
+        }
+    }
+}
+
+impl Default for RuntimeHasher {
+    fn default() -> Self {
+        Self::new(HashAlgorithm::default(), HashFormat::default())
+    }
+}
+
+impl TreeHasher for RuntimeHasher {
+    fn hash(&self, data: &[u8]) -> String {
+        match self {
+            RuntimeHasher::Sha256(h) => h.hash(data),
+            RuntimeHasher::DoubleSha256(h) => h.hash(data),
+            #[cfg(feature = "blake3")]
+            RuntimeHasher::Blake3(h) => h.hash(data),
+            #[cfg(feature = "keccak")]
+            RuntimeHasher::Keccak256(h) => h.hash(data),
+        }
+    }
+
+    fn format(&self) -> HashFormat {
+        match self {
+            RuntimeHasher::Sha256(h) => h.format(),
+            RuntimeHasher::DoubleSha256(h) => h.format(),
+            #[cfg(feature = "blake3")]
+            RuntimeHasher::Blake3(h) => h.format(),
+            #[cfg(feature = "keccak")]
+            RuntimeHasher::Keccak256(h) => h.format(),
+        }
+    }
+
+    fn algorithm(&self) -> Option<HashAlgorithm> {
+        Some(RuntimeHasher::algorithm(self))
+    }
+
+    fn salt(&self) -> Option<&[u8]> {
+        match self {
+            RuntimeHasher::Sha256(h) => h.salt(),
+            RuntimeHasher::DoubleSha256(h) => h.salt(),
+            #[cfg(feature = "blake3")]
+            RuntimeHasher::Blake3(h) => h.salt(),
+            #[cfg(feature = "keccak")]
+            RuntimeHasher::Keccak256(h) => h.salt(),
+        }
+    }
+}
+
+/// Decodes a hex string, returning `None` if it is malformed.
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    use core::fmt::Write;
+
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
+
+/// Serde adapter writing byte blobs as lowercase hex strings
+pub(crate) mod hex_bytes {
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::Serializer;
+
+    use crate::prelude::*;
+
+    pub(crate) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_hex(bytes))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        super::from_hex(&hex).ok_or_else(|| de::Error::custom("invalid hex string"))
+    }
+}
+
+#[cfg(all(test, feature = "tree"))]
+mod tests {
+    use super::*;
+    use crate::{verify_proof, verify_proof_with, CryptoBinaryTree};
+    use sha2::Sha512;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_sha256_matches_reference() {
+        assert_eq!(
+            Sha256Hasher::new().hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_double_sha256_matches_bitcoin() {
+        assert_eq!(
+            DoubleSha256Hasher::new().hash(b"hello"),
+            "9595c9df90075148eb06860365df33584b75bff782a510c6cd4883a419833d50"
+        );
+
+        // The genesis block coinbase transaction and its txid
+        let coinbase = from_hex(concat!(
+            "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d01",
+            "04455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f662073",
+            "65636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe55482719",
+            "67f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a",
+            "4c702b6bf11d5fac00000000",
+        ))
+        .unwrap();
+        let digest = DoubleSha256Hasher::new().hash(&coinbase);
+        assert_eq!(
+            DoubleSha256Hasher::display_order(&digest).unwrap(),
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+        );
+    }
+
+    #[test]
+    fn test_double_sha256_leaf() {
+        let tx = sample_tx("tx_01", 10);
+        let mut tree = CryptoBinaryTree::with_hasher(DoubleSha256Hasher::new());
+        tree.insert(tx.clone());
+
+        let node = crate::encoding::encode_node_v3(0x00, &tx, "0", "0", 1, 1).unwrap();
+        let once = Sha256::digest(&node);
+        assert_eq!(tree.merkle_root(), to_hex(&Sha256::digest(once)));
+
+        let runtime = RuntimeHasher::new(HashAlgorithm::DoubleSha256, HashFormat::default());
+        assert_eq!(runtime.hash(&node), tree.merkle_root());
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_blake3_matches_reference() {
+        assert_eq!(
+            Blake3Hasher::new().hash(b"abc"),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+    }
+
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn test_keccak256_matches_evm() {
+        // keccak256("") as returned by Solidity
+        assert_eq!(
+            Keccak256Hasher::new().hash(b""),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(Keccak256Hasher::new().algorithm(), Some(HashAlgorithm::Keccak256));
+        assert_eq!(DigestHasher::<Sha512>::new().algorithm(), None);
+    }
+
+    #[test]
+    fn test_hmac_sha256_matches_rfc4231() {
+        let hasher = Sha256Hasher::new().with_key(b"Jefe");
+        assert_eq!(
+            hasher.hash(b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // Keys longer than a block are hashed first
+        let long_key = Sha256Hasher::new().with_key(&[0xaa; 131]);
+        assert_eq!(
+            long_key.hash(b"Test Using Larger Than Block-Size Key - Hash Key First"),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+        assert!(!format!("{:?}", hasher).contains("Jefe"));
+    }
+
+    #[test]
+    fn test_keyed_tree() {
+        let key = [42u8; 32];
+        let mut tree = CryptoBinaryTree::with_hasher(Sha256Hasher::new().with_key(&key));
+        let mut plain = CryptoBinaryTree::new();
+        for i in 1..=10 {
+            tree.insert(sample_tx(&format!("tx_{:02}", i), 10));
+            plain.insert(sample_tx(&format!("tx_{:02}", i), 10));
+        }
+        assert_ne!(tree.merkle_root(), plain.merkle_root());
+        assert!(tree.verify_integrity());
+
+        let tx = tree.search("tx_06").unwrap();
+        let proof = tree.get_proof_of_inclusion("tx_06").unwrap();
+        assert!(verify_proof_with(&Sha256Hasher::new().with_key(&key), tree.merkle_root(), tx, &proof));
+        assert!(!verify_proof_with(&Sha256Hasher::new().with_key(&[0; 32]), tree.merkle_root(), tx, &proof));
+        assert!(!verify_proof(tree.merkle_root(), tx, &proof));
+
+        let runtime = RuntimeHasher::keyed(HashAlgorithm::Sha256, HashFormat::default(), key);
+        assert_eq!(runtime.hash(b"node"), Sha256Hasher::new().with_key(&key).hash(b"node"));
+        let double = RuntimeHasher::keyed(HashAlgorithm::DoubleSha256, HashFormat::default(), key);
+        assert_ne!(double.hash(b"node"), DoubleSha256Hasher::new().hash(b"node"));
+
+        // The key never leaves the process with the tree
+        let json = serde_json::to_string(&tree).unwrap();
+        assert!(!json.contains(&to_hex(&key)));
+    }
+
+    #[test]
+    fn test_legacy_json_format() {
+        let tx = sample_tx("tx_01", 10);
+        let mut tree = CryptoBinaryTree::with_hasher(Sha256Hasher::with_format(HashFormat::JsonV0));
+        tree.insert(tx.clone());
+
+        let json = format!(
+            "{{\"transaction\":{},\"left_hash\":\"0\",\"right_hash\":\"0\",\"height\":1}}",
+            serde_json::to_string(&tx).unwrap()
+        );
+        assert_eq!(tree.merkle_root(), Sha256Hasher::new().hash(json.as_bytes()));
+
+        let mut current = CryptoBinaryTree::new();
+        current.insert(tx);
+        assert_ne!(current.merkle_root(), tree.merkle_root());
+    }
+
+    #[test]
+    fn test_domain_separated_nodes() {
+        let mut tree = CryptoBinaryTree::new();
+        for id in ["tx_01", "tx_02", "tx_03"] {
+            tree.insert(sample_tx(id, 10));
+        }
+        let hasher = Sha256Hasher::new();
+        let leaf = |id: &str| hasher.hash(&crate::encoding::encode_node_v3(0x00, &sample_tx(id, 10), "0", "0", 1, 1).unwrap());
+        let (left, right) = (leaf("tx_01"), leaf("tx_03"));
+        let root = crate::encoding::encode_node_v3(0x01, &sample_tx("tx_02", 10), &left, &right, 2, 3).unwrap();
+        assert_eq!(tree.merkle_root(), hasher.hash(&root));
+
+        let custom = Sha256Hasher::with_format(HashFormat::BinaryV3 {
+            leaf_prefix: 0x10,
+            internal_prefix: 0x11,
+        });
+        let mut other = CryptoBinaryTree::with_hasher(custom.clone());
+        for id in ["tx_01", "tx_02", "tx_03"] {
+            other.insert(sample_tx(id, 10));
+        }
+        assert_ne!(other.merkle_root(), tree.merkle_root());
+        let proof = other.get_proof_of_inclusion("tx_03").unwrap();
+        assert!(verify_proof_with(&custom, other.merkle_root(), &sample_tx("tx_03", 10), &proof));
+        assert!(!verify_proof(other.merkle_root(), &sample_tx("tx_03", 10), &proof));
+    }
+
+    #[test]
+    fn test_sha512_tree() {
+        let mut tree = CryptoBinaryTree::with_hasher(DigestHasher::<Sha512>::new());
+        for i in 1..=10 {
+            tree.insert(sample_tx(&format!("tx_{:02}", i), 10));
+        }
+        assert_eq!(tree.merkle_root().len(), 128);
+        assert!(tree.verify_integrity());
+
+        let tx = tree.search("tx_04").unwrap();
+        let proof = tree.get_proof_of_inclusion("tx_04").unwrap();
+        assert!(verify_proof_with(tree.hasher(), tree.merkle_root(), tx, &proof));
+        assert!(!verify_proof(tree.merkle_root(), tx, &proof));
+    }
+}
@@ -0,0 +1,160 @@
+//! The sequence of Merkle roots a tree went through.
+//!
+//! Once enabled with [`CryptoBinaryTree::enable_root_history`], every root a
+//! mutation produces is recorded with a sequence number and the time it was
+//! reached, so a root reported to someone can later be matched to its place
+//! in the tree's history.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{CryptoBinaryTree, TreeKey};
+
+/// One root in a [`RootHistory`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootRecord {
+    /// Position in the history, starting at 0 for the root at enable time
+    pub seq: u64,
+    pub root: String,
+    /// Unix timestamp of when the root was reached, never decreasing
+    pub timestamp: u64,
+}
+
+/// Every Merkle root of a tree since its history was enabled, oldest first
+///
+/// A mutation that leaves the root unchanged records nothing, and with lazy
+/// hashing a root is recorded when `flush_hashes` computes it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootHistory {
+    records: Vec<RootRecord>,
+}
+
+impl RootHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `root` unless it is pending or the latest root already.
+    pub(crate) fn record(&mut self, root: &str) {
+        let last = self.records.last();
+        if root.is_empty() || last.is_some_and(|r| r.root == root) {
+            return;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        self.records.push(RootRecord {
+            seq: last.map_or(0, |r| r.seq + 1),
+            root: root.to_string(),
+            // A clock stepped back must not unsort the history
+            timestamp: last.map_or(now, |r| r.timestamp.max(now)),
+        });
+    }
+
+    /// The root with sequence number `seq`
+    pub fn root_at(&self, seq: u64) -> Option<&str> {
+        let first = self.records.first()?.seq;
+        let i = usize::try_from(seq.checked_sub(first)?).ok()?;
+        self.records.get(i).map(|r| r.root.as_str())
+    }
+
+    /// The roots reached within `start..=end`, in Unix seconds, oldest first
+    pub fn roots_between(&self, start: u64, end: u64) -> &[RootRecord] {
+        let from = self.records.partition_point(|r| r.timestamp < start);
+        let to = self.records.partition_point(|r| r.timestamp <= end).max(from);
+        &self.records[from..to]
+    }
+
+    /// Sequence number of the latest record with this root. A root can recur,
+    /// e.g. after an insert is undone by a removal.
+    pub fn seq_of(&self, root: &str) -> Option<u64> {
+        self.records.iter().rev().find(|r| r.root == root).map(|r| r.seq)
+    }
+
+    pub fn latest(&self) -> Option<&RootRecord> {
+        self.records.last()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &RootRecord> + '_ {
+        self.records.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+impl<T: TreeKey, H> CryptoBinaryTree<T, H> {
+    /// Starts recording every root this tree reaches, beginning with the
+    /// current one as sequence number 0. Does nothing if already enabled.
+    ///
+    /// The history is cloned with the tree but not kept in snapshots; save it
+    /// separately, it serializes with serde.
+    pub fn enable_root_history(&mut self) {
+        if self.history.is_none() {
+            let mut history = RootHistory::new();
+            history.record(&self.merkle_root);
+            self.history = Some(history);
+        }
+    }
+
+    /// The recorded roots, `None` unless `enable_root_history` was called
+    pub fn root_history(&self) -> Option<&RootHistory> {
+        self.history.as_ref()
+    }
+
+    /// Stops recording roots and returns the history so far.
+    pub fn take_root_history(&mut self) -> Option<RootHistory> {
+        self.history.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CryptoBinaryTree;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_records_every_root() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.insert(sample_tx("tx_a", 1));
+        tree.enable_root_history();
+        let mut roots = vec![tree.merkle_root().to_string()];
+        tree.insert(sample_tx("tx_b", 2));
+        roots.push(tree.merkle_root().to_string());
+        assert!(!tree.insert(sample_tx("tx_b", 3)));
+        tree.update("tx_a", |tx| tx.amount = 10).unwrap();
+        roots.push(tree.merkle_root().to_string());
+        tree.remove("tx_b");
+        roots.push(tree.merkle_root().to_string());
+
+        let history = tree.root_history().unwrap();
+        assert_eq!(history.len(), 4, "a rejected insert records nothing");
+        for (seq, root) in roots.iter().enumerate() {
+            assert_eq!(history.root_at(seq as u64), Some(root.as_str()));
+        }
+        assert_eq!(history.root_at(4), None);
+        assert_eq!(history.seq_of(&roots[1]), Some(1));
+        assert_eq!(history.roots_between(0, u64::MAX).len(), 4);
+        assert!(history.roots_between(0, 1).is_empty());
+        let now = history.latest().unwrap().timestamp;
+        assert_eq!(history.roots_between(now, now).last().unwrap().root, roots[3]);
+    }
+
+    #[test]
+    fn test_lazy_hashing_records_on_flush() {
+        let mut tree: CryptoBinaryTree = CryptoBinaryTree::new();
+        tree.enable_root_history();
+        tree.set_lazy_hashing(true);
+        for i in 0..10 {
+            tree.insert(sample_tx(&format!("tx_{}", i), 1));
+        }
+        let root = tree.flush_hashes().to_string();
+        let history = tree.root_history().unwrap();
+        assert!(history.len() < 10 && history.iter().all(|r| !r.root.is_empty()));
+        assert_eq!(history.latest().unwrap().root, root);
+    }
+}
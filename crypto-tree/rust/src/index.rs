@@ -0,0 +1,575 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::prelude::*;
+use crate::{key_string, CryptoBinaryTree, CryptoTreeError, Result, Transaction, TraversalOrder, TreeHasher, TreeKey};
+
+/// Payloads that move value between two addresses.
+///
+/// Implementing this unlocks the secondary-index queries on
+/// `CryptoBinaryTree`, such as `transactions_from` and `range_by_time`.
+pub trait LedgerEntry: TreeKey {
+    fn sender(&self) -> &str;
+    fn recipient(&self) -> &str;
+
+    /// Value moved from `sender` to `recipient`
+    fn amount(&self) -> u128;
+
+    /// Unix timestamp of the entry, if it carries one
+    fn timestamp(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl LedgerEntry for Transaction {
+    fn sender(&self) -> &str {
+        &self.from
+    }
+
+    fn recipient(&self) -> &str {
+        &self.to
+    }
+
+    fn amount(&self) -> u128 {
+        self.amount
+    }
+
+    fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+}
+
+/// Opt-in ledger constraints enforced when transactions are added.
+///
+/// With rules installed, a transaction whose amount exceeds its sender's
+/// running balance is rejected with `InsufficientBalance`, unless the sender
+/// is exempt, e.g. a mint or genesis address that creates value.
+#[derive(Debug, Clone, Default)]
+pub struct LedgerRules {
+    exempt: BTreeSet<String>,
+}
+
+impl LedgerRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows `address` to send more than it holds.
+    pub fn with_exempt(mut self, address: impl Into<String>) -> Self {
+        self.exempt.insert(address.into());
+        self
+    }
+
+    /// Returns `true` if `address` may overdraw.
+    pub fn is_exempt(&self, address: &str) -> bool {
+        self.exempt.contains(address)
+    }
+}
+
+/// Owned copy of the indexed fields of one payload
+#[derive(Debug)]
+pub(crate) struct IndexEntry<K> {
+    id: K,
+    sender: String,
+    recipient: String,
+    amount: u128,
+    timestamp: Option<u64>,
+}
+
+impl<K> IndexEntry<K> {
+    fn of<T: LedgerEntry<Key = K>>(transaction: &T) -> Self
+    where
+        K: Clone,
+    {
+        Self {
+            id: transaction.key().clone(),
+            sender: transaction.sender().to_string(),
+            recipient: transaction.recipient().to_string(),
+            amount: transaction.amount(),
+            timestamp: transaction.timestamp(),
+        }
+    }
+}
+
+/// Lookup tables from addresses and timestamps to the ids stored in the tree,
+/// plus the running balance of every address.
+///
+/// The index is derived data: it is not part of any node hash, so it has no
+/// effect on the Merkle root, and it is not serialized with the tree.
+#[derive(Debug, Clone)]
+pub(crate) struct SecondaryIndex<T: TreeKey> {
+    // Captured when the index is enabled, so the generic insert/remove paths
+    // can maintain it without a `LedgerEntry` bound
+    view: fn(&T) -> IndexEntry<T::Key>,
+    by_sender: BTreeMap<String, BTreeSet<T::Key>>,
+    by_recipient: BTreeMap<String, BTreeSet<T::Key>>,
+    // Entries without a timestamp are not indexed by time
+    by_time: BTreeMap<u64, BTreeSet<T::Key>>,
+    // Zero balances are dropped to keep the map to active addresses
+    balances: BTreeMap<String, i128>,
+    rules: Option<LedgerRules>,
+}
+
+impl<T: TreeKey> SecondaryIndex<T> {
+    fn new() -> Self
+    where
+        T: LedgerEntry,
+    {
+        Self {
+            view: IndexEntry::of::<T>,
+            by_sender: BTreeMap::new(),
+            by_recipient: BTreeMap::new(),
+            by_time: BTreeMap::new(),
+            balances: BTreeMap::new(),
+            rules: None,
+        }
+    }
+
+    pub(crate) fn entry(&self, transaction: &T) -> IndexEntry<T::Key> {
+        (self.view)(transaction)
+    }
+
+    /// Checks `transaction` against the ledger rules and the balance range,
+    /// returning its entry if it may be added.
+    pub(crate) fn admit(&self, transaction: &T) -> Result<IndexEntry<T::Key>> {
+        self._admit_replacing(transaction, None)
+    }
+
+    /// Checks `new` as `replace(old, new)` would, without changing the index.
+    #[cfg(feature = "std")]
+    pub(crate) fn check_replace(&self, old: &T, new: &T) -> Result<()> {
+        self._admit_replacing(new, Some(&self.entry(old))).map(drop)
+    }
+
+    /// `admit` against the balances as they would be with `released` removed
+    fn _admit_replacing(&self, transaction: &T, released: Option<&IndexEntry<T::Key>>) -> Result<IndexEntry<T::Key>> {
+        let balance = |address: &str| match released {
+            Some(old) => {
+                let amount = saturate(old.amount);
+                let mut balance = self.balance(address);
+                if old.sender == address {
+                    balance = balance.saturating_add(amount);
+                }
+                if old.recipient == address {
+                    balance = balance.saturating_sub(amount);
+                }
+                balance
+            }
+            None => self.balance(address),
+        };
+        let entry = self.entry(transaction);
+        let sender_balance = balance(&entry.sender);
+        if let Some(rules) = &self.rules {
+            let covered = i128::try_from(entry.amount).is_ok_and(|amount| sender_balance >= amount);
+            if !covered && !rules.is_exempt(&entry.sender) {
+                return Err(CryptoTreeError::InsufficientBalance {
+                    id: key_string(&entry.id),
+                    sender: entry.sender,
+                    balance: sender_balance,
+                    amount: entry.amount,
+                });
+            }
+        }
+
+        let amount = i128::try_from(entry.amount).ok();
+        if amount.and_then(|a| sender_balance.checked_sub(a)).is_none() {
+            return Err(CryptoTreeError::BalanceOverflow {
+                id: key_string(&entry.id),
+                address: entry.sender,
+            });
+        }
+        let recipient_balance = balance(&entry.recipient);
+        if amount.and_then(|a| recipient_balance.checked_add(a)).is_none() {
+            return Err(CryptoTreeError::BalanceOverflow {
+                id: key_string(&entry.id),
+                address: entry.recipient,
+            });
+        }
+        Ok(entry)
+    }
+
+    fn balance(&self, address: &str) -> i128 {
+        self.balances.get(address).copied().unwrap_or(0)
+    }
+
+    /// Swaps the entry of `old` for that of `new`, leaving the index untouched if the rules reject `new`.
+    pub(crate) fn replace(&mut self, old: &T, new: &T) -> Result<()> {
+        self.remove(old);
+        match self.admit(new) {
+            Ok(entry) => {
+                self.add(entry);
+                Ok(())
+            }
+            Err(e) => {
+                self.add(self.entry(old));
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) fn add(&mut self, entry: IndexEntry<T::Key>) {
+        let amount = saturate(entry.amount);
+        self.credit(&entry.sender, -amount);
+        self.credit(&entry.recipient, amount);
+        if let Some(timestamp) = entry.timestamp {
+            self.by_time.entry(timestamp).or_default().insert(entry.id.clone());
+        }
+        self.by_sender.entry(entry.sender).or_default().insert(entry.id.clone());
+        self.by_recipient.entry(entry.recipient).or_default().insert(entry.id);
+    }
+
+    pub(crate) fn remove(&mut self, transaction: &T) {
+        let entry = self.entry(transaction);
+        let amount = saturate(entry.amount);
+        self.credit(&entry.sender, amount);
+        self.credit(&entry.recipient, -amount);
+        detach(&mut self.by_sender, &entry.sender, &entry.id);
+        detach(&mut self.by_recipient, &entry.recipient, &entry.id);
+        if let Some(timestamp) = entry.timestamp {
+            if let Some(ids) = self.by_time.get_mut(&timestamp) {
+                ids.remove(&entry.id);
+                if ids.is_empty() {
+                    self.by_time.remove(&timestamp);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.by_sender.clear();
+        self.by_recipient.clear();
+        self.by_time.clear();
+        self.balances.clear();
+    }
+
+    // Saturates rather than wraps; `admit` keeps checked inserts in range, so
+    // this only matters for out-of-range data that predates the index
+    fn credit(&mut self, address: &str, delta: i128) {
+        let balance = self.balances.entry(address.to_string()).or_default();
+        *balance = balance.saturating_add(delta);
+        if *balance == 0 {
+            self.balances.remove(address);
+        }
+    }
+}
+
+fn saturate(amount: u128) -> i128 {
+    i128::try_from(amount).unwrap_or(i128::MAX)
+}
+
+fn detach<K: Ord>(map: &mut BTreeMap<String, BTreeSet<K>>, address: &str, id: &K) {
+    if let Some(ids) = map.get_mut(address) {
+        ids.remove(id);
+        if ids.is_empty() {
+            map.remove(address);
+        }
+    }
+}
+
+impl<T: TreeKey, H> CryptoBinaryTree<T, H> {
+    /// Rebuilds the index, if enabled, after the nodes were replaced wholesale.
+    pub(crate) fn _reindex(&mut self) {
+        if let Some(mut index) = self.index.take() {
+            index.clear();
+            for n in self.nodes.iter() {
+                index.add(index.entry(&n.transaction));
+            }
+            self.index = Some(index);
+        }
+    }
+}
+
+impl<T: LedgerEntry + Serialize + Clone, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Builds the secondary address and time indexes and the balance table, and keeps it up to date from now on.
+    ///
+    /// The index is excluded from the Merkle hash and is not persisted, so it
+    /// must be enabled again after loading a tree. Does nothing if already enabled.
+    pub fn enable_index(&mut self) {
+        if self.index.is_some() {
+            return;
+        }
+        let mut index = SecondaryIndex::new();
+        self.traverse(TraversalOrder::InOrder, |n| index.add(index.entry(&n.transaction)));
+        self.index = Some(index);
+    }
+
+    /// Enforces `rules` on every later insert and update, enabling the index if needed.
+    ///
+    /// Transactions already stored are not re-checked, and removals are never
+    /// rejected even if they leave a balance negative.
+    pub fn set_ledger_rules(&mut self, rules: LedgerRules) {
+        self.enable_index();
+        if let Some(index) = self.index.as_mut() {
+            index.rules = Some(rules);
+        }
+    }
+
+    /// Returns `true` if the secondary index is maintained.
+    pub fn has_index(&self) -> bool {
+        self.index.is_some()
+    }
+
+    /// Returns the transactions sent by `address`, in id order.
+    ///
+    /// Uses the secondary index when enabled and falls back to a full scan otherwise.
+    pub fn transactions_from(&self, address: &str) -> Vec<&T> {
+        match &self.index {
+            Some(index) => self.lookup(index.by_sender.get(address)),
+            None => self.scan(|tx| tx.sender() == address),
+        }
+    }
+
+    /// Returns the transactions received by `address`, in id order.
+    ///
+    /// Uses the secondary index when enabled and falls back to a full scan otherwise.
+    pub fn transactions_to(&self, address: &str) -> Vec<&T> {
+        match &self.index {
+            Some(index) => self.lookup(index.by_recipient.get(address)),
+            None => self.scan(|tx| tx.recipient() == address),
+        }
+    }
+
+    /// Returns the transactions timestamped within `start..=end`, ordered by
+    /// timestamp and then id.
+    ///
+    /// Transactions without a timestamp are never returned. Uses the secondary
+    /// index when enabled and falls back to a full scan otherwise.
+    pub fn range_by_time(&self, start: u64, end: u64) -> Vec<&T> {
+        if start > end {
+            return Vec::new();
+        }
+        match &self.index {
+            Some(index) => index
+                .by_time
+                .range(start..=end)
+                .flat_map(|(_, ids)| ids)
+                .map(|id| self.search(id).expect("indexed ids are stored in the tree"))
+                .collect(),
+            None => {
+                let mut found = self.scan(|tx| tx.timestamp().is_some_and(|t| (start..=end).contains(&t)));
+                // The scan yields id order; a stable sort keeps it within equal timestamps
+                found.sort_by_key(|tx| tx.timestamp());
+                found
+            }
+        }
+    }
+
+    /// Returns everything received by `address` minus everything it sent.
+    ///
+    /// Maintained incrementally while the index is enabled, so this is a
+    /// single lookup; without the index every transaction is scanned. While
+    /// indexed, inserts that would take a balance outside `i128` are rejected
+    /// with `BalanceOverflow`; an unindexed scan saturates instead.
+    pub fn balance(&self, address: &str) -> i128 {
+        match &self.index {
+            Some(index) => index.balance(address),
+            None => {
+                let mut balance: i128 = 0;
+                self.traverse(TraversalOrder::InOrder, |n| {
+                    let tx = &n.transaction;
+                    if tx.sender() == address {
+                        balance = balance.saturating_sub(saturate(tx.amount()));
+                    }
+                    if tx.recipient() == address {
+                        balance = balance.saturating_add(saturate(tx.amount()));
+                    }
+                });
+                balance
+            }
+        }
+    }
+
+    fn lookup(&self, ids: Option<&BTreeSet<T::Key>>) -> Vec<&T> {
+        ids.into_iter()
+            .flatten()
+            .map(|id| self.search(id).expect("indexed ids are stored in the tree"))
+            .collect()
+    }
+
+    fn scan(&self, mut keep: impl FnMut(&T) -> bool) -> Vec<&T> {
+        self._in_order()
+            .into_iter()
+            .map(|id| &self.nodes[id].transaction)
+            .filter(|tx| keep(tx))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CryptoBinaryTree, Transaction};
+
+    fn transfer(id: &str, from: &str, to: &str) -> Transaction {
+        Transaction {
+            id: id.to_string(),
+            from: from.into(),
+            to: to.into(),
+            amount: 1,
+            timestamp: None,
+            ..Default::default()
+        }
+    }
+
+    fn ids(found: Vec<&Transaction>) -> Vec<&str> {
+        found.into_iter().map(|tx| tx.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_index_tracks_mutations() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.insert(transfer("tx_1", "Alice", "Bob"));
+        tree.insert(transfer("tx_2", "Bob", "Carol"));
+        let root = tree.merkle_root().to_string();
+
+        tree.enable_index();
+        assert_eq!(tree.merkle_root(), root);
+        tree.insert(transfer("tx_3", "Alice", "Carol"));
+        tree.insert_batch(vec![transfer("tx_4", "Carol", "Alice"), transfer("tx_1", "Dave", "Dave")]);
+        assert_eq!(ids(tree.transactions_from("Alice")), ["tx_1", "tx_3"]);
+        assert_eq!(ids(tree.transactions_to("Carol")), ["tx_2", "tx_3"]);
+        assert!(tree.transactions_from("Dave").is_empty());
+
+        tree.remove("tx_1");
+        tree.update("tx_3", |tx| tx.from = "Eve".into()).unwrap();
+        assert!(tree.update("tx_2", |tx| tx.id = "tx_9".to_string()).is_err());
+        assert_eq!(ids(tree.transactions_from("Alice")), Vec::<&str>::new());
+        assert_eq!(ids(tree.transactions_from("Eve")), ["tx_3"]);
+        assert_eq!(ids(tree.transactions_from("Bob")), ["tx_2"]);
+
+        tree.retain(|tx| tx.to != "Carol");
+        assert_eq!(ids(tree.transactions_to("Alice")), ["tx_4"]);
+        assert!(tree.transactions_to("Carol").is_empty());
+
+        tree.clear();
+        assert!(tree.transactions_to("Alice").is_empty());
+    }
+
+    #[test]
+    fn test_unindexed_queries_scan() {
+        let mut indexed = CryptoBinaryTree::new();
+        for i in 0..40 {
+            let from = if i % 3 == 0 { "Alice" } else { "Bob" };
+            indexed.insert(transfer(&format!("tx_{:02}", i), from, "Carol"));
+        }
+        let plain = indexed.clone();
+        indexed.enable_index();
+        assert!(indexed.has_index() && !plain.has_index());
+        assert_eq!(ids(indexed.transactions_from("Alice")), ids(plain.transactions_from("Alice")));
+        assert_eq!(indexed.transactions_to("Carol").len(), 40);
+        assert_eq!(plain.transactions_to("Carol").len(), 40);
+    }
+
+    #[test]
+    fn test_range_by_time() {
+        let mut tree = CryptoBinaryTree::new();
+        let stamps = [("tx_a", Some(30)), ("tx_b", Some(10)), ("tx_c", None), ("tx_d", Some(20)), ("tx_e", Some(20))];
+        for (id, timestamp) in stamps {
+            let mut tx = transfer(id, "Alice", "Bob");
+            tx.timestamp = timestamp;
+            tree.insert(tx);
+        }
+        let mut last = transfer("tx_f", "Alice", "Bob");
+        last.timestamp = Some(u64::MAX);
+        tree.insert(last);
+
+        let plain = tree.clone();
+        tree.enable_index();
+        for t in [&tree, &plain] {
+            assert_eq!(ids(t.range_by_time(10, 20)), ["tx_b", "tx_d", "tx_e"]);
+            assert_eq!(ids(t.range_by_time(20, 20)), ["tx_d", "tx_e"]);
+            assert_eq!(ids(t.range_by_time(0, u64::MAX)), ["tx_b", "tx_d", "tx_e", "tx_a", "tx_f"]);
+            assert!(t.range_by_time(31, 40).is_empty());
+            assert!(t.range_by_time(20, 10).is_empty());
+        }
+
+        tree.update("tx_b", |tx| tx.timestamp = Some(25)).unwrap();
+        tree.remove("tx_d");
+        assert_eq!(ids(tree.range_by_time(10, 30)), ["tx_e", "tx_b", "tx_a"]);
+    }
+
+    #[test]
+    fn test_balance() {
+        let mut tree = CryptoBinaryTree::new();
+        let payment = |id: &str, from: &str, to: &str, amount: u128| {
+            let mut tx = transfer(id, from, to);
+            tx.amount = amount;
+            tx
+        };
+        tree.insert(payment("tx_1", "mint", "Alice", 100));
+        tree.insert(payment("tx_2", "Alice", "Bob", 30));
+        tree.insert(payment("tx_3", "Bob", "Bob", 5));
+        let plain = tree.clone();
+        tree.enable_index();
+        for t in [&tree, &plain] {
+            assert_eq!(t.balance("Alice"), 70);
+            assert_eq!(t.balance("Bob"), 30);
+            assert_eq!(t.balance("mint"), -100);
+            assert_eq!(t.balance("Nobody"), 0);
+        }
+
+        tree.insert(payment("tx_4", "Bob", "Carol", 30));
+        tree.update("tx_2", |tx| tx.amount = 50).unwrap();
+        tree.remove("tx_1");
+        assert_eq!(tree.balance("Alice"), -50);
+        assert_eq!(tree.balance("Bob"), 20);
+        assert_eq!(tree.balance("Carol"), 30);
+        assert_eq!(tree.balance("mint"), 0);
+    }
+
+    #[test]
+    fn test_ledger_rules() {
+        use crate::{CryptoTreeError, LedgerRules};
+
+        let payment = |id: &str, from: &str, to: &str, amount: u128| {
+            let mut tx = transfer(id, from, to);
+            tx.amount = amount;
+            tx
+        };
+        let mut tree = CryptoBinaryTree::new();
+        tree.set_ledger_rules(LedgerRules::new().with_exempt("genesis"));
+        assert!(tree.has_index());
+
+        tree.try_insert(payment("tx_1", "genesis", "Alice", 50)).unwrap();
+        tree.try_insert(payment("tx_2", "Alice", "Bob", 50)).unwrap();
+        let err = tree.try_insert(payment("tx_3", "Alice", "Bob", 1)).unwrap_err();
+        assert!(matches!(
+            err,
+            CryptoTreeError::InsufficientBalance { balance: 0, amount: 1, .. }
+        ));
+        assert_eq!(tree.len(), 2);
+
+        let result = tree.insert_batch(vec![payment("tx_3", "Bob", "Carol", 20), payment("tx_4", "Carol", "Dave", 30)]);
+        assert_eq!(result.inserted, 1);
+        assert_eq!(result.failed[0].0, "tx_4");
+
+        let root = tree.merkle_root().to_string();
+        assert!(tree.update("tx_3", |tx| tx.amount = 60).is_err());
+        assert_eq!(tree.merkle_root(), root);
+        assert_eq!(tree.balance("Bob"), 30);
+        tree.update("tx_3", |tx| tx.amount = 50).unwrap();
+        assert_eq!(tree.balance("Bob"), 0);
+    }
+
+    #[test]
+    fn test_large_amounts_are_checked() {
+        use crate::CryptoTreeError;
+
+        let payment = |id: &str, amount: u128| {
+            let mut tx = transfer(id, "mint", "Alice");
+            tx.amount = amount;
+            tx
+        };
+        let mut tree = CryptoBinaryTree::new();
+        tree.enable_index();
+        tree.try_insert(payment("tx_1", u64::MAX as u128 * 4)).unwrap();
+        assert_eq!(tree.balance("Alice"), u64::MAX as i128 * 4);
+
+        tree.try_insert(payment("tx_2", i128::MAX as u128 - u64::MAX as u128 * 4)).unwrap();
+        assert_eq!(tree.balance("Alice"), i128::MAX);
+        let err = tree.try_insert(payment("tx_3", 1)).unwrap_err();
+        assert!(matches!(err, CryptoTreeError::BalanceOverflow { ref address, .. } if address == "Alice"));
+        assert!(tree.try_insert(payment("tx_4", u128::MAX)).is_err());
+        assert_eq!(tree.len(), 2);
+    }
+}
@@ -0,0 +1,125 @@
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::{key_string, CryptoBinaryTree, NodeId, TreeEvent, TreeHasher, TreeKey};
+
+/// A node whose stored hash differs from the one recomputed from its contents
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashMismatch {
+    /// Id of the node's transaction
+    pub id: String,
+    /// Hash recomputed from the transaction and the children's stored hashes,
+    /// `None` if the transaction cannot be encoded
+    pub expected: Option<String>,
+    /// Hash stored in the node
+    pub found: String,
+    /// Distance from the root, which is at depth 0
+    pub depth: usize,
+}
+
+/// Outcome of rehashing every node of a tree
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    /// Number of nodes rehashed
+    pub checked: usize,
+    /// Every mismatching node, in pre-order
+    pub mismatches: Vec<HashMismatch>,
+}
+
+impl IntegrityReport {
+    /// Returns `true` if every stored hash matched.
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    /// The mismatch closest to the root, e.g. the subtree to rebuild first
+    pub fn shallowest(&self) -> Option<&HashMismatch> {
+        self.mismatches.iter().min_by_key(|m| m.depth)
+    }
+}
+
+impl fmt::Display for IntegrityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} nodes checked, {} corrupted", self.checked, self.mismatches.len())?;
+        for m in &self.mismatches {
+            let expected = m.expected.as_deref().unwrap_or("<unencodable>");
+            write!(f, "\n  {} at depth {}: expected {}, found {}", m.id, m.depth, expected, m.found)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Recomputes every node hash and lists all nodes whose stored hash differs.
+    ///
+    /// Unlike [`check_integrity`](Self::check_integrity) it does not stop at
+    /// the first corrupted node. Each node is checked against its children's
+    /// stored hashes, so a tampered node is reported on its own rather than
+    /// with all of its ancestors.
+    pub fn verify_integrity_report(&self) -> IntegrityReport {
+        self._assert_hashed();
+        let mut report = IntegrityReport::default();
+        let mut stack: Vec<(NodeId, usize)> = self.root.map(|id| (id, 0)).into_iter().collect();
+        while let Some((id, depth)) = stack.pop() {
+            let n = &self.nodes[id];
+            let (left_hash, right_hash) = (self.nodes.hash(n.left), self.nodes.hash(n.right));
+            let expected =
+                crate::encoding::hash_node(&self.hasher, &n.transaction, left_hash, right_hash, n.height, n.size).ok();
+            if expected.as_ref() != Some(&n.hash) {
+                report.mismatches.push(HashMismatch {
+                    id: key_string(n.transaction.key()),
+                    expected,
+                    found: n.hash.clone(),
+                    depth,
+                });
+            }
+            report.checked += 1;
+            stack.extend(n.right.map(|c| (c, depth + 1)));
+            stack.extend(n.left.map(|c| (c, depth + 1)));
+        }
+        if let Some(observer) = self._observer() {
+            observer.on_event(&TreeEvent::IntegrityChecked {
+                nodes: report.checked,
+                corrupted: report.mismatches.first().map(|m| m.id.as_str()),
+            });
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CryptoBinaryTree;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_report_lists_every_mismatch() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 1..=31 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 10));
+        }
+        let report = tree.verify_integrity_report();
+        assert!(report.is_ok());
+        assert_eq!(report.checked, 31);
+
+        let root = tree.root.unwrap();
+        let node = tree.nodes[tree.nodes[root].left.unwrap()].left.unwrap();
+        let stored = tree.nodes[root].hash.clone();
+        tree.nodes[root].transaction.amount = 11;
+        tree.nodes[node].hash = "00".repeat(32);
+
+        let report = tree.verify_integrity_report();
+        assert!(!report.is_ok() && !tree.verify_integrity());
+        assert_eq!(report.checked, 31);
+        let ids: Vec<_> = report.mismatches.iter().map(|m| (m.id.as_str(), m.depth)).collect();
+        let node_id = tree.nodes[node].transaction.id.clone();
+        // The parent now disagrees with the tampered stored hash as well
+        let parent_id = tree.nodes[tree.nodes[root].left.unwrap()].transaction.id.clone();
+        assert_eq!(ids, [("tx_016", 0), (parent_id.as_str(), 1), (node_id.as_str(), 2)]);
+        assert_eq!(report.mismatches[0].found, stored);
+        assert_eq!(report.shallowest().unwrap().id, "tx_016");
+        assert!(report.to_string().starts_with("31 nodes checked, 3 corrupted\n  tx_016 at depth 0"));
+    }
+}
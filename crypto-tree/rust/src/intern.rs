@@ -0,0 +1,179 @@
+//! Shared storage for repeated strings such as addresses.
+//!
+//! The same sender and recipient appear on many transactions. An [`Address`]
+//! is a reference-counted string, and each tree keeps an [`Interner`] that
+//! hands out one shared copy per distinct value as payloads come in.
+//! Interning never changes what is serialized or hashed: an `Address`
+//! encodes exactly like the `String` it replaces.
+
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use core::borrow::Borrow;
+use core::fmt;
+use core::ops::Deref;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::prelude::*;
+
+/// An immutable, cheaply cloned string, e.g. a sender or recipient address
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Address(Arc<str>);
+
+impl Address {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns `true` if both addresses share one allocation.
+    pub fn ptr_eq(&self, other: &Address) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Deref for Address {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Address {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Address {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Address {
+    fn from(s: &str) -> Self {
+        Address(Arc::from(s))
+    }
+}
+
+impl From<String> for Address {
+    fn from(s: String) -> Self {
+        Address(Arc::from(s))
+    }
+}
+
+impl From<Address> for String {
+    fn from(address: Address) -> Self {
+        address.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for Address {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Address {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Address {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl fmt::Debug for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Address::from)
+    }
+}
+
+/// Pool of the distinct addresses held by one tree
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    pool: BTreeSet<Address>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the pooled copy of `address`, adding it if it is new.
+    pub fn intern(&mut self, address: &Address) -> Address {
+        if let Some(pooled) = self.pool.get(address.as_str()) {
+            return pooled.clone();
+        }
+        self.pool.insert(address.clone());
+        address.clone()
+    }
+
+    /// Number of distinct addresses pooled
+    pub fn len(&self) -> usize {
+        self.pool.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pool.is_empty()
+    }
+
+    /// Drops the addresses nothing but the pool refers to any more.
+    pub fn purge(&mut self) {
+        self.pool.retain(|address| Arc::strong_count(&address.0) > 1);
+    }
+
+    pub fn clear(&mut self) {
+        self.pool.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_shares_storage() {
+        let mut interner = Interner::new();
+        let a = interner.intern(&Address::from("Alice"));
+        let b = interner.intern(&Address::from("Alice".to_string()));
+        assert!(a.ptr_eq(&b));
+        assert_eq!(interner.len(), 1);
+
+        drop((a, b));
+        interner.intern(&Address::from("Bob"));
+        let bob = interner.intern(&Address::from("Bob"));
+        interner.purge();
+        assert_eq!(interner.len(), 1);
+        assert_eq!(bob, "Bob");
+    }
+
+    #[test]
+    fn test_serializes_as_string() {
+        let address = Address::from("Alice");
+        assert_eq!(serde_json::to_string(&address).unwrap(), "\"Alice\"");
+        assert_eq!(serde_json::from_str::<Address>("\"Alice\"").unwrap(), address);
+    }
+}
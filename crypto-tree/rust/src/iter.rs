@@ -0,0 +1,199 @@
+use alloc::collections::VecDeque;
+
+use crate::prelude::*;
+use crate::{CryptoBinaryTree, CryptoTreeNode, NodeId, TreeKey};
+
+/// Order in which `CryptoBinaryTree::traverse` visits nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraversalOrder {
+    /// Node, then left subtree, then right subtree
+    PreOrder,
+    /// Left subtree, node, right subtree: ascending id order
+    InOrder,
+    /// Left subtree, right subtree, then node; children before parents
+    PostOrder,
+    /// Breadth-first, top level first and left to right within a level
+    LevelOrder,
+}
+
+impl<T: TreeKey, H> CryptoBinaryTree<T, H> {
+    /// Calls `visitor` on every node in the given order.
+    ///
+    /// The visitor sees the whole node, including its hash and height. All
+    /// orders are iterative, so deep trees never exhaust the call stack.
+    pub fn traverse(&self, order: TraversalOrder, mut visitor: impl FnMut(&CryptoTreeNode<T>)) {
+        let Some(root) = self.root else { return };
+        let nodes = &self.nodes;
+        match order {
+            TraversalOrder::PreOrder => {
+                let mut stack = vec![root];
+                while let Some(id) = stack.pop() {
+                    let n = &nodes[id];
+                    visitor(n);
+                    stack.extend(n.right);
+                    stack.extend(n.left);
+                }
+            }
+            TraversalOrder::InOrder => {
+                for id in self._in_order() {
+                    visitor(&nodes[id]);
+                }
+            }
+            TraversalOrder::PostOrder => {
+                // Reverse of a node-right-left pre-order walk
+                let mut stack = vec![root];
+                let mut output = Vec::with_capacity(nodes.len());
+                while let Some(id) = stack.pop() {
+                    let n = &nodes[id];
+                    output.push(n);
+                    stack.extend(n.left);
+                    stack.extend(n.right);
+                }
+                output.into_iter().rev().for_each(visitor);
+            }
+            TraversalOrder::LevelOrder => {
+                let mut queue = VecDeque::from([root]);
+                while let Some(id) = queue.pop_front() {
+                    let n = &nodes[id];
+                    visitor(n);
+                    queue.extend(n.left);
+                    queue.extend(n.right);
+                }
+            }
+        }
+    }
+
+    /// The transactions in ascending key order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &T> + '_ {
+        self._in_order().into_iter().map(|id| &self.nodes[id].transaction)
+    }
+
+    /// Node ids in ascending key order.
+    pub(crate) fn _in_order(&self) -> Vec<NodeId> {
+        let mut ids = Vec::with_capacity(self.nodes.len());
+        let mut stack = Vec::new();
+        let mut current = self.root;
+        loop {
+            while let Some(id) = current {
+                stack.push(id);
+                current = self.nodes[id].left;
+            }
+            let Some(id) = stack.pop() else { break };
+            ids.push(id);
+            current = self.nodes[id].right;
+        }
+        ids
+    }
+}
+
+/// Owning in-order iterator over the transactions of a tree.
+///
+/// Created by `CryptoBinaryTree::into_iter`. The arena is reordered by key
+/// in place and then drained, so no payload is ever cloned.
+#[derive(Debug)]
+pub struct IntoIter<T> {
+    nodes: alloc::vec::IntoIter<CryptoTreeNode<T>>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.nodes.next().map(|n| n.transaction)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> core::iter::FusedIterator for IntoIter<T> {}
+
+impl<T: TreeKey + Clone, H> IntoIterator for CryptoBinaryTree<T, H> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    /// Consumes the tree, yielding its transactions in ascending id order.
+    fn into_iter(self) -> IntoIter<T> {
+        // rank[i] is the key-order position of the node in slot i
+        let mut rank = vec![0; self.nodes.len()];
+        for (position, id) in self._in_order().into_iter().enumerate() {
+            rank[id.index()] = position;
+        }
+        let mut nodes = self.nodes.into_vec();
+        for slot in 0..nodes.len() {
+            while rank[slot] != slot {
+                let target = rank[slot];
+                nodes.swap(slot, target);
+                rank.swap(slot, target);
+            }
+        }
+        IntoIter { nodes: nodes.into_iter() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TraversalOrder;
+    use crate::CryptoBinaryTree;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_into_iter_yields_key_order() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in [5, 3, 8, 1, 4, 7, 9, 2, 6] {
+            tree.insert(sample_tx(&format!("tx_{}", i), 1));
+        }
+        let iter = tree.into_iter();
+        assert_eq!(iter.len(), 9);
+        let ids: Vec<String> = iter.map(|tx| tx.id).collect();
+        let expected: Vec<String> = (1..=9).map(|i| format!("tx_{}", i)).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_into_iter_partial_and_empty() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 0..100 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        let mut iter = tree.into_iter();
+        assert_eq!(iter.next().unwrap().id, "tx_000");
+        assert_eq!(iter.len(), 99);
+        drop(iter);
+
+        let empty = crate::TransactionTree::new();
+        assert_eq!(empty.into_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_traverse_orders() {
+        //        tx_4
+        //      /      \
+        //   tx_2      tx_6
+        //   /  \     /  \
+        // tx_1 tx_3 tx_5 tx_7
+        let ids: Vec<_> = (1..=7).map(|i| sample_tx(&format!("tx_{}", i), 1)).collect();
+        let tree = CryptoBinaryTree::from_sorted(ids).unwrap();
+
+        let visit = |order| {
+            let mut seen = Vec::new();
+            tree.traverse(order, |n| seen.push(n.transaction.id[3..].parse::<u32>().unwrap()));
+            seen
+        };
+        assert_eq!(visit(TraversalOrder::PreOrder), [4, 2, 1, 3, 6, 5, 7]);
+        assert_eq!(visit(TraversalOrder::InOrder), [1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(visit(TraversalOrder::PostOrder), [1, 3, 2, 5, 7, 6, 4]);
+        assert_eq!(visit(TraversalOrder::LevelOrder), [4, 2, 6, 1, 3, 5, 7]);
+
+        let mut heights = Vec::new();
+        tree.traverse(TraversalOrder::LevelOrder, |n| heights.push(n.height));
+        assert_eq!(heights, [3, 2, 2, 1, 1, 1, 1]);
+
+        let mut root_hash = None;
+        tree.traverse(TraversalOrder::PostOrder, |n| root_hash = Some(n.hash.clone()));
+        assert_eq!(root_hash.as_deref(), Some(tree.merkle_root()));
+    }
+}
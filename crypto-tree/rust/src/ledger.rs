@@ -0,0 +1,175 @@
+//! Blocks of transactions chained by hash.
+//!
+//! A [`Ledger`] collects inserts into a pending batch and
+//! [`commit_block`](Ledger::commit_block) seals it into a [`BlockHeader`]
+//! that commits to the tree's Merkle root at that point and to the hash of
+//! the previous header. Anyone holding the headers can check that no block
+//! was altered, dropped or reordered, and a transaction's inclusion proof
+//! against a block's root shows it was in the ledger by then.
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::{encode_canonical, CryptoBinaryTree, Result, Sha256Hasher, Transaction, TreeHasher, TreeKey};
+
+/// Header of a sealed block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    /// Position in the chain, 0 for the first block
+    pub height: u64,
+    /// Hash of the previous header, `"0"` for the first block
+    pub prev_block_hash: String,
+    /// Merkle root of the tree once the block's transactions were inserted
+    pub merkle_root: String,
+    /// Transactions inserted since the previous block
+    pub tx_count: u64,
+    /// Unix timestamp the block was sealed at
+    pub timestamp: u64,
+}
+
+impl BlockHeader {
+    /// Hashes the canonical encoding of the header with `hasher`.
+    pub fn hash<H: TreeHasher>(&self, hasher: &H) -> String {
+        hasher.hash(&encode_canonical(self).expect("block headers always encode"))
+    }
+}
+
+/// A tree whose inserts are grouped into hash-chained blocks
+///
+/// Transactions can only be added, so every sealed root stays a state the
+/// tree really passed through.
+#[derive(Debug, Clone)]
+pub struct Ledger<T: TreeKey = Transaction, H = Sha256Hasher> {
+    tree: CryptoBinaryTree<T, H>,
+    blocks: Vec<BlockHeader>,
+    /// Hash of the last header, `"0"` before the first block
+    head: String,
+    pending: u64,
+}
+
+impl<T: TreeKey + Serialize + Clone> Ledger<T> {
+    /// Creates an empty ledger hashed with SHA-256.
+    pub fn new() -> Self {
+        Self::with_tree(CryptoBinaryTree::new())
+    }
+}
+
+impl<T: TreeKey + Serialize + Clone> Default for Ledger<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> Ledger<T, H> {
+    /// Starts a ledger on `tree`; its transactions go into the first block,
+    /// and its hasher also hashes the block headers.
+    pub fn with_tree(tree: CryptoBinaryTree<T, H>) -> Self {
+        Ledger {
+            pending: tree.len() as u64,
+            tree,
+            blocks: Vec::new(),
+            head: "0".to_string(),
+        }
+    }
+
+    pub fn tree(&self) -> &CryptoBinaryTree<T, H> {
+        &self.tree
+    }
+
+    /// Inserts a transaction into the pending batch, failing as
+    /// `CryptoBinaryTree::try_insert` does.
+    pub fn try_insert(&mut self, transaction: T) -> Result<()> {
+        self.tree.try_insert(transaction)?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Number of transactions inserted since the last block
+    pub fn pending(&self) -> u64 {
+        self.pending
+    }
+
+    /// The sealed blocks, oldest first
+    pub fn blocks(&self) -> &[BlockHeader] {
+        &self.blocks
+    }
+
+    /// Hash of the latest header, `"0"` before the first block
+    pub fn head_hash(&self) -> &str {
+        &self.head
+    }
+
+    /// Seals the pending batch, even an empty one, into a block stamped with
+    /// the current time.
+    #[cfg(feature = "std")]
+    pub fn commit_block(&mut self) -> &BlockHeader {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        self.commit_block_at(now)
+    }
+
+    /// Seals the pending batch into a block stamped with `timestamp`.
+    pub fn commit_block_at(&mut self, timestamp: u64) -> &BlockHeader {
+        let header = BlockHeader {
+            height: self.blocks.len() as u64,
+            prev_block_hash: self.head.clone(),
+            merkle_root: self.tree.flush_hashes().to_string(),
+            tx_count: self.pending,
+            timestamp,
+        };
+        self.head = header.hash(&self.tree.hasher);
+        self.pending = 0;
+        self.blocks.push(header);
+        &self.blocks[self.blocks.len() - 1]
+    }
+}
+
+/// Checks that `headers` form a chain from the first block, each naming the
+/// hash of the one before under `hasher`, and returns the hash of the last.
+/// Fails with the height of the first header that does not link up.
+pub fn verify_chain<H: TreeHasher>(headers: &[BlockHeader], hasher: &H) -> core::result::Result<String, u64> {
+    let mut prev = "0".to_string();
+    for (height, header) in headers.iter().enumerate() {
+        if header.height != height as u64 || header.prev_block_hash != prev {
+            return Err(height as u64);
+        }
+        prev = header.hash(hasher);
+    }
+    Ok(prev)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_blocks_chain() {
+        let mut ledger: Ledger = Ledger::new();
+        for block in 0..3 {
+            for i in 0..5 {
+                ledger.try_insert(sample_tx(&format!("tx_{}_{}", block, i), 10)).unwrap();
+            }
+            assert_eq!(ledger.pending(), 5);
+            let header = ledger.commit_block_at(1_700_000_000 + block);
+            assert_eq!((header.height, header.tx_count), (block, 5));
+        }
+        assert!(ledger.try_insert(sample_tx("tx_0_0", 1)).is_err());
+        assert_eq!(ledger.pending(), 0);
+
+        let hasher = Sha256Hasher::new();
+        let blocks = ledger.blocks();
+        assert_eq!(blocks[0].prev_block_hash, "0");
+        assert_eq!(blocks[2].merkle_root, ledger.tree().merkle_root());
+        assert_eq!(verify_chain(blocks, &hasher).unwrap(), ledger.head_hash());
+        let tx = ledger.tree().search("tx_1_3").unwrap();
+        let proof = ledger.tree().get_proof_of_inclusion("tx_1_3").unwrap();
+        assert!(verify_proof(&blocks[2].merkle_root, tx, &proof));
+
+        let mut forged = blocks.to_vec();
+        forged[1].tx_count = 4;
+        assert_eq!(verify_chain(&forged, &hasher), Err(2));
+        forged.remove(1);
+        assert_eq!(verify_chain(&forged, &hasher), Err(1));
+    }
+}
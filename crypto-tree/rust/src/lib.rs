@@ -1,363 +1,1705 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(not(feature = "lockfree"), forbid(unsafe_code))]
+
+extern crate alloc;
+
+#[cfg(feature = "tree")]
+use core::borrow::Borrow;
+#[cfg(feature = "tree")]
+use core::cmp::Ordering;
+use alloc::collections::BTreeMap;
+
 use serde::{Serialize, Deserialize};
-use sha2::{Sha256, Digest};
+
+#[cfg(feature = "tree")]
+use arena::Arena;
+#[cfg(feature = "tree")]
+use index::SecondaryIndex;
+#[cfg(feature = "tree")]
+use observe::Observer;
+#[cfg(feature = "tree")]
+use subscribe::Subscribers;
+#[cfg(feature = "tree")]
+use sync::SetDigest;
+use prelude::*;
+#[cfg(feature = "tree")]
+use validate::{Policy, Validator};
+#[cfg(feature = "tree")]
+use versions::Versions;
+
+/// `alloc` types that `std` would otherwise bring into scope
+#[cfg_attr(not(feature = "tree"), allow(unused_imports))]
+mod prelude {
+    pub(crate) use alloc::borrow::ToOwned;
+    pub(crate) use alloc::boxed::Box;
+    pub(crate) use alloc::string::{String, ToString};
+    pub(crate) use alloc::vec::Vec;
+    pub(crate) use alloc::{format, vec};
+}
+
+#[cfg(feature = "tree")]
+mod arena;
+#[cfg(feature = "tree")]
+mod audit;
+#[cfg(feature = "tree")]
+mod btree;
+#[cfg(feature = "tree")]
+mod builder;
+#[cfg(all(feature = "std", feature = "tree"))]
+mod checkpoint;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "tree")]
+mod delta;
+#[cfg(feature = "tree")]
+mod diff;
+#[cfg(all(feature = "std", feature = "tree"))]
+mod encrypt;
+mod encoding;
+mod error;
+#[cfg(feature = "tree")]
+mod frozen;
+mod hasher;
+#[cfg(all(feature = "std", feature = "tree"))]
+mod history;
+#[cfg(feature = "tree")]
+mod index;
+#[cfg(feature = "tree")]
+mod integrity;
+mod intern;
+#[cfg(feature = "tree")]
+mod iter;
+#[cfg(feature = "tree")]
+mod ledger;
+#[cfg(feature = "light")]
+pub mod light;
+#[cfg(all(feature = "std", feature = "tree"))]
+mod mapped;
+#[cfg(feature = "tree")]
+mod merge;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod mmr;
+#[cfg(feature = "tree")]
+mod multiproof;
+#[cfg(feature = "tree")]
+mod observe;
+#[cfg(feature = "tree")]
+mod partial;
+mod proof;
+#[cfg(feature = "tree")]
+mod proof_bytes;
+#[cfg(feature = "tree")]
+mod range;
+#[cfg(feature = "lockfree")]
+mod rcu;
+#[cfg(feature = "parallel")]
+mod parallel;
+#[cfg(feature = "ed25519")]
+mod signature;
+#[cfg(feature = "ed25519")]
+mod signed_root;
+#[cfg(feature = "storage-sled")]
+mod sled_store;
+#[cfg(all(feature = "std", feature = "tree"))]
+mod snapshot;
+mod sparse;
+#[cfg(feature = "tree")]
+mod state;
+#[cfg(feature = "store")]
+mod store;
+#[cfg(feature = "tree")]
+mod subscribe;
+#[cfg(feature = "tree")]
+mod sync;
+#[cfg(test)]
+mod test_util;
+#[cfg(feature = "tracing")]
+mod trace;
+#[cfg(feature = "tree")]
+mod trie;
+#[cfg(feature = "tree")]
+mod validate;
+#[cfg(feature = "tree")]
+mod versions;
+#[cfg(all(feature = "std", feature = "tree"))]
+mod wal;
+
+#[cfg(feature = "tree")]
+pub use arena::NodeId;
+#[cfg(feature = "tree")]
+pub use audit::{verify_audit_log, AuditEntry, AuditLog, AuditOp};
+#[cfg(feature = "tree")]
+pub use btree::{verify_btree_proof, verify_btree_proof_with, BTreeProofStep, MerkleBTree, DEFAULT_FANOUT};
+#[cfg(feature = "tree")]
+pub use builder::TreeBuilder;
+#[cfg(all(feature = "std", feature = "tree"))]
+pub use checkpoint::{Checkpoint, CheckpointPolicy};
+#[cfg(feature = "tree")]
+pub use delta::{DeltaNode, TreeDelta};
+#[cfg(feature = "tree")]
+pub use diff::TreeDiff;
+pub use encoding::{encode_canonical, EncodingError, HashFormat};
+#[cfg(all(feature = "std", feature = "tree"))]
+pub use encrypt::{AtRestCipher, SEALED_MAGIC};
+pub use error::{CryptoTreeError, Invariant, Result, ValidationError};
+#[cfg(feature = "tree")]
+pub use frozen::TreeSnapshot;
+#[cfg(feature = "blake3")]
+pub use hasher::Blake3Hasher;
+#[cfg(feature = "keccak")]
+pub use hasher::Keccak256Hasher;
+pub use hasher::{DigestHasher, DoubleSha256Hasher, HashAlgorithm, RuntimeHasher, Sha256Hasher, TreeHasher};
+#[cfg(all(feature = "std", feature = "tree"))]
+pub use history::{RootHistory, RootRecord};
+#[cfg(feature = "tree")]
+pub use index::{LedgerEntry, LedgerRules};
+#[cfg(feature = "metrics")]
+pub use metrics::{HistogramSnapshot, MetricsSnapshot, TreeMetrics, BUCKETS};
+#[cfg(feature = "tree")]
+pub use observe::{TreeEvent, TreeObserver};
+#[cfg(feature = "tree")]
+pub use partial::PartialTree;
+#[cfg(feature = "tree")]
+pub use integrity::{HashMismatch, IntegrityReport};
+pub use intern::{Address, Interner};
+#[cfg(feature = "tree")]
+pub use iter::{IntoIter, TraversalOrder};
+#[cfg(feature = "tree")]
+pub use ledger::{verify_chain, BlockHeader, Ledger};
+#[cfg(all(feature = "std", feature = "tree"))]
+pub use mapped::{MappedTree, MAPPED_MAGIC, MAPPED_VERSION};
+#[cfg(feature = "tree")]
+pub use merge::{MergePolicy, MergeResult};
+pub use mmr::{verify_consistency, MerkleMountainRange, MmrConsistencyProof, MmrProof};
+#[cfg(feature = "tree")]
+pub use multiproof::{
+    check_multi_proof_with, verify_multi_proof, verify_multi_proof_with, MultiProof, MultiProofEntry,
+};
+pub use proof::{
+    check_proof_with, verify_absence_proof, verify_absence_proof_with, verify_proof, verify_proof_with, AbsenceProof,
+    Proof, ProofStep, Side, MAX_TREE_HEIGHT,
+};
+#[cfg(feature = "tree")]
+pub use range::{check_range_proof_with, verify_range_proof, verify_range_proof_with, RangeProof};
+#[cfg(feature = "lockfree")]
+pub use rcu::{TreeReader, TreeWriter};
+#[cfg(feature = "ed25519")]
+pub use signature::{SigningKey, VerifyingKey};
+#[cfg(feature = "storage-sled")]
+pub use sled_store::{SledStore, SledStoreError, NODES_TREE, ROOTS_TREE};
+#[cfg(feature = "ed25519")]
+pub use signed_root::SignedRoot;
+#[cfg(all(feature = "std", feature = "tree"))]
+pub use snapshot::{SnapshotError, SNAPSHOT_MAGIC, SNAPSHOT_VERSION};
+pub use sparse::{SparseMerkleTree, SparseProof, SPARSE_DEPTH};
+#[cfg(feature = "tree")]
+pub use state::TreeState;
+#[cfg(feature = "store")]
+pub use store::{AsyncCryptoTree, MemoryStore, StoredNode, TreeStore};
+#[cfg(feature = "tree")]
+pub use subscribe::{ChangeEvent, ChangeSubscriber};
+#[cfg(feature = "tree")]
+pub use sync::{KeyRange, RangeFingerprint, SyncMessage};
+#[cfg(feature = "tracing")]
+pub use trace::{TracingObserver, TRACING_TARGET};
+#[cfg(feature = "tree")]
+pub use trie::{PatriciaTrie, TrieProof, TrieProofNode};
+#[cfg(feature = "tree")]
+pub use versions::TreeView;
+#[cfg(all(feature = "std", feature = "tree"))]
+pub use wal::{LoggedTree, WalError, WalOptions};
+
+/// Extracts the ordering key of a value stored in the tree
+pub trait TreeKey {
+    /// Type the tree is ordered by, e.g. `String`, `u64` or `[u8; 32]`
+    type Key: Ord + Clone + Serialize + core::fmt::Debug;
+
+    fn key(&self) -> &Self::Key;
+
+    /// Swaps repeated strings, such as addresses, for the tree's shared copies.
+    ///
+    /// Called on every payload the tree takes in; the default keeps the payload as is.
+    fn intern(&mut self, _interner: &mut Interner) {}
+}
+
+/// Renders a key for error messages and batch reports: strings as-is, other
+/// keys as compact JSON.
+#[cfg(feature = "tree")]
+pub(crate) fn key_string<K: Serialize + ?Sized>(key: &K) -> String {
+    match serde_json::to_value(key) {
+        Ok(serde_json::Value::String(s)) => s,
+        Ok(value) => value.to_string(),
+        Err(e) => format!("<unprintable key: {}>", e),
+    }
+}
 
 /// A transaction in the CryptoTree
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
 pub struct Transaction {
     pub id: String,
-    pub from: String,
-    pub to: String,
-    pub amount: u64,
+    pub from: Address,
+    pub to: Address,
+    /// Value transferred, in the token's smallest unit
+    #[serde(serialize_with = "serialize_amount")]
+    pub amount: u128,
     pub timestamp: Option<u64>,
+    /// Extra fields such as a fee, nonce or memo, committed into the node hash
+    /// in key order; omitted from the encoding when empty
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, serde_json::Value>,
+    /// Opaque attachment such as a document hash or contract call, committed
+    /// into the node hash; hex in JSON and omitted when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "hasher::hex_bytes")]
+    pub data: Vec<u8>,
+    /// Hex Ed25519 signature over the transaction's signing bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Hex Ed25519 public key of the signer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+/// Writes amounts that fit in a `u64` as one, so payloads and node hashes
+/// from before 128-bit amounts are reproduced exactly.
+fn serialize_amount<S: serde::Serializer>(amount: &u128, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+    match u64::try_from(*amount) {
+        Ok(amount) => serializer.serialize_u64(amount),
+        Err(_) => serializer.serialize_u128(*amount),
+    }
 }
 
+impl TreeKey for Transaction {
+    type Key = String;
+
+    fn key(&self) -> &String {
+        &self.id
+    }
+
+    fn intern(&mut self, interner: &mut Interner) {
+        self.from = interner.intern(&self.from);
+        self.to = interner.intern(&self.to);
+    }
+}
+
+impl Transaction {
+    /// Derives an id from the transaction's content.
+    ///
+    /// The id is the hex SHA-256 of the canonical encoding of every field
+    /// except `id` and `signature`, so two different payloads cannot claim the
+    /// same id and the id can still be covered by a signature made afterwards.
+    pub fn compute_id(&self) -> String {
+        let content = Transaction {
+            id: String::new(),
+            signature: None,
+            ..self.clone()
+        };
+        let bytes = encode_canonical(&content).expect("transactions always have a canonical encoding");
+        Sha256Hasher::default().hash(&bytes)
+    }
+
+    /// Returns `IdMismatch` unless `id` equals [`Transaction::compute_id`].
+    pub fn check_id(&self) -> Result<()> {
+        let expected = self.compute_id();
+        if self.id != expected {
+            return Err(CryptoTreeError::IdMismatch {
+                id: self.id.clone(),
+                expected,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tree")]
 /// A node in the AVL tree
-#[derive(Debug)]
-pub struct CryptoTreeNode {
-    pub transaction: Transaction,
-    pub left: Option<Box<CryptoTreeNode>>,
-    pub right: Option<Box<CryptoTreeNode>>,
+///
+/// Children are ids into the owning tree's arena, see [`CryptoBinaryTree::node`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CryptoTreeNode<T = Transaction> {
+    pub transaction: T,
+    pub left: Option<NodeId>,
+    pub right: Option<NodeId>,
     pub height: i32,
-    pub hash: String, // SHA-256 hex string
+    /// Number of nodes in the subtree rooted here, including this one
+    #[serde(default)]
+    pub size: usize,
+    pub hash: String, // hex digest produced by the tree's hasher
+    /// Order-independent digest of the subtree's payloads for `sync`,
+    /// computed on first use and dropped whenever the hash changes
+    #[serde(skip)]
+    pub(crate) digest: Option<SetDigest>,
 }
 
-impl CryptoTreeNode {
-    pub fn new(transaction: Transaction) -> Self {
-        let hash = Self::calculate_hash(&transaction, &None, &None, 1);
-        Self {
+#[cfg(feature = "tree")]
+impl<T: Serialize> CryptoTreeNode<T> {
+    /// Creates a leaf node hashed with SHA-256.
+    pub fn new(transaction: T) -> Result<Self> {
+        Self::with_hasher(transaction, &Sha256Hasher::default())
+    }
+
+    /// Creates a leaf node hashed with the given hasher.
+    ///
+    /// Fails if the payload cannot be encoded for hashing.
+    pub fn with_hasher<H: TreeHasher>(transaction: T, hasher: &H) -> Result<Self> {
+        let hash = encoding::hash_node(hasher, &transaction, None, None, 1, 1)?;
+        Ok(Self {
             transaction,
             left: None,
             right: None,
             height: 1,
+            size: 1,
             hash,
+            digest: None,
+        })
+    }
+
+    /// Creates a leaf whose hash is left pending (see `CryptoTreeNode::is_dirty`).
+    fn unhashed(transaction: T) -> Self {
+        Self {
+            transaction,
+            left: None,
+            right: None,
+            height: 1,
+            size: 1,
+            hash: String::new(),
+            digest: None,
         }
     }
 
-    fn calculate_hash(transaction: &Transaction, left_hash: &Option<String>, right_hash: &Option<String>, height: i32) -> String {
-        let zero_string = "0".to_string();
-        let left_hash_str = left_hash.as_ref().unwrap_or(&zero_string);
-        let right_hash_str = right_hash.as_ref().unwrap_or(&zero_string);
-        
-        let node_data = CryptoTreeNodeData {
-            transaction: transaction.clone(),
-            left_hash: left_hash_str.clone(),
-            right_hash: right_hash_str.clone(),
-            height, // Use the actual height instead of hardcoded 1
-        };
-        
-        let json_str = serde_json::to_string(&node_data).unwrap();
-        let mut hasher = Sha256::new();
-        hasher.update(json_str.as_bytes());
-        let result = hasher.finalize();
-        format!("{:x}", result)
+    /// A node is dirty while its hash is pending recomputation.
+    fn is_dirty(&self) -> bool {
+        self.hash.is_empty()
+    }
+
+    fn child_mut(&mut self, direction: Direction) -> &mut Option<NodeId> {
+        match direction {
+            Direction::Left => &mut self.left,
+            Direction::Right => &mut self.right,
+        }
     }
+}
 
-    fn update_hash(&mut self, left_hash: &Option<String>, right_hash: &Option<String>) {
-        self.hash = Self::calculate_hash(&self.transaction, left_hash, right_hash, self.height);
+#[cfg(feature = "tree")]
+impl<T: Serialize + Clone> Arena<T> {
+    /// Recomputes a node's hash from its payload, height and current children.
+    ///
+    /// Only used for nodes already in the tree: their payload was encoded
+    /// successfully when the node was created, so encoding it again cannot fail.
+    fn rehash<H: TreeHasher>(&mut self, id: NodeId, hasher: &H) {
+        let n = &self[id];
+        let hash = encoding::hash_node(hasher, &n.transaction, self.hash(n.left), self.hash(n.right), n.height, n.size)
+            .expect("payload was encodable when the node was created");
+        self[id].hash = hash;
+        self[id].digest = None;
+        self.work.hashes += 1;
     }
 
-    fn get_balance_factor(&self) -> i32 {
-        let left_height = self.left.as_ref().map_or(0, |n| n.height);
-        let right_height = self.right.as_ref().map_or(0, |n| n.height);
-        left_height - right_height
+    /// Rehashes a node, or marks it dirty when hashing is deferred (`hasher` is `None`).
+    fn refresh_hash<H: TreeHasher>(&mut self, id: NodeId, hasher: Option<&H>) {
+        match hasher {
+            Some(hasher) => self.rehash(id, hasher),
+            None => {
+                self[id].hash.clear();
+                self[id].digest = None;
+            }
+        }
     }
 
-    fn update_height(&mut self) {
-        let left_height = self.left.as_ref().map_or(0, |n| n.height);
-        let right_height = self.right.as_ref().map_or(0, |n| n.height);
-        self.height = std::cmp::max(left_height, right_height) + 1;
+    fn balance_factor(&self, id: NodeId) -> i32 {
+        self.height(self[id].left) - self.height(self[id].right)
+    }
+
+    /// Recomputes height and subtree size from the children.
+    fn update_stats(&mut self, id: NodeId) {
+        let (left, right) = (self[id].left, self[id].right);
+        let height = core::cmp::max(self.height(left), self.height(right)) + 1;
+        let size = 1 + self.size(left) + self.size(right);
+        let n = &mut self[id];
+        n.height = height;
+        n.size = size;
+    }
+}
+
+#[cfg(feature = "tree")]
+impl<T: TreeKey> CryptoTreeNode<T> {
+    /// Compares `key` with the key of the transaction stored here.
+    fn cmp_key<Q>(&self, key: &Q) -> Ordering
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        key.cmp(self.transaction.key().borrow())
     }
 }
 
-/// Data structure used for deterministic serialization
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct CryptoTreeNodeData {
-    transaction: Transaction,
-    left_hash: String,
-    right_hash: String,
-    height: i32,
+#[cfg(feature = "tree")]
+/// Position of the root of a balanced subtree over `len` sorted nodes: the
+/// middle one, rounding down. `None` for an empty subtree.
+fn balanced_root(len: usize) -> Option<usize> {
+    len.checked_sub(1).map(|n| n / 2)
 }
 
-/// The main CryptoTree structure
-#[derive(Debug)]
-pub struct CryptoBinaryTree {
-    root: Option<Box<CryptoTreeNode>>,
-    size: usize,
+#[cfg(feature = "tree")]
+/// Which child of a node a search path continues into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Direction {
+    Left,
+    Right,
+}
+
+#[cfg(feature = "tree")]
+/// The main CryptoTree structure, generic over the stored payload and the node hasher
+#[derive(Debug, Clone)]
+pub struct CryptoBinaryTree<T: TreeKey = Transaction, H = Sha256Hasher> {
+    root: Option<NodeId>,
+    nodes: Arena<T>,
     merkle_root: String,
+    hasher: H,
+    index: Option<SecondaryIndex<T>>,
+    validator: Option<Validator<T>>,
+    policies: Vec<Policy<T>>,
+    /// Shared copies of the strings repeated across payloads
+    interner: Interner,
+    /// Defer rehashing to `flush_hashes` (see `set_lazy_hashing`)
+    lazy_hashing: bool,
+    observer: Option<Observer>,
+    subscribers: Subscribers<T>,
+    /// Earlier versions kept for `view_at` (see `retain_versions`)
+    versions: Option<Versions<T>>,
+    /// Hash-chained record of mutating calls (see `enable_audit_log`)
+    audit: Option<AuditLog>,
+    #[cfg(feature = "std")]
+    history: Option<RootHistory>,
+    #[cfg(feature = "ed25519")]
+    root_signer: Option<SigningKey>,
+}
+
+#[cfg(feature = "tree")]
+impl<T: TreeKey, H> CryptoBinaryTree<T, H> {
+    /// Returns `true` while some node hashes wait for [`flush_hashes`](CryptoBinaryTree::flush_hashes).
+    pub fn has_pending_hashes(&self) -> bool {
+        self.root.is_some_and(|id| self.nodes[id].hash.is_empty())
+    }
+
+    /// Id of the root node, `None` for an empty tree
+    pub fn root_id(&self) -> Option<NodeId> {
+        self.root
+    }
+
+    /// The node with the given id, e.g. a child of another node.
+    pub fn node(&self, id: NodeId) -> Option<&CryptoTreeNode<T>> {
+        self.nodes.get(id)
+    }
+
+    /// Panics if a read would see hashes that are still pending.
+    pub(crate) fn _assert_hashed(&self) {
+        assert!(
+            !self.has_pending_hashes(),
+            "tree has pending hashes, call flush_hashes() first"
+        );
+    }
+}
+
+#[cfg(feature = "tree")]
+/// A tree of payment transactions
+pub type TransactionTree = CryptoBinaryTree<Transaction>;
+
+#[cfg(feature = "tree")]
+impl<H: TreeHasher> CryptoBinaryTree<Transaction, H> {
+    /// Rejects every later insert or update of a transaction whose id is not
+    /// its content hash (see [`Transaction::compute_id`]).
+    pub fn require_content_ids(&mut self) {
+        self._set_policy("content ids", Transaction::check_id);
+    }
+
+    /// Rejects every later insert or update whose `data` exceeds `limit` bytes,
+    /// or lifts the limit with `None`.
+    pub fn set_max_data_size(&mut self, limit: Option<usize>) {
+        match limit {
+            Some(limit) => self._set_policy("data size", move |tx: &Transaction| {
+                if tx.data.len() > limit {
+                    return Err(CryptoTreeError::DataTooLarge {
+                        id: tx.id.clone(),
+                        size: tx.data.len(),
+                        limit,
+                    });
+                }
+                Ok(())
+            }),
+            None => self._remove_policy("data size"),
+        }
+    }
+
+    /// Returns the `data` attachment of a stored transaction without cloning it.
+    pub fn data<Q>(&self, tx_id: &Q) -> Option<&[u8]>
+    where
+        String: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(tx_id).map(|tx| tx.data.as_slice())
+    }
+}
+
+#[cfg(feature = "tree")]
+/// Outcome of `CryptoBinaryTree::insert_batch`
+#[derive(Debug, Default)]
+pub struct BatchResult {
+    /// Number of transactions added to the tree
+    pub inserted: usize,
+    /// Ids skipped because they were already present
+    pub duplicates: Vec<String>,
+    /// Ids skipped because their payload could not be encoded or was rejected by ledger rules, the validator or a policy
+    pub failed: Vec<(String, CryptoTreeError)>,
 }
 
-impl Default for CryptoBinaryTree {
+#[cfg(feature = "tree")]
+/// Two trees are equal when their Merkle roots are; see
+/// [`CryptoBinaryTree::structurally_equal`] for a comparison that does not
+/// rely on the stored hashes.
+impl<T: TreeKey, H> PartialEq for CryptoBinaryTree<T, H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.merkle_root == other.merkle_root
+    }
+}
+
+#[cfg(feature = "tree")]
+impl<T: TreeKey, H> Eq for CryptoBinaryTree<T, H> {}
+
+#[cfg(feature = "tree")]
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher + Default> Default for CryptoBinaryTree<T, H> {
     fn default() -> Self {
-        Self::new()
+        Self::with_hasher(H::default())
     }
 }
 
-impl CryptoBinaryTree {
+#[cfg(feature = "tree")]
+impl<T: TreeKey + Serialize + Clone> CryptoBinaryTree<T> {
+    /// Creates an empty tree hashed with SHA-256.
     pub fn new() -> Self {
+        Self::with_hasher(Sha256Hasher::default())
+    }
+
+    /// Builds a SHA-256 tree from transactions sorted by id; see [`CryptoBinaryTree::from_sorted_with_hasher`].
+    pub fn from_sorted(transactions: Vec<T>) -> Result<Self> {
+        Self::from_sorted_with_hasher(transactions, Sha256Hasher::default())
+    }
+}
+
+#[cfg(feature = "tree")]
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Creates an empty tree whose nodes are hashed with `hasher`.
+    pub fn with_hasher(hasher: H) -> Self {
         Self {
             root: None,
-            size: 0,
+            nodes: Arena::new(),
             merkle_root: "0".to_string(),
+            hasher,
+            index: None,
+            validator: None,
+            policies: Vec::new(),
+            interner: Interner::new(),
+            lazy_hashing: false,
+            observer: None,
+            subscribers: Subscribers::new(),
+            versions: None,
+            audit: None,
+            #[cfg(feature = "std")]
+            history: None,
+            #[cfg(feature = "ed25519")]
+            root_signer: None,
         }
     }
 
-    pub fn insert(&mut self, transaction: Transaction) -> bool {
-        if self.root.is_none() {
-            self.root = Some(Box::new(CryptoTreeNode::new(transaction)));
-            self.size = 1;
-            self._update_merkle_root();
-            return true;
+    /// Builds a perfectly balanced tree from transactions sorted by id, in O(n).
+    ///
+    /// Nodes are assembled bottom-up and each hash is computed exactly once,
+    /// which is much faster than repeated `insert` for large inputs. Fails with
+    /// `UnsortedInput` or `DuplicateId` if the ids are not strictly increasing.
+    pub fn from_sorted_with_hasher(transactions: Vec<T>, hasher: H) -> Result<Self> {
+        Self::_check_sorted(&transactions)?;
+
+        let mut nodes: Vec<CryptoTreeNode<T>> = transactions.into_iter().map(CryptoTreeNode::unhashed).collect();
+        Self::_build_balanced(&mut nodes, 0, &hasher)?;
+        Ok(Self::_from_balanced(nodes, hasher))
+    }
+
+    /// Wraps nodes linked by `_build_balanced` in a tree.
+    fn _from_balanced(nodes: Vec<CryptoTreeNode<T>>, hasher: H) -> Self {
+        let mut tree = Self::with_hasher(hasher);
+        tree.root = balanced_root(nodes.len()).map(NodeId::new);
+        tree.nodes = Arena::from_vec(nodes);
+        tree._intern_all();
+        tree._update_merkle_root();
+        tree
+    }
+
+    /// Fails unless the ids of `transactions` are strictly increasing.
+    fn _check_sorted(transactions: &[T]) -> Result<()> {
+        for pair in transactions.windows(2) {
+            match pair[0].key().cmp(pair[1].key()) {
+                Ordering::Less => {}
+                Ordering::Equal => return Err(CryptoTreeError::DuplicateId(key_string(pair[1].key()))),
+                Ordering::Greater => return Err(CryptoTreeError::UnsortedInput(key_string(pair[1].key()))),
+            }
         }
+        Ok(())
+    }
+
+    /// Links and hashes a slice of sorted nodes into a balanced subtree.
+    ///
+    /// The subtree root is the middle node (see [`balanced_root`]), so the
+    /// arena keeps the nodes in key order. `offset` is the arena position of
+    /// the first node of the slice.
+    fn _build_balanced(nodes: &mut [CryptoTreeNode<T>], offset: usize, hasher: &H) -> Result<()> {
+        let Some(mid) = balanced_root(nodes.len()) else {
+            return Ok(());
+        };
+        Self::_build_balanced(&mut nodes[..mid], offset, hasher)?;
+        Self::_build_balanced(&mut nodes[mid + 1..], offset + mid + 1, hasher)?;
+        Self::_join_balanced(nodes, offset, hasher)
+    }
+
+    /// Links and hashes the middle node of a slice whose two halves are already built.
+    fn _join_balanced(nodes: &mut [CryptoTreeNode<T>], offset: usize, hasher: &H) -> Result<()> {
+        let size = nodes.len();
+        let mid = balanced_root(size).expect("slice is not empty");
+        let left = balanced_root(mid);
+        let right = balanced_root(size - mid - 1).map(|r| mid + 1 + r);
+
+        let height = 1 + core::cmp::max(left.map_or(0, |l| nodes[l].height), right.map_or(0, |r| nodes[r].height));
+        let left_hash = left.map(|l| nodes[l].hash.as_str());
+        let right_hash = right.map(|r| nodes[r].hash.as_str());
+        let hash = encoding::hash_node(hasher, &nodes[mid].transaction, left_hash, right_hash, height, size)?;
+
+        let node = &mut nodes[mid];
+        node.left = left.map(|l| NodeId::new(offset + l));
+        node.right = right.map(|r| NodeId::new(offset + r));
+        node.height = height;
+        node.size = size;
+        node.hash = hash;
+        node.digest = None;
+        Ok(())
+    }
 
-        let mut inserted = false;
-        let root = std::mem::take(&mut self.root);
-        self.root = Self::_insert_recursive(root, transaction, &mut inserted);
-        if inserted {
-            self.size += 1;
-            self._update_merkle_root();
+    /// Returns `true` if both trees have the same shape and, node for node,
+    /// equal payloads, heights, sizes and hashes.
+    ///
+    /// Unlike `==`, which only compares Merkle roots, this walks every node
+    /// and so also tells apart trees whose stored hashes have gone stale.
+    pub fn structurally_equal(&self, other: &Self) -> bool
+    where
+        T: PartialEq,
+    {
+        if self.len() != other.len() {
+            return false;
         }
-        inserted
+        let mut stack = vec![(self.root, other.root)];
+        while let Some(pair) = stack.pop() {
+            match pair {
+                (None, None) => {}
+                (Some(a), Some(b)) => {
+                    let (a, b) = (&self.nodes[a], &other.nodes[b]);
+                    if a.transaction != b.transaction || a.height != b.height || a.size != b.size || a.hash != b.hash {
+                        return false;
+                    }
+                    stack.push((a.left, b.left));
+                    stack.push((a.right, b.right));
+                }
+                _ => return false,
+            }
+        }
+        true
     }
 
-    fn _insert_recursive(
-        node: Option<Box<CryptoTreeNode>>, 
-        transaction: Transaction, 
-        inserted: &mut bool
-    ) -> Option<Box<CryptoTreeNode>> {
-        match node {
-            None => {
-                *inserted = true;
-                Some(Box::new(CryptoTreeNode::new(transaction)))
+    /// Returns the hasher used for node commitments.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Switches lazy hashing on or off.
+    ///
+    /// While it is on, `insert`, `remove`, `retain` and `insert_batch` only
+    /// mark the nodes they touch dirty; [`flush_hashes`](Self::flush_hashes)
+    /// then rehashes each of them once in a single bottom-up pass. This saves
+    /// the per-insert path hashing when loading many transactions without
+    /// reading the root in between. [`root`](Self::root) and
+    /// [`proof`](Self::proof) flush on demand; the `&self` readers cannot, so
+    /// until the flush `merkle_root()`, `get_proof_of_inclusion()` and
+    /// integrity checks panic and snapshots fail with `PendingHashes`.
+    /// `update` and `upsert` return the new root and so flush first.
+    /// Switching it off flushes.
+    pub fn set_lazy_hashing(&mut self, enabled: bool) {
+        self.lazy_hashing = enabled;
+        if !enabled {
+            self.flush_hashes();
+        }
+    }
+
+    /// Recomputes every pending node hash and returns the Merkle root.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn flush_hashes(&mut self) -> &str {
+        if let Some(root) = self.root.filter(|&root| self.nodes[root].is_dirty()) {
+            let before = self.nodes.work;
+            Self::_rehash_dirty(&mut self.nodes, root, &self.hasher);
+            if let Some(observer) = self._observer() {
+                let hashes = self.nodes.work.since(before).hashes;
+                observer.on_event(&TreeEvent::Flushed { hashes });
             }
-            Some(mut n) => {
-                let tx_id = &transaction.id;
-                let node_tx_id = &n.transaction.id;
+        }
+        self._update_merkle_root();
+        &self.merkle_root
+    }
 
-                if tx_id == node_tx_id {
-                    // Duplicate
-                    return Some(n);
-                }
+    /// Inserts a transaction, returning `false` if it was rejected.
+    ///
+    /// Thin wrapper around [`CryptoBinaryTree::try_insert`] for callers that
+    /// only care whether the transaction was added.
+    pub fn insert(&mut self, transaction: T) -> bool {
+        self.try_insert(transaction).is_ok()
+    }
+
+    /// Inserts a transaction, rebalancing and rehashing the affected path.
+    ///
+    /// Fails with `DuplicateId` if the id is already stored, with
+    /// `SerializationFailed` if the payload cannot be encoded for hashing and
+    /// with `InsufficientBalance`, `Rejected` or another policy error if
+    /// installed `LedgerRules`, the validator or an enabled mode reject it.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all, fields(id = %key_string(transaction.key()))))]
+    pub fn try_insert(&mut self, transaction: T) -> Result<()> {
+        if self.observer.is_none() {
+            return self._try_insert(transaction);
+        }
+        let id = key_string(transaction.key());
+        let before = self.nodes.work;
+        let outcome = self._try_insert(transaction);
+        let work = self.nodes.work.since(before);
+        if let Some(observer) = self._observer() {
+            observer.on_event(&match &outcome {
+                Ok(()) => TreeEvent::Inserted {
+                    id: &id,
+                    rotations: work.rotations,
+                    hashes: work.hashes,
+                },
+                Err(error) => TreeEvent::InsertRejected { id: &id, error },
+            });
+        }
+        outcome
+    }
+
+    fn _try_insert(&mut self, mut transaction: T) -> Result<()> {
+        self._admit(&transaction)?;
+        transaction.intern(&mut self.interner);
+        // Hash the new leaf up front so that rebalancing never meets an unencodable payload
+        let leaf = CryptoTreeNode::with_hasher(transaction, &self.hasher)?;
+        let entry = match self.index.as_ref() {
+            Some(index) => Some(index.admit(&leaf.transaction)?),
+            None => None,
+        };
+        let published = self.subscribers.is_active().then(|| leaf.transaction.clone());
+        let audited = self.audit.is_some().then(|| key_string(leaf.transaction.key()));
+        let hasher = if self.lazy_hashing { None } else { Some(&self.hasher) };
+        if let Err(leaf) = Self::_insert_leaf(&mut self.nodes, &mut self.root, leaf, hasher) {
+            return Err(CryptoTreeError::DuplicateId(key_string(leaf.transaction.key())));
+        }
+        if let (Some(index), Some(entry)) = (self.index.as_mut(), entry) {
+            index.add(entry);
+        }
+        if let Some(transaction) = published {
+            self.subscribers.send(ChangeEvent::Inserted(transaction));
+        }
+        self._update_merkle_root();
+        if let Some(id) = audited {
+            self._audit(AuditOp::Insert, vec![id]);
+        }
+        Ok(())
+    }
 
-                if tx_id < node_tx_id {
-                    n.left = Self::_insert_recursive(n.left, transaction.clone(), inserted);
-                } else {
-                    n.right = Self::_insert_recursive(n.right, transaction.clone(), inserted);
+    /// Inserts many transactions, recomputing hashes once at the end.
+    ///
+    /// Every item is placed and the tree rebalanced as with `insert`, but the
+    /// nodes touched are only marked dirty; a single bottom-up pass then rehashes
+    /// each of them exactly once. Duplicates (against the tree or earlier items
+    /// of the batch) and payloads that cannot be encoded are skipped and reported.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all, fields(len = transactions.len())))]
+    pub fn insert_batch(&mut self, transactions: Vec<T>) -> BatchResult {
+        let mut result = BatchResult::default();
+        let mut audited = Vec::new();
+        let format = self.hasher.format();
+        let before = self.nodes.work;
+
+        for mut transaction in transactions {
+            if let Err(e) = self._admit(&transaction).and_then(|()| encoding::encode_node_in(format, &transaction, None, None, 1, 1)) {
+                result.failed.push((key_string(transaction.key()), e));
+                continue;
+            }
+            let entry = match self.index.as_ref().map(|index| index.admit(&transaction)).transpose() {
+                Ok(entry) => entry,
+                Err(e) => {
+                    result.failed.push((key_string(transaction.key()), e));
+                    continue;
                 }
+            };
+            transaction.intern(&mut self.interner);
+            let published = self.subscribers.is_active().then(|| transaction.clone());
+            let id = self.audit.is_some().then(|| key_string(transaction.key()));
+            let leaf = CryptoTreeNode::unhashed(transaction);
+            match Self::_insert_leaf(&mut self.nodes, &mut self.root, leaf, None::<&H>) {
+                Ok(()) => {
+                    if let (Some(index), Some(entry)) = (self.index.as_mut(), entry) {
+                        index.add(entry);
+                    }
+                    if let Some(transaction) = published {
+                        self.subscribers.send(ChangeEvent::Inserted(transaction));
+                    }
+                    audited.extend(id);
+                    result.inserted += 1;
+                }
+                Err(leaf) => result.duplicates.push(key_string(leaf.transaction.key())),
+            }
+        }
+
+        if let Some(observer) = self._observer() {
+            observer.on_event(&TreeEvent::BatchInserted {
+                inserted: result.inserted,
+                duplicates: result.duplicates.len(),
+                failed: result.failed.len(),
+                rotations: self.nodes.work.since(before).rotations,
+            });
+        }
+        if !self.lazy_hashing {
+            self.flush_hashes();
+        }
+        if result.inserted > 0 {
+            self._audit(AuditOp::InsertBatch, audited);
+        }
+        result
+    }
+
+    /// Walks from `root` towards `key`, returning every node passed with the
+    /// direction taken, and the node holding `key` if there is one.
+    fn _search_path<Q>(nodes: &Arena<T>, root: Option<NodeId>, key: &Q) -> (Vec<(NodeId, Direction)>, Option<NodeId>)
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut path = Vec::new();
+        let mut current = root;
+        while let Some(id) = current {
+            let n = &nodes[id];
+            let direction = match n.cmp_key(key) {
+                Ordering::Less => Direction::Left,
+                Ordering::Greater => Direction::Right,
+                Ordering::Equal => return (path, Some(id)),
+            };
+            path.push((id, direction));
+            current = match direction {
+                Direction::Left => n.left,
+                Direction::Right => n.right,
+            };
+        }
+        (path, None)
+    }
 
-                if *inserted {
-                    // Update height first
-                    n.update_height();
-                    
-                    // Balance the node
-                    n = Self::_balance_node(n);
-                    
-                    // Now update the hash after balancing
-                    let left_hash = n.left.as_ref().map(|l| l.hash.clone());
-                    let right_hash = n.right.as_ref().map(|r| r.hash.clone());
-                    n.update_hash(&left_hash, &right_hash);
+    /// Places `leaf` under `root`, handing it back if its id is already present.
+    fn _insert_leaf(
+        nodes: &mut Arena<T>,
+        root: &mut Option<NodeId>,
+        leaf: CryptoTreeNode<T>,
+        hasher: Option<&H>,
+    ) -> core::result::Result<(), CryptoTreeNode<T>> {
+        let (path, found) = Self::_search_path(nodes, *root, leaf.transaction.key());
+        if found.is_some() {
+            return Err(leaf);
+        }
+        let leaf = nodes.push(leaf);
+        *root = Self::_reattach(nodes, path, Some(leaf), hasher);
+        Ok(())
+    }
+
+    /// Recomputes the hash of every dirty node below and including `id`.
+    ///
+    /// Ancestors of a dirty node are always dirty themselves, so clean subtrees are skipped.
+    fn _rehash_dirty(nodes: &mut Arena<T>, id: NodeId, hasher: &H) {
+        if !nodes[id].is_dirty() {
+            return;
+        }
+        if let Some(left) = nodes[id].left {
+            Self::_rehash_dirty(nodes, left, hasher);
+        }
+        if let Some(right) = nodes[id].right {
+            Self::_rehash_dirty(nodes, right, hasher);
+        }
+        nodes.rehash(id, hasher);
+    }
+
+    /// Fails as `try_insert(transaction)` would, without changing the tree.
+    #[cfg(feature = "std")]
+    pub(crate) fn _check_insert(&self, transaction: &T) -> Result<()> {
+        if self.search(transaction.key()).is_some() {
+            return Err(CryptoTreeError::DuplicateId(key_string(transaction.key())));
+        }
+        self._admit(transaction)?;
+        encoding::encode_node_in(self.hasher.format(), transaction, None, None, 1, 1)?;
+        if let Some(index) = &self.index {
+            index.admit(transaction)?;
+        }
+        Ok(())
+    }
+
+    /// Fails as an `update` of `tx_id` that leaves `updated` stored would,
+    /// without changing the tree.
+    #[cfg(feature = "std")]
+    pub(crate) fn _check_update<Q>(&self, tx_id: &Q, updated: &T) -> Result<()>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + Serialize + ?Sized,
+    {
+        let Some(stored) = self.search(tx_id) else {
+            return Err(CryptoTreeError::NotFound(key_string(tx_id)));
+        };
+        if tx_id != updated.key().borrow() {
+            return Err(CryptoTreeError::KeyChanged(key_string(tx_id)));
+        }
+        self._admit(updated)?;
+        encoding::encode_node_in(self.hasher.format(), updated, None, None, 1, 1)?;
+        if let Some(index) = &self.index {
+            index.check_replace(stored, updated)?;
+        }
+        Ok(())
+    }
+
+    /// Mutates the stored transaction with the given id in place and returns the new Merkle root.
+    ///
+    /// Hashes are recomputed from the node up to the root. The update is
+    /// all-or-nothing: it fails with `NotFound` if the id is not stored, with
+    /// `KeyChanged` if `f` alters the id, with `SerializationFailed` if the
+    /// result cannot be encoded and with `InsufficientBalance`, `Rejected` or
+    /// another policy error if installed `LedgerRules`, the validator or an
+    /// enabled mode reject it; in all but the first case the payload is restored.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all, fields(id = %key_string(tx_id))))]
+    pub fn update<Q>(&mut self, tx_id: &Q, f: impl FnOnce(&mut T)) -> Result<String>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + Serialize + ?Sized,
+    {
+        self.flush_hashes();
+        let before = self.nodes.work;
+        let outcome = self._update(tx_id, f);
+        if let Some(observer) = self._observer() {
+            observer.on_event(&TreeEvent::Updated {
+                id: &key_string(tx_id),
+                hashes: self.nodes.work.since(before).hashes,
+                error: outcome.as_ref().err(),
+            });
+        }
+        outcome
+    }
+
+    fn _update<Q>(&mut self, tx_id: &Q, f: impl FnOnce(&mut T)) -> Result<String>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + Serialize + ?Sized,
+    {
+        let (path, found) = Self::_search_path(&self.nodes, self.root, tx_id);
+        let Some(id) = found else {
+            return Err(CryptoTreeError::NotFound(key_string(tx_id)));
+        };
+        let original = self.nodes[id].transaction.clone();
+        f(&mut self.nodes[id].transaction);
+        self.nodes[id].transaction.intern(&mut self.interner);
+        let n = &self.nodes[id];
+        let outcome = if !n.cmp_key(tx_id).is_eq() {
+            Err(CryptoTreeError::KeyChanged(key_string(tx_id)))
+        } else if let Err(e) = self._admit(&n.transaction) {
+            Err(e)
+        } else {
+            let (left_hash, right_hash) = (self.nodes.hash(n.left), self.nodes.hash(n.right));
+            encoding::hash_node(&self.hasher, &n.transaction, left_hash, right_hash, n.height, n.size)
+        };
+        let outcome = outcome.and_then(|hash| match self.index.as_mut() {
+            Some(index) => index.replace(&original, &self.nodes[id].transaction).map(|()| hash),
+            None => Ok(hash),
+        });
+
+        match outcome {
+            Ok(hash) => {
+                self.nodes[id].hash = hash;
+                self.nodes[id].digest = None;
+                if self.subscribers.is_active() {
+                    self.subscribers.send(ChangeEvent::Updated(self.nodes[id].transaction.clone()));
                 }
+                // The shape is unchanged, so this only rehashes the ancestors
+                self.root = Self::_reattach(&mut self.nodes, path, Some(id), Some(&self.hasher));
+                self._update_merkle_root();
+                if self.audit.is_some() {
+                    self._audit(AuditOp::Update, vec![key_string(tx_id)]);
+                }
+                Ok(self.merkle_root.clone())
+            }
+            Err(e) => {
+                self.nodes[id].transaction = original;
+                Err(e)
+            }
+        }
+    }
+
+    /// Inserts `transaction`, or replaces the stored one with the same id, and returns the new Merkle root.
+    pub fn upsert(&mut self, transaction: T) -> Result<String> {
+        if self.search(transaction.key()).is_some() {
+            let tx_id = transaction.key().clone();
+            self.update(&tx_id, move |stored| *stored = transaction)
+        } else {
+            self.try_insert(transaction)?;
+            Ok(self.flush_hashes().to_string())
+        }
+    }
 
-                Some(n)
+    /// Removes the transaction with the given id, returning it if it was present.
+    ///
+    /// The tree is rebalanced and every hash on the affected path is recomputed,
+    /// so `verify_integrity()` and `merkle_root()` stay consistent.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn remove<Q>(&mut self, tx_id: &Q) -> Option<T>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let hasher = if self.lazy_hashing { None } else { Some(&self.hasher) };
+        let before = self.nodes.work;
+        let removed = Self::_remove_key(&mut self.nodes, &mut self.root, tx_id, hasher)?;
+        if let Some(index) = self.index.as_mut() {
+            index.remove(&removed);
+        }
+        if self.subscribers.is_active() {
+            self.subscribers.send(ChangeEvent::Removed(removed.key().clone()));
+        }
+        self._update_merkle_root();
+        if self.audit.is_some() {
+            self._audit(AuditOp::Remove, vec![key_string(removed.key())]);
+        }
+        if let Some(observer) = self._observer() {
+            let work = self.nodes.work.since(before);
+            observer.on_event(&TreeEvent::Removed {
+                id: &key_string(removed.key()),
+                rotations: work.rotations,
+                hashes: work.hashes,
+            });
+        }
+        Some(removed)
+    }
+
+    /// Keeps only the transactions for which `f` returns `true`.
+    ///
+    /// Transactions are offered in ascending id order. Failing ones are
+    /// removed and the tree rebalanced as with `remove`, but hashes are
+    /// recomputed in a single pass at the end, so each affected node is
+    /// rehashed once however many neighbours were removed.
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        let mut doomed = Vec::new();
+        self.traverse(TraversalOrder::InOrder, |n| {
+            if !f(&n.transaction) {
+                doomed.push(n.transaction.key().clone());
+            }
+        });
+        if doomed.is_empty() {
+            return;
+        }
+
+        let audited = if self.audit.is_some() { doomed.iter().map(key_string).collect() } else { Vec::new() };
+        for tx_id in doomed {
+            let removed = Self::_remove_key(&mut self.nodes, &mut self.root, &tx_id, None::<&H>);
+            if let (Some(index), Some(removed)) = (self.index.as_mut(), removed) {
+                index.remove(&removed);
+            }
+            if self.subscribers.is_active() {
+                self.subscribers.send(ChangeEvent::Removed(tx_id));
+            }
+        }
+        self.interner.purge();
+        if !self.lazy_hashing {
+            self.flush_hashes();
+        }
+        self._audit(AuditOp::Retain, audited);
+    }
+
+    /// Removes every transaction, resetting the tree to empty.
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.nodes.clear();
+        self.interner.clear();
+        if let Some(index) = self.index.as_mut() {
+            index.clear();
+        }
+        self._update_merkle_root();
+        self._audit(AuditOp::Clear, Vec::new());
+    }
+
+    /// Number of distinct interned strings, such as addresses, the tree holds
+    pub fn interned_len(&self) -> usize {
+        self.interner.len()
+    }
+
+    /// Releases interned strings no stored payload uses any more.
+    ///
+    /// `retain` and `clear` do this themselves; after many single removals it
+    /// returns the memory of addresses that have left the tree.
+    pub fn purge_interned(&mut self) {
+        self.interner.purge();
+    }
+
+    /// Interns every stored payload, e.g. after loading nodes wholesale.
+    pub(crate) fn _intern_all(&mut self) {
+        for n in self.nodes.iter_mut() {
+            n.transaction.intern(&mut self.interner);
+        }
+    }
+
+    /// Unlinks the node holding `tx_id` from under `root` and frees it, returning its transaction.
+    fn _remove_key<Q>(nodes: &mut Arena<T>, root: &mut Option<NodeId>, tx_id: &Q, hasher: Option<&H>) -> Option<T>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (path, found) = Self::_search_path(nodes, *root, tx_id);
+        let id = found?;
+        let (replacement, freed) = match (nodes[id].left, nodes[id].right) {
+            (None, None) => (None, id),
+            (Some(child), None) | (None, Some(child)) => (Some(child), id),
+            (Some(_), Some(right)) => {
+                // Take over the payload of the in-order successor and free its node instead
+                let (right, successor) = Self::_remove_min(nodes, right, hasher);
+                nodes[id].right = right;
+                nodes.swap_transactions(id, successor);
+                (Some(Self::_rebalance(nodes, id, hasher)), successor)
+            }
+        };
+
+        *root = Self::_reattach(nodes, path, replacement, hasher);
+        Some(Self::_free(nodes, root, freed).transaction)
+    }
+
+    /// Unlinks the smallest node of a subtree, returning the remaining subtree and the unlinked node.
+    fn _remove_min(nodes: &mut Arena<T>, id: NodeId, hasher: Option<&H>) -> (Option<NodeId>, NodeId) {
+        let mut path = Vec::new();
+        let mut current = id;
+        while let Some(left) = nodes[current].left {
+            path.push((current, Direction::Left));
+            current = left;
+        }
+        let right = nodes[current].right.take();
+        (Self::_reattach(nodes, path, right, hasher), current)
+    }
+
+    /// Drops an unlinked node from the arena.
+    ///
+    /// The last node of the arena moves into the freed slot, so the link
+    /// pointing at it, found by searching for its key, is updated.
+    fn _free(nodes: &mut Arena<T>, root: &mut Option<NodeId>, id: NodeId) -> CryptoTreeNode<T> {
+        let last = nodes.last_id().expect("a node to free is in the arena");
+        if last != id {
+            let (path, found) = Self::_search_path(nodes, *root, nodes[last].transaction.key());
+            debug_assert_eq!(found, Some(last));
+            match path.last() {
+                Some(&(parent, direction)) => *nodes[parent].child_mut(direction) = Some(id),
+                None => *root = Some(id),
             }
         }
+        nodes.swap_remove(id)
+    }
+
+    /// Hangs `child` back under a search path, bottom-up, returning the new subtree root.
+    ///
+    /// Every node on the path is rebalanced and rehashed. Passing `None` as
+    /// `hasher` defers hashing: touched nodes are marked dirty instead.
+    fn _reattach(
+        nodes: &mut Arena<T>,
+        mut path: Vec<(NodeId, Direction)>,
+        mut child: Option<NodeId>,
+        hasher: Option<&H>,
+    ) -> Option<NodeId> {
+        while let Some((parent, direction)) = path.pop() {
+            *nodes[parent].child_mut(direction) = child;
+            child = Some(Self::_rebalance(nodes, parent, hasher));
+        }
+        child
+    }
+
+    /// Restores height, balance and hash of a node whose subtree has changed,
+    /// returning the root of the rebalanced subtree.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", name = "rebalance", skip_all))]
+    fn _rebalance(nodes: &mut Arena<T>, id: NodeId, hasher: Option<&H>) -> NodeId {
+        // Update height and size first
+        nodes.update_stats(id);
+
+        // Balance the node
+        let id = Self::_balance_node(nodes, id, hasher);
+
+        // Now update the hash after balancing
+        nodes.refresh_hash(id, hasher);
+        id
     }
 
-    fn _balance_node(mut node: Box<CryptoTreeNode>) -> Box<CryptoTreeNode> {
-        let balance = node.get_balance_factor();
+    fn _balance_node(nodes: &mut Arena<T>, id: NodeId, hasher: Option<&H>) -> NodeId {
+        let balance = nodes.balance_factor(id);
 
         // Left heavy
         if balance > 1 {
-            if node.left.as_ref().map_or(0, |l| l.get_balance_factor()) < 0 {
+            let left = nodes[id].left.expect("left-heavy node has a left child");
+            if nodes.balance_factor(left) < 0 {
                 // Left-Right case
-                node.left = Some(Self::_rotate_left(Box::new(*node.left.unwrap())));
+                nodes[id].left = Some(Self::_rotate_left(nodes, left, hasher));
             }
             // Left-Left case
-            node = Self::_rotate_right(node); // ✅ Fixed: return is Box, assign directly
+            return Self::_rotate_right(nodes, id, hasher);
         }
         // Right heavy
-        else if balance < -1 {
-            if node.right.as_ref().map_or(0, |r| r.get_balance_factor()) > 0 {
+        if balance < -1 {
+            let right = nodes[id].right.expect("right-heavy node has a right child");
+            if nodes.balance_factor(right) > 0 {
                 // Right-Left case
-                node.right = Some(Self::_rotate_right(Box::new(*node.right.unwrap())));
+                nodes[id].right = Some(Self::_rotate_right(nodes, right, hasher));
             }
             // Right-Right case
-            node = Self::_rotate_left(node); // ✅ Fixed: return is Box, assign directly
+            return Self::_rotate_left(nodes, id, hasher);
         }
 
-        node
+        id
     }
 
-    fn _rotate_left(mut z: Box<CryptoTreeNode>) -> Box<CryptoTreeNode> {
-        // Update heights before rotation
-        z.update_height();
-        
-        // Get the right child (y)
-        let mut y = z.right.take().unwrap();
-        y.update_height();
-        
+    fn _rotate_left(nodes: &mut Arena<T>, z: NodeId, hasher: Option<&H>) -> NodeId {
+        let y = nodes[z].right.expect("rotated node has a right child");
+        nodes.work.rotations += 1;
+
         // Perform the rotation
-        let t2 = y.left.take();
-        z.right = t2;
-        y.left = Some(z);
-        
-        // Update heights after rotation
-        y.left.as_mut().unwrap().update_height();
-        y.update_height();
-        
-        // Update hashes after rotation using current children
-        let z_node = y.left.as_mut().unwrap();
-        let z_left_hash = z_node.left.as_ref().map(|l| l.hash.clone());
-        let z_right_hash = z_node.right.as_ref().map(|r| r.hash.clone());
-        z_node.update_hash(&z_left_hash, &z_right_hash);
-        
-        let y_left_hash = y.left.as_ref().map(|l| l.hash.clone());
-        let y_right_hash = y.right.as_ref().map(|r| r.hash.clone());
-        y.update_hash(&y_left_hash, &y_right_hash);
+        nodes[z].right = nodes[y].left;
+        nodes[y].left = Some(z);
 
+        // Update heights, sizes and hashes after rotation, child first
+        nodes.update_stats(z);
+        nodes.update_stats(y);
+        nodes.refresh_hash(z, hasher);
+        nodes.refresh_hash(y, hasher);
         y
     }
 
-    fn _rotate_right(mut z: Box<CryptoTreeNode>) -> Box<CryptoTreeNode> {
-        // Update heights before rotation
-        z.update_height();
-        
-        // Get the left child (y)
-        let mut y = z.left.take().unwrap();
-        y.update_height();
-        
+    fn _rotate_right(nodes: &mut Arena<T>, z: NodeId, hasher: Option<&H>) -> NodeId {
+        let y = nodes[z].left.expect("rotated node has a left child");
+        nodes.work.rotations += 1;
+
         // Perform the rotation
-        let t3 = y.right.take();
-        z.left = t3;
-        y.right = Some(z);
-        
-        // Update heights after rotation
-        y.right.as_mut().unwrap().update_height();
-        y.update_height();
-        
-        // Update hashes after rotation using current children
-        let z_node = y.right.as_mut().unwrap();
-        let z_left_hash = z_node.left.as_ref().map(|l| l.hash.clone());
-        let z_right_hash = z_node.right.as_ref().map(|r| r.hash.clone());
-        z_node.update_hash(&z_left_hash, &z_right_hash);
-        
-        let y_left_hash = y.left.as_ref().map(|l| l.hash.clone());
-        let y_right_hash = y.right.as_ref().map(|r| r.hash.clone());
-        y.update_hash(&y_left_hash, &y_right_hash);
+        nodes[z].left = nodes[y].right;
+        nodes[y].right = Some(z);
 
+        // Update heights, sizes and hashes after rotation, child first
+        nodes.update_stats(z);
+        nodes.update_stats(y);
+        nodes.refresh_hash(z, hasher);
+        nodes.refresh_hash(y, hasher);
         y
     }
 
-    pub fn search<'a>(&'a self, tx_id: &str) -> Option<&'a Transaction> {
-        Self::_search_recursive(&self.root, tx_id)
+    pub fn search<Q>(&self, tx_id: &Q) -> Option<&T>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut current = self.root;
+        while let Some(id) = current {
+            let n = &self.nodes[id];
+            current = match n.cmp_key(tx_id) {
+                Ordering::Equal => return Some(&n.transaction),
+                Ordering::Less => n.left,
+                Ordering::Greater => n.right,
+            };
+        }
+        None
     }
 
-    fn _search_recursive<'a>(node: &'a Option<Box<CryptoTreeNode>>, tx_id: &str) -> Option<&'a Transaction> {
-        match node {
-            None => None,
-            Some(n) => {
-                if tx_id == n.transaction.id {
-                    Some(&n.transaction)
-                } else if tx_id < &n.transaction.id {
-                    Self::_search_recursive(&n.left, tx_id)
-                } else {
-                    Self::_search_recursive(&n.right, tx_id)
-                }
+    /// Returns the transaction with the smallest id.
+    pub fn first(&self) -> Option<&T> {
+        let mut node = &self.nodes[self.root?];
+        while let Some(left) = node.left {
+            node = &self.nodes[left];
+        }
+        Some(&node.transaction)
+    }
+
+    /// Returns the transaction with the largest id.
+    pub fn last(&self) -> Option<&T> {
+        let mut node = &self.nodes[self.root?];
+        while let Some(right) = node.right {
+            node = &self.nodes[right];
+        }
+        Some(&node.transaction)
+    }
+
+    /// Returns the transaction with the smallest id strictly greater than `tx_id`.
+    ///
+    /// `tx_id` itself does not need to be stored in the tree.
+    pub fn successor<Q>(&self, tx_id: &Q) -> Option<&T>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut best = None;
+        let mut current = self.root;
+        while let Some(id) = current {
+            let n = &self.nodes[id];
+            if n.cmp_key(tx_id).is_lt() {
+                best = Some(&n.transaction);
+                current = n.left;
+            } else {
+                current = n.right;
             }
         }
+        best
     }
 
-    pub fn verify_integrity(&self) -> bool {
-        Self::_verify_recursive(&self.root)
+    /// Returns the transaction with the largest id strictly smaller than `tx_id`.
+    ///
+    /// `tx_id` itself does not need to be stored in the tree.
+    pub fn predecessor<Q>(&self, tx_id: &Q) -> Option<&T>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut best = None;
+        let mut current = self.root;
+        while let Some(id) = current {
+            let n = &self.nodes[id];
+            if n.cmp_key(tx_id).is_gt() {
+                best = Some(&n.transaction);
+                current = n.right;
+            } else {
+                current = n.left;
+            }
+        }
+        best
     }
 
-    fn _verify_recursive(node: &Option<Box<CryptoTreeNode>>) -> bool {
-        match node {
-            None => true,
-            Some(n) => {
-                let left_hash = n.left.as_ref().map(|l| l.hash.clone());
-                let right_hash = n.right.as_ref().map(|r| r.hash.clone());
-                let expected_hash = CryptoTreeNode::calculate_hash(&n.transaction, &left_hash, &right_hash, n.height);
-                if n.hash != expected_hash {
-                    eprintln!("❌ Hash mismatch at transaction {}", n.transaction.id);
-                    return false;
+    /// Returns the `k`-th smallest transaction (0-based), in O(log n).
+    pub fn select(&self, k: usize) -> Option<&T> {
+        let mut k = k;
+        let mut current = self.root;
+        while let Some(id) = current {
+            let n = &self.nodes[id];
+            let left_size = self.nodes.size(n.left);
+            current = match k.cmp(&left_size) {
+                Ordering::Less => n.left,
+                Ordering::Equal => return Some(&n.transaction),
+                Ordering::Greater => {
+                    k -= left_size + 1;
+                    n.right
+                }
+            };
+        }
+        None
+    }
+
+    /// Returns the number of stored ids strictly smaller than `tx_id`, in O(log n).
+    ///
+    /// For a stored id this is its 0-based position in key order.
+    pub fn rank<Q>(&self, tx_id: &Q) -> usize
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut rank = 0;
+        let mut current = self.root;
+        while let Some(id) = current {
+            let n = &self.nodes[id];
+            match n.cmp_key(tx_id) {
+                Ordering::Less => current = n.left,
+                Ordering::Equal => return rank + self.nodes.size(n.left),
+                Ordering::Greater => {
+                    rank += self.nodes.size(n.left) + 1;
+                    current = n.right;
                 }
-                Self::_verify_recursive(&n.left) && Self::_verify_recursive(&n.right)
             }
         }
+        rank
     }
 
-    fn _update_merkle_root(&mut self) {
-        self.merkle_root = self.root.as_ref().map(|n| n.hash.clone()).unwrap_or("0".to_string());
+    /// Removes and returns the transaction with the smallest id.
+    pub fn pop_first(&mut self) -> Option<T> {
+        let id = self.first()?.key().clone();
+        self.remove(&id)
     }
 
-    pub fn get_proof_of_inclusion(&self, tx_id: &str) -> Option<Vec<ProofStep>> {
-        let mut proof = Vec::new();
-        if Self::_get_proof_recursive(&self.root, tx_id, &mut proof) {
-            Some(proof)
-        } else {
-            None
+    /// Removes and returns the transaction with the largest id.
+    pub fn pop_last(&mut self) -> Option<T> {
+        let id = self.last()?.key().clone();
+        self.remove(&id)
+    }
+
+    /// Returns `true` if every stored hash matches, see
+    /// [`verify_integrity_report`](Self::verify_integrity_report) for which ones do not.
+    pub fn verify_integrity(&self) -> bool {
+        self.check_integrity().is_ok()
+    }
+
+    /// Recomputes every node hash, failing with `CorruptedNode` at the first mismatch.
+    pub fn check_integrity(&self) -> Result<()> {
+        self._assert_hashed();
+        let outcome = Self::_check_subtree(&self.nodes, self.root, &self.hasher);
+        self._observe_check(&outcome);
+        outcome
+    }
+
+    /// Recomputes every hash below and including `node`, in pre-order.
+    fn _check_subtree(nodes: &Arena<T>, id: Option<NodeId>, hasher: &H) -> Result<()> {
+        let mut stack: Vec<NodeId> = id.into_iter().collect();
+        while let Some(id) = stack.pop() {
+            let n = &nodes[id];
+            Self::_check_node(nodes, n, hasher)?;
+            stack.extend(n.right);
+            stack.extend(n.left);
         }
+        Ok(())
     }
 
-    fn _get_proof_recursive(node: &Option<Box<CryptoTreeNode>>, tx_id: &str, proof: &mut Vec<ProofStep>) -> bool {
-        match node {
-            None => false,
-            Some(n) => {
-                if tx_id == n.transaction.id {
-                    true
-                } else if tx_id < &n.transaction.id {
-                    if let Some(ref right) = n.right {
-                        proof.push(ProofStep {
-                            side: "right".to_string(),
-                            hash: right.hash.clone(),
-                        });
+    /// Checks the stored hash of a single node against its children's stored hashes.
+    fn _check_node(nodes: &Arena<T>, n: &CryptoTreeNode<T>, hasher: &H) -> Result<()> {
+        let (left_hash, right_hash) = (nodes.hash(n.left), nodes.hash(n.right));
+        let expected_hash = encoding::hash_node(hasher, &n.transaction, left_hash, right_hash, n.height, n.size);
+        if expected_hash.ok().as_ref() != Some(&n.hash) {
+            return Err(CryptoTreeError::CorruptedNode {
+                id: key_string(n.transaction.key()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the tree is a balanced search tree, see [`check_invariants`](Self::check_invariants).
+    pub fn verify_invariants(&self) -> bool {
+        self.check_invariants().is_ok()
+    }
+
+    /// Checks the shape of the tree, which the node hashes do not fully cover.
+    ///
+    /// Ids must increase in order, every stored height and subtree size must
+    /// match the node's children and every balance factor must lie in
+    /// `[-1, 1]`. Fails with `InvariantViolated` at the first offending node
+    /// in post-order. Hashes are not recomputed; see `check_integrity`.
+    pub fn check_invariants(&self) -> Result<()> {
+        Self::_check_shape(&self.nodes, self.root, None, None).map(|_| ())
+    }
+
+    /// Checks the subtree at `id`, whose ids must lie strictly between the
+    /// bounds, returning its actual height and size.
+    fn _check_shape(
+        nodes: &Arena<T>,
+        id: Option<NodeId>,
+        low: Option<&T::Key>,
+        high: Option<&T::Key>,
+    ) -> Result<(i32, usize)> {
+        let Some(id) = id else {
+            return Ok((0, 0));
+        };
+        let n = &nodes[id];
+        let key = n.transaction.key();
+        let violated = |invariant| CryptoTreeError::InvariantViolated {
+            id: key_string(key),
+            invariant,
+        };
+        let (left_height, left_size) = Self::_check_shape(nodes, n.left, low, Some(key))?;
+        let (right_height, right_size) = Self::_check_shape(nodes, n.right, Some(key), high)?;
+
+        if low.is_some_and(|low| key <= low) || high.is_some_and(|high| key >= high) {
+            return Err(violated(Invariant::KeyOrder));
+        }
+        let height = 1 + left_height.max(right_height);
+        if n.height != height {
+            return Err(violated(Invariant::Height {
+                stored: n.height,
+                actual: height,
+            }));
+        }
+        if (left_height - right_height).abs() > 1 {
+            return Err(violated(Invariant::Balance(left_height - right_height)));
+        }
+        let size = 1 + left_size + right_size;
+        if n.size != size {
+            return Err(violated(Invariant::Size { stored: n.size, actual: size }));
+        }
+        Ok((height, size))
+    }
+
+    fn _update_merkle_root(&mut self) {
+        self.merkle_root = self.nodes.hash(self.root).unwrap_or("0").to_string();
+        if let Some(versions) = self.versions.as_mut() {
+            versions.record(&self.merkle_root, self.root, &self.nodes);
+        }
+        self.subscribers.send_root(&self.merkle_root);
+        #[cfg(feature = "std")]
+        if let Some(history) = self.history.as_mut() {
+            history.record(&self.merkle_root);
+        }
+    }
+
+    /// Builds a proof that `tx_id` is stored in the tree.
+    ///
+    /// Steps are ordered from the root down. Every ancestor on the search path
+    /// contributes one step carrying its transaction, height, subtree size and the hash of
+    /// the sibling subtree (`"0"` when empty). The target node contributes one
+    /// step per existing child, with `transaction` left as `None`.
+    /// Use [`verify_proof`] to check the result against a Merkle root.
+    pub fn get_proof_of_inclusion<Q>(&self, tx_id: &Q) -> Option<Vec<ProofStep<T>>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self._assert_hashed();
+        let mut proof = Vec::new();
+        let mut current = self.root;
+        while let Some(id) = current {
+            let n = &self.nodes[id];
+            current = match n.cmp_key(tx_id) {
+                Ordering::Equal => {
+                    if let Some(left) = self.nodes.hash(n.left) {
+                        proof.push(ProofStep::new(Side::Left, left.to_string(), n.height, n.size, None));
                     }
-                    Self::_get_proof_recursive(&n.left, tx_id, proof)
-                } else {
-                    if let Some(ref left) = n.left {
-                        proof.push(ProofStep {
-                            side: "left".to_string(),
-                            hash: left.hash.clone(),
-                        });
+                    if let Some(right) = self.nodes.hash(n.right) {
+                        proof.push(ProofStep::new(Side::Right, right.to_string(), n.height, n.size, None));
                     }
-                    Self::_get_proof_recursive(&n.right, tx_id, proof)
+                    self._observe_proof(n.transaction.key(), proof.len());
+                    return Some(proof);
                 }
+                Ordering::Less => {
+                    let sibling = self.nodes.hash(n.right).unwrap_or("0").to_string();
+                    proof.push(ProofStep::new(Side::Right, sibling, n.height, n.size, Some(n.transaction.clone())));
+                    n.left
+                }
+                Ordering::Greater => {
+                    let sibling = self.nodes.hash(n.left).unwrap_or("0").to_string();
+                    proof.push(ProofStep::new(Side::Left, sibling, n.height, n.size, Some(n.transaction.clone())));
+                    n.right
+                }
+            };
+        }
+        None
+    }
+
+    /// Like [`get_proof_of_inclusion`](Self::get_proof_of_inclusion), but
+    /// first rehashes the nodes lazy hashing left pending, so it never panics.
+    pub fn proof<Q>(&mut self, tx_id: &Q) -> Option<Vec<ProofStep<T>>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if self.has_pending_hashes() {
+            self.flush_hashes();
+        }
+        self.get_proof_of_inclusion(tx_id)
+    }
+
+    /// Builds a [`Proof`] that `tx_id` is stored in the tree.
+    ///
+    /// Unlike [`get_proof_of_inclusion`](Self::get_proof_of_inclusion) the
+    /// result carries the transaction, the current Merkle root and the tree
+    /// height, so it can be verified with [`Proof::verify`] alone.
+    pub fn get_proof<Q>(&self, tx_id: &Q) -> Option<Proof<T>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        Some(Proof {
+            transaction: self.search(tx_id)?.clone(),
+            root: self.merkle_root.clone(),
+            height: self.height(),
+            steps: self.get_proof_of_inclusion(tx_id)?,
+        })
+    }
+
+    /// Builds a proof that `tx_id` is *not* stored in the tree.
+    ///
+    /// The proof carries an inclusion proof of the last node on the search path
+    /// (whose child towards `tx_id` is empty) together with the ids of the
+    /// neighbouring keys that bracket the missing id. Returns `None` if `tx_id`
+    /// is present. Use [`verify_absence_proof`] to check it against a Merkle root.
+    pub fn get_proof_of_absence<Q>(&self, tx_id: &Q) -> Option<AbsenceProof<T>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ToOwned<Owned = T::Key> + ?Sized,
+    {
+        let mut predecessor = None;
+        let mut successor = None;
+        let mut terminal = None;
+        let mut current = self.root;
+
+        while let Some(id) = current {
+            let n = &self.nodes[id];
+            let ordering = n.cmp_key(tx_id);
+            if ordering.is_eq() {
+                return None;
+            }
+            terminal = Some(&n.transaction);
+            if ordering.is_lt() {
+                successor = Some(n.transaction.key().clone());
+                current = n.left;
+            } else {
+                predecessor = Some(n.transaction.key().clone());
+                current = n.right;
             }
         }
+
+        let path = match terminal {
+            Some(t) => self.get_proof_of_inclusion::<T::Key>(t.key())?,
+            None => Vec::new(),
+        };
+        Some(AbsenceProof {
+            tx_id: tx_id.to_owned(),
+            terminal: terminal.cloned(),
+            path,
+            predecessor,
+            successor,
+        })
     }
 
     pub fn len(&self) -> usize {
-        self.size
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.size == 0
+    /// Height of the root node, 0 for an empty tree
+    pub fn height(&self) -> i32 {
+        self.nodes.height(self.root)
     }
 
     pub fn merkle_root(&self) -> &str {
+        self._assert_hashed();
         &self.merkle_root
     }
-}
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct ProofStep {
-    pub side: String, // "left" or "right"
-    pub hash: String,
+    /// Returns the Merkle root, first rehashing the nodes lazy hashing left
+    /// pending. Unlike [`merkle_root`](Self::merkle_root) it never panics.
+    pub fn root(&mut self) -> &str {
+        if self.has_pending_hashes() {
+            self.flush_hashes();
+        }
+        &self.merkle_root
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "tree"))]
 mod tests {
     use super::*;
+    use crate::test_util::sample_tx;
 
     #[test]
     fn test_insert_single() {
         let mut tree = CryptoBinaryTree::new();
         let tx = Transaction {
             id: "tx_001".to_string(),
-            from: "Alice".to_string(),
-            to: "Bob".to_string(),
+            from: "Alice".into(),
+            to: "Bob".into(),
             amount: 100,
             timestamp: Some(1640995200),
+            ..Default::default()
         };
         assert!(tree.insert(tx));
         assert_eq!(tree.len(), 1);
@@ -369,10 +1711,11 @@ mod tests {
         let mut tree = CryptoBinaryTree::new();
         let tx = Transaction {
             id: "tx_001".to_string(),
-            from: "Alice".to_string(),
-            to: "Bob".to_string(),
+            from: "Alice".into(),
+            to: "Bob".into(),
             amount: 100,
             timestamp: Some(1640995200),
+            ..Default::default()
         };
         assert!(tree.insert(tx.clone())); // First insert - clone for ownership
         assert!(!tree.insert(tx)); // Duplicate - use original (now moved)
@@ -384,10 +1727,11 @@ mod tests {
         let mut tree = CryptoBinaryTree::new();
         let tx = Transaction {
             id: "tx_001".to_string(),
-            from: "Alice".to_string(),
-            to: "Bob".to_string(),
+            from: "Alice".into(),
+            to: "Bob".into(),
             amount: 100,
             timestamp: Some(1640995200),
+            ..Default::default()
         };
         tree.insert(tx);
         assert!(tree.search("tx_999").is_none());
@@ -399,24 +1743,27 @@ mod tests {
         let transactions = vec![
             Transaction {
                 id: "tx_003".to_string(),
-                from: "Bob".to_string(),
-                to: "Charlie".to_string(),
+                from: "Bob".into(),
+                to: "Charlie".into(),
                 amount: 50,
                 timestamp: Some(1640995300),
+                ..Default::default()
             },
             Transaction {
                 id: "tx_001".to_string(),
-                from: "Alice".to_string(),
-                to: "Bob".to_string(),
+                from: "Alice".into(),
+                to: "Bob".into(),
                 amount: 100,
                 timestamp: Some(1640995200),
+                ..Default::default()
             },
             Transaction {
                 id: "tx_005".to_string(),
-                from: "Charlie".to_string(),
-                to: "Dave".to_string(),
+                from: "Charlie".into(),
+                to: "Dave".into(),
                 amount: 25,
                 timestamp: Some(1640995400),
+                ..Default::default()
             },
         ];
 
@@ -433,38 +1780,43 @@ mod tests {
         let transactions = vec![
             Transaction {
                 id: "tx_005".to_string(),
-                from: "Alice".to_string(),
-                to: "Bob".to_string(),
+                from: "Alice".into(),
+                to: "Bob".into(),
                 amount: 100,
                 timestamp: Some(1640995200),
+                ..Default::default()
             },
             Transaction {
                 id: "tx_003".to_string(),
-                from: "Bob".to_string(),
-                to: "Charlie".to_string(),
+                from: "Bob".into(),
+                to: "Charlie".into(),
                 amount: 50,
                 timestamp: Some(1640995300),
+                ..Default::default()
             },
             Transaction {
                 id: "tx_007".to_string(),
-                from: "Charlie".to_string(),
-                to: "Dave".to_string(),
+                from: "Charlie".into(),
+                to: "Dave".into(),
                 amount: 25,
                 timestamp: Some(1640995400),
+                ..Default::default()
             },
             Transaction {
                 id: "tx_001".to_string(),
-                from: "Dave".to_string(),
-                to: "Eve".to_string(),
+                from: "Dave".into(),
+                to: "Eve".into(),
                 amount: 75,
                 timestamp: Some(1640995500),
+                ..Default::default()
             },
             Transaction {
                 id: "tx_009".to_string(),
-                from: "Eve".to_string(),
-                to: "Frank".to_string(),
+                from: "Eve".into(),
+                to: "Frank".into(),
                 amount: 30,
                 timestamp: Some(1640995600),
+                ..Default::default()
             },
         ];
 
@@ -478,7 +1830,6 @@ mod tests {
         assert!(!proof.is_empty());
 
         for step in &proof {
-            assert!(step.side == "left" || step.side == "right");
             assert_eq!(step.hash.len(), 64); // SHA-256 hex
         }
     }
@@ -490,10 +1841,11 @@ mod tests {
         for i in 1..=100 {
             let tx = Transaction {
                 id: format!("tx_{:03}", i),
-                from: "A".to_string(),
-                to: "B".to_string(),
-                amount: i as u64,
+                from: "A".into(),
+                to: "B".into(),
+                amount: u128::from(i),
                 timestamp: Some(1640995200 + i),
+                ..Default::default()
             };
             tree.insert(tx);
         }
@@ -501,4 +1853,768 @@ mod tests {
         assert!(tree.verify_integrity());
         assert!(tree.search("tx_050").is_some());
     }
+
+    fn assert_avl(tree: &CryptoBinaryTree) -> i32 {
+        assert_avl_at(&tree.nodes, tree.root)
+    }
+
+    fn assert_avl_at(nodes: &Arena<Transaction>, node: Option<NodeId>) -> i32 {
+        match node.map(|id| &nodes[id]) {
+            None => 0,
+            Some(n) => {
+                let lh = assert_avl_at(nodes, n.left);
+                let rh = assert_avl_at(nodes, n.right);
+                assert!((lh - rh).abs() <= 1, "unbalanced at {}", n.transaction.id);
+                assert_eq!(n.height, lh.max(rh) + 1);
+                n.height
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove_missing() {
+        let mut tree = CryptoBinaryTree::new();
+        assert!(tree.remove("tx_001").is_none());
+        tree.insert(sample_tx("tx_001", 1));
+        let root = tree.merkle_root().to_string();
+        assert!(tree.remove("tx_002").is_none());
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.merkle_root(), root);
+    }
+
+    #[test]
+    fn test_remove_to_empty() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.insert(sample_tx("tx_001", 1));
+        let removed = tree.remove("tx_001").unwrap();
+        assert_eq!(removed.id, "tx_001");
+        assert!(tree.is_empty());
+        assert_eq!(tree.merkle_root(), "0");
+    }
+
+    #[test]
+    fn test_removal_keeps_arena_dense() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 1..=50 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        for i in (1..=50).filter(|i| i % 3 != 0) {
+            tree.remove(format!("tx_{:03}", i).as_str());
+            assert_eq!(tree.nodes.len(), tree.len());
+            assert_eq!(tree._in_order().len(), tree.len());
+            assert!(tree.nodes.iter().all(|n| {
+                [n.left, n.right].into_iter().flatten().all(|c| tree.nodes.get(c).is_some())
+            }));
+        }
+        assert_eq!(tree.len(), 16);
+        assert_eq!(tree.node(tree.root_id().unwrap()).unwrap().size, 16);
+        assert!(tree.verify_integrity());
+    }
+
+    #[test]
+    fn test_addresses_interned() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 1..=20 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        let first = tree.search("tx_001").unwrap();
+        let last = tree.search("tx_020").unwrap();
+        assert!(first.from.ptr_eq(&last.from) && first.to.ptr_eq(&last.to));
+        assert_eq!(tree.interned_len(), 2);
+
+        let root = tree.update("tx_005", |tx| tx.to = "C".into()).unwrap();
+        assert_eq!(tree.interned_len(), 3);
+        let restored: CryptoBinaryTree = serde_json::from_str(&serde_json::to_string(&tree).unwrap()).unwrap();
+        assert_eq!(restored.merkle_root(), root);
+        assert_eq!(restored.interned_len(), 3);
+        assert!(restored.search("tx_001").unwrap().from.ptr_eq(&restored.search("tx_005").unwrap().from));
+
+        tree.retain(|tx| tx.to != "C");
+        assert_eq!(tree.interned_len(), 2);
+        tree.clear();
+        assert_eq!(tree.interned_len(), 0);
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 1..=30 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        for i in (1..=30).step_by(4) {
+            tree.remove(format!("tx_{:03}", i).as_str());
+            assert!(tree.check_invariants().is_ok());
+        }
+        let root = tree.root.unwrap();
+        let left = tree.nodes[root].left.unwrap();
+
+        let mut swapped = tree.clone();
+        swapped.nodes.swap_transactions(root, left);
+        assert!(matches!(
+            swapped.check_invariants(),
+            Err(CryptoTreeError::InvariantViolated { invariant: Invariant::KeyOrder, .. })
+        ));
+
+        let mut taller = tree.clone();
+        taller.nodes[left].height += 1;
+        assert!(matches!(
+            taller.check_invariants(),
+            Err(CryptoTreeError::InvariantViolated { invariant: Invariant::Height { .. }, .. })
+        ));
+
+        let mut resized = tree.clone();
+        resized.nodes[root].size -= 1;
+        let id = resized.nodes[root].transaction.id.clone();
+        match resized.check_invariants() {
+            Err(CryptoTreeError::InvariantViolated { id: at, invariant: Invariant::Size { stored, actual } }) => {
+                assert_eq!((at, stored + 1), (id, actual));
+            }
+            other => panic!("expected a size violation, got {:?}", other),
+        }
+        // Stale hashes are a separate check
+        assert!(resized.check_integrity().is_err());
+
+        // A right-leaning chain a -> b -> c with consistent heights and sizes
+        let mut chain = CryptoBinaryTree::new();
+        for id in ["a", "b", "c"] {
+            chain.insert(sample_tx(id, 1));
+        }
+        let find = |tree: &CryptoBinaryTree, id: &str| {
+            NodeId::new(tree.nodes.iter().position(|n| n.transaction.id == id).unwrap())
+        };
+        let (a, b, c) = (find(&chain, "a"), find(&chain, "b"), find(&chain, "c"));
+        chain.root = Some(a);
+        chain.nodes[a].right = Some(b);
+        chain.nodes[b].left = None;
+        chain.nodes[b].right = Some(c);
+        for (id, height) in [(a, 3), (b, 2), (c, 1)] {
+            chain.nodes[id].height = height;
+            chain.nodes[id].size = height as usize;
+        }
+        assert!(matches!(
+            chain.check_invariants(),
+            Err(CryptoTreeError::InvariantViolated { invariant: Invariant::Balance(-2), .. })
+        ));
+        assert!(!chain.verify_invariants());
+    }
+
+    #[test]
+    fn test_remove_rebalances_and_rehashes() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 1..=64 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        // Mix of leaves, single-child and two-children nodes, including the root
+        let root_id = tree.nodes[tree.root.unwrap()].transaction.id.clone();
+        assert!(tree.remove(&root_id).is_some());
+        let mut expected_len = 63;
+        for i in (1..=64).step_by(3) {
+            let id = format!("tx_{:03}", i);
+            if id != root_id {
+                assert_eq!(tree.remove(&id).unwrap().id, id);
+                expected_len -= 1;
+            }
+            assert!(tree.verify_integrity());
+            assert_avl(&tree);
+            assert_eq!(tree.merkle_root(), tree.nodes[tree.root.unwrap()].hash);
+        }
+        assert!(tree.search(&root_id).is_none());
+        assert!(tree.search("tx_002").is_some());
+        assert_eq!(tree.len(), expected_len);
+    }
+
+    #[derive(Serialize, Clone, Debug)]
+    struct Document {
+        path: String,
+        digest: String,
+    }
+
+    impl TreeKey for Document {
+        type Key = String;
+
+        fn key(&self) -> &String {
+            &self.path
+        }
+    }
+
+    #[test]
+    fn test_generic_payload() {
+        let mut tree: CryptoBinaryTree<Document> = CryptoBinaryTree::new();
+        for name in ["b.txt", "a.txt", "c.txt"] {
+            assert!(tree.insert(Document {
+                path: name.to_string(),
+                digest: format!("digest-of-{}", name),
+            }));
+        }
+        assert!(tree.verify_integrity());
+        assert_eq!(tree.search("a.txt").unwrap().digest, "digest-of-a.txt");
+
+        let doc = tree.search("c.txt").unwrap();
+        let proof = tree.get_proof_of_inclusion("c.txt").unwrap();
+        assert!(verify_proof(tree.merkle_root(), doc, &proof));
+        assert_eq!(tree.remove("b.txt").unwrap().path, "b.txt");
+        assert!(tree.verify_integrity());
+    }
+
+    #[derive(Serialize, Clone, Debug)]
+    struct Block {
+        height: u64,
+        digest: [u8; 32],
+    }
+
+    impl TreeKey for Block {
+        type Key = u64;
+
+        fn key(&self) -> &u64 {
+            &self.height
+        }
+    }
+
+    #[derive(Serialize, Clone, Debug)]
+    struct Blob {
+        digest: [u8; 32],
+        size: usize,
+    }
+
+    impl TreeKey for Blob {
+        type Key = [u8; 32];
+
+        fn key(&self) -> &[u8; 32] {
+            &self.digest
+        }
+    }
+
+    #[test]
+    fn test_numeric_keys_sort_numerically() {
+        let mut tree: CryptoBinaryTree<Block> = CryptoBinaryTree::new();
+        for height in [100, 9, 10, 1] {
+            tree.insert(Block { height, digest: [height as u8; 32] });
+        }
+        let order: Vec<u64> = tree.clone().into_iter().map(|b| b.height).collect();
+        assert_eq!(order, [1, 9, 10, 100]);
+        assert_eq!(tree.rank(&10), 2);
+        assert_eq!(tree.successor(&9).unwrap().height, 10);
+        assert!(tree.verify_integrity());
+
+        let proof = tree.get_proof_of_inclusion(&9).unwrap();
+        assert!(verify_proof(tree.merkle_root(), tree.search(&9).unwrap(), &proof));
+        let absence = tree.get_proof_of_absence(&50).unwrap();
+        assert_eq!((absence.predecessor, absence.successor), (Some(10), Some(100)));
+
+        match tree.try_insert(Block { height: 9, digest: [0; 32] }) {
+            Err(CryptoTreeError::DuplicateId(id)) => assert_eq!(id, "9"),
+            other => panic!("expected DuplicateId, got {:?}", other),
+        }
+        assert_eq!(tree.remove(&100).unwrap().height, 100);
+    }
+
+    #[test]
+    fn test_byte_array_keys() {
+        let mut tree: CryptoBinaryTree<Blob> = CryptoBinaryTree::new();
+        for byte in [3u8, 1, 2] {
+            tree.insert(Blob { digest: [byte; 32], size: byte as usize });
+        }
+        assert_eq!(tree.first().unwrap().size, 1);
+        assert_eq!(tree.search(&[2u8; 32]).unwrap().size, 2);
+        assert!(tree.verify_integrity());
+    }
+
+    #[test]
+    fn test_try_insert_errors() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.try_insert(sample_tx("tx_001", 1)).unwrap();
+        match tree.try_insert(sample_tx("tx_001", 1)) {
+            Err(CryptoTreeError::DuplicateId(id)) => assert_eq!(id, "tx_001"),
+            other => panic!("expected DuplicateId, got {:?}", other),
+        }
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn test_check_integrity_reports_node() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 1..=7 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        assert!(tree.check_integrity().is_ok());
+        let left = tree.nodes[tree.root.unwrap()].left.unwrap();
+        tree.nodes[left].transaction.amount = 99;
+        let id = tree.nodes[tree.nodes[tree.root.unwrap()].left.unwrap()].transaction.id.clone();
+        match tree.check_integrity() {
+            Err(CryptoTreeError::CorruptedNode { id: found }) => assert_eq!(found, id),
+            other => panic!("expected CorruptedNode, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_interleaved_operations_keep_invariants() {
+        let mut tree = CryptoBinaryTree::new();
+        let mut present = alloc::collections::BTreeSet::new();
+        // Deterministic pseudo-random sequence of inserts and removes
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..2000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let id = format!("tx_{:04}", state % 500);
+            if state.is_multiple_of(3) {
+                assert_eq!(tree.remove(&id).is_some(), present.remove(&id));
+            } else {
+                assert_eq!(tree.insert(sample_tx(&id, 1)), present.insert(id));
+            }
+        }
+        assert_eq!(tree.len(), present.len());
+        assert_avl(&tree);
+        assert!(tree.check_integrity().is_ok());
+        for id in &present {
+            assert!(tree.search(id).is_some());
+        }
+    }
+
+    #[test]
+    fn test_insert_batch_matches_sequential() {
+        let ids: Vec<String> = (0..300).map(|i| format!("tx_{:03}", (i * 37) % 300)).collect();
+
+        let mut sequential = CryptoBinaryTree::new();
+        for id in &ids {
+            sequential.insert(sample_tx(id, 1));
+        }
+
+        let mut batched = CryptoBinaryTree::new();
+        batched.insert(sample_tx("tx_005", 1));
+        let mut batch: Vec<Transaction> = ids.iter().map(|id| sample_tx(id, 1)).collect();
+        batch.push(sample_tx("tx_010", 1));
+        let result = batched.insert_batch(batch);
+
+        assert_eq!(result.inserted, 299);
+        assert_eq!(result.duplicates, vec!["tx_005".to_string(), "tx_010".to_string()]);
+        assert!(result.failed.is_empty());
+        assert_eq!(batched.len(), 300);
+        assert_avl(&batched);
+        assert!(batched.check_integrity().is_ok());
+        assert_eq!(batched.merkle_root(), batched.nodes[batched.root.unwrap()].hash);
+        assert!(batched.search("tx_299").is_some());
+    }
+
+    #[test]
+    fn test_insert_batch_empty() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.insert(sample_tx("tx_001", 1));
+        let root = tree.merkle_root().to_string();
+        let result = tree.insert_batch(Vec::new());
+        assert_eq!(result.inserted, 0);
+        assert_eq!(tree.merkle_root(), root);
+    }
+
+    #[test]
+    fn test_lazy_hashing_matches_eager() {
+        let ids: Vec<String> = (0..200).map(|i| format!("tx_{:03}", (i * 37) % 200)).collect();
+
+        let mut eager = CryptoBinaryTree::new();
+        let mut lazy = CryptoBinaryTree::new();
+        lazy.set_lazy_hashing(true);
+        for id in &ids {
+            eager.insert(sample_tx(id, 1));
+            lazy.insert(sample_tx(id, 1));
+        }
+        for id in ["tx_007", "tx_100", "tx_199"] {
+            eager.remove(id);
+            lazy.remove(id);
+        }
+        assert!(lazy.has_pending_hashes());
+        assert_avl(&lazy);
+
+        assert_eq!(lazy.flush_hashes(), eager.merkle_root());
+        assert!(!lazy.has_pending_hashes());
+        assert!(lazy.structurally_equal(&eager));
+
+        // `update` flushes before returning the new root
+        lazy.insert(sample_tx("tx_200", 1));
+        eager.insert(sample_tx("tx_200", 1));
+        let root = lazy.update("tx_050", |tx| tx.amount = 7).unwrap();
+        assert_eq!(root, eager.update("tx_050", |tx| tx.amount = 7).unwrap());
+        assert!(lazy.check_integrity().is_ok());
+
+        lazy.insert(sample_tx("tx_201", 1));
+        lazy.set_lazy_hashing(false);
+        assert!(!lazy.has_pending_hashes());
+        let proof = lazy.get_proof_of_inclusion("tx_201").unwrap();
+        assert!(verify_proof(lazy.merkle_root(), lazy.search("tx_201").unwrap(), &proof));
+    }
+
+    #[test]
+    #[should_panic(expected = "pending hashes")]
+    fn test_lazy_hashing_requires_flush() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.set_lazy_hashing(true);
+        tree.insert(sample_tx("tx_001", 1));
+        tree.insert(sample_tx("tx_002", 1));
+        tree.merkle_root();
+    }
+
+    #[test]
+    fn test_lazy_hashing_flushes_on_read() {
+        let mut eager = CryptoBinaryTree::new();
+        let mut lazy = CryptoBinaryTree::new();
+        lazy.set_lazy_hashing(true);
+        for i in 0..50 {
+            eager.insert(sample_tx(&format!("tx_{:02}", i), 1));
+            lazy.insert(sample_tx(&format!("tx_{:02}", i), 1));
+        }
+        assert!(lazy.has_pending_hashes());
+        let proof = lazy.proof("tx_17").unwrap();
+        assert!(!lazy.has_pending_hashes());
+        assert!(verify_proof(eager.merkle_root(), lazy.search("tx_17").unwrap(), &proof));
+
+        eager.remove("tx_30");
+        lazy.remove("tx_30");
+        assert!(lazy.has_pending_hashes());
+        assert_eq!(lazy.root(), eager.merkle_root());
+        assert_eq!(lazy.merkle_root(), eager.merkle_root());
+        assert!(lazy.proof("tx_30").is_none());
+    }
+
+    #[test]
+    fn test_from_sorted() {
+        let txs: Vec<Transaction> = (1..=1000).map(|i| sample_tx(&format!("tx_{:04}", i), 1)).collect();
+        let tree = CryptoBinaryTree::from_sorted(txs).unwrap();
+        assert_eq!(tree.len(), 1000);
+        assert_eq!(assert_avl(&tree), 10);
+        assert!(tree.check_integrity().is_ok());
+        assert!(tree.search("tx_0500").is_some());
+
+        let tx = tree.search("tx_0777").unwrap();
+        let proof = tree.get_proof_of_inclusion("tx_0777").unwrap();
+        assert!(verify_proof(tree.merkle_root(), tx, &proof));
+
+        let empty = TransactionTree::from_sorted(Vec::new()).unwrap();
+        assert_eq!(empty.merkle_root(), "0");
+    }
+
+    #[test]
+    fn test_from_sorted_rejects_bad_input() {
+        let unsorted = vec![sample_tx("tx_2", 1), sample_tx("tx_1", 1)];
+        assert!(matches!(
+            TransactionTree::from_sorted(unsorted),
+            Err(CryptoTreeError::UnsortedInput(id)) if id == "tx_1"
+        ));
+        let duplicated = vec![sample_tx("tx_1", 1), sample_tx("tx_1", 1)];
+        assert!(matches!(TransactionTree::from_sorted(duplicated), Err(CryptoTreeError::DuplicateId(_))));
+    }
+
+    #[test]
+    fn test_first_last_and_pop() {
+        let mut tree = CryptoBinaryTree::new();
+        assert!(tree.first().is_none());
+        assert!(tree.pop_last().is_none());
+
+        for id in ["tx_050", "tx_010", "tx_090", "tx_030", "tx_070"] {
+            tree.insert(sample_tx(id, 1));
+        }
+        assert_eq!(tree.first().unwrap().id, "tx_010");
+        assert_eq!(tree.last().unwrap().id, "tx_090");
+
+        assert_eq!(tree.pop_first().unwrap().id, "tx_010");
+        assert_eq!(tree.pop_last().unwrap().id, "tx_090");
+        assert_eq!(tree.first().unwrap().id, "tx_030");
+        assert_eq!(tree.last().unwrap().id, "tx_070");
+        assert_eq!(tree.len(), 3);
+        assert!(tree.check_integrity().is_ok());
+    }
+
+    #[test]
+    fn test_successor_predecessor() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in (10..=100).step_by(10) {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        // Present ids
+        assert_eq!(tree.successor("tx_050").unwrap().id, "tx_060");
+        assert_eq!(tree.predecessor("tx_050").unwrap().id, "tx_040");
+        // Absent ids
+        assert_eq!(tree.successor("tx_055").unwrap().id, "tx_060");
+        assert_eq!(tree.predecessor("tx_055").unwrap().id, "tx_050");
+        assert_eq!(tree.successor("tx_000").unwrap().id, "tx_010");
+        // Boundaries
+        assert!(tree.successor("tx_100").is_none());
+        assert!(tree.predecessor("tx_010").is_none());
+        assert!(TransactionTree::new().successor("tx_001").is_none());
+    }
+
+    #[test]
+    fn test_select_and_rank() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in (0..200).rev() {
+            tree.insert(sample_tx(&format!("tx_{:03}", i * 2), 1));
+        }
+        for i in (0..200).step_by(7) {
+            tree.remove(&format!("tx_{:03}", i * 2));
+        }
+        let ids: Vec<String> = (0..200)
+            .filter(|i| i % 7 != 0)
+            .map(|i| format!("tx_{:03}", i * 2))
+            .collect();
+        assert_eq!(tree.nodes[tree.root.unwrap()].size, ids.len());
+
+        for (k, id) in ids.iter().enumerate() {
+            assert_eq!(&tree.select(k).unwrap().id, id);
+            assert_eq!(tree.rank(id), k);
+        }
+        assert!(tree.select(ids.len()).is_none());
+        assert_eq!(tree.rank("tx_003"), 1); // only tx_002 is smaller
+        assert_eq!(tree.rank("tx_999"), ids.len());
+    }
+
+    #[test]
+    fn test_subtree_size_is_committed() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 1..=15 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        assert!(tree.check_integrity().is_ok());
+        tree.nodes[tree.root.unwrap()].size += 1;
+        assert!(tree.check_integrity().is_err());
+    }
+
+    #[test]
+    fn test_update_and_upsert() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 1..=20 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        let before = tree.merkle_root().to_string();
+
+        let root = tree.update("tx_007", |tx| tx.amount = 999).unwrap();
+        assert_eq!(root, tree.merkle_root());
+        assert_ne!(root, before);
+        assert_eq!(tree.search("tx_007").unwrap().amount, 999);
+        assert!(tree.verify_integrity());
+
+        let mut replacement = sample_tx("tx_007", 1);
+        replacement.amount = 2;
+        tree.upsert(replacement).unwrap();
+        assert_eq!(tree.search("tx_007").unwrap().amount, 2);
+        assert_eq!(tree.len(), 20);
+
+        let root = tree.upsert(sample_tx("tx_021", 1)).unwrap();
+        assert_eq!(root, tree.merkle_root());
+        assert_eq!(tree.len(), 21);
+        assert!(tree.verify_integrity());
+    }
+
+    #[test]
+    fn test_update_errors_leave_tree_unchanged() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 1..=10 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        let before = tree.merkle_root().to_string();
+
+        assert!(matches!(tree.update("tx_999", |_| {}), Err(CryptoTreeError::NotFound(_))));
+        let result = tree.update("tx_005", |tx| {
+            tx.id = "tx_500".to_string();
+            tx.amount = 0;
+        });
+        assert!(matches!(result, Err(CryptoTreeError::KeyChanged(_))));
+        assert_eq!(tree.search("tx_005").unwrap().amount, 1);
+        assert_eq!(tree.merkle_root(), before);
+        assert!(tree.verify_integrity());
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 0..300 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        let untouched = tree.merkle_root().to_string();
+        tree.retain(|_| true);
+        assert_eq!(tree.merkle_root(), untouched);
+
+        tree.retain(|tx| !tx.id.ends_with('7'));
+        assert_eq!(tree.len(), 270);
+        assert!(tree.search("tx_017").is_none());
+        assert!(tree.search("tx_018").is_some());
+        assert_avl(&tree);
+        assert!(tree.verify_integrity());
+
+        // Same result as removing one by one
+        let mut expected = CryptoBinaryTree::new();
+        for i in 0..300 {
+            expected.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        for i in (0..300).filter(|i| i % 10 == 7) {
+            expected.remove(&format!("tx_{:03}", i));
+        }
+        assert_eq!(tree.merkle_root(), expected.merkle_root());
+
+        tree.retain(|_| false);
+        assert!(tree.is_empty());
+        assert_eq!(tree.merkle_root(), "0");
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 0..100 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        tree.clear();
+        assert!(tree.is_empty());
+        assert!(tree.root.is_none());
+        assert_eq!(tree.merkle_root(), "0");
+        assert!(tree.verify_integrity());
+
+        tree.insert(sample_tx("tx_001", 1));
+        assert_eq!(tree.len(), 1);
+        assert!(tree.verify_integrity());
+    }
+
+    #[test]
+    fn test_clone_and_equality() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 1..=50 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 1));
+        }
+        let snapshot = tree.clone();
+        assert!(snapshot == tree);
+        assert!(snapshot.structurally_equal(&tree));
+
+        tree.insert(sample_tx("tx_051", 1));
+        assert!(snapshot != tree);
+        assert_eq!(snapshot.len(), 50);
+        assert!(snapshot.verify_integrity());
+
+        // Same contents, different shape
+        let ids: Vec<_> = (1..=50).map(|i| sample_tx(&format!("tx_{:03}", i), 1)).collect();
+        let sorted = CryptoBinaryTree::from_sorted(ids).unwrap();
+        assert!(!sorted.structurally_equal(&snapshot));
+
+        // Stale hashes only show up structurally
+        let mut tampered = snapshot.clone();
+        tampered.nodes[tampered.root.unwrap()].transaction.amount = 7;
+        assert!(tampered == snapshot);
+        assert!(!tampered.structurally_equal(&snapshot));
+    }
+
+    #[test]
+    fn test_content_ids() {
+        let mut tx = sample_tx("placeholder", 1);
+        tx.id = tx.compute_id();
+        assert_eq!(tx.id.len(), 64);
+        assert!(tx.check_id().is_ok());
+
+        let mut other = tx.clone();
+        other.amount += 1;
+        assert_ne!(other.compute_id(), tx.id);
+        assert!(matches!(other.check_id(), Err(CryptoTreeError::IdMismatch { .. })));
+
+        let mut tree = CryptoBinaryTree::new();
+        tree.require_content_ids();
+        assert!(!tree.insert(sample_tx("tx_1", 1)));
+        tree.try_insert(tx.clone()).unwrap();
+        // A second payload claiming the same id is refused before the duplicate check
+        assert!(matches!(tree.try_insert(other), Err(CryptoTreeError::IdMismatch { .. })));
+        assert!(tree.update(&tx.id, |stored| stored.amount = 5).is_err());
+    }
+
+    #[test]
+    fn test_u128_amounts() {
+        #[derive(Serialize)]
+        struct LegacyTransaction {
+            id: String,
+            from: String,
+            to: String,
+            amount: u64,
+            timestamp: Option<u64>,
+        }
+
+        // Amounts within u64 encode exactly as before
+        let tx = sample_tx("tx_1", 1);
+        let legacy = LegacyTransaction {
+            id: tx.id.clone(),
+            from: tx.from.to_string(),
+            to: tx.to.to_string(),
+            amount: 1,
+            timestamp: None,
+        };
+        assert_eq!(encode_canonical(&tx).unwrap(), encode_canonical(&legacy).unwrap());
+        assert_eq!(serde_json::to_string(&tx).unwrap(), serde_json::to_string(&legacy).unwrap());
+
+        let mut big = sample_tx("tx_2", 1);
+        big.amount = u128::from(u64::MAX) + 1;
+        let json = serde_json::to_string(&big).unwrap();
+        assert!(json.contains("\"amount\":18446744073709551616"));
+        assert_eq!(serde_json::from_str::<Transaction>(&json).unwrap(), big);
+
+        let mut tree = CryptoBinaryTree::new();
+        tree.insert(tx);
+        tree.insert(big);
+        assert!(tree.verify_integrity());
+        let proof = tree.get_proof_of_inclusion("tx_2").unwrap();
+        assert!(verify_proof(tree.merkle_root(), tree.search("tx_2").unwrap(), &proof));
+    }
+
+    #[test]
+    fn test_metadata_is_committed() {
+        let mut tree = CryptoBinaryTree::new();
+        let plain = sample_tx("tx_1", 1);
+        tree.insert(plain.clone());
+        let root = tree.merkle_root().to_string();
+        assert!(!serde_json::to_string(&plain).unwrap().contains("metadata"));
+
+        let root_with = |entries: &[(&str, serde_json::Value)]| {
+            let mut tx = sample_tx("tx_1", 1);
+            for (key, value) in entries {
+                tx.metadata.insert(key.to_string(), value.clone());
+            }
+            let mut tree = CryptoBinaryTree::new();
+            tree.insert(tx);
+            tree.merkle_root().to_string()
+        };
+        let fee = ("fee", serde_json::json!(25));
+        let memo = ("memo", serde_json::json!({"invoice": 42, "note": "rent"}));
+        let with_both = root_with(&[fee.clone(), memo.clone()]);
+        assert_ne!(with_both, root);
+        assert_eq!(with_both, root_with(&[memo.clone(), fee.clone()]));
+        assert_ne!(with_both, root_with(&[fee, ("memo", serde_json::json!({"invoice": 43, "note": "rent"}))]));
+
+        tree.update("tx_1", |tx| {
+            tx.metadata.insert("nonce".to_string(), serde_json::json!(7));
+        })
+        .unwrap();
+        assert_ne!(tree.merkle_root(), root);
+        let proof = tree.get_proof_of_inclusion("tx_1").unwrap();
+        assert!(verify_proof(tree.merkle_root(), tree.search("tx_1").unwrap(), &proof));
+    }
+
+    #[test]
+    fn test_data_attachments() {
+        let mut tx = sample_tx("tx_1", 1);
+        tx.data = vec![0xde, 0xad, 0xbe, 0xef];
+        let json = serde_json::to_string(&tx).unwrap();
+        assert!(json.contains("\"data\":\"deadbeef\""));
+        assert_eq!(serde_json::from_str::<Transaction>(&json).unwrap(), tx);
+        assert!(serde_json::from_str::<Transaction>(&json.replace("deadbeef", "xyz")).is_err());
+
+        let mut tree = CryptoBinaryTree::new();
+        tree.set_max_data_size(Some(4));
+        tree.try_insert(tx).unwrap();
+        assert_eq!(tree.data("tx_1"), Some(&[0xde, 0xad, 0xbe, 0xef][..]));
+        assert_eq!(tree.data("tx_missing"), None);
+
+        let mut big = sample_tx("tx_2", 1);
+        big.data = vec![0; 5];
+        assert!(matches!(
+            tree.try_insert(big.clone()),
+            Err(CryptoTreeError::DataTooLarge { size: 5, limit: 4, .. })
+        ));
+
+        let root = tree.merkle_root().to_string();
+        tree.update("tx_1", |tx| tx.data[0] = 0).unwrap();
+        assert_ne!(tree.merkle_root(), root);
+
+        tree.set_max_data_size(None);
+        assert!(tree.insert(big));
+    }
 }
@@ -0,0 +1,186 @@
+//! Verification-only surface for wallets, browsers and other light clients.
+//!
+//! Everything here checks what a full node hands out — inclusion proofs,
+//! signed roots and consistency between two roots of an append-only log —
+//! without building or holding a tree. Roots go in and come out as
+//! [`Hash256`] rather than hex strings.
+//!
+//! Build with `default-features = false, features = ["light"]` to leave out
+//! the tree, its arena, snapshots and the write-ahead log; the module also
+//! works without `std`.
+
+use core::fmt;
+use core::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hasher::{from_hex, to_hex};
+use crate::prelude::*;
+use crate::proof::proof_root;
+use crate::Result as TreeResult;
+
+pub use crate::hasher::{Sha256Hasher, TreeHasher};
+pub use crate::mmr::MmrConsistencyProof;
+pub use crate::proof::{Proof, ProofStep, Side};
+#[cfg(feature = "ed25519")]
+pub use crate::signed_root::SignedRoot;
+#[cfg(feature = "ed25519")]
+pub use crate::signature::VerifyingKey;
+
+/// A 256-bit digest, such as a SHA-256 Merkle root
+///
+/// Roots and node hashes travel as lowercase hex strings; parse them into a
+/// `Hash256` to hold them in fixed size and compare them byte for byte.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Hash256(pub [u8; 32]);
+
+impl Hash256 {
+    /// Parses 64 hex digits, either case; `None` for anything else.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        from_hex(hex).and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()).map(Hash256)
+    }
+
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.0)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl FromStr for Hash256 {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Hash256::from_hex(s).ok_or_else(|| format!("not a 256-bit hex digest: {}", s))
+    }
+}
+
+impl TryFrom<String> for Hash256 {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+impl From<Hash256> for String {
+    fn from(hash: Hash256) -> String {
+        hash.to_hex()
+    }
+}
+
+impl PartialEq<str> for Hash256 {
+    fn eq(&self, other: &str) -> bool {
+        Hash256::from_hex(other) == Some(*self)
+    }
+}
+
+/// Verifies an inclusion proof against a trusted SHA-256 root; see
+/// [`crate::verify_proof`].
+pub fn verify_proof<T: Serialize>(root: &Hash256, transaction: &T, proof: &[ProofStep<T>]) -> bool {
+    verify_proof_with(&Sha256Hasher::default(), root, transaction, proof)
+}
+
+/// Like [`verify_proof`], for trees built with a custom hasher.
+pub fn verify_proof_with<T: Serialize, H: TreeHasher>(
+    hasher: &H,
+    root: &Hash256,
+    transaction: &T,
+    proof: &[ProofStep<T>],
+) -> bool {
+    check_proof_with(hasher, root, transaction, proof).is_ok()
+}
+
+/// Like [`verify_proof_with`], reporting why a proof was rejected.
+pub fn check_proof_with<T: Serialize, H: TreeHasher>(
+    hasher: &H,
+    root: &Hash256,
+    transaction: &T,
+    proof: &[ProofStep<T>],
+) -> TreeResult<()> {
+    crate::proof::check_proof_with(hasher, &root.to_hex(), transaction, proof)
+}
+
+/// The root an inclusion proof leads to, or `None` for a malformed proof or
+/// a hasher whose digests are not 256 bits.
+///
+/// Lets a client that pins roots by some other means look the result up
+/// instead of passing in every root it trusts.
+pub fn proven_root<T: Serialize, H: TreeHasher>(hasher: &H, transaction: &T, proof: &[ProofStep<T>]) -> Option<Hash256> {
+    proof_root(hasher, transaction, proof).ok().and_then(|root| Hash256::from_hex(&root))
+}
+
+/// Checks that `new_root` extends `old_root` of the same append-only log;
+/// see [`crate::verify_consistency`].
+pub fn verify_consistency<H: TreeHasher>(old_root: &Hash256, new_root: &Hash256, proof: &MmrConsistencyProof, hasher: &H) -> bool {
+    crate::mmr::verify_consistency(&old_root.to_hex(), &new_root.to_hex(), proof, hasher)
+}
+
+/// The root `signed` vouches for, if it is signed by `public_key`.
+#[cfg(feature = "ed25519")]
+pub fn verify_signed_root(signed: &SignedRoot, public_key: &VerifyingKey) -> Option<Hash256> {
+    if !signed.verify(public_key) {
+        return None;
+    }
+    Hash256::from_hex(&signed.root)
+}
+
+#[cfg(all(test, feature = "tree"))]
+mod tests {
+    use super::*;
+    use crate::{CryptoBinaryTree, MerkleMountainRange};
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_light_client_checks() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 0..10 {
+            tree.insert(sample_tx(&format!("tx_{}", i), 10));
+        }
+        let root: Hash256 = tree.merkle_root().parse().unwrap();
+        assert!(root == *tree.merkle_root());
+        assert_eq!(serde_json::to_string(&root).unwrap(), format!("{:?}", tree.merkle_root()));
+        assert!(Hash256::from_hex("abc").is_none());
+
+        // The client only sees the proof and the root it trusts
+        let proof = tree.get_proof_of_inclusion("tx_4").unwrap();
+        let tx = tree.search("tx_4").unwrap();
+        assert!(verify_proof(&root, tx, &proof));
+        assert_eq!(proven_root(&Sha256Hasher::new(), tx, &proof), Some(root));
+        assert!(!verify_proof(&Hash256::default(), tx, &proof));
+        assert!(check_proof_with(&Sha256Hasher::new(), &root, &sample_tx("tx_4", 11), &proof).is_err());
+
+        let mut log: MerkleMountainRange = MerkleMountainRange::new();
+        log.append(sample_tx("tx_a", 1)).unwrap();
+        let trusted: Hash256 = log.root().parse().unwrap();
+        log.append(sample_tx("tx_b", 1)).unwrap();
+        let latest: Hash256 = log.root().parse().unwrap();
+        let proof = log.prove_consistency(1).unwrap();
+        assert!(verify_consistency(&trusted, &latest, &proof, &Sha256Hasher::new()));
+        assert!(!verify_consistency(&latest, &trusted, &proof, &Sha256Hasher::new()));
+    }
+
+    #[cfg(feature = "ed25519")]
+    #[test]
+    fn test_signed_root_yields_hash() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.insert(sample_tx("tx_1", 10));
+        tree.set_root_signer(Some(crate::SigningKey::from_bytes(&[7; 32])));
+        let public_key = tree.root_verifying_key().unwrap();
+        let head = tree.signed_root_at(1_700_000_000).unwrap();
+
+        let root = verify_signed_root(&head, &public_key).unwrap();
+        assert!(root == *tree.merkle_root());
+        let stranger = crate::SigningKey::from_bytes(&[8; 32]).verifying_key();
+        assert!(verify_signed_root(&head, &stranger).is_none());
+    }
+}
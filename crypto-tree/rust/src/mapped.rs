@@ -0,0 +1,407 @@
+//! A flat, read-only tree format for memory-mapping.
+//!
+//! [`CryptoBinaryTree::save_mapped`] lays the tree out as a fixed-size node
+//! table followed by the node payloads. [`MappedTree`] answers searches and
+//! builds inclusion proofs straight from those bytes, typically a memory map
+//! of the file, decoding only the nodes on the search path. Opening is O(1)
+//! however large the tree, so a read-mostly audit server can serve tens of
+//! millions of transactions without loading them into memory.
+//!
+//! Layout, little-endian:
+//!
+//! - header, 48 bytes: `MAPPED_MAGIC`, `u16` version, `u8` hash format tag and
+//!   the two `BinaryV3` domain prefixes (zero otherwise), `u8` hash
+//!   algorithm, `u8` flags, three zero bytes, `u64` node count, `u64` offset
+//!   of the node table, `u64` offset of the blob area, eight zero bytes;
+//! - node table, 32 bytes per node in pre-order, the root first: `u32` left
+//!   and right child index (`u32::MAX` for none), `i32` height, `u32` subtree
+//!   size, `u64` blob offset relative to the blob area, `u32` blob length,
+//!   four zero bytes;
+//! - blobs: `u16` hash length and the hash, `u32` key length and the JSON
+//!   key, then the JSON payload.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::arena::Arena;
+use crate::snapshot::{algorithm_from_tag, algorithm_tag, read_format, write_format};
+use crate::{
+    CryptoBinaryTree, HashAlgorithm, HashFormat, NodeId, ProofStep, Side, SnapshotError, TreeHasher, TreeKey,
+    MAX_TREE_HEIGHT,
+};
+
+/// Magic bytes at the start of every mapped tree file
+pub const MAPPED_MAGIC: &[u8; 6] = b"CTMMAP";
+
+/// Current mapped tree format version
+pub const MAPPED_VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 48;
+const RECORD_LEN: usize = 32;
+const NO_CHILD: u32 = u32::MAX;
+
+/// Header flag: node hashes are salted (see [`TreeHasher::salt`])
+const SALTED: u8 = 0b01;
+
+impl<T, H> CryptoBinaryTree<T, H>
+where
+    T: TreeKey + Serialize + Clone,
+    H: TreeHasher,
+{
+    /// Writes the tree in the mapped format described in the `mapped` module
+    /// to `path`, for reading with [`MappedTree`].
+    pub fn save_mapped<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_mapped(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the mapped format to any writer.
+    ///
+    /// Fails with `Io` if the tree has `u32::MAX` nodes or more.
+    pub fn write_mapped<W: Write>(&self, writer: &mut W) -> Result<(), SnapshotError> {
+        self._assert_hashed();
+        if self.len() >= NO_CHILD as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many nodes for the mapped format").into());
+        }
+        let mut table = Vec::with_capacity(self.len() * RECORD_LEN);
+        let mut blobs = Vec::new();
+        if let Some(root) = self.root {
+            write_record(&self.nodes, root, &mut table, &mut blobs)?;
+        }
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAPPED_MAGIC);
+        header.extend_from_slice(&MAPPED_VERSION.to_le_bytes());
+        write_format(&mut header, self.hasher.format())?;
+        header.resize(11, 0);
+        header.push(algorithm_tag(self.hasher.algorithm()));
+        header.push(if self.hasher.salt().is_some() { SALTED } else { 0 });
+        header.resize(16, 0);
+        header.extend_from_slice(&(self.len() as u64).to_le_bytes());
+        header.extend_from_slice(&(HEADER_LEN as u64).to_le_bytes());
+        header.extend_from_slice(&((HEADER_LEN + table.len()) as u64).to_le_bytes());
+        header.resize(HEADER_LEN, 0);
+
+        writer.write_all(&header)?;
+        writer.write_all(&table)?;
+        writer.write_all(&blobs)?;
+        Ok(())
+    }
+}
+
+/// Appends the record of `id` and its subtree in pre-order, returning its index.
+fn write_record<T: TreeKey + Serialize>(
+    nodes: &Arena<T>,
+    id: NodeId,
+    table: &mut Vec<u8>,
+    blobs: &mut Vec<u8>,
+) -> Result<u32, SnapshotError> {
+    let n = &nodes[id];
+    let index = table.len() / RECORD_LEN;
+    table.resize(table.len() + RECORD_LEN, 0);
+
+    let offset = blobs.len();
+    let encode = |e: serde_json::Error| SnapshotError::Corrupted(format!("payload could not be encoded: {}", e));
+    let key = serde_json::to_vec(n.transaction.key()).map_err(encode)?;
+    blobs.extend_from_slice(&(n.hash.len() as u16).to_le_bytes());
+    blobs.extend_from_slice(n.hash.as_bytes());
+    blobs.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    blobs.extend_from_slice(&key);
+    serde_json::to_writer(&mut *blobs, &n.transaction).map_err(encode)?;
+    let blob_len = blobs.len() - offset;
+
+    let left = n.left.map(|l| write_record(nodes, l, table, blobs)).transpose()?;
+    let right = n.right.map(|r| write_record(nodes, r, table, blobs)).transpose()?;
+    let record = &mut table[index * RECORD_LEN..][..RECORD_LEN];
+    record[0..4].copy_from_slice(&left.unwrap_or(NO_CHILD).to_le_bytes());
+    record[4..8].copy_from_slice(&right.unwrap_or(NO_CHILD).to_le_bytes());
+    record[8..12].copy_from_slice(&n.height.to_le_bytes());
+    record[12..16].copy_from_slice(&(n.size as u32).to_le_bytes());
+    record[16..24].copy_from_slice(&(offset as u64).to_le_bytes());
+    record[24..28].copy_from_slice(&(blob_len as u32).to_le_bytes());
+    Ok(index as u32)
+}
+
+/// A tree read in place from the bytes written by `write_mapped`
+///
+/// Every lookup decodes the nodes it visits from the bytes, so a damaged
+/// file surfaces as `SnapshotError::Corrupted` from the lookup that reaches
+/// the damage rather than from `new`. Hashes are not recomputed; check
+/// proofs against a trusted root with `verify_proof`.
+#[derive(Debug, Clone, Copy)]
+pub struct MappedTree<'a, T> {
+    bytes: &'a [u8],
+    len: usize,
+    table: usize,
+    blobs: usize,
+    format: HashFormat,
+    algorithm: Option<HashAlgorithm>,
+    salted: bool,
+    _payload: PhantomData<fn() -> T>,
+}
+
+/// One decoded node table record
+struct Record<'a> {
+    left: Option<usize>,
+    right: Option<usize>,
+    height: i32,
+    size: usize,
+    hash: &'a str,
+    key: &'a [u8],
+    payload: &'a [u8],
+}
+
+fn corrupted(what: &str) -> SnapshotError {
+    SnapshotError::Corrupted(format!("mapped tree: {}", what))
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn le_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+impl<'a, T> MappedTree<'a, T>
+where
+    T: TreeKey + DeserializeOwned,
+    T::Key: DeserializeOwned,
+{
+    /// Checks the header and the bounds of the node table, in O(1).
+    pub fn new(bytes: &'a [u8]) -> Result<Self, SnapshotError> {
+        let header = bytes.get(..HEADER_LEN).ok_or_else(|| corrupted("truncated header"))?;
+        if &header[..6] != MAPPED_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = u16::from_le_bytes([header[6], header[7]]);
+        if version != MAPPED_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let format = read_format(&mut &header[8..11])?;
+        let algorithm = algorithm_from_tag(header[11])?;
+        let to_usize = |n: u64| usize::try_from(n).map_err(|_| corrupted("offset out of range"));
+        let len = to_usize(le_u64(header, 16))?;
+        let table = to_usize(le_u64(header, 24))?;
+        let blobs = to_usize(le_u64(header, 32))?;
+        let table_end = len
+            .checked_mul(RECORD_LEN)
+            .and_then(|n| n.checked_add(table))
+            .ok_or_else(|| corrupted("node count out of range"))?;
+        if table < HEADER_LEN || table_end > blobs || blobs > bytes.len() || len >= NO_CHILD as usize {
+            return Err(corrupted("node table out of bounds"));
+        }
+        Ok(MappedTree {
+            bytes,
+            len,
+            table,
+            blobs,
+            format,
+            algorithm,
+            salted: header[12] & SALTED != 0,
+            _payload: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Node hash layout the tree was written with
+    pub fn hash_format(&self) -> HashFormat {
+        self.format
+    }
+
+    /// Hash function the tree was written with, `None` for a custom hasher
+    pub fn hash_algorithm(&self) -> Option<HashAlgorithm> {
+        self.algorithm
+    }
+
+    pub fn is_salted(&self) -> bool {
+        self.salted
+    }
+
+    /// Merkle root as stored with the root node, `"0"` for an empty tree
+    pub fn merkle_root(&self) -> Result<&'a str, SnapshotError> {
+        match self.len {
+            0 => Ok("0"),
+            _ => Ok(self.record(0)?.hash),
+        }
+    }
+
+    fn record(&self, index: usize) -> Result<Record<'a>, SnapshotError> {
+        let bytes = self.bytes;
+        let at = self.table + index * RECORD_LEN;
+        let child = |at| match le_u32(bytes, at) {
+            NO_CHILD => Ok(None),
+            i if (i as usize) < self.len => Ok(Some(i as usize)),
+            _ => Err(corrupted("child index out of range")),
+        };
+        let (left, right) = (child(at)?, child(at + 4)?);
+        let height = i32::from_le_bytes(bytes[at + 8..at + 12].try_into().unwrap());
+        let size = le_u32(bytes, at + 12) as usize;
+        let blob = usize::try_from(le_u64(bytes, at + 16))
+            .ok()
+            .and_then(|offset| self.blobs.checked_add(offset))
+            .and_then(|start| bytes.get(start..start.checked_add(le_u32(bytes, at + 24) as usize)?))
+            .ok_or_else(|| corrupted("blob out of bounds"))?;
+
+        let hash_len = usize::from(u16::from_le_bytes(blob.get(..2).ok_or_else(|| corrupted("truncated blob"))?.try_into().unwrap()));
+        let hash = blob.get(2..2 + hash_len).ok_or_else(|| corrupted("truncated hash"))?;
+        let hash = std::str::from_utf8(hash).map_err(|_| corrupted("hash is not UTF-8"))?;
+        let rest = &blob[2 + hash_len..];
+        let key_len = rest.get(..4).ok_or_else(|| corrupted("truncated key"))?;
+        let key_len = u32::from_le_bytes(key_len.try_into().unwrap()) as usize;
+        let key = rest.get(4..4 + key_len).ok_or_else(|| corrupted("truncated key"))?;
+        Ok(Record {
+            left,
+            right,
+            height,
+            size,
+            hash,
+            key,
+            payload: &rest[4 + key_len..],
+        })
+    }
+
+    fn payload(record: &Record<'_>) -> Result<T, SnapshotError> {
+        serde_json::from_slice(record.payload).map_err(|e| corrupted(&format!("undecodable payload: {}", e)))
+    }
+
+    /// Walks from the root towards `key`, handing each record and the way
+    /// the walk continues to `visit`, and returns the matching record.
+    fn descend<Q>(&self, key: &Q, mut visit: impl FnMut(&Record<'a>, Ordering) -> Result<(), SnapshotError>) -> Result<Option<Record<'a>>, SnapshotError>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut current = if self.len == 0 { None } else { Some(0) };
+        let mut depth = 0;
+        while let Some(index) = current {
+            depth += 1;
+            if depth > MAX_TREE_HEIGHT {
+                return Err(corrupted("search path too long"));
+            }
+            let record = self.record(index)?;
+            let stored: T::Key =
+                serde_json::from_slice(record.key).map_err(|e| corrupted(&format!("undecodable key: {}", e)))?;
+            let ordering = key.cmp(stored.borrow());
+            current = match ordering {
+                Ordering::Equal => return Ok(Some(record)),
+                Ordering::Less => record.left,
+                Ordering::Greater => record.right,
+            };
+            visit(&record, ordering)?;
+        }
+        Ok(None)
+    }
+
+    /// Decodes the transaction with the given id.
+    pub fn search<Q>(&self, tx_id: &Q) -> Result<Option<T>, SnapshotError>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.descend(tx_id, |_, _| Ok(()))?.map(|r| Self::payload(&r)).transpose()
+    }
+
+    /// Builds the same proof as `CryptoBinaryTree::get_proof_of_inclusion`.
+    pub fn get_proof_of_inclusion<Q>(&self, tx_id: &Q) -> Result<Option<Vec<ProofStep<T>>>, SnapshotError>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let hash_of = |child: Option<usize>| -> Result<Option<&'a str>, SnapshotError> {
+            child.map(|i| self.record(i).map(|r| r.hash)).transpose()
+        };
+        let mut proof = Vec::new();
+        let found = self.descend(tx_id, |record, ordering| {
+            let (side, sibling) = match ordering {
+                Ordering::Less => (Side::Right, record.right),
+                _ => (Side::Left, record.left),
+            };
+            let sibling = hash_of(sibling)?.unwrap_or("0").to_string();
+            proof.push(ProofStep::new(side, sibling, record.height, record.size, Some(Self::payload(record)?)));
+            Ok(())
+        })?;
+        let Some(target) = found else {
+            return Ok(None);
+        };
+        if let Some(left) = hash_of(target.left)? {
+            proof.push(ProofStep::new(Side::Left, left.to_string(), target.height, target.size, None));
+        }
+        if let Some(right) = hash_of(target.right)? {
+            proof.push(ProofStep::new(Side::Right, right.to_string(), target.height, target.size, None));
+        }
+        Ok(Some(proof))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_proof, Transaction, TransactionTree};
+    use crate::test_util::build_tree;
+
+    #[test]
+    fn test_mapped_matches_tree() {
+        let tree = build_tree(1000);
+        let mut bytes = Vec::new();
+        tree.write_mapped(&mut bytes).unwrap();
+        let mapped = MappedTree::<Transaction>::new(&bytes).unwrap();
+        assert_eq!(mapped.len(), 1000);
+        assert_eq!(mapped.merkle_root().unwrap(), tree.merkle_root());
+        assert_eq!(mapped.hash_algorithm(), Some(HashAlgorithm::Sha256));
+
+        for id in ["tx_001", "tx_500", "tx_1000"] {
+            let tx = mapped.search(id).unwrap().unwrap();
+            assert_eq!(&tx, tree.search(id).unwrap());
+            let proof = mapped.get_proof_of_inclusion(id).unwrap().unwrap();
+            let expected = tree.get_proof_of_inclusion(id).unwrap();
+            assert_eq!(serde_json::to_string(&proof).unwrap(), serde_json::to_string(&expected).unwrap());
+            assert!(verify_proof(tree.merkle_root(), &tx, &proof));
+        }
+        assert!(mapped.search("tx_9999").unwrap().is_none());
+
+        let mut empty = Vec::new();
+        TransactionTree::new().write_mapped(&mut empty).unwrap();
+        let empty = MappedTree::<Transaction>::new(&empty).unwrap();
+        assert_eq!((empty.len(), empty.merkle_root().unwrap()), (0, "0"));
+        assert!(empty.search("tx_001").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_mapped_rejects_damage() {
+        let tree = build_tree(20);
+        let mut bytes = Vec::new();
+        tree.write_mapped(&mut bytes).unwrap();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(MappedTree::<Transaction>::new(&bad_magic), Err(SnapshotError::BadMagic)));
+        assert!(MappedTree::<Transaction>::new(&bytes[..HEADER_LEN + 10]).is_err());
+
+        // Point the root's left child back at the root, forming a cycle
+        let mut cyclic = bytes.clone();
+        cyclic[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&0u32.to_le_bytes());
+        let mapped = MappedTree::<Transaction>::new(&cyclic).unwrap();
+        assert!(matches!(mapped.search("tx_001"), Err(SnapshotError::Corrupted(_))));
+
+        // Cut into the blobs: lookups reaching them fail instead of panicking
+        let mapped = MappedTree::<Transaction>::new(&bytes[..bytes.len() - 50]).unwrap();
+        assert!((1..=20).any(|i| mapped.search(format!("tx_{:03}", i).as_str()).is_err()));
+    }
+}
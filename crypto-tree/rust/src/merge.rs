@@ -0,0 +1,278 @@
+//! Merging the transactions of one tree into another.
+
+use core::fmt;
+
+use serde::Serialize;
+
+use crate::arena::{Arena, NodeId};
+use crate::index::SecondaryIndex;
+use crate::prelude::*;
+use crate::{key_string, AuditOp, ChangeEvent, CryptoBinaryTree, CryptoTreeError, CryptoTreeNode, Result, TreeEvent, TreeHasher, TreeKey};
+
+type ResolveFn<'a, T> = dyn FnMut(&T, T) -> T + 'a;
+
+/// How [`CryptoBinaryTree::merge`] resolves a key both trees hold with
+/// different contents
+pub enum MergePolicy<'a, T> {
+    /// Keep this tree's transaction
+    KeepExisting,
+    /// Replace it with the other tree's
+    TakeOther,
+    /// Fail the merge with `DuplicateId`, leaving the tree unchanged
+    ErrorOnConflict,
+    /// Call the closure with this tree's and the other tree's transaction and
+    /// store what it returns, which must keep the key
+    Custom(Box<ResolveFn<'a, T>>),
+}
+
+impl<T> fmt::Debug for MergePolicy<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MergePolicy::KeepExisting => "KeepExisting",
+            MergePolicy::TakeOther => "TakeOther",
+            MergePolicy::ErrorOnConflict => "ErrorOnConflict",
+            MergePolicy::Custom(_) => "Custom",
+        })
+    }
+}
+
+/// Outcome of `CryptoBinaryTree::merge`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    /// Transactions whose keys were new to this tree
+    pub added: usize,
+    /// Conflicts resolved by storing a different transaction
+    pub replaced: usize,
+    /// Conflicts resolved by keeping this tree's transaction
+    pub kept: usize,
+}
+
+impl<T: TreeKey + Serialize + Clone + PartialEq, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Adds the transactions of `other` to this tree, resolving keys both
+    /// hold with `policy`. Transactions stored identically in both trees are
+    /// not conflicts.
+    ///
+    /// Every change is checked before the first is made: a conflict under
+    /// `ErrorOnConflict`, a custom resolution that changes the key
+    /// (`KeyChanged`), a transaction the validator or a policy rejects, or
+    /// one that cannot be encoded fails the merge and leaves the tree
+    /// unchanged. With the index enabled, the changes are applied to the
+    /// running balances in `other`'s key order, and one that breaks the
+    /// ledger rules or overflows a balance fails the whole merge the same
+    /// way. As with `insert_batch`,
+    /// changed nodes are rehashed once at the end, or on `flush_hashes`
+    /// with lazy hashing, and the index is kept up to date.
+    pub fn merge<H2>(&mut self, other: CryptoBinaryTree<T, H2>, mut policy: MergePolicy<'_, T>) -> Result<MergeResult> {
+        self.flush_hashes();
+        let format = self.hasher.format();
+        let mut result = MergeResult::default();
+        let mut changes = Vec::new();
+        for theirs in other {
+            let (_, found) = Self::_search_path(&self.nodes, self.root, theirs.key());
+            let Some(id) = found else {
+                changes.push((theirs, None));
+                continue;
+            };
+            let ours = &self.nodes[id].transaction;
+            if *ours == theirs {
+                continue;
+            }
+            let resolved = match &mut policy {
+                MergePolicy::KeepExisting => None,
+                MergePolicy::TakeOther => Some(theirs),
+                MergePolicy::ErrorOnConflict => return Err(CryptoTreeError::DuplicateId(key_string(theirs.key()))),
+                MergePolicy::Custom(resolve) => {
+                    let key = theirs.key().clone();
+                    let resolved = resolve(ours, theirs);
+                    if *resolved.key() != key {
+                        return Err(CryptoTreeError::KeyChanged(key_string(&key)));
+                    }
+                    (resolved != *ours).then_some(resolved)
+                }
+            };
+            match resolved {
+                Some(resolved) => changes.push((resolved, Some(id))),
+                None => result.kept += 1,
+            }
+        }
+        for (transaction, _) in &changes {
+            self._admit(transaction)?;
+            crate::encoding::encode_node_in(format, transaction, None, None, 1, 1)?;
+        }
+        if let Some(index) = self.index.as_mut() {
+            Self::_index_changes(index, &self.nodes, &changes)?;
+        }
+
+        let audited = if self.audit.is_some() { changes.iter().map(|(t, _)| key_string(t.key())).collect() } else { Vec::new() };
+        let changed = !changes.is_empty();
+        let before = self.nodes.work;
+        for (mut transaction, existing) in changes {
+            transaction.intern(&mut self.interner);
+            if let Some(id) = existing {
+                let (path, _) = Self::_search_path(&self.nodes, self.root, transaction.key());
+                if self.subscribers.is_active() {
+                    self.subscribers.send(ChangeEvent::Updated(transaction.clone()));
+                }
+                self.nodes[id].transaction = transaction;
+                self.nodes[id].hash.clear();
+                self.nodes[id].digest = None;
+                self.root = Self::_reattach(&mut self.nodes, path, Some(id), None);
+                result.replaced += 1;
+            } else {
+                if self.subscribers.is_active() {
+                    self.subscribers.send(ChangeEvent::Inserted(transaction.clone()));
+                }
+                let leaf = CryptoTreeNode::unhashed(transaction);
+                if Self::_insert_leaf(&mut self.nodes, &mut self.root, leaf, None).is_err() {
+                    unreachable!("merged keys are unique and checked against the tree");
+                }
+                result.added += 1;
+            }
+        }
+
+        if let Some(observer) = self._observer() {
+            observer.on_event(&TreeEvent::BatchInserted {
+                inserted: result.added,
+                duplicates: result.kept,
+                failed: 0,
+                rotations: self.nodes.work.since(before).rotations,
+            });
+        }
+        if !self.lazy_hashing {
+            self.flush_hashes();
+        }
+        if changed {
+            self._audit(AuditOp::Merge, audited);
+        }
+        Ok(result)
+    }
+
+    /// Applies the index side of `changes` in order, each checked against the
+    /// balances left by the ones before it. On the first rejection the
+    /// applied ones are undone and the error returned.
+    fn _index_changes(index: &mut SecondaryIndex<T>, nodes: &Arena<T>, changes: &[(T, Option<NodeId>)]) -> Result<()> {
+        for (applied, (transaction, existing)) in changes.iter().enumerate() {
+            let checked = match existing {
+                Some(id) => index.replace(&nodes[*id].transaction, transaction),
+                None => index.admit(transaction).map(|entry| index.add(entry)),
+            };
+            if let Err(e) = checked {
+                for (transaction, existing) in changes[..applied].iter().rev() {
+                    index.remove(transaction);
+                    if let Some(id) = existing {
+                        index.add(index.entry(&nodes[*id].transaction));
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::{CryptoBinaryTree, CryptoTreeError, MergePolicy, Transaction};
+    use crate::test_util::sample_tx;
+
+    /// Two regions sharing the keys 50..100, with different amounts from 75 on
+    fn regions() -> (CryptoBinaryTree, CryptoBinaryTree) {
+        let (mut east, mut west) = (CryptoBinaryTree::new(), CryptoBinaryTree::new());
+        for i in 0..100 {
+            east.insert(sample_tx(&format!("tx_{:03}", i), 10));
+        }
+        for i in 50..150 {
+            west.insert(sample_tx(&format!("tx_{:03}", i), if i < 75 { 10 } else { 20 }));
+        }
+        (east, west)
+    }
+
+    #[test]
+    fn test_merge_policies() {
+        let (mut east, west) = regions();
+        let result = east.merge(west.clone(), MergePolicy::KeepExisting).unwrap();
+        assert_eq!((result.added, result.replaced, result.kept), (50, 0, 25));
+        assert_eq!(east.len(), 150);
+        assert_eq!(east.search("tx_080").unwrap().amount, 10);
+        assert!(east.verify_integrity() && east.verify_invariants());
+
+        let (mut east, west) = regions();
+        east.enable_index();
+        east.merge(west.clone(), MergePolicy::TakeOther).unwrap();
+        assert_eq!(east.search("tx_080").unwrap().amount, 20);
+        let expected: CryptoBinaryTree = CryptoBinaryTree::from_sorted(
+            (0..150).map(|i| sample_tx(&format!("tx_{:03}", i), if i < 75 { 10 } else { 20 })).collect(),
+        )
+        .unwrap();
+        assert!(east.diff(&expected).is_empty());
+        assert_eq!(east.transactions_from("Alice").len(), 150);
+
+        let (mut east, west) = regions();
+        let root = east.merkle_root().to_string();
+        assert!(matches!(
+            east.merge(west.clone(), MergePolicy::ErrorOnConflict),
+            Err(CryptoTreeError::DuplicateId(id)) if id == "tx_075"
+        ));
+        assert_eq!(east.merkle_root(), root);
+
+        let sum = |ours: &Transaction, mut theirs: Transaction| {
+            theirs.amount += ours.amount;
+            theirs
+        };
+        east.merge(west, MergePolicy::Custom(Box::new(sum))).unwrap();
+        assert_eq!(east.search("tx_080").unwrap().amount, 30);
+        assert_eq!(east.search("tx_060").unwrap().amount, 10);
+        assert!(east.verify_integrity() && east.verify_invariants());
+    }
+
+    #[test]
+    fn test_merge_checks_ledger_rules() {
+        use crate::LedgerRules;
+
+        let payment = |id: &str, from: &str, to: &str, amount: u128| Transaction {
+            id: id.to_string(),
+            from: from.into(),
+            to: to.into(),
+            amount,
+            ..Default::default()
+        };
+        let mut local = CryptoBinaryTree::new();
+        local.set_ledger_rules(LedgerRules::new().with_exempt("mint"));
+        local.insert(payment("tx_1", "mint", "Alice", 50));
+        let root = local.merkle_root().to_string();
+
+        // tx_2 alone is covered, but with tx_3 Alice sends 60 of her 50
+        let mut remote = CryptoBinaryTree::new();
+        remote.insert(payment("tx_2", "Alice", "Bob", 40));
+        remote.insert(payment("tx_3", "Alice", "Carol", 20));
+        let err = local.merge(remote, MergePolicy::KeepExisting).unwrap_err();
+        assert!(matches!(err, CryptoTreeError::InsufficientBalance { ref id, balance: 10, amount: 20, .. } if id == "tx_3"));
+        assert_eq!(local.len(), 1);
+        assert_eq!(local.merkle_root(), root);
+        assert_eq!((local.balance("Alice"), local.balance("Bob")), (50, 0));
+
+        // Raising the mint conflicts with tx_1 and funds both payments
+        let mut remote = CryptoBinaryTree::new();
+        remote.insert(payment("tx_1", "mint", "Alice", 60));
+        remote.insert(payment("tx_2", "Alice", "Bob", 40));
+        remote.insert(payment("tx_3", "Alice", "Carol", 20));
+        let result = local.merge(remote.clone(), MergePolicy::TakeOther).unwrap();
+        assert_eq!((result.added, result.replaced), (2, 1));
+        assert_eq!(local.balance("Alice"), 0);
+
+        // A resolved conflict is checked against the balance without the
+        // transaction it replaces, and earlier changes are undone with it
+        let root = local.merkle_root().to_string();
+        let mut remote = CryptoBinaryTree::new();
+        remote.insert(payment("tx_0", "mint", "Dave", 5));
+        remote.insert(payment("tx_2", "Alice", "Bob", 41));
+        assert!(matches!(
+            local.merge(remote, MergePolicy::TakeOther),
+            Err(CryptoTreeError::InsufficientBalance { balance: 40, amount: 41, .. })
+        ));
+        assert_eq!(local.len(), 3);
+        assert_eq!(local.merkle_root(), root);
+        assert_eq!((local.balance("Alice"), local.balance("Bob"), local.balance("Dave")), (0, 40, 0));
+    }
+}
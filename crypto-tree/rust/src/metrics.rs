@@ -0,0 +1,270 @@
+//! Operation counters for monitoring (`metrics` feature).
+//!
+//! [`TreeMetrics`] is a [`TreeObserver`] that counts what the trees it is
+//! installed on do, in lock-free atomics. Read the totals with
+//! [`TreeMetrics::snapshot`] or render them in the Prometheus text format
+//! with [`TreeMetrics::render_prometheus`], e.g. from a `/metrics` endpoint.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::{CryptoTreeError, TreeEvent, TreeObserver};
+
+/// Upper bounds of the histogram buckets; larger values land in `+Inf` only
+pub const BUCKETS: [u64; 8] = [0, 1, 2, 4, 8, 16, 32, 64];
+
+/// Distribution of a per-operation count, e.g. proof steps
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations at most `BUCKETS[i]`, not cumulative
+    buckets: [AtomicU64; BUCKETS.len()],
+    sum: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, value: u64) {
+        if let Some(i) = BUCKETS.iter().position(|&bound| value <= bound) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = self
+            .buckets
+            .iter()
+            .map(|b| {
+                cumulative += b.load(Ordering::Relaxed);
+                cumulative
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            sum: self.sum.load(Ordering::Relaxed),
+            count: self.count.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Totals of a histogram at one point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistogramSnapshot {
+    /// Observations at most the matching bound in [`BUCKETS`], cumulative
+    pub buckets: Vec<u64>,
+    pub sum: u64,
+    pub count: u64,
+}
+
+/// Counters fed by tree events; install with `set_observer(Arc::new(..))`
+#[derive(Debug, Default)]
+pub struct TreeMetrics {
+    inserts: AtomicU64,
+    duplicates_rejected: AtomicU64,
+    inserts_rejected: AtomicU64,
+    removals: AtomicU64,
+    updates: AtomicU64,
+    rotations: AtomicU64,
+    hash_computations: AtomicU64,
+    proofs_generated: AtomicU64,
+    integrity_checks: AtomicU64,
+    verify_failures: AtomicU64,
+    insert_rotations: Histogram,
+    proof_steps: Histogram,
+}
+
+/// Totals of a [`TreeMetrics`] at one point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// Transactions inserted, one by one or in batches
+    pub inserts: u64,
+    /// Inserts refused with `DuplicateId`, including batch duplicates
+    pub duplicates_rejected: u64,
+    /// Inserts refused for any other reason, e.g. by a validator
+    pub inserts_rejected: u64,
+    pub removals: u64,
+    /// Successful in-place updates
+    pub updates: u64,
+    /// AVL rotations performed while rebalancing
+    pub rotations: u64,
+    /// Node hashes recomputed
+    pub hash_computations: u64,
+    pub proofs_generated: u64,
+    pub integrity_checks: u64,
+    /// Integrity checks that found a corrupted node
+    pub verify_failures: u64,
+    /// Rotations per single insert
+    pub insert_rotations: HistogramSnapshot,
+    /// Steps per inclusion proof, about the depth of the proven node
+    pub proof_steps: HistogramSnapshot,
+}
+
+fn add(counter: &AtomicU64, n: u64) {
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+impl TreeMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads every counter. Counters are read one at a time, so a snapshot
+    /// taken while trees are busy may mix totals from consecutive operations.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        MetricsSnapshot {
+            inserts: load(&self.inserts),
+            duplicates_rejected: load(&self.duplicates_rejected),
+            inserts_rejected: load(&self.inserts_rejected),
+            removals: load(&self.removals),
+            updates: load(&self.updates),
+            rotations: load(&self.rotations),
+            hash_computations: load(&self.hash_computations),
+            proofs_generated: load(&self.proofs_generated),
+            integrity_checks: load(&self.integrity_checks),
+            verify_failures: load(&self.verify_failures),
+            insert_rotations: self.insert_rotations.snapshot(),
+            proof_steps: self.proof_steps.snapshot(),
+        }
+    }
+
+    /// Renders every counter in the Prometheus text exposition format,
+    /// with metric names prefixed by `crypto_tree_`.
+    pub fn render_prometheus(&self) -> String {
+        let s = self.snapshot();
+        let mut out = String::new();
+        let counters = [
+            ("inserts_total", "Transactions inserted", s.inserts),
+            ("duplicates_rejected_total", "Inserts refused for a duplicate id", s.duplicates_rejected),
+            ("inserts_rejected_total", "Inserts refused for another reason", s.inserts_rejected),
+            ("removals_total", "Transactions removed", s.removals),
+            ("updates_total", "Transactions updated in place", s.updates),
+            ("rotations_total", "AVL rotations performed", s.rotations),
+            ("hash_computations_total", "Node hashes recomputed", s.hash_computations),
+            ("proofs_generated_total", "Inclusion proofs built", s.proofs_generated),
+            ("integrity_checks_total", "Integrity checks run", s.integrity_checks),
+            ("verify_failures_total", "Integrity checks that found a corrupted node", s.verify_failures),
+        ];
+        for (name, help, value) in counters {
+            let _ = write!(
+                out,
+                "# HELP crypto_tree_{name} {help}\n# TYPE crypto_tree_{name} counter\ncrypto_tree_{name} {value}\n"
+            );
+        }
+        let histograms = [
+            ("insert_rotations", "Rotations per insert", &s.insert_rotations),
+            ("proof_steps", "Steps per inclusion proof", &s.proof_steps),
+        ];
+        for (name, help, h) in histograms {
+            let _ = write!(out, "# HELP crypto_tree_{name} {help}\n# TYPE crypto_tree_{name} histogram\n");
+            for (bound, count) in BUCKETS.iter().zip(&h.buckets) {
+                let _ = writeln!(out, "crypto_tree_{name}_bucket{{le=\"{bound}\"}} {count}");
+            }
+            let _ = write!(
+                out,
+                "crypto_tree_{name}_bucket{{le=\"+Inf\"}} {count}\ncrypto_tree_{name}_sum {sum}\ncrypto_tree_{name}_count {count}\n",
+                count = h.count,
+                sum = h.sum
+            );
+        }
+        out
+    }
+}
+
+impl TreeObserver for TreeMetrics {
+    fn on_event(&self, event: &TreeEvent<'_>) {
+        match *event {
+            TreeEvent::Inserted { rotations, hashes, .. } => {
+                add(&self.inserts, 1);
+                add(&self.rotations, rotations);
+                add(&self.hash_computations, hashes);
+                self.insert_rotations.observe(rotations);
+            }
+            TreeEvent::InsertRejected {
+                error: CryptoTreeError::DuplicateId(_),
+                ..
+            } => add(&self.duplicates_rejected, 1),
+            TreeEvent::InsertRejected { .. } => add(&self.inserts_rejected, 1),
+            TreeEvent::BatchInserted {
+                inserted,
+                duplicates,
+                failed,
+                rotations,
+            } => {
+                add(&self.inserts, inserted as u64);
+                add(&self.duplicates_rejected, duplicates as u64);
+                add(&self.inserts_rejected, failed as u64);
+                add(&self.rotations, rotations);
+            }
+            TreeEvent::Removed { rotations, hashes, .. } => {
+                add(&self.removals, 1);
+                add(&self.rotations, rotations);
+                add(&self.hash_computations, hashes);
+            }
+            TreeEvent::Updated { hashes, error, .. } => {
+                if error.is_none() {
+                    add(&self.updates, 1);
+                }
+                add(&self.hash_computations, hashes);
+            }
+            TreeEvent::Flushed { hashes } => add(&self.hash_computations, hashes),
+            TreeEvent::ProofGenerated { steps, .. } => {
+                add(&self.proofs_generated, 1);
+                self.proof_steps.observe(steps as u64);
+            }
+            TreeEvent::IntegrityChecked { corrupted, .. } => {
+                add(&self.integrity_checks, 1);
+                if corrupted.is_some() {
+                    add(&self.verify_failures, 1);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+
+    use super::*;
+    use crate::CryptoBinaryTree;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_metrics_count_operations() {
+        let metrics = Arc::new(TreeMetrics::new());
+        let mut tree = CryptoBinaryTree::new();
+        tree.set_observer(metrics.clone());
+        for i in 1..=3 {
+            tree.insert(sample_tx(&format!("tx_{}", i), 10));
+        }
+        tree.insert(sample_tx("tx_1", 10));
+        tree.insert_batch(vec![sample_tx("tx_4", 10), sample_tx("tx_2", 10)]);
+        tree.get_proof_of_inclusion("tx_1").unwrap();
+        tree.remove("tx_4");
+        let root = tree.root.unwrap();
+        tree.nodes[root].transaction.amount = 11;
+        assert!(!tree.verify_integrity());
+
+        let s = metrics.snapshot();
+        assert_eq!((s.inserts, s.duplicates_rejected, s.inserts_rejected), (4, 2, 0));
+        assert_eq!((s.removals, s.rotations, s.proofs_generated), (1, 1, 1));
+        assert_eq!((s.integrity_checks, s.verify_failures), (1, 1));
+        // 0 + 1 + 4 on the single inserts, 3 to flush the batch, 2 on the removal
+        assert_eq!(s.hash_computations, 10);
+        assert_eq!(s.insert_rotations.buckets[..3], [2, 3, 3]);
+        assert_eq!((s.insert_rotations.sum, s.insert_rotations.count), (1, 3));
+        assert_eq!((s.proof_steps.sum, s.proof_steps.count), (1, 1));
+
+        let text = metrics.render_prometheus();
+        assert!(text.contains("# TYPE crypto_tree_inserts_total counter\ncrypto_tree_inserts_total 4\n"));
+        assert!(text.contains("crypto_tree_verify_failures_total 1\n"));
+        assert!(text.contains("crypto_tree_insert_rotations_bucket{le=\"1\"} 3\n"));
+        assert!(text.contains("crypto_tree_proof_steps_bucket{le=\"+Inf\"} 1\ncrypto_tree_proof_steps_sum 1\n"));
+    }
+}
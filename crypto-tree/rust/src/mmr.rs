@@ -0,0 +1,322 @@
+//! Merkle Mountain Range for append-only logs.
+//!
+//! A [`MerkleMountainRange`] keeps transactions in arrival order as a list of
+//! perfect binary trees, the peaks, one for each set bit of the leaf count.
+//! Appending a leaf merges equal-height peaks, which costs one hash plus one
+//! per merge, amortized O(1), and never touches older nodes. The root bags
+//! the peaks together from right to left, and a [`MmrProof`] shows that a
+//! transaction is the leaf at a given index. Since old nodes never change,
+//! an [`MmrConsistencyProof`] shows that a later root extends an earlier
+//! one: every peak of the earlier range is a node under the later peaks.
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::sparse::node_hash;
+use crate::{encode_canonical, Result, Sha256Hasher, Transaction, TreeHasher};
+
+fn leaf_hash<T: Serialize, H: TreeHasher>(hasher: &H, transaction: &T) -> Result<String> {
+    let mut data = vec![0];
+    data.extend(encode_canonical(transaction)?);
+    Ok(hasher.hash(&data))
+}
+
+/// Bags `peaks` from right to left into a root, `"0"` for none
+fn bag_peaks<H: TreeHasher>(hasher: &H, peaks: &[&str]) -> String {
+    let Some((last, rest)) = peaks.split_last() else {
+        return "0".to_string();
+    };
+    rest.iter().rev().fold(last.to_string(), |acc, peak| node_hash(hasher, peak, &acc))
+}
+
+/// Heights of the peaks of a range with `leaf_count` leaves, left to right,
+/// each with the index of its first leaf
+fn peak_heights(leaf_count: u64) -> impl Iterator<Item = (usize, u64)> {
+    (0..u64::BITS as usize).rev().filter(move |&h| leaf_count >> h & 1 == 1).scan(0, |first, h| {
+        let start = *first;
+        *first += 1 << h;
+        Some((h, start))
+    })
+}
+
+/// Position among the peaks, and height, of the peak holding leaf `index`
+fn peak_of(leaf_count: u64, index: u64) -> Option<(usize, usize)> {
+    peak_heights(leaf_count)
+        .enumerate()
+        .find(|&(_, (h, start))| index >= start && index - start < 1 << h)
+        .map(|(position, (h, _))| (position, h))
+}
+
+/// Proof that a transaction is the leaf at `leaf_index` of a range
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MmrProof {
+    pub leaf_index: u64,
+    /// Number of leaves in the range the proof was made against
+    pub leaf_count: u64,
+    /// Sibling hashes from the leaf up to its peak
+    pub siblings: Vec<String>,
+    /// Every peak of the range, left to right
+    pub peaks: Vec<String>,
+}
+
+impl MmrProof {
+    /// Checks that `transaction` is the leaf at `leaf_index` of the range
+    /// with root `root`.
+    pub fn verify<T: Serialize, H: TreeHasher>(&self, root: &str, transaction: &T, hasher: &H) -> bool {
+        let Some((position, height)) = peak_of(self.leaf_count, self.leaf_index) else {
+            return false;
+        };
+        if self.siblings.len() != height || self.peaks.len() != self.leaf_count.count_ones() as usize {
+            return false;
+        }
+        let Ok(mut current) = leaf_hash(hasher, transaction) else {
+            return false;
+        };
+        for (h, sibling) in self.siblings.iter().enumerate() {
+            current = if self.leaf_index >> h & 1 == 1 {
+                node_hash(hasher, sibling, &current)
+            } else {
+                node_hash(hasher, &current, sibling)
+            };
+        }
+        let peaks: Vec<&str> = self.peaks.iter().map(String::as_str).collect();
+        current == peaks[position] && bag_peaks(hasher, &peaks) == root
+    }
+}
+
+/// Proof that the range with `new_len` leaves extends the one with `old_len`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MmrConsistencyProof {
+    pub old_len: u64,
+    pub new_len: u64,
+    /// Peaks of the earlier range, left to right
+    pub old_peaks: Vec<String>,
+    /// For each earlier peak, the sibling hashes from it up to its later peak
+    pub paths: Vec<Vec<String>>,
+    /// Peaks of the later range, left to right
+    pub new_peaks: Vec<String>,
+}
+
+/// Checks that `proof` shows the range with root `new_root` to be the one
+/// with root `old_root` with leaves appended.
+pub fn verify_consistency<H: TreeHasher>(old_root: &str, new_root: &str, proof: &MmrConsistencyProof, hasher: &H) -> bool {
+    let old_count = proof.old_len.count_ones() as usize;
+    if proof.old_len > proof.new_len
+        || proof.old_peaks.len() != old_count
+        || proof.paths.len() != old_count
+        || proof.new_peaks.len() != proof.new_len.count_ones() as usize
+    {
+        return false;
+    }
+    let old_peaks: Vec<&str> = proof.old_peaks.iter().map(String::as_str).collect();
+    let new_peaks: Vec<&str> = proof.new_peaks.iter().map(String::as_str).collect();
+    if bag_peaks(hasher, &old_peaks) != old_root || bag_peaks(hasher, &new_peaks) != new_root {
+        return false;
+    }
+    peak_heights(proof.old_len).zip(old_peaks).zip(&proof.paths).all(|(((height, start), peak), path)| {
+        let Some((position, top)) = peak_of(proof.new_len, start) else {
+            return false;
+        };
+        if path.len() + height != top {
+            return false;
+        }
+        let mut current = peak.to_string();
+        for (h, sibling) in (height..).zip(path) {
+            current = if start >> h & 1 == 1 {
+                node_hash(hasher, sibling, &current)
+            } else {
+                node_hash(hasher, &current, sibling)
+            };
+        }
+        current == new_peaks[position]
+    })
+}
+
+/// An append-only log of transactions committed to by a Merkle Mountain Range
+///
+/// Leaves are addressed by their index in arrival order; there is no search
+/// by id.
+#[derive(Debug, Clone)]
+pub struct MerkleMountainRange<T = Transaction, H = Sha256Hasher> {
+    hasher: H,
+    leaves: Vec<T>,
+    /// Node hashes by height; `levels[h][i]` covers leaves `i << h..(i + 1) << h`
+    levels: Vec<Vec<String>>,
+}
+
+impl<T: Serialize> MerkleMountainRange<T> {
+    /// Creates an empty range hashed with SHA-256.
+    pub fn new() -> Self {
+        Self::with_hasher(Sha256Hasher::new())
+    }
+}
+
+impl<T: Serialize> Default for MerkleMountainRange<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Serialize, H: TreeHasher> MerkleMountainRange<T, H> {
+    /// Creates an empty range whose nodes are hashed with `hasher`.
+    pub fn with_hasher(hasher: H) -> Self {
+        MerkleMountainRange {
+            hasher,
+            leaves: Vec::new(),
+            levels: Vec::new(),
+        }
+    }
+
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Appends a transaction and returns its leaf index.
+    ///
+    /// Fails with `SerializationFailed`, leaving the range unchanged, if the
+    /// transaction cannot be encoded for hashing.
+    pub fn append(&mut self, transaction: T) -> Result<u64> {
+        let mut current = leaf_hash(&self.hasher, &transaction)?;
+        let index = self.leaves.len() as u64;
+        self.leaves.push(transaction);
+        let mut position = index;
+        for height in 0.. {
+            if self.levels.len() == height {
+                self.levels.push(Vec::new());
+            }
+            let level = &mut self.levels[height];
+            level.push(current);
+            if position & 1 == 0 {
+                break;
+            }
+            current = node_hash(&self.hasher, &level[level.len() - 2], &level[level.len() - 1]);
+            position >>= 1;
+        }
+        Ok(index)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.leaves.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// The transaction at `index`
+    pub fn get(&self, index: u64) -> Option<&T> {
+        self.leaves.get(usize::try_from(index).ok()?)
+    }
+
+    /// Transactions in arrival order
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &T> + '_ {
+        self.leaves.iter()
+    }
+
+    /// Hashes of the peaks, left to right
+    pub fn peaks(&self) -> Vec<&str> {
+        peak_heights(self.len()).map(|(h, start)| self.levels[h][(start >> h) as usize].as_str()).collect()
+    }
+
+    /// The peaks bagged into one hash, `"0"` while the range is empty
+    pub fn root(&self) -> String {
+        bag_peaks(&self.hasher, &self.peaks())
+    }
+
+    /// Builds a proof that the transaction at `index` is in the range.
+    pub fn prove(&self, index: u64) -> Option<MmrProof> {
+        let (_, height) = peak_of(self.len(), index)?;
+        let siblings = (0..height).map(|h| self.levels[h][((index >> h) ^ 1) as usize].clone()).collect();
+        Some(MmrProof {
+            leaf_index: index,
+            leaf_count: self.len(),
+            siblings,
+            peaks: self.peaks().into_iter().map(str::to_string).collect(),
+        })
+    }
+
+    /// Builds a proof that the current range extends the one it was when it
+    /// held `old_len` leaves, checked with `verify_consistency` against the
+    /// root it had then.
+    pub fn prove_consistency(&self, old_len: u64) -> Option<MmrConsistencyProof> {
+        if old_len > self.len() {
+            return None;
+        }
+        let (mut old_peaks, mut paths) = (Vec::new(), Vec::new());
+        for (height, start) in peak_heights(old_len) {
+            let (_, top) = peak_of(self.len(), start)?;
+            old_peaks.push(self.levels[height][(start >> height) as usize].clone());
+            paths.push((height..top).map(|h| self.levels[h][((start >> h) ^ 1) as usize].clone()).collect());
+        }
+        Some(MmrConsistencyProof {
+            old_len,
+            new_len: self.len(),
+            old_peaks,
+            paths,
+            new_peaks: self.peaks().into_iter().map(str::to_string).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_mmr_proofs() {
+        let mut mmr: MerkleMountainRange = MerkleMountainRange::new();
+        assert_eq!(mmr.root(), "0");
+        let hasher = Sha256Hasher::new();
+        let mut roots = Vec::new();
+        for i in 0..37 {
+            assert_eq!(mmr.append(sample_tx(&format!("tx_{}", i), i)).unwrap(), i as u64);
+            roots.push(mmr.root());
+        }
+        // 37 = 32 + 4 + 1
+        assert_eq!(mmr.peaks().len(), 3);
+
+        let root = mmr.root();
+        for i in 0..37 {
+            let proof = mmr.prove(i).unwrap();
+            assert!(proof.verify(&root, mmr.get(i).unwrap(), &hasher), "leaf {}", i);
+        }
+        assert!(mmr.prove(37).is_none());
+
+        let proof = mmr.prove(33).unwrap();
+        assert_eq!(proof.siblings.len(), 2);
+        assert!(!proof.verify(&root, &sample_tx("tx_33", 34), &hasher));
+        assert!(!proof.verify(&roots[35], mmr.get(33).unwrap(), &hasher));
+        let mut moved = proof.clone();
+        moved.leaf_index = 32;
+        assert!(!moved.verify(&root, mmr.get(33).unwrap(), &hasher));
+    }
+
+    #[test]
+    fn test_mmr_consistency() {
+        let hasher = Sha256Hasher::new();
+        let mut mmr: MerkleMountainRange = MerkleMountainRange::new();
+        let mut roots = vec![mmr.root()];
+        for i in 0..21 {
+            mmr.append(sample_tx(&format!("tx_{}", i), i)).unwrap();
+            roots.push(mmr.root());
+        }
+        for old_len in 0..=21 {
+            let proof = mmr.prove_consistency(old_len).unwrap();
+            assert!(verify_consistency(&roots[old_len as usize], &roots[21], &proof, &hasher), "from {}", old_len);
+        }
+        assert!(mmr.prove_consistency(22).is_none());
+
+        let proof = mmr.prove_consistency(13).unwrap();
+        assert!(!verify_consistency(&roots[12], &roots[21], &proof, &hasher));
+        // A range that rewrote history is not consistent with its past root
+        let mut forked: MerkleMountainRange = MerkleMountainRange::new();
+        for i in 0..21 {
+            forked.append(sample_tx(&format!("tx_{}", i), if i == 5 { 0 } else { i })).unwrap();
+        }
+        let mut forged = forked.prove_consistency(13).unwrap();
+        assert!(!verify_consistency(&roots[13], &forked.root(), &forged, &hasher));
+        forged.old_peaks = proof.old_peaks.clone();
+        assert!(!verify_consistency(&roots[13], &forked.root(), &forged, &hasher));
+    }
+}
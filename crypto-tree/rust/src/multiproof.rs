@@ -0,0 +1,214 @@
+use alloc::collections::BTreeMap;
+use core::borrow::Borrow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::{
+    encode_canonical, key_string, CryptoBinaryTree, CryptoTreeError, Result, Sha256Hasher,
+    Transaction, TreeHasher, TreeKey,
+};
+
+/// One entry of a [`MultiProof`], in pre-order
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiProofEntry<T = Transaction> {
+    /// A node on the path to at least one target; followed by the entries of
+    /// its left subtree and then its right subtree
+    Node { transaction: T, height: i32, size: usize },
+    /// Hash of a subtree no target lies in, `"0"` when empty
+    Pruned(String),
+}
+
+/// A single proof covering many transactions at once.
+///
+/// It is the part of the tree spanned by the search paths to all targets,
+/// listed in pre-order: every node on a path appears once with its
+/// transaction, every subtree off the paths is replaced by its hash. Shared
+/// ancestors are therefore included (and rehashed) only once.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MultiProof<T = Transaction> {
+    pub entries: Vec<MultiProofEntry<T>>,
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Builds one proof that every id in `tx_ids` is stored in the tree.
+    ///
+    /// Duplicate ids are proven once. Returns `None` if any id is missing.
+    /// Use [`verify_multi_proof`] to check the result against a Merkle root.
+    pub fn get_multi_proof<Q>(&self, tx_ids: &[&Q]) -> Option<MultiProof<T>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self._assert_hashed();
+        let mut ids = tx_ids.to_vec();
+        ids.sort();
+        ids.dedup();
+
+        let mut entries = Vec::new();
+        let mut stack = vec![(self.root, &ids[..])];
+        while let Some((node, ids)) = stack.pop() {
+            let Some(n) = node.map(|id| &self.nodes[id]) else {
+                if !ids.is_empty() {
+                    return None;
+                }
+                entries.push(MultiProofEntry::Pruned("0".to_string()));
+                continue;
+            };
+            if ids.is_empty() {
+                entries.push(MultiProofEntry::Pruned(n.hash.clone()));
+                continue;
+            }
+            entries.push(MultiProofEntry::Node {
+                transaction: n.transaction.clone(),
+                height: n.height,
+                size: n.size,
+            });
+            let lower = ids.partition_point(|id| n.cmp_key(*id).is_lt());
+            let upper = ids.partition_point(|id| !n.cmp_key(*id).is_gt());
+            // Right first, so the left subtree is emitted next
+            stack.push((n.right, &ids[upper..]));
+            stack.push((n.left, &ids[..lower]));
+        }
+        Some(MultiProof { entries })
+    }
+}
+
+/// Verifies a proof produced by `CryptoBinaryTree::get_multi_proof`.
+///
+/// The root hash is recomputed from the proof alone and must equal `root`;
+/// each of `transactions` must then match a node of the proof exactly. Nodes
+/// are assumed to be hashed with SHA-256; see [`verify_multi_proof_with`].
+pub fn verify_multi_proof<T: TreeKey + Serialize>(root: &str, transactions: &[T], proof: &MultiProof<T>) -> bool {
+    verify_multi_proof_with(&Sha256Hasher::default(), root, transactions, proof)
+}
+
+/// Like [`verify_multi_proof`], for trees built with a custom hasher.
+pub fn verify_multi_proof_with<T: TreeKey + Serialize, H: TreeHasher>(
+    hasher: &H,
+    root: &str,
+    transactions: &[T],
+    proof: &MultiProof<T>,
+) -> bool {
+    check_multi_proof_with(hasher, root, transactions, proof).is_ok()
+}
+
+/// Like [`verify_multi_proof_with`], reporting why a proof was rejected.
+pub fn check_multi_proof_with<T: TreeKey + Serialize, H: TreeHasher>(
+    hasher: &H,
+    root: &str,
+    transactions: &[T],
+    proof: &MultiProof<T>,
+) -> Result<()> {
+    let invalid = |reason: &str| CryptoTreeError::InvalidProof(reason.to_string());
+    check_root(hasher, root, &proof.entries)?;
+
+    let proven: BTreeMap<_, _> = proof
+        .entries
+        .iter()
+        .filter_map(|e| match e {
+            MultiProofEntry::Node { transaction, .. } => Some((transaction.key(), transaction)),
+            MultiProofEntry::Pruned(_) => None,
+        })
+        .collect();
+    for transaction in transactions {
+        let Some(node) = proven.get(transaction.key()) else {
+            return Err(invalid(&format!("{} is not covered", key_string(transaction.key()))));
+        };
+        if encode_canonical(transaction)? != encode_canonical(node)? {
+            return Err(invalid(&format!("{} does not match the proven payload", key_string(transaction.key()))));
+        }
+    }
+    Ok(())
+}
+
+/// Recomputes the root hash of a pre-order entry list and compares it with `root`.
+pub(crate) fn check_root<T: Serialize, H: TreeHasher>(hasher: &H, root: &str, entries: &[MultiProofEntry<T>]) -> Result<()> {
+    let invalid = |reason: &str| CryptoTreeError::InvalidProof(reason.to_string());
+
+    // Walking the pre-order list backwards, both subtrees of a node have been
+    // folded into hashes (left on top) by the time the node itself is reached
+    let mut hashes: Vec<String> = Vec::new();
+    for entry in entries.iter().rev() {
+        match entry {
+            MultiProofEntry::Pruned(hash) => hashes.push(hash.clone()),
+            MultiProofEntry::Node { transaction, height, size } => {
+                let (Some(left), Some(right)) = (hashes.pop(), hashes.pop()) else {
+                    return Err(invalid("node is missing a subtree"));
+                };
+                let hash = crate::encoding::hash_node(hasher, transaction, Some(&left), Some(&right), *height, *size)?;
+                hashes.push(hash);
+            }
+        }
+    }
+    if hashes.len() != 1 || hashes[0] != root {
+        return Err(invalid("recomputed root does not match"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+    use crate::test_util::build_tree;
+
+    #[test]
+    fn test_multi_proof_round_trip() {
+        let tree = build_tree(100);
+        let ids = ["tx_007", "tx_042", "tx_043", "tx_099", "tx_042"];
+        let proof = tree.get_multi_proof(&ids).unwrap();
+        let txs: Vec<Transaction> = ids.iter().map(|id| tree.search(*id).unwrap().clone()).collect();
+        assert!(verify_multi_proof(tree.merkle_root(), &txs, &proof));
+
+        // Shared ancestors appear once, so the proof is smaller than separate proofs
+        let nodes = proof.entries.iter().filter(|e| matches!(e, MultiProofEntry::Node { .. })).count();
+        let separate: usize = ids[..4].iter().map(|id| tree.get_proof_of_inclusion(*id).unwrap().len()).sum();
+        assert!(nodes < separate);
+        for tx in &txs {
+            let single = tree.get_proof_of_inclusion(&tx.id).unwrap();
+            assert!(verify_proof(tree.merkle_root(), tx, &single));
+        }
+
+        let json = serde_json::to_string(&proof).unwrap();
+        let decoded: MultiProof = serde_json::from_str(&json).unwrap();
+        assert!(verify_multi_proof(tree.merkle_root(), &txs, &decoded));
+    }
+
+    #[test]
+    fn test_multi_proof_rejects_tampering() {
+        let tree = build_tree(50);
+        let proof = tree.get_multi_proof(&["tx_010", "tx_020"]).unwrap();
+        let mut tx = tree.search("tx_010").unwrap().clone();
+        assert!(verify_multi_proof(tree.merkle_root(), std::slice::from_ref(&tx), &proof));
+
+        tx.amount += 1;
+        assert!(!verify_multi_proof(tree.merkle_root(), &[tx], &proof));
+        let uncovered = tree.search("tx_030").unwrap().clone();
+        assert!(!verify_multi_proof(tree.merkle_root(), &[uncovered], &proof));
+
+        let mut forged = proof.clone();
+        if let Some(MultiProofEntry::Node { transaction, .. }) = forged.entries.first_mut() {
+            transaction.amount += 1;
+        }
+        assert!(!verify_multi_proof(tree.merkle_root(), &[], &forged));
+
+        let mut truncated = proof;
+        truncated.entries.pop();
+        assert!(!verify_multi_proof(tree.merkle_root(), &[], &truncated));
+    }
+
+    #[test]
+    fn test_multi_proof_missing_and_empty() {
+        let tree = build_tree(10);
+        assert!(tree.get_multi_proof(&["tx_003", "nope"]).is_none());
+
+        let none = tree.get_multi_proof::<str>(&[]).unwrap();
+        assert_eq!(none.entries.len(), 1);
+        assert!(verify_multi_proof(tree.merkle_root(), &[], &none));
+
+        let empty = crate::TransactionTree::new();
+        assert!(empty.get_multi_proof(&["tx_001"]).is_none());
+    }
+}
@@ -0,0 +1,179 @@
+//! Hooks for watching a tree at work.
+//!
+//! Outside the `tracing` feature the library never logs. Instead a
+//! [`TreeObserver`] installed with `set_observer` is handed a [`TreeEvent`]
+//! after every insert, removal, update, hash flush, proof and integrity
+//! check, together with the number of rotations and node rehashes it took.
+//! Forward them to a logging or metrics backend; with the `tracing` feature
+//! `TracingObserver` emits them as `tracing` events. Without an observer no
+//! event is built, so the hooks cost a branch per operation.
+
+use alloc::sync::Arc;
+use core::fmt;
+
+use serde::Serialize;
+
+use crate::{key_string, CryptoBinaryTree, CryptoTreeError, TreeHasher, TreeKey};
+
+/// Something a tree did, as reported to a [`TreeObserver`]
+///
+/// `rotations` counts the AVL rotations performed while rebalancing and
+/// `hashes` the node hashes recomputed. With lazy hashing inserts and
+/// removals only mark nodes dirty; the rehashing shows up in `Flushed`.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum TreeEvent<'a> {
+    /// A transaction was inserted
+    Inserted { id: &'a str, rotations: u64, hashes: u64 },
+    /// An insert failed, e.g. with `DuplicateId` or `Rejected`
+    InsertRejected { id: &'a str, error: &'a CryptoTreeError },
+    /// `insert_batch` placed `inserted` transactions and skipped the rest
+    BatchInserted { inserted: usize, duplicates: usize, failed: usize, rotations: u64 },
+    /// A transaction was removed
+    Removed { id: &'a str, rotations: u64, hashes: u64 },
+    /// A stored transaction was changed in place, or the change was refused with `error`
+    Updated { id: &'a str, hashes: u64, error: Option<&'a CryptoTreeError> },
+    /// Pending hashes were recomputed, see `flush_hashes`
+    Flushed { hashes: u64 },
+    /// An inclusion proof of `steps` steps was built
+    ProofGenerated { id: &'a str, steps: usize },
+    /// An integrity check finished; `corrupted` names the first bad node, if any
+    IntegrityChecked { nodes: usize, corrupted: Option<&'a str> },
+}
+
+/// Receives the events of the trees it is installed on
+pub trait TreeObserver: Send + Sync {
+    fn on_event(&self, event: &TreeEvent<'_>);
+}
+
+impl<O: TreeObserver + ?Sized> TreeObserver for Arc<O> {
+    fn on_event(&self, event: &TreeEvent<'_>) {
+        (**self).on_event(event);
+    }
+}
+
+/// Both observers see every event, the first one first
+impl<A: TreeObserver, B: TreeObserver> TreeObserver for (A, B) {
+    fn on_event(&self, event: &TreeEvent<'_>) {
+        self.0.on_event(event);
+        self.1.on_event(event);
+    }
+}
+
+/// An installed observer; clones of a tree report to the same one
+#[derive(Clone)]
+pub(crate) struct Observer(Arc<dyn TreeObserver>);
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+impl<T: TreeKey, H> CryptoBinaryTree<T, H> {
+    /// Installs `observer`, replacing any earlier one.
+    pub fn set_observer(&mut self, observer: Arc<dyn TreeObserver>) {
+        self.observer = Some(Observer(observer));
+    }
+
+    /// Removes the observer installed with `set_observer`.
+    pub fn clear_observer(&mut self) {
+        self.observer = None;
+    }
+
+    pub(crate) fn _observer(&self) -> Option<&dyn TreeObserver> {
+        self.observer.as_ref().map(|o| &*o.0)
+    }
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Reports the outcome of an integrity check.
+    pub(crate) fn _observe_check(&self, outcome: &crate::Result<()>) {
+        if let Some(observer) = self._observer() {
+            let corrupted = match outcome {
+                Err(CryptoTreeError::CorruptedNode { id }) => Some(id.as_str()),
+                _ => None,
+            };
+            observer.on_event(&TreeEvent::IntegrityChecked {
+                nodes: self.len(),
+                corrupted,
+            });
+        }
+    }
+
+    /// Reports a proof built for `key`.
+    pub(crate) fn _observe_proof(&self, key: &T::Key, steps: usize) {
+        if let Some(observer) = self._observer() {
+            observer.on_event(&TreeEvent::ProofGenerated {
+                id: &key_string(key),
+                steps,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_util::sample_tx;
+
+    /// Records events as strings
+    #[derive(Default)]
+    struct Log(Mutex<Vec<String>>);
+
+    impl TreeObserver for Log {
+        fn on_event(&self, event: &TreeEvent<'_>) {
+            self.0.lock().unwrap().push(format!("{:?}", event));
+        }
+    }
+
+    #[test]
+    fn test_observer_sees_operations() {
+        let log = Arc::new(Log::default());
+        let mut tree = CryptoBinaryTree::new();
+        tree.set_observer(log.clone());
+        tree.insert(sample_tx("tx_1", 10));
+        tree.insert(sample_tx("tx_2", 10));
+        tree.insert(sample_tx("tx_3", 10));
+        tree.insert(sample_tx("tx_3", 10));
+        tree.get_proof_of_inclusion("tx_1").unwrap();
+        tree.remove("tx_1");
+        tree.update("tx_9", |tx| tx.amount = 1).unwrap_err();
+        assert!(tree.verify_integrity());
+
+        let events = log.0.lock().unwrap();
+        assert_eq!(
+            events[..3],
+            [
+                "Inserted { id: \"tx_1\", rotations: 0, hashes: 0 }",
+                "Inserted { id: \"tx_2\", rotations: 0, hashes: 1 }",
+                "Inserted { id: \"tx_3\", rotations: 1, hashes: 4 }",
+            ]
+        );
+        assert!(events[3].starts_with("InsertRejected { id: \"tx_3\", error: DuplicateId"));
+        assert_eq!(events[4], "ProofGenerated { id: \"tx_1\", steps: 1 }");
+        assert!(events[5].starts_with("Removed { id: \"tx_1\""));
+        assert!(events[6].starts_with("Updated { id: \"tx_9\", hashes: 0, error: Some(NotFound"));
+        assert_eq!(events[7], "IntegrityChecked { nodes: 2, corrupted: None }");
+        assert_eq!(events.len(), 8);
+    }
+
+    #[test]
+    fn test_observer_sees_corruption() {
+        let log = Arc::new(Log::default());
+        let mut tree = CryptoBinaryTree::new();
+        tree.insert(sample_tx("tx_1", 10));
+        tree.set_observer(log.clone());
+        let root = tree.root.unwrap();
+        tree.nodes[root].transaction.amount = 11;
+        assert!(!tree.verify_integrity());
+        tree.clear_observer();
+        assert!(!tree.verify_integrity());
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            ["IntegrityChecked { nodes: 1, corrupted: Some(\"tx_1\") }"]
+        );
+    }
+}
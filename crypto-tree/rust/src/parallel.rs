@@ -0,0 +1,99 @@
+//! Multi-threaded integrity checks and bulk loading (`parallel` feature).
+//!
+//! Work runs on rayon's global thread pool. Nodes live in one paged arena,
+//! so checking them is a flat scan that rayon splits by page. A balanced
+//! build lays the nodes out in key order, so the two halves below any node
+//! are disjoint slices that `rayon::join` links and hashes side by side;
+//! small subtrees run the sequential code unchanged and results match it exactly.
+
+use rayon::iter::ParallelIterator;
+use serde::Serialize;
+
+use crate::{balanced_root, CryptoBinaryTree, CryptoTreeNode, Result, TreeHasher, TreeKey};
+
+/// Subtrees with fewer nodes are not worth a task
+const SEQUENTIAL_CUTOFF: usize = 4096;
+
+impl<T, H> CryptoBinaryTree<T, H>
+where
+    T: TreeKey + Serialize + Clone + Send + Sync,
+    H: TreeHasher + Sync,
+{
+    /// Like [`check_integrity`](Self::check_integrity), spreading the rehashing over all cores.
+    ///
+    /// Which corrupted node is reported may differ from the sequential check
+    /// when there are several.
+    pub fn par_check_integrity(&self) -> Result<()> {
+        self._assert_hashed();
+        if self.nodes.len() < SEQUENTIAL_CUTOFF {
+            return self.check_integrity();
+        }
+        let (nodes, hasher) = (&self.nodes, &self.hasher);
+        let outcome = nodes.par_iter().try_for_each(|n| Self::_check_node(nodes, n, hasher));
+        self._observe_check(&outcome);
+        outcome
+    }
+
+    /// Like [`verify_integrity`](Self::verify_integrity), using [`par_check_integrity`](Self::par_check_integrity).
+    pub fn par_verify_integrity(&self) -> bool {
+        self.par_check_integrity().is_ok()
+    }
+
+    /// Like [`from_sorted_with_hasher`](Self::from_sorted_with_hasher),
+    /// hashing independent subtrees on all cores; the result is identical.
+    pub fn par_from_sorted_with_hasher(transactions: Vec<T>, hasher: H) -> Result<Self> {
+        Self::_check_sorted(&transactions)?;
+        let mut nodes: Vec<CryptoTreeNode<T>> = transactions.into_iter().map(CryptoTreeNode::unhashed).collect();
+        Self::_par_build(&mut nodes, 0, &hasher)?;
+        Ok(Self::_from_balanced(nodes, hasher))
+    }
+
+    /// Same as `_build_balanced`, building the two halves as separate rayon tasks.
+    fn _par_build(nodes: &mut [CryptoTreeNode<T>], offset: usize, hasher: &H) -> Result<()> {
+        if nodes.len() < SEQUENTIAL_CUTOFF {
+            return Self::_build_balanced(nodes, offset, hasher);
+        }
+        let mid = balanced_root(nodes.len()).expect("slice is not empty");
+        let (left, rest) = nodes.split_at_mut(mid);
+        let right = &mut rest[1..];
+        let (left, right) = rayon::join(
+            || Self::_par_build(left, offset, hasher),
+            || Self::_par_build(right, offset + mid + 1, hasher),
+        );
+        left.and(right)?;
+        Self::_join_balanced(nodes, offset, hasher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CryptoBinaryTree, CryptoTreeError, NodeId, Sha256Hasher, Transaction};
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let txs: Vec<Transaction> = (0..20_000).map(|i| sample_tx(&format!("tx_{:05}", i), 10)).collect();
+        let sequential = CryptoBinaryTree::from_sorted(txs.clone()).unwrap();
+        let parallel = CryptoBinaryTree::par_from_sorted_with_hasher(txs, Sha256Hasher::new()).unwrap();
+        assert!(parallel.structurally_equal(&sequential));
+        assert!(parallel.par_check_integrity().is_ok());
+    }
+
+    #[test]
+    fn test_parallel_detects_corruption() {
+        let txs: Vec<Transaction> = (0..20_000).map(|i| sample_tx(&format!("tx_{:05}", i), 10)).collect();
+        let mut tree = CryptoBinaryTree::par_from_sorted_with_hasher(txs, Sha256Hasher::new()).unwrap();
+        // Nodes of a balanced build are stored in key order
+        tree.nodes[NodeId::new(0)].transaction.amount = 11;
+        assert!(matches!(
+            tree.par_check_integrity(),
+            Err(CryptoTreeError::CorruptedNode { id }) if id == "tx_00000"
+        ));
+        assert!(!tree.par_verify_integrity());
+
+        assert!(matches!(
+            CryptoBinaryTree::par_from_sorted_with_hasher(vec![sample_tx("b", 10), sample_tx("a", 10)], Sha256Hasher::new()),
+            Err(CryptoTreeError::UnsortedInput(_))
+        ));
+    }
+}
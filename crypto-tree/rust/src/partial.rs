@@ -0,0 +1,368 @@
+//! Partial trees rebuilt from verified proofs.
+//!
+//! A [`PartialTree`] starts from a trusted Merkle root and grows as proofs
+//! against it are added: each proof reveals some nodes of the full tree and
+//! the hashes of the subtrees it leaves out. The revealed part answers
+//! searches and range queries exactly as the full tree would, including "not
+//! stored" when a search ends at an empty child, and serves inclusion proofs
+//! for what it holds. A query that runs into a subtree known only by its hash
+//! fails with `NotWitnessed` instead of guessing, so a light server can hold
+//! just the hot part of the state.
+
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+
+use serde::Serialize;
+
+use crate::prelude::*;
+use crate::proof::MAX_TREE_HEIGHT;
+use crate::{
+    check_proof_with, key_string, AbsenceProof, CryptoTreeError, MultiProof, MultiProofEntry,
+    ProofStep, RangeProof, Result, Sha256Hasher, Side, Transaction, TreeHasher, TreeKey,
+};
+
+/// A node of the full tree, or a subtree known only by its hash (`"0"` when empty)
+#[derive(Debug, Clone)]
+enum Witness<T> {
+    Node {
+        transaction: T,
+        height: i32,
+        size: usize,
+        hash: String,
+        left: Box<Witness<T>>,
+        right: Box<Witness<T>>,
+    },
+    Pruned(String),
+}
+
+impl<T: Serialize> Witness<T> {
+    fn hash(&self) -> &str {
+        match self {
+            Witness::Node { hash, .. } | Witness::Pruned(hash) => hash,
+        }
+    }
+
+    fn node<H: TreeHasher>(hasher: &H, transaction: T, height: i32, size: usize, left: Self, right: Self) -> Result<Self> {
+        let hash = crate::encoding::hash_node(hasher, &transaction, Some(left.hash()), Some(right.hash()), height, size)?;
+        Ok(Witness::Node { transaction, height, size, hash, left: Box::new(left), right: Box::new(right) })
+    }
+
+    /// Rebuilds the revealed nodes of a pre-order entry list, as carried by
+    /// multi and range proofs
+    fn from_entries<H, I>(hasher: &H, entries: &mut I, depth: i32) -> Result<Self>
+    where
+        H: TreeHasher,
+        I: Iterator<Item = MultiProofEntry<T>>,
+        T: Clone,
+    {
+        if depth > MAX_TREE_HEIGHT {
+            return Err(CryptoTreeError::InvalidProof("proof is deeper than any tree is tall".to_string()));
+        }
+        match entries.next() {
+            Some(MultiProofEntry::Pruned(hash)) => Ok(Witness::Pruned(hash)),
+            Some(MultiProofEntry::Node { transaction, height, size }) => {
+                let left = Self::from_entries(hasher, entries, depth + 1)?;
+                let right = Self::from_entries(hasher, entries, depth + 1)?;
+                Self::node(hasher, transaction, height, size, left, right)
+            }
+            None => Err(CryptoTreeError::InvalidProof("node is missing a subtree".to_string())),
+        }
+    }
+
+    /// Rebuilds the nodes of an inclusion proof of `transaction`, which must already be checked
+    fn from_path<H: TreeHasher>(hasher: &H, transaction: T, proof: &[ProofStep<T>]) -> Result<Self>
+    where
+        T: Clone,
+    {
+        let split = proof.iter().rposition(|s| s.transaction.is_some()).map_or(0, |i| i + 1);
+        let (ancestors, target) = proof.split_at(split);
+        let child = |side| {
+            let step = target.iter().find(|s| s.side == side);
+            Witness::Pruned(step.map_or("0", |s| s.hash.as_str()).to_string())
+        };
+        let (height, size) = target.first().map_or((1, 1), |s| (s.height, s.size));
+        let mut current = Self::node(hasher, transaction, height, size, child(Side::Left), child(Side::Right))?;
+        for step in ancestors.iter().rev() {
+            let ancestor = step.transaction.clone().expect("checked proof");
+            let sibling = Witness::Pruned(step.hash.clone());
+            let (left, right) = match step.side {
+                Side::Left => (sibling, current),
+                Side::Right => (current, sibling),
+            };
+            current = Self::node(hasher, ancestor, step.height, step.size, left, right)?;
+        }
+        Ok(current)
+    }
+
+    /// Reveals the nodes `other` knows of the same subtree.
+    fn absorb(&mut self, other: Self) {
+        match (self, other) {
+            (this @ Witness::Pruned(_), other @ Witness::Node { .. }) => *this = other,
+            (Witness::Node { left, right, .. }, Witness::Node { left: l, right: r, .. }) => {
+                left.absorb(*l);
+                right.absorb(*r);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The part of a tree revealed by proofs against a trusted root
+///
+/// Add proofs with `add_proof`, `add_absence_proof`, `add_multi_proof` and
+/// `add_range_proof`; each is checked against the root first and a proof
+/// that does not verify is refused with `InvalidProof`, leaving the partial
+/// tree unchanged.
+#[derive(Debug, Clone)]
+pub struct PartialTree<T: TreeKey = Transaction, H = Sha256Hasher> {
+    hasher: H,
+    root: Witness<T>,
+}
+
+impl<T: TreeKey + Serialize + Clone> PartialTree<T> {
+    /// Starts from `root` with nothing revealed, for trees hashed with SHA-256.
+    pub fn new(root: impl Into<String>) -> Self {
+        Self::with_hasher(root, Sha256Hasher::default())
+    }
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> PartialTree<T, H> {
+    /// Starts from `root` with nothing revealed, for trees hashed with `hasher`.
+    pub fn with_hasher(root: impl Into<String>, hasher: H) -> Self {
+        PartialTree { hasher, root: Witness::Pruned(root.into()) }
+    }
+
+    /// The trusted Merkle root
+    pub fn merkle_root(&self) -> &str {
+        self.root.hash()
+    }
+
+    /// Number of transactions in the full tree, once its root node is revealed
+    pub fn tree_len(&self) -> Option<usize> {
+        match &self.root {
+            Witness::Node { size, .. } => Some(*size),
+            Witness::Pruned(hash) => (hash == "0").then_some(0),
+        }
+    }
+
+    /// Adds an inclusion proof of `transaction`, as from `get_proof_of_inclusion`.
+    pub fn add_proof(&mut self, transaction: &T, proof: &[ProofStep<T>]) -> Result<()> {
+        check_proof_with(&self.hasher, self.merkle_root(), transaction, proof)?;
+        let witness = Witness::from_path(&self.hasher, transaction.clone(), proof)?;
+        self.root.absorb(witness);
+        Ok(())
+    }
+
+    /// Adds a proof that an id is not stored, as from `get_proof_of_absence`.
+    ///
+    /// The search path it reveals ends at an empty child, so later queries for
+    /// the id return `Ok(None)`.
+    pub fn add_absence_proof(&mut self, proof: &AbsenceProof<T>) -> Result<()> {
+        match &proof.terminal {
+            Some(terminal) => self.add_proof(terminal, &proof.path),
+            None if self.merkle_root() == "0" => Ok(()),
+            None => Err(CryptoTreeError::InvalidProof("tree is not empty".to_string())),
+        }
+    }
+
+    /// Adds a proof of several transactions, as from `get_multi_proof`.
+    pub fn add_multi_proof(&mut self, proof: &MultiProof<T>) -> Result<()> {
+        self._add_entries(&proof.entries)
+    }
+
+    /// Adds a proof of the contents of a key range, as from `get_range_proof`;
+    /// afterwards `range` answers for any range inside it.
+    pub fn add_range_proof(&mut self, proof: &RangeProof<T>) -> Result<()> {
+        self._add_entries(&proof.entries)
+    }
+
+    fn _add_entries(&mut self, entries: &[MultiProofEntry<T>]) -> Result<()> {
+        let mut entries = entries.iter().cloned();
+        let witness = Witness::from_entries(&self.hasher, &mut entries, 0)?;
+        if entries.next().is_some() {
+            return Err(CryptoTreeError::InvalidProof("trailing entries".to_string()));
+        }
+        if witness.hash() != self.merkle_root() {
+            return Err(CryptoTreeError::InvalidProof("recomputed root does not match".to_string()));
+        }
+        self.root.absorb(witness);
+        Ok(())
+    }
+
+    /// Looks up `tx_id`: `Ok(None)` if it is proven not to be stored, or
+    /// `NotWitnessed` if the proofs added so far do not reach it.
+    pub fn search<Q>(&self, tx_id: &Q) -> Result<Option<&T>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + Serialize + ?Sized,
+    {
+        Ok(self._search_path(tx_id)?.last().filter(|(_, side)| side.is_none()).map(|(tx, _)| *tx))
+    }
+
+    /// The transactions with keys in `start..=end`, in key order, or
+    /// `NotWitnessed` unless the proofs added so far cover the whole range.
+    pub fn range<Q>(&self, start: &Q, end: &Q) -> Result<Vec<&T>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + Serialize + ?Sized,
+    {
+        let mut found = Vec::new();
+        let mut stack = vec![(&self.root, false)];
+        while let Some((node, visited)) = stack.pop() {
+            let (transaction, left, right) = match node {
+                Witness::Pruned(hash) if hash == "0" => continue,
+                Witness::Pruned(_) => {
+                    return Err(CryptoTreeError::NotWitnessed(format!("range ending at {}", key_string(end))));
+                }
+                Witness::Node { transaction, left, right, .. } => (transaction, left, right),
+            };
+            let key = transaction.key().borrow();
+            if visited {
+                if start <= key && key <= end {
+                    found.push(transaction);
+                }
+                continue;
+            }
+            // In-order: left subtree, then the node, then the right subtree
+            if key < end {
+                stack.push((right, false));
+            }
+            stack.push((node, true));
+            if key > start {
+                stack.push((left, false));
+            }
+        }
+        Ok(found)
+    }
+
+    /// Builds an inclusion proof of `tx_id` from the revealed nodes, the same
+    /// one the full tree would give; `Ok(None)` if it is proven not to be stored.
+    pub fn get_proof_of_inclusion<Q>(&self, tx_id: &Q) -> Result<Option<Vec<ProofStep<T>>>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + Serialize + ?Sized,
+    {
+        let path = self._search_path(tx_id)?;
+        let mut proof = Vec::new();
+        let mut node = &self.root;
+        for (_, side) in &path {
+            let Witness::Node { transaction, height, size, left, right, .. } = node else {
+                unreachable!("search path runs through revealed nodes");
+            };
+            match side {
+                Some(Side::Left) => {
+                    proof.push(ProofStep::new(Side::Right, right.hash().to_string(), *height, *size, Some(transaction.clone())));
+                    node = left;
+                }
+                Some(Side::Right) => {
+                    proof.push(ProofStep::new(Side::Left, left.hash().to_string(), *height, *size, Some(transaction.clone())));
+                    node = right;
+                }
+                None => {
+                    for (side, child) in [(Side::Left, left), (Side::Right, right)] {
+                        if child.hash() != "0" {
+                            proof.push(ProofStep::new(side, child.hash().to_string(), *height, *size, None));
+                        }
+                    }
+                    return Ok(Some(proof));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Revealed transactions in key order
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        let mut found = Vec::new();
+        let mut stack = vec![(&self.root, false)];
+        while let Some((node, visited)) = stack.pop() {
+            if let Witness::Node { transaction, left, right, .. } = node {
+                if visited {
+                    found.push(transaction);
+                } else {
+                    stack.extend([(&**right, false), (node, true), (&**left, false)]);
+                }
+            }
+        }
+        found.into_iter()
+    }
+
+    /// The revealed nodes a search for `tx_id` passes, each with the side it
+    /// leaves by, `None` for the node holding `tx_id`
+    fn _search_path<Q>(&self, tx_id: &Q) -> Result<Vec<(&T, Option<Side>)>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + Serialize + ?Sized,
+    {
+        let mut path = Vec::new();
+        let mut node = &self.root;
+        loop {
+            match node {
+                Witness::Pruned(hash) if hash == "0" => return Ok(path),
+                Witness::Pruned(_) => return Err(CryptoTreeError::NotWitnessed(key_string(tx_id))),
+                Witness::Node { transaction, left, right, .. } => match tx_id.cmp(transaction.key().borrow()) {
+                    Ordering::Equal => {
+                        path.push((transaction, None));
+                        return Ok(path);
+                    }
+                    Ordering::Less => {
+                        path.push((transaction, Some(Side::Left)));
+                        node = left;
+                    }
+                    Ordering::Greater => {
+                        path.push((transaction, Some(Side::Right)));
+                        node = right;
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_proof, CryptoBinaryTree};
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_partial_tree_from_proofs() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 0..200 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), i));
+        }
+        let root = tree.merkle_root().to_string();
+        let mut partial: PartialTree = PartialTree::new(root.clone());
+        assert!(matches!(partial.search("tx_010"), Err(CryptoTreeError::NotWitnessed(_))));
+        assert_eq!(partial.tree_len(), None);
+
+        let tx = tree.search("tx_010").unwrap().clone();
+        partial.add_proof(&tx, &tree.get_proof_of_inclusion("tx_010").unwrap()).unwrap();
+        partial.add_absence_proof(&tree.get_proof_of_absence("tx_0105").unwrap()).unwrap();
+        partial.add_multi_proof(&tree.get_multi_proof(&["tx_150", "tx_151"]).unwrap()).unwrap();
+        partial.add_range_proof(&tree.get_range_proof("tx_050", "tx_059")).unwrap();
+        assert_eq!(partial.merkle_root(), root);
+        assert_eq!(partial.tree_len(), Some(200));
+
+        assert_eq!(partial.search("tx_010").unwrap(), Some(&tx));
+        assert_eq!(partial.search("tx_151").unwrap().unwrap().amount, 151);
+        assert_eq!(partial.search("tx_0105").unwrap(), None);
+        assert!(matches!(partial.search("tx_120"), Err(CryptoTreeError::NotWitnessed(_))));
+        assert_eq!(partial.range("tx_052", "tx_054").unwrap().len(), 3);
+        assert!(partial.range("tx_040", "tx_054").is_err());
+        assert!(partial.iter().count() >= 13);
+
+        // Served proofs check against the root like the full tree's
+        let proof = partial.get_proof_of_inclusion("tx_055").unwrap().unwrap();
+        assert!(verify_proof(&root, tree.search("tx_055").unwrap(), &proof));
+        assert_eq!(proof.len(), tree.get_proof_of_inclusion("tx_055").unwrap().len());
+
+        // Proofs against another root are refused
+        let mut forged = tx.clone();
+        forged.amount += 1;
+        assert!(partial.add_proof(&forged, &tree.get_proof_of_inclusion("tx_010").unwrap()).is_err());
+        tree.insert(sample_tx("tx_999", 1));
+        assert!(partial.add_range_proof(&tree.get_range_proof("tx_000", "tx_005")).is_err());
+        assert!(partial.search("tx_000").is_err());
+    }
+}
@@ -0,0 +1,465 @@
+use serde::{Serialize, Deserialize};
+
+use crate::prelude::*;
+use crate::{CryptoTreeError, Result, Sha256Hasher, Transaction, TreeHasher, TreeKey};
+
+/// Upper bound on the height of any tree that fits in memory.
+///
+/// An AVL tree of height `h` holds at least `F(h + 2) - 1` nodes (`F` being
+/// the Fibonacci numbers), so no tree with at most `u64::MAX` nodes is taller
+/// than 92. Verifiers reject longer proofs before doing any hashing.
+pub const MAX_TREE_HEIGHT: i32 = 92;
+
+/// Which child of a node a proof step's hash belongs to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    #[serde(rename = "l", alias = "left")]
+    Left,
+    #[serde(rename = "r", alias = "right")]
+    Right,
+}
+
+/// One step of a proof of inclusion
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProofStep<T = Transaction> {
+    /// Side of the subtree whose hash is `hash`
+    pub side: Side,
+    pub hash: String,
+    /// Height of the node this step belongs to
+    #[serde(default)]
+    pub height: i32,
+    /// Subtree size of the node this step belongs to
+    #[serde(default)]
+    pub size: usize,
+    /// Transaction of the ancestor this step passes through, `None` for the target node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<T>,
+}
+
+#[cfg(feature = "tree")]
+impl<T> ProofStep<T> {
+    pub(crate) fn new(side: Side, hash: String, height: i32, size: usize, transaction: Option<T>) -> Self {
+        Self {
+            side,
+            hash,
+            height,
+            size,
+            transaction,
+        }
+    }
+}
+
+/// Verifies a proof produced by `CryptoBinaryTree::get_proof_of_inclusion`.
+///
+/// The target node's hash is recomputed from `transaction` and the trailing
+/// target steps, then folded upwards through every ancestor step. The proof is
+/// valid when the final hash equals `root`. No access to the tree is needed.
+/// Nodes are assumed to be hashed with SHA-256; see [`verify_proof_with`].
+pub fn verify_proof<T: Serialize>(root: &str, transaction: &T, proof: &[ProofStep<T>]) -> bool {
+    verify_proof_with(&Sha256Hasher::default(), root, transaction, proof)
+}
+
+/// Like [`verify_proof`], for trees built with a custom hasher.
+pub fn verify_proof_with<T: Serialize, H: TreeHasher>(
+    hasher: &H,
+    root: &str,
+    transaction: &T,
+    proof: &[ProofStep<T>],
+) -> bool {
+    check_proof_with(hasher, root, transaction, proof).is_ok()
+}
+
+/// Like [`verify_proof_with`], reporting why a proof was rejected.
+pub fn check_proof_with<T: Serialize, H: TreeHasher>(
+    hasher: &H,
+    root: &str,
+    transaction: &T,
+    proof: &[ProofStep<T>],
+) -> Result<()> {
+    if proof_root(hasher, transaction, proof)? != root {
+        return Err(CryptoTreeError::InvalidProof("recomputed root does not match".to_string()));
+    }
+    Ok(())
+}
+
+/// Hash of the root an inclusion proof leads to, folded up from `transaction`
+pub(crate) fn proof_root<T: Serialize, H: TreeHasher>(hasher: &H, transaction: &T, proof: &[ProofStep<T>]) -> Result<String> {
+    let invalid = |reason: &str| CryptoTreeError::InvalidProof(reason.to_string());
+    // At most one step per ancestor plus two for the target's children
+    if proof.len() > MAX_TREE_HEIGHT as usize + 1 {
+        return Err(invalid("proof is longer than any tree is tall"));
+    }
+    let split = proof.iter().rposition(|s| s.transaction.is_some()).map_or(0, |i| i + 1);
+    let (ancestors, target) = proof.split_at(split);
+
+    // Target node: children default to the empty sentinel
+    let mut left_hash = None;
+    let mut right_hash = None;
+    let mut height = None;
+    let mut size = 1;
+    for step in target {
+        let slot = match step.side {
+            Side::Left => &mut left_hash,
+            Side::Right => &mut right_hash,
+        };
+        if slot.is_some() || height.is_some_and(|h| h != step.height) || (height.is_some() && size != step.size) {
+            return Err(invalid("inconsistent target node steps"));
+        }
+        *slot = Some(step.hash.clone());
+        height = Some(step.height);
+        size = step.size;
+    }
+    let height = height.unwrap_or(1);
+    let mut current = crate::encoding::hash_node(hasher, transaction, left_hash.as_deref(), right_hash.as_deref(), height, size)?;
+
+    for step in ancestors.iter().rev() {
+        let Some(ancestor) = step.transaction.as_ref() else {
+            return Err(invalid("ancestor step without a transaction"));
+        };
+        let sibling = Some(step.hash.as_str());
+        let (left_hash, right_hash) = match step.side {
+            Side::Left => (sibling, Some(current.as_str())),
+            Side::Right => (Some(current.as_str()), sibling),
+        };
+        current = crate::encoding::hash_node(hasher, ancestor, left_hash, right_hash, step.height, step.size)?;
+    }
+    Ok(current)
+}
+
+/// A self-contained proof of inclusion.
+///
+/// Bundles the target transaction, the Merkle root the proof was issued
+/// against and the tree height at that time with the steps of
+/// `CryptoBinaryTree::get_proof_of_inclusion`, so it can be checked with
+/// [`Proof::verify`] alone. `verify` only shows the proof is consistent with
+/// its own `root`; callers must still compare `root` with a root they trust.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Proof<T = Transaction> {
+    /// The transaction proven to be included
+    pub transaction: T,
+    /// Merkle root the proof recomputes to
+    pub root: String,
+    /// Height of the tree, i.e. of its root node
+    pub height: i32,
+    /// Steps from the root down to the target node
+    pub steps: Vec<ProofStep<T>>,
+}
+
+impl<T: Serialize> Proof<T> {
+    /// Verifies the proof against its own root, assuming SHA-256 node hashes.
+    pub fn verify(&self) -> bool {
+        self.verify_with(&Sha256Hasher::default())
+    }
+
+    /// Like [`Proof::verify`], for trees built with a custom hasher.
+    pub fn verify_with<H: TreeHasher>(&self, hasher: &H) -> bool {
+        self.check_with(hasher).is_ok()
+    }
+
+    /// Like [`Proof::verify_with`], reporting why the proof was rejected.
+    ///
+    /// Before recomputing the root this checks that the claimed tree height is
+    /// plausible, that the proof is no longer than that height allows and that
+    /// the topmost step sits at that height.
+    pub fn check_with<H: TreeHasher>(&self, hasher: &H) -> Result<()> {
+        let invalid = |reason: &str| CryptoTreeError::InvalidProof(reason.to_string());
+        if !(1..=MAX_TREE_HEIGHT).contains(&self.height) {
+            return Err(invalid("implausible tree height"));
+        }
+        let ancestors = self.steps.iter().filter(|s| s.transaction.is_some()).count();
+        if ancestors >= self.height as usize || self.steps.len() > ancestors + 2 {
+            return Err(invalid("proof is longer than the tree is tall"));
+        }
+        let top = self.steps.first().map_or(1, |s| s.height);
+        if top != self.height {
+            return Err(invalid("tree height does not match"));
+        }
+        check_proof_with(hasher, &self.root, &self.transaction, &self.steps)
+    }
+}
+
+/// A proof that a transaction id is not stored in the tree
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AbsenceProof<T: TreeKey = Transaction> {
+    /// The id proven to be absent
+    pub tx_id: T::Key,
+    /// Last node on the search path for `tx_id`, `None` for an empty tree
+    pub terminal: Option<T>,
+    /// Inclusion proof of `terminal`
+    pub path: Vec<ProofStep<T>>,
+    /// Largest id in the tree smaller than `tx_id`
+    pub predecessor: Option<T::Key>,
+    /// Smallest id in the tree larger than `tx_id`
+    pub successor: Option<T::Key>,
+}
+
+/// Verifies a proof produced by `CryptoBinaryTree::get_proof_of_absence`.
+///
+/// Checks that the terminal node is included under `root`, that every step of
+/// its path is the one a search for `tx_id` would take, that the terminal's
+/// child towards `tx_id` is empty, and that the claimed bracketing ids match
+/// the path. Nodes are assumed to be hashed with SHA-256; see
+/// [`verify_absence_proof_with`].
+pub fn verify_absence_proof<T: TreeKey + Serialize>(root: &str, proof: &AbsenceProof<T>) -> bool {
+    verify_absence_proof_with(&Sha256Hasher::default(), root, proof)
+}
+
+/// Like [`verify_absence_proof`], for trees built with a custom hasher.
+pub fn verify_absence_proof_with<T: TreeKey + Serialize, H: TreeHasher>(
+    hasher: &H,
+    root: &str,
+    proof: &AbsenceProof<T>,
+) -> bool {
+    let Some(terminal) = &proof.terminal else {
+        return root == "0"
+            && proof.path.is_empty()
+            && proof.predecessor.is_none()
+            && proof.successor.is_none();
+    };
+    if !verify_proof_with(hasher, root, terminal, &proof.path) {
+        return false;
+    }
+
+    let tx_id = &proof.tx_id;
+    let mut predecessor = None;
+    let mut successor = None;
+    let mut target_children = Vec::new();
+
+    for step in &proof.path {
+        let Some(ancestor) = &step.transaction else {
+            target_children.push(step);
+            continue;
+        };
+        // The sibling lies on the opposite side of the direction taken
+        match step.side {
+            Side::Right if tx_id < ancestor.key() => successor = Some(ancestor.key()),
+            Side::Left if tx_id > ancestor.key() => predecessor = Some(ancestor.key()),
+            _ => return false,
+        }
+    }
+
+    let towards = if tx_id < terminal.key() {
+        successor = Some(terminal.key());
+        Side::Left
+    } else if tx_id > terminal.key() {
+        predecessor = Some(terminal.key());
+        Side::Right
+    } else {
+        return false;
+    };
+    if target_children.iter().any(|s| s.side == towards && s.hash != "0") {
+        return false;
+    }
+
+    predecessor == proof.predecessor.as_ref() && successor == proof.successor.as_ref()
+}
+
+#[cfg(all(test, feature = "tree"))]
+mod tests {
+    use super::*;
+    use crate::TransactionTree;
+    use crate::test_util::build_tree;
+
+    #[test]
+    fn test_verify_every_proof() {
+        let tree = build_tree(50);
+        for i in 1..=50 {
+            let id = format!("tx_{:03}", i);
+            let tx = tree.search(&id).unwrap();
+            let proof = tree.get_proof_of_inclusion(&id).unwrap();
+            assert!(verify_proof(tree.merkle_root(), tx, &proof), "proof for {} failed", id);
+        }
+    }
+
+    /// Recomputes the root from a proof following docs/spec.md §3.3 directly,
+    /// using nothing but the canonical encoding and SHA-256
+    fn spec_root(transaction: &Transaction, proof: &[ProofStep]) -> String {
+        use sha2::{Digest, Sha256};
+
+        fn node_hash(tx: &Transaction, left: &str, right: &str, height: i32, size: usize) -> String {
+            let domain = if left == "0" && right == "0" { 0x00 } else { 0x01 };
+            let mut data = vec![domain, 0x03];
+            for field in [crate::encode_canonical(tx).unwrap(), left.as_bytes().to_vec(), right.as_bytes().to_vec()] {
+                data.extend_from_slice(&(field.len() as u64).to_be_bytes());
+                data.extend_from_slice(&field);
+            }
+            data.extend_from_slice(&height.to_be_bytes());
+            data.extend_from_slice(&(size as u64).to_be_bytes());
+            crate::hasher::to_hex(&Sha256::digest(&data))
+        }
+
+        let (ancestors, target): (Vec<_>, Vec<_>) = proof.iter().partition(|s| s.transaction.is_some());
+        let child = |side: Side| target.iter().find(|s| s.side == side).map_or("0", |s| s.hash.as_str());
+        let (height, size) = target.first().map_or((1, 1), |s| (s.height, s.size));
+        let mut current = node_hash(transaction, child(Side::Left), child(Side::Right), height, size);
+        for step in ancestors.iter().rev() {
+            let ancestor = step.transaction.as_ref().unwrap();
+            current = match step.side {
+                Side::Left => node_hash(ancestor, &step.hash, &current, step.height, step.size),
+                Side::Right => node_hash(ancestor, &current, &step.hash, step.height, step.size),
+            };
+        }
+        current
+    }
+
+    #[test]
+    fn test_proof_recomputable_without_tree() {
+        let tree = build_tree(64);
+        for i in [1, 17, 32, 63, 64] {
+            let id = format!("tx_{:03}", i);
+            let tx = tree.search(&id).unwrap().clone();
+            let json = serde_json::to_string(&tree.get_proof_of_inclusion(&id).unwrap()).unwrap();
+            // The verifier only sees the serialized proof and the transaction
+            let proof: Vec<ProofStep> = serde_json::from_str(&json).unwrap();
+            assert_eq!(spec_root(&tx, &proof), tree.merkle_root(), "spec recomputation failed for {}", id);
+        }
+    }
+
+    #[test]
+    fn test_verify_single_node_tree() {
+        let tree = build_tree(1);
+        let tx = tree.search("tx_001").unwrap();
+        let proof = tree.get_proof_of_inclusion("tx_001").unwrap();
+        assert!(proof.is_empty());
+        assert!(verify_proof(tree.merkle_root(), tx, &proof));
+    }
+
+    #[test]
+    fn test_self_contained_proof() {
+        let tree = build_tree(40);
+        let proof = tree.get_proof("tx_021").unwrap();
+        assert_eq!(proof.root, tree.merkle_root());
+        assert_eq!(proof.height, tree.height());
+        assert!(proof.verify());
+
+        let json = serde_json::to_string(&proof).unwrap();
+        let decoded: Proof = serde_json::from_str(&json).unwrap();
+        assert!(decoded.verify());
+
+        let mut tampered = proof.clone();
+        tampered.transaction.amount += 1;
+        assert!(!tampered.verify());
+
+        let mut wrong_height = proof;
+        wrong_height.height += 1;
+        assert!(!wrong_height.verify());
+
+        assert!(tree.get_proof("tx_999").is_none());
+        assert!(build_tree(1).get_proof("tx_001").unwrap().verify());
+    }
+
+    #[test]
+    fn test_absence_proofs() {
+        let mut tree = build_tree(30);
+        tree.remove("tx_015");
+
+        let proof = tree.get_proof_of_absence("tx_015").unwrap();
+        assert_eq!(proof.predecessor.as_deref(), Some("tx_014"));
+        assert_eq!(proof.successor.as_deref(), Some("tx_016"));
+        assert!(verify_absence_proof(tree.merkle_root(), &proof));
+
+        let below = tree.get_proof_of_absence("tx_000").unwrap();
+        assert!(below.predecessor.is_none());
+        assert_eq!(below.successor.as_deref(), Some("tx_001"));
+        assert!(verify_absence_proof(tree.merkle_root(), &below));
+
+        let above = tree.get_proof_of_absence("tx_999").unwrap();
+        assert_eq!(above.predecessor.as_deref(), Some("tx_030"));
+        assert!(above.successor.is_none());
+        assert!(verify_absence_proof(tree.merkle_root(), &above));
+
+        assert!(tree.get_proof_of_absence("tx_016").is_none());
+    }
+
+    #[test]
+    fn test_absence_empty_tree() {
+        let tree = TransactionTree::new();
+        let proof = tree.get_proof_of_absence("tx_001").unwrap();
+        assert!(verify_absence_proof(tree.merkle_root(), &proof));
+        assert!(!verify_absence_proof(&"a".repeat(64), &proof));
+    }
+
+    #[test]
+    fn test_reject_forged_absence() {
+        let mut tree = build_tree(30);
+        tree.remove("tx_015");
+        let proof = tree.get_proof_of_absence("tx_015").unwrap();
+
+        let mut wrong_bracket = proof.clone();
+        wrong_bracket.predecessor = Some("tx_013".to_string());
+        assert!(!verify_absence_proof(tree.merkle_root(), &wrong_bracket));
+
+        // Reusing the path to claim a present id is absent must fail
+        let mut present = proof.clone();
+        present.tx_id = "tx_016".to_string();
+        assert!(!verify_absence_proof(tree.merkle_root(), &present));
+
+        assert!(!verify_absence_proof("deadbeef", &proof));
+    }
+
+    #[test]
+    fn test_check_proof_reports_reason() {
+        let tree = build_tree(10);
+        let tx = tree.search("tx_003").unwrap();
+        let mut proof = tree.get_proof_of_inclusion("tx_003").unwrap();
+        let hasher = Sha256Hasher::new();
+        assert!(check_proof_with(&hasher, tree.merkle_root(), tx, &proof).is_ok());
+
+        let duplicate = proof.last().unwrap().clone();
+        proof.push(duplicate);
+        assert!(matches!(
+            check_proof_with(&hasher, tree.merkle_root(), tx, &proof),
+            Err(CryptoTreeError::InvalidProof(_))
+        ));
+    }
+
+    #[test]
+    fn test_reject_oversized_proofs() {
+        let tree = build_tree(20);
+        let tx = tree.search("tx_004").unwrap();
+        let mut steps = tree.get_proof_of_inclusion("tx_004").unwrap();
+        let ancestor = steps[0].clone();
+        steps.splice(0..0, core::iter::repeat_n(ancestor, 100));
+        assert!(!verify_proof(tree.merkle_root(), tx, &steps));
+
+        let mut proof = tree.get_proof("tx_004").unwrap();
+        proof.height = MAX_TREE_HEIGHT + 1;
+        assert!(!proof.verify());
+
+        // A proof with more ancestors than the claimed height is rejected outright
+        let mut padded = tree.get_proof("tx_004").unwrap();
+        let ancestor = padded.steps[0].clone();
+        padded.steps.splice(0..0, core::iter::repeat_n(ancestor, padded.height as usize));
+        assert!(matches!(
+            padded.check_with(&Sha256Hasher::new()),
+            Err(CryptoTreeError::InvalidProof(reason)) if reason.contains("longer")
+        ));
+    }
+
+    #[test]
+    fn test_side_serialization() {
+        let proof = build_tree(5).get_proof_of_inclusion("tx_001").unwrap();
+        let json = serde_json::to_string(&proof[0]).unwrap();
+        assert!(json.starts_with(r#"{"side":"r""#) || json.starts_with(r#"{"side":"l""#));
+
+        // Proofs written with the old spelled-out sides still load
+        let legacy: ProofStep = serde_json::from_str(r#"{"side":"left","hash":"0"}"#).unwrap();
+        assert_eq!(legacy.side, Side::Left);
+    }
+
+    #[test]
+    fn test_reject_tampered_proof() {
+        let tree = build_tree(20);
+        let mut tx = tree.search("tx_007").unwrap().clone();
+        let mut proof = tree.get_proof_of_inclusion("tx_007").unwrap();
+
+        assert!(!verify_proof("deadbeef", &tx, &proof));
+
+        proof[0].hash = "0".repeat(64);
+        assert!(!verify_proof(tree.merkle_root(), &tx, &proof));
+
+        let proof = tree.get_proof_of_inclusion("tx_007").unwrap();
+        tx.amount += 1;
+        assert!(!verify_proof(tree.merkle_root(), &tx, &proof));
+    }
+}
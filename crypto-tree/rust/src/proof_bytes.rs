@@ -0,0 +1,289 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::prelude::*;
+use crate::hasher::{from_hex, to_hex};
+use crate::{CryptoTreeError, Proof, ProofStep, Result, Side, MAX_TREE_HEIGHT};
+
+/// Magic bytes at the start of every binary proof
+const PROOF_MAGIC: &[u8; 4] = b"CTPF";
+/// Current binary proof layout version
+const PROOF_VERSION: u8 = 1;
+
+/// The step's sibling lies on the right (otherwise left)
+const SIDE_RIGHT: u8 = 0b001;
+/// The step belongs to an ancestor and carries its transaction
+const HAS_TRANSACTION: u8 = 0b010;
+/// The step's hash is the empty-subtree sentinel `"0"` and is omitted
+const EMPTY_HASH: u8 = 0b100;
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn malformed(reason: &str) -> CryptoTreeError {
+    CryptoTreeError::SerializationFailed(format!("malformed proof bytes: {}", reason))
+}
+
+impl<T: Serialize + DeserializeOwned> Proof<T> {
+    /// Encodes the proof in a compact binary layout.
+    ///
+    /// Layout: `CTPF`, `u8` version, `u8` digest length `d`, the raw root
+    /// (`d` bytes), `i32` tree height, the length-prefixed JSON transaction,
+    /// a `u32` step count, then per step a flags byte (side, ancestor, empty
+    /// sibling), `i32` height, `u64` size, the raw `d`-byte hash unless empty
+    /// and, for ancestors, the length-prefixed JSON transaction. Integers are
+    /// big-endian. Hashes are stored as raw bytes, half the size of hex.
+    ///
+    /// Fails if a hash is not lowercase hex of the same length as the root.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let root = digest_bytes(&self.root)?;
+        let digest_len = u8::try_from(root.len()).map_err(|_| malformed("digest longer than 255 bytes"))?;
+
+        let mut out = Vec::new();
+        out.extend_from_slice(PROOF_MAGIC);
+        out.push(PROOF_VERSION);
+        out.push(digest_len);
+        out.extend_from_slice(&root);
+        out.extend_from_slice(&self.height.to_be_bytes());
+        write_payload(&mut out, &self.transaction)?;
+        let count = u32::try_from(self.steps.len()).map_err(|_| malformed("too many steps"))?;
+        out.extend_from_slice(&count.to_be_bytes());
+
+        for step in &self.steps {
+            let mut flags = match step.side {
+                Side::Left => 0,
+                Side::Right => SIDE_RIGHT,
+            };
+            if step.transaction.is_some() {
+                flags |= HAS_TRANSACTION;
+            }
+            if step.hash == "0" {
+                flags |= EMPTY_HASH;
+            }
+            out.push(flags);
+            out.extend_from_slice(&step.height.to_be_bytes());
+            out.extend_from_slice(&(step.size as u64).to_be_bytes());
+            if flags & EMPTY_HASH == 0 {
+                let hash = digest_bytes(&step.hash)?;
+                if hash.len() != root.len() {
+                    return Err(malformed("hashes differ in length"));
+                }
+                out.extend_from_slice(&hash);
+            }
+            if let Some(transaction) = &step.transaction {
+                write_payload(&mut out, transaction)?;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Decodes a proof written by [`Proof::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader { bytes };
+        if reader.take(PROOF_MAGIC.len())? != PROOF_MAGIC {
+            return Err(malformed("bad magic"));
+        }
+        let version = reader.take(1)?[0];
+        if version != PROOF_VERSION {
+            return Err(malformed(&format!("unsupported version {}", version)));
+        }
+        let digest_len = usize::from(reader.take(1)?[0]);
+        let root = to_hex(reader.take(digest_len)?);
+        let height = i32::from_be_bytes(reader.array()?);
+        let transaction = reader.payload()?;
+        let count = u32::from_be_bytes(reader.array()?);
+        if count > MAX_TREE_HEIGHT as u32 + 1 {
+            return Err(malformed("too many steps"));
+        }
+
+        let mut steps = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let flags = reader.take(1)?[0];
+            if flags & !(SIDE_RIGHT | HAS_TRANSACTION | EMPTY_HASH) != 0 {
+                return Err(malformed("unknown step flags"));
+            }
+            let side = if flags & SIDE_RIGHT != 0 { Side::Right } else { Side::Left };
+            let step_height = i32::from_be_bytes(reader.array()?);
+            let size = usize::try_from(u64::from_be_bytes(reader.array()?)).map_err(|_| malformed("size overflow"))?;
+            let hash = if flags & EMPTY_HASH != 0 {
+                "0".to_string()
+            } else {
+                to_hex(reader.take(digest_len)?)
+            };
+            let ancestor = if flags & HAS_TRANSACTION != 0 {
+                Some(reader.payload()?)
+            } else {
+                None
+            };
+            steps.push(ProofStep::new(side, hash, step_height, size, ancestor));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(malformed("trailing bytes"));
+        }
+        Ok(Proof {
+            transaction,
+            root,
+            height,
+            steps,
+        })
+    }
+
+    /// [`Proof::to_bytes`] as unpadded URL-safe base64, for URLs and QR codes.
+    pub fn to_base64(&self) -> Result<String> {
+        Ok(to_base64(&self.to_bytes()?))
+    }
+
+    /// Decodes a proof written by [`Proof::to_base64`].
+    pub fn from_base64(encoded: &str) -> Result<Self> {
+        let bytes = from_base64(encoded).ok_or_else(|| malformed("invalid base64"))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// [`Proof::to_bytes`] as lowercase hex.
+    pub fn to_hex(&self) -> Result<String> {
+        Ok(to_hex(&self.to_bytes()?))
+    }
+
+    /// Decodes a proof written by [`Proof::to_hex`].
+    pub fn from_hex(encoded: &str) -> Result<Self> {
+        let bytes = from_hex(encoded).ok_or_else(|| malformed("invalid hex"))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+/// Decodes a lowercase hex digest, rejecting anything that would not
+/// re-encode to the same string
+fn digest_bytes(hash: &str) -> Result<Vec<u8>> {
+    match from_hex(hash) {
+        Some(bytes) if !bytes.is_empty() && to_hex(&bytes) == hash => Ok(bytes),
+        _ => Err(malformed(&format!("{:?} is not a lowercase hex digest", hash))),
+    }
+}
+
+fn write_payload<T: Serialize>(out: &mut Vec<u8>, transaction: &T) -> Result<()> {
+    let payload = serde_json::to_vec(transaction)?;
+    out.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    out.extend_from_slice(&payload);
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(malformed("truncated"));
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("took exactly N bytes"))
+    }
+
+    fn payload<T: DeserializeOwned>(&mut self) -> Result<T> {
+        let len = usize::try_from(u64::from_be_bytes(self.array()?)).map_err(|_| malformed("truncated"))?;
+        Ok(serde_json::from_slice(self.take(len)?)?)
+    }
+}
+
+fn to_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+fn from_base64(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.as_bytes();
+    if encoded.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(encoded.len() / 4 * 3 + 2);
+    for chunk in encoded.chunks(4) {
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let value = BASE64_ALPHABET.iter().position(|a| a == c)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+        let decoded = n.to_be_bytes();
+        out.extend_from_slice(&decoded[1..chunk.len()]);
+        // Unused low bits must be zero so every proof has exactly one encoding
+        if chunk.len() < 4 && n & ((1 << (8 * (4 - chunk.len()))) - 1) != 0 {
+            return None;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CryptoBinaryTree, Transaction};
+
+    fn sample_proof() -> Proof {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 0..40u64 {
+            tree.insert(Transaction {
+                id: format!("tx_{:03}", i),
+                from: "Alice".into(),
+                to: "Bob".into(),
+                amount: u128::from(i),
+                ..Default::default()
+            });
+        }
+        tree.get_proof("tx_039").unwrap()
+    }
+
+    #[test]
+    fn test_proof_bytes_round_trip() {
+        let proof = sample_proof();
+        let bytes = proof.to_bytes().unwrap();
+        assert!(bytes.len() < serde_json::to_vec(&proof).unwrap().len());
+
+        let decoded = Proof::<Transaction>::from_bytes(&bytes).unwrap();
+        assert!(decoded.verify());
+        assert_eq!(decoded.to_bytes().unwrap(), bytes);
+        // The rightmost leaf has empty children, stored without a hash
+        assert!(decoded.steps.iter().any(|s| s.hash == "0"));
+
+        let base64 = proof.to_base64().unwrap();
+        assert!(base64.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'));
+        assert!(Proof::<Transaction>::from_base64(&base64).unwrap().verify());
+        assert!(Proof::<Transaction>::from_hex(&proof.to_hex().unwrap()).unwrap().verify());
+    }
+
+    #[test]
+    fn test_base64_codec() {
+        for len in 0..8 {
+            let bytes: Vec<u8> = (0..len).map(|i| 0xf0 ^ i as u8).collect();
+            assert_eq!(from_base64(&to_base64(&bytes)).unwrap(), bytes);
+        }
+        assert_eq!(to_base64(b"\xfb\xff"), "-_8");
+        assert!(from_base64("-_9").is_none());
+        assert!(from_base64("A").is_none());
+        assert!(from_base64("AA=A").is_none());
+    }
+
+    #[test]
+    fn test_malformed_proof_bytes() {
+        let bytes = sample_proof().to_bytes().unwrap();
+        for len in [0, 5, bytes.len() / 2, bytes.len() - 1] {
+            assert!(Proof::<Transaction>::from_bytes(&bytes[..len]).is_err(), "accepted {} bytes", len);
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Proof::<Transaction>::from_bytes(&trailing).is_err());
+
+        let mut upper = sample_proof();
+        upper.root = upper.root.to_uppercase();
+        assert!(upper.to_bytes().is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::multiproof::check_root;
+use crate::{
+    CryptoBinaryTree, CryptoTreeError, MultiProofEntry, Result, Sha256Hasher, Transaction, TreeHasher, TreeKey,
+};
+
+/// A proof that a set of transactions is exactly the contents of a key range.
+///
+/// Like a [`MultiProof`](crate::MultiProof) it lists, in pre-order, every node
+/// whose subtree may hold a key in `start..=end`, with every other subtree
+/// replaced by its hash. A verifier can tell from the ancestors' keys that
+/// each pruned subtree lies entirely outside the range, so no matching
+/// transaction can have been left out.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RangeProof<T: TreeKey = Transaction> {
+    /// Lower bound of the range, inclusive
+    pub start: T::Key,
+    /// Upper bound of the range, inclusive
+    pub end: T::Key,
+    pub entries: Vec<MultiProofEntry<T>>,
+}
+
+impl<T: TreeKey> RangeProof<T> {
+    /// The transactions in `start..=end` carried by the proof, in key order.
+    ///
+    /// Only meaningful once the proof has been checked with
+    /// [`verify_range_proof`].
+    pub fn transactions(&self) -> Vec<&T> {
+        let mut found: Vec<&T> = self
+            .entries
+            .iter()
+            .filter_map(|e| match e {
+                MultiProofEntry::Node { transaction, .. } if self.contains(transaction.key()) => Some(transaction),
+                _ => None,
+            })
+            .collect();
+        found.sort_by(|a, b| a.key().cmp(b.key()));
+        found
+    }
+
+    fn contains(&self, key: &T::Key) -> bool {
+        &self.start <= key && key <= &self.end
+    }
+}
+
+/// Whether a subtree holding only keys strictly between `lower` and `upper`
+/// can contain a key in `start..=end`
+fn may_overlap<K: Ord + ?Sized>(lower: Option<&K>, upper: Option<&K>, start: &K, end: &K) -> bool {
+    upper.is_none_or(|u| u > start) && lower.is_none_or(|l| l < end)
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Builds a proof that the transactions with keys in `start..=end` are
+    /// exactly those returned by [`RangeProof::transactions`].
+    ///
+    /// Use [`verify_range_proof`] to check the result against a Merkle root.
+    pub fn get_range_proof<Q>(&self, start: &Q, end: &Q) -> RangeProof<T>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ToOwned<Owned = T::Key> + ?Sized,
+    {
+        self._assert_hashed();
+        let mut entries = Vec::new();
+        let mut stack = vec![(self.root, None, None)];
+        while let Some((node, lower, upper)) = stack.pop() {
+            let Some(n) = node.map(|id| &self.nodes[id]) else {
+                entries.push(MultiProofEntry::Pruned("0".to_string()));
+                continue;
+            };
+            if !may_overlap::<Q>(lower, upper, start, end) {
+                entries.push(MultiProofEntry::Pruned(n.hash.clone()));
+                continue;
+            }
+            entries.push(MultiProofEntry::Node {
+                transaction: n.transaction.clone(),
+                height: n.height,
+                size: n.size,
+            });
+            let key = n.transaction.key().borrow();
+            stack.push((n.right, Some(key), upper));
+            stack.push((n.left, lower, Some(key)));
+        }
+        RangeProof {
+            start: start.to_owned(),
+            end: end.to_owned(),
+            entries,
+        }
+    }
+}
+
+/// Verifies a proof produced by `CryptoBinaryTree::get_range_proof`.
+///
+/// Checks that the entries recompute to `root`, that the expanded nodes are
+/// in search-tree order and that every pruned subtree lies outside the range.
+/// Nodes are assumed to be hashed with SHA-256; see [`verify_range_proof_with`].
+pub fn verify_range_proof<T: TreeKey + Serialize>(root: &str, proof: &RangeProof<T>) -> bool {
+    verify_range_proof_with(&Sha256Hasher::default(), root, proof)
+}
+
+/// Like [`verify_range_proof`], for trees built with a custom hasher.
+pub fn verify_range_proof_with<T: TreeKey + Serialize, H: TreeHasher>(
+    hasher: &H,
+    root: &str,
+    proof: &RangeProof<T>,
+) -> bool {
+    check_range_proof_with(hasher, root, proof).is_ok()
+}
+
+/// Like [`verify_range_proof_with`], reporting why a proof was rejected.
+pub fn check_range_proof_with<T: TreeKey + Serialize, H: TreeHasher>(
+    hasher: &H,
+    root: &str,
+    proof: &RangeProof<T>,
+) -> Result<()> {
+    let invalid = |reason: &str| CryptoTreeError::InvalidProof(reason.to_string());
+
+    // Replay the pre-order walk, tracking the key interval each entry covers
+    let mut pending = vec![(None, None)];
+    for entry in &proof.entries {
+        let Some((lower, upper)) = pending.pop() else {
+            return Err(invalid("trailing entries"));
+        };
+        match entry {
+            MultiProofEntry::Node { transaction, .. } => {
+                let key = transaction.key();
+                if lower.is_some_and(|l| key.cmp(l) != Ordering::Greater)
+                    || upper.is_some_and(|u| key.cmp(u) != Ordering::Less)
+                {
+                    return Err(invalid("nodes are out of order"));
+                }
+                pending.push((Some(key), upper));
+                pending.push((lower, Some(key)));
+            }
+            MultiProofEntry::Pruned(hash) => {
+                if hash != "0" && may_overlap(lower, upper, &proof.start, &proof.end) {
+                    return Err(invalid("pruned subtree may overlap the range"));
+                }
+            }
+        }
+    }
+    if !pending.is_empty() {
+        return Err(invalid("node is missing a subtree"));
+    }
+    check_root(hasher, root, &proof.entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionTree;
+    use crate::test_util::build_tree;
+
+    fn ids(proof: &RangeProof) -> Vec<&str> {
+        proof.transactions().iter().map(|tx| tx.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_range_proof_exact_contents() {
+        let tree = build_tree(100);
+        let proof = tree.get_range_proof("tx_020", "tx_029");
+        assert!(verify_range_proof(tree.merkle_root(), &proof));
+        let expected: Vec<String> = (20..30).map(|i| format!("tx_{:03}", i)).collect();
+        assert_eq!(ids(&proof), expected);
+
+        // Bounds need not be stored keys
+        let proof = tree.get_range_proof("tx_0955", "zzz");
+        assert!(verify_range_proof(tree.merkle_root(), &proof));
+        assert_eq!(ids(&proof), ["tx_096", "tx_097", "tx_098", "tx_099", "tx_100"]);
+
+        let none = tree.get_range_proof("tx_0505", "tx_0507");
+        assert!(verify_range_proof(tree.merkle_root(), &none));
+        assert!(none.transactions().is_empty());
+
+        let json = serde_json::to_string(&none).unwrap();
+        let decoded: RangeProof = serde_json::from_str(&json).unwrap();
+        assert!(verify_range_proof(tree.merkle_root(), &decoded));
+    }
+
+    #[test]
+    fn test_range_proof_empty_tree() {
+        let tree = TransactionTree::new();
+        let proof = tree.get_range_proof("a", "z");
+        assert!(verify_range_proof(tree.merkle_root(), &proof));
+        assert!(proof.transactions().is_empty());
+    }
+
+    #[test]
+    fn test_range_proof_rejects_omission() {
+        let tree = build_tree(100);
+        let proof = tree.get_range_proof("tx_040", "tx_060");
+
+        // Widening the claimed range exposes subtrees that were pruned
+        let mut widened = proof.clone();
+        widened.end = "tx_070".to_string();
+        assert!(!verify_range_proof(tree.merkle_root(), &widened));
+
+        // Replacing an in-range node by its hash hides it from the result
+        let mut hidden = tree.get_range_proof("tx_000", "tx_099");
+        let position = hidden
+            .entries
+            .iter()
+            .rposition(|e| matches!(e, MultiProofEntry::Node { transaction, .. } if transaction.id == "tx_050"))
+            .unwrap();
+        hidden.entries[position] = MultiProofEntry::Pruned("f".repeat(64));
+        assert!(!verify_range_proof(tree.merkle_root(), &hidden));
+
+        let mut tampered = proof;
+        for entry in &mut tampered.entries {
+            if let MultiProofEntry::Node { transaction, .. } = entry {
+                transaction.amount += 1;
+                break;
+            }
+        }
+        assert!(!verify_range_proof(tree.merkle_root(), &tampered));
+    }
+}
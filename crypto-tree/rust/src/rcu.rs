@@ -0,0 +1,197 @@
+//! Lock-free reads alongside a single writer (`lockfree` feature).
+//!
+//! A [`TreeWriter`] owns the tree and, after each write, publishes a frozen
+//! [`TreeSnapshot`] of it. Any number of [`TreeReader`]s load the latest
+//! published snapshot without taking a lock: they clone an `Arc` out of one
+//! of two slots and then search and build proofs on their own copy, however
+//! long the writer takes to build the next version. Old versions are freed
+//! when their last reader drops them.
+//!
+//! Publishing is left-right: the writer fills the slot readers are not
+//! using, switches readers over to it, and before reusing the other slot
+//! waits for the few readers still cloning from it, which takes no longer
+//! than an `Arc` clone.
+
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use core::hint;
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+use serde::Serialize;
+
+use crate::{CryptoBinaryTree, Sha256Hasher, Transaction, TreeHasher, TreeKey, TreeSnapshot};
+
+/// Two slots holding the current and the previous snapshot
+struct Slots<S> {
+    slots: [UnsafeCell<Arc<S>>; 2],
+    /// Index of the slot new readers clone from
+    active: AtomicUsize,
+    /// Readers currently cloning from each slot
+    readers: [AtomicUsize; 2],
+}
+
+// SAFETY: slots hold `Arc<S>`, shared across threads like `Arc<S>` itself.
+// A slot is only written by the single writer while `active` points at the
+// other slot and no reader is registered on it, see `load` and `store`.
+unsafe impl<S: Send + Sync> Sync for Slots<S> {}
+unsafe impl<S: Send + Sync> Send for Slots<S> {}
+
+impl<S> Slots<S> {
+    fn new(value: Arc<S>) -> Self {
+        Slots {
+            slots: [UnsafeCell::new(value.clone()), UnsafeCell::new(value)],
+            active: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+        }
+    }
+
+    fn load(&self) -> Arc<S> {
+        loop {
+            let i = self.active.load(SeqCst);
+            self.readers[i].fetch_add(1, SeqCst);
+            // Still active after registering: the writer will not touch slot i
+            // until this reader deregisters
+            if self.active.load(SeqCst) == i {
+                // SAFETY: see above; only shared access while registered
+                let value = unsafe { (*self.slots[i].get()).clone() };
+                self.readers[i].fetch_sub(1, SeqCst);
+                return value;
+            }
+            self.readers[i].fetch_sub(1, SeqCst);
+        }
+    }
+
+    /// Publishes `value`. Callers must not store concurrently.
+    fn store(&self, value: Arc<S>) {
+        let next = 1 - self.active.load(SeqCst);
+        // Readers registered on `next` started before the last switch; any that
+        // register from now on see it inactive and back off without reading
+        while self.readers[next].load(SeqCst) != 0 {
+            hint::spin_loop();
+        }
+        // SAFETY: `next` is inactive and has no registered reader, and there
+        // is a single writer
+        unsafe { *self.slots[next].get() = value };
+        self.active.store(next, SeqCst);
+    }
+}
+
+/// Hands out the latest snapshot published by a [`TreeWriter`]; cheap to clone
+pub struct TreeReader<T: TreeKey = Transaction, H = Sha256Hasher> {
+    slots: Arc<Slots<TreeSnapshot<T, H>>>,
+}
+
+impl<T: TreeKey, H> Clone for TreeReader<T, H> {
+    fn clone(&self) -> Self {
+        TreeReader {
+            slots: Arc::clone(&self.slots),
+        }
+    }
+}
+
+impl<T: TreeKey, H> TreeReader<T, H> {
+    /// The latest published version of the tree, loaded without locking
+    pub fn load(&self) -> Arc<TreeSnapshot<T, H>> {
+        self.slots.load()
+    }
+}
+
+/// The single writer of a tree whose readers never lock
+///
+/// Mutate the tree with [`write`](Self::write), which publishes the result
+/// to every [`TreeReader`] when the closure returns.
+pub struct TreeWriter<T: TreeKey = Transaction, H = Sha256Hasher> {
+    tree: CryptoBinaryTree<T, H>,
+    slots: Arc<Slots<TreeSnapshot<T, H>>>,
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher + Clone> TreeWriter<T, H> {
+    /// Takes ownership of `tree` and publishes its current state.
+    pub fn new(mut tree: CryptoBinaryTree<T, H>) -> Self {
+        tree.flush_hashes();
+        let slots = Arc::new(Slots::new(Arc::new(tree.snapshot())));
+        TreeWriter { tree, slots }
+    }
+
+    /// A reader of the versions this writer publishes
+    pub fn reader(&self) -> TreeReader<T, H> {
+        TreeReader {
+            slots: Arc::clone(&self.slots),
+        }
+    }
+
+    /// The tree as last written, including anything not yet visible to readers
+    pub fn tree(&self) -> &CryptoBinaryTree<T, H> {
+        &self.tree
+    }
+
+    /// Runs `f` on the tree, then publishes it to readers.
+    ///
+    /// Readers keep seeing the previous version until `f` returns. Pending
+    /// hashes are flushed before publishing. Since readers share the tree's
+    /// pages, the next write copies the page table, one pointer per 64 nodes,
+    /// so batch small writes into one call where possible.
+    pub fn write<R>(&mut self, f: impl FnOnce(&mut CryptoBinaryTree<T, H>) -> R) -> R {
+        let result = f(&mut self.tree);
+        self.tree.flush_hashes();
+        self.slots.store(Arc::new(self.tree.snapshot()));
+        result
+    }
+
+    /// Stops publishing and returns the tree; readers keep the last version.
+    pub fn into_tree(self) -> CryptoBinaryTree<T, H> {
+        self.tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+
+    use super::*;
+    use crate::verify_proof;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_readers_see_published_versions() {
+        let mut writer = TreeWriter::new(CryptoBinaryTree::new());
+        let reader = writer.reader();
+        let before = reader.load();
+        assert!(writer.write(|tree| tree.insert(sample_tx("tx_1", 10))));
+        assert_eq!(before.len(), 0);
+        let after = reader.load();
+        assert_eq!(after.len(), 1);
+        assert_eq!(after.merkle_root(), writer.tree().merkle_root());
+    }
+
+    #[test]
+    fn test_concurrent_readers_see_consistent_versions() {
+        let mut writer: TreeWriter = TreeWriter::new(CryptoBinaryTree::new());
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let reader = writer.reader();
+                let done = &done;
+                s.spawn(move || {
+                    let mut seen = 0;
+                    while !done.load(SeqCst) {
+                        let version = reader.load();
+                        // Versions only grow, and each one is complete
+                        assert!(version.len() >= seen);
+                        seen = version.len();
+                        if let Some(tx) = version.last() {
+                            let proof = version.get_proof_of_inclusion(tx.id.as_str()).unwrap();
+                            assert!(verify_proof(version.merkle_root(), tx, &proof));
+                        }
+                    }
+                });
+            }
+            for i in 0..300 {
+                writer.write(|tree| tree.insert(sample_tx(&format!("tx_{:04}", i), 10)));
+            }
+            done.store(true, SeqCst);
+        });
+        assert_eq!(writer.reader().load().len(), 300);
+    }
+}
@@ -0,0 +1,120 @@
+use ed25519_dalek::{Signature, Signer, Verifier};
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use crate::prelude::*;
+use crate::hasher::{from_hex, to_hex};
+use crate::{encode_canonical, CryptoTreeError, Result, Transaction};
+#[cfg(feature = "tree")]
+use crate::{CryptoBinaryTree, TreeHasher};
+
+impl Transaction {
+    /// Bytes covered by the signature: the canonical encoding of the
+    /// transaction with `signature` cleared, so the public key is bound too.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let unsigned = Transaction {
+            signature: None,
+            ..self.clone()
+        };
+        encode_canonical(&unsigned).expect("transactions always have a canonical encoding")
+    }
+
+    /// Signs the transaction with `keypair`, recording the signature and public key.
+    pub fn sign(&mut self, keypair: &SigningKey) {
+        self.public_key = Some(to_hex(keypair.verifying_key().as_bytes()));
+        let signature = keypair.sign(&self.signing_bytes());
+        self.signature = Some(to_hex(&signature.to_bytes()));
+    }
+
+    /// Returns `true` if the transaction is signed by the key it carries.
+    pub fn verify_signature(&self) -> bool {
+        self.check_signature().is_ok()
+    }
+
+    /// Like [`Transaction::verify_signature`], but reports `Unsigned` or
+    /// `InvalidSignature` on failure.
+    pub fn check_signature(&self) -> Result<()> {
+        let (Some(signature), Some(public_key)) = (&self.signature, &self.public_key) else {
+            return Err(CryptoTreeError::Unsigned(self.id.clone()));
+        };
+        let invalid = || CryptoTreeError::InvalidSignature(self.id.clone());
+        let key_bytes: [u8; 32] = from_hex(public_key)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(invalid)?;
+        let signature_bytes: [u8; 64] = from_hex(signature)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(invalid)?;
+        let key = VerifyingKey::from_bytes(&key_bytes).map_err(|_| invalid())?;
+        key.verify(&self.signing_bytes(), &Signature::from_bytes(&signature_bytes))
+            .map_err(|_| invalid())
+    }
+}
+
+#[cfg(feature = "tree")]
+impl<H: TreeHasher> CryptoBinaryTree<Transaction, H> {
+    /// Rejects every later insert or update of an unsigned or invalidly signed transaction.
+    pub fn require_signatures(&mut self) {
+        self._set_policy("signatures", Transaction::check_signature);
+    }
+}
+
+#[cfg(all(test, feature = "tree"))]
+mod tests {
+    use super::SigningKey;
+    use crate::{CryptoBinaryTree, CryptoTreeError, Transaction};
+    use crate::test_util::sample_tx;
+
+    fn keypair(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let mut tx = sample_tx("tx_1", 10);
+        assert!(!tx.verify_signature());
+        tx.sign(&keypair(1));
+        assert!(tx.verify_signature());
+
+        let mut tampered = tx.clone();
+        tampered.amount = 11;
+        assert!(matches!(tampered.check_signature(), Err(CryptoTreeError::InvalidSignature(_))));
+
+        let mut swapped = tx.clone();
+        swapped.public_key = Some(crate::hasher::to_hex(keypair(2).verifying_key().as_bytes()));
+        assert!(!swapped.verify_signature());
+
+        let mut garbled = tx;
+        garbled.signature = Some("zz".to_string());
+        assert!(!garbled.verify_signature());
+    }
+
+    #[test]
+    fn test_require_signatures() {
+        let mut tree = CryptoBinaryTree::new();
+        assert!(tree.insert(sample_tx("tx_0", 10)));
+        tree.require_signatures();
+
+        assert!(matches!(tree.try_insert(sample_tx("tx_1", 10)), Err(CryptoTreeError::Unsigned(_))));
+        let mut tx = sample_tx("tx_1", 10);
+        tx.sign(&keypair(1));
+        tree.try_insert(tx).unwrap();
+
+        // Editing a signed payload without re-signing is rejected
+        assert!(tree.update("tx_1", |tx| tx.amount = 99).is_err());
+        let key = keypair(1);
+        tree.update("tx_1", |tx| {
+            tx.amount = 99;
+            tx.sign(&key);
+        })
+        .unwrap();
+        assert!(tree.verify_integrity());
+    }
+
+    #[test]
+    fn test_unsigned_encoding_is_unchanged() {
+        // Optional signature fields are omitted, so existing roots still reproduce
+        let tx = sample_tx("tx_1", 10);
+        let json = serde_json::to_string(&tx).unwrap();
+        assert!(!json.contains("signature"));
+        assert_eq!(serde_json::from_str::<Transaction>(&json).unwrap(), tx);
+    }
+}
@@ -0,0 +1,158 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::hasher::{from_hex, to_hex};
+use crate::encode_canonical;
+#[cfg(feature = "tree")]
+use crate::{CryptoBinaryTree, TreeKey};
+
+/// Prefix of the signed bytes, so a root signature can never be replayed as a
+/// transaction signature or vice versa
+const SIGNED_ROOT_DOMAIN: &[u8] = b"crypto-tree/signed-root/v1";
+
+/// A Merkle root signed by the tree owner (a signed tree head).
+///
+/// Clients that hold the owner's public key can check a `SignedRoot` with
+/// [`SignedRoot::verify`] and then trust inclusion, absence and range proofs
+/// that verify against `root`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SignedRoot {
+    pub root: String,
+    /// Number of transactions in the tree
+    pub size: usize,
+    /// Signing time, seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Hex-encoded Ed25519 signature over [`SignedRoot::signing_bytes`]
+    pub signature: String,
+}
+
+impl SignedRoot {
+    /// Signs `root`, `size` and `timestamp` with `key`.
+    pub fn sign(root: &str, size: usize, timestamp: u64, key: &SigningKey) -> Self {
+        let mut signed = SignedRoot {
+            root: root.to_string(),
+            size,
+            timestamp,
+            signature: String::new(),
+        };
+        signed.signature = to_hex(&key.sign(&signed.signing_bytes()).to_bytes());
+        signed
+    }
+
+    /// Bytes covered by the signature: a domain prefix followed by the
+    /// canonical encoding of root, size and timestamp.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = SIGNED_ROOT_DOMAIN.to_vec();
+        let head = (&self.root, self.size as u64, self.timestamp);
+        bytes.extend(encode_canonical(&head).expect("signed roots always have a canonical encoding"));
+        bytes
+    }
+
+    /// Returns `true` if the signature was made by the key matching `public_key`.
+    pub fn verify(&self, public_key: &VerifyingKey) -> bool {
+        let Some(signature) = from_hex(&self.signature).and_then(|b| <[u8; 64]>::try_from(b).ok()) else {
+            return false;
+        };
+        public_key
+            .verify(&self.signing_bytes(), &Signature::from_bytes(&signature))
+            .is_ok()
+    }
+}
+
+#[cfg(feature = "tree")]
+impl<T: TreeKey, H> CryptoBinaryTree<T, H> {
+    /// Sets the key used by [`signed_root`](Self::signed_root); `None` disables signing.
+    pub fn set_root_signer(&mut self, key: Option<SigningKey>) {
+        self.root_signer = key;
+    }
+
+    /// Public key clients should use to verify this tree's signed roots.
+    pub fn root_verifying_key(&self) -> Option<VerifyingKey> {
+        self.root_signer.as_ref().map(SigningKey::verifying_key)
+    }
+
+    /// Signs the current Merkle root and size, timestamped now.
+    ///
+    /// Returns `None` if no signer is configured.
+    #[cfg(feature = "std")]
+    pub fn signed_root(&self) -> Option<SignedRoot> {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        self.signed_root_at(timestamp)
+    }
+
+    /// Like [`signed_root`](Self::signed_root), with an explicit timestamp.
+    pub fn signed_root_at(&self, timestamp: u64) -> Option<SignedRoot> {
+        self._assert_hashed();
+        let key = self.root_signer.as_ref()?;
+        Some(SignedRoot::sign(&self.merkle_root, self.nodes.len(), timestamp, key))
+    }
+}
+
+#[cfg(all(test, feature = "tree"))]
+mod tests {
+    use super::*;
+    use crate::{verify_proof, Transaction, TransactionTree};
+
+    fn sample_tree() -> TransactionTree {
+        let mut tree = TransactionTree::new();
+        for i in 0..10 {
+            tree.insert(Transaction {
+                id: format!("tx_{}", i),
+                from: "A".into(),
+                to: "B".into(),
+                amount: 10,
+                ..Default::default()
+            });
+        }
+        tree
+    }
+
+    #[test]
+    fn test_signed_root_verifies() {
+        let mut tree = sample_tree();
+        assert!(tree.signed_root_at(1_700_000_000).is_none());
+        tree.set_root_signer(Some(SigningKey::from_bytes(&[7; 32])));
+        let public_key = tree.root_verifying_key().unwrap();
+
+        let head = tree.signed_root_at(1_700_000_000).unwrap();
+        assert_eq!(head.root, tree.merkle_root());
+        assert_eq!(head.size, 10);
+        assert!(head.verify(&public_key));
+
+        // The signed root anchors inclusion proofs
+        let tx = tree.search("tx_3").unwrap();
+        let proof = tree.get_proof_of_inclusion("tx_3").unwrap();
+        assert!(verify_proof(&head.root, tx, &proof));
+
+        let json = serde_json::to_string(&head).unwrap();
+        let decoded: SignedRoot = serde_json::from_str(&json).unwrap();
+        assert!(decoded.verify(&public_key));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_signed_root_rejects_tampering() {
+        let mut tree = sample_tree();
+        tree.set_root_signer(Some(SigningKey::from_bytes(&[7; 32])));
+        let public_key = tree.root_verifying_key().unwrap();
+        let head = tree.signed_root().unwrap();
+
+        let mut resized = head.clone();
+        resized.size += 1;
+        assert!(!resized.verify(&public_key));
+
+        let mut rerooted = head.clone();
+        rerooted.root = "0".repeat(64);
+        assert!(!rerooted.verify(&public_key));
+
+        let mut garbled = head.clone();
+        garbled.signature = "zz".to_string();
+        assert!(!garbled.verify(&public_key));
+
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        assert!(!head.verify(&other));
+    }
+}
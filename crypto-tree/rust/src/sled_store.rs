@@ -0,0 +1,192 @@
+//! Persistent [sled](https://docs.rs/sled) storage (`storage-sled` feature).
+//!
+//! [`SledStore`] implements [`TreeStore`], so an [`AsyncCryptoTree`] kept in
+//! it grows beyond RAM and survives restarts: reopening the database and the
+//! tree picks up the last committed root, and each operation loads only the
+//! nodes on its search path. Nodes live in the `nodes` tree under their hash;
+//! every committed root is appended to the `roots` tree, a journal in commit
+//! order whose last entry is the current root.
+//!
+//! [`AsyncCryptoTree`]: crate::AsyncCryptoTree
+
+use core::fmt;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::prelude::*;
+use crate::{StoredNode, TreeStore};
+
+/// Name of the sled tree holding nodes by hash
+pub const NODES_TREE: &str = "nodes";
+/// Name of the sled tree journaling committed roots by sequence number
+pub const ROOTS_TREE: &str = "roots";
+
+/// Failure of a [`SledStore`]
+#[derive(Debug)]
+pub enum SledStoreError {
+    /// sled could not read or write
+    Sled(sled::Error),
+    /// A stored node could not be encoded or decoded
+    Encoding(serde_json::Error),
+    /// A journaled root is not a valid hash
+    MalformedRoot,
+}
+
+impl fmt::Display for SledStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SledStoreError::Sled(e) => write!(f, "sled: {}", e),
+            SledStoreError::Encoding(e) => write!(f, "node encoding: {}", e),
+            SledStoreError::MalformedRoot => f.write_str("malformed root in journal"),
+        }
+    }
+}
+
+impl std::error::Error for SledStoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SledStoreError::Sled(e) => Some(e),
+            SledStoreError::Encoding(e) => Some(e),
+            SledStoreError::MalformedRoot => None,
+        }
+    }
+}
+
+impl From<sled::Error> for SledStoreError {
+    fn from(e: sled::Error) -> Self {
+        SledStoreError::Sled(e)
+    }
+}
+
+impl From<serde_json::Error> for SledStoreError {
+    fn from(e: serde_json::Error) -> Self {
+        SledStoreError::Encoding(e)
+    }
+}
+
+/// A [`TreeStore`] in a sled database
+///
+/// Node writes are buffered by sled; `commit_root` journals the root and
+/// waits for the database to be flushed, so a root is only reported as
+/// committed once it and every node it reaches are on disk.
+#[derive(Debug, Clone)]
+pub struct SledStore<T> {
+    db: sled::Db,
+    nodes: sled::Tree,
+    roots: sled::Tree,
+    _payload: PhantomData<fn() -> T>,
+}
+
+impl<T> SledStore<T> {
+    /// Opens or creates the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, SledStoreError> {
+        Self::with_db(sled::open(path)?)
+    }
+
+    /// Keeps the tree in `db`, next to whatever else it holds.
+    pub fn with_db(db: sled::Db) -> Result<Self, SledStoreError> {
+        Ok(SledStore {
+            nodes: db.open_tree(NODES_TREE)?,
+            roots: db.open_tree(ROOTS_TREE)?,
+            db,
+            _payload: PhantomData,
+        })
+    }
+
+    pub fn db(&self) -> &sled::Db {
+        &self.db
+    }
+
+    /// Every committed root with its sequence number, oldest first; `None`
+    /// records a commit of the empty tree.
+    pub fn root_history(&self) -> Result<Vec<(u64, Option<String>)>, SledStoreError> {
+        self.roots.iter().map(|entry| decode_root(entry?)).collect()
+    }
+
+    /// Number of nodes stored, including those only reachable from old roots
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+fn decode_root((key, value): (sled::IVec, sled::IVec)) -> Result<(u64, Option<String>), SledStoreError> {
+    let seq = <[u8; 8]>::try_from(key.as_ref()).map_err(|_| SledStoreError::MalformedRoot)?;
+    let root = match value.as_ref() {
+        [] => None,
+        hash => Some(String::from_utf8(hash.to_vec()).map_err(|_| SledStoreError::MalformedRoot)?),
+    };
+    Ok((u64::from_be_bytes(seq), root))
+}
+
+impl<T: Serialize + DeserializeOwned + Send + Sync> TreeStore<T> for SledStore<T> {
+    type Error = SledStoreError;
+
+    async fn get_node(&self, hash: &str) -> Result<Option<StoredNode<T>>, SledStoreError> {
+        match self.nodes.get(hash)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put_node(&self, hash: &str, node: &StoredNode<T>) -> Result<(), SledStoreError> {
+        // Nodes are content-addressed, so one already stored is identical
+        if !self.nodes.contains_key(hash)? {
+            self.nodes.insert(hash, serde_json::to_vec(node)?)?;
+        }
+        Ok(())
+    }
+
+    async fn commit_root(&self, root: Option<&str>) -> Result<(), SledStoreError> {
+        // Ids from `generate_id` only grow, also across restarts
+        let seq = self.db.generate_id()?;
+        self.roots.insert(seq.to_be_bytes(), root.unwrap_or("").as_bytes())?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn load_root(&self) -> Result<Option<String>, SledStoreError> {
+        match self.roots.last()? {
+            Some(entry) => Ok(decode_root(entry)?.1),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::store::tests::block_on;
+
+    use super::*;
+    use crate::test_util::{sample_tx, temp_path};
+    use crate::{verify_proof, AsyncCryptoTree, CryptoBinaryTree, Sha256Hasher, Transaction};
+
+    #[test]
+    fn test_survives_reopen() {
+        let dir = temp_path("sled");
+        let mut reference = CryptoBinaryTree::new();
+        {
+            let store = SledStore::open(&dir).unwrap();
+            let mut tree = block_on(AsyncCryptoTree::open(store, Sha256Hasher::new())).unwrap();
+            for i in 0..100 {
+                let tx = sample_tx(&format!("tx_{:03}", i), 10);
+                block_on(tree.insert(tx.clone())).unwrap();
+                reference.insert(tx);
+            }
+            assert_eq!(tree.merkle_root(), reference.merkle_root());
+        }
+
+        let store = SledStore::<Transaction>::open(&dir).unwrap();
+        assert_eq!(store.root_history().unwrap().len(), 100);
+        let tree = block_on(AsyncCryptoTree::open(store, Sha256Hasher::new())).unwrap();
+        assert_eq!(tree.len(), 100);
+        assert_eq!(tree.merkle_root(), reference.merkle_root());
+        let tx = block_on(tree.search("tx_042")).unwrap().unwrap();
+        let proof = block_on(tree.get_proof_of_inclusion("tx_042")).unwrap().unwrap();
+        assert!(verify_proof(tree.merkle_root(), &tx, &proof));
+        drop(tree);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
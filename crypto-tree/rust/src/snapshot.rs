@@ -0,0 +1,563 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::arena::Arena;
+use crate::{
+    CryptoBinaryTree, CryptoTreeNode, HashAlgorithm, HashFormat, NodeId, Sha256Hasher, TreeHasher, TreeKey, MAX_TREE_HEIGHT,
+    SEALED_MAGIC,
+};
+
+/// Magic bytes at the start of every snapshot file
+pub const SNAPSHOT_MAGIC: &[u8; 6] = b"CTSNAP";
+
+/// Current snapshot format version
+///
+/// Version 2 added the hash algorithm to the header and version 3 the domain
+/// prefixes of `HashFormat::BinaryV3`, version 4 a flags byte recording
+/// whether node hashes are salted; version 1 snapshots are still read,
+/// without checking the algorithm.
+pub const SNAPSHOT_VERSION: u16 = 4;
+
+const HAS_LEFT: u8 = 0b01;
+const HAS_RIGHT: u8 = 0b10;
+
+/// Header flag: node hashes are salted (see [`TreeHasher::salt`])
+const SALTED: u8 = 0b01;
+
+/// Error raised while saving or loading a snapshot
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The file does not start with `SNAPSHOT_MAGIC`
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// The snapshot was written with a different node hash layout than the loading hasher
+    FormatMismatch { stored: HashFormat, expected: HashFormat },
+    /// The snapshot was hashed with a different hash function than the loading
+    /// hasher; `None` stands for a custom [`TreeHasher`]
+    AlgorithmMismatch {
+        stored: Option<HashAlgorithm>,
+        expected: Option<HashAlgorithm>,
+    },
+    /// The snapshot is salted and the loading hasher is not, or vice versa;
+    /// `salted` is the stored flag
+    SaltMismatch { salted: bool },
+    /// A record could not be decoded
+    Corrupted(String),
+    /// The reconstructed Merkle root differs from the one stored in the header
+    RootMismatch { stored: String, computed: String },
+    /// The file is an encrypted snapshot; load it with `load_encrypted`
+    Encrypted,
+    /// The cipher could not decrypt the file: wrong key, or the file was altered
+    Decryption,
+    /// Lazy hashing left node hashes pending; call `flush_hashes` before writing
+    PendingHashes,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot I/O error: {}", e),
+            SnapshotError::BadMagic => write!(f, "not a crypto-tree snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {}", v),
+            SnapshotError::FormatMismatch { stored, expected } => {
+                write!(f, "snapshot uses hash format {:?}, hasher expects {:?}", stored, expected)
+            }
+            SnapshotError::AlgorithmMismatch { stored, expected } => {
+                write!(f, "snapshot uses hash algorithm {:?}, hasher implements {:?}", stored, expected)
+            }
+            SnapshotError::SaltMismatch { salted: true } => write!(f, "snapshot is salted, hasher is not"),
+            SnapshotError::SaltMismatch { salted: false } => write!(f, "snapshot is not salted, hasher is"),
+            SnapshotError::Corrupted(msg) => write!(f, "corrupted snapshot: {}", msg),
+            SnapshotError::RootMismatch { stored, computed } => {
+                write!(f, "merkle root mismatch: stored {}, computed {}", stored, computed)
+            }
+            SnapshotError::Encrypted => write!(f, "snapshot is encrypted"),
+            SnapshotError::Decryption => write!(f, "snapshot could not be decrypted: wrong key or altered file"),
+            SnapshotError::PendingHashes => write!(f, "tree has pending hashes, call flush_hashes() first"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+/// Writes the format tag, followed by the domain prefixes for `BinaryV3`.
+pub(crate) fn write_format<W: Write>(writer: &mut W, format: HashFormat) -> io::Result<()> {
+    match format {
+        HashFormat::JsonV0 => writer.write_all(&[0]),
+        HashFormat::BinaryV1 => writer.write_all(&[1]),
+        HashFormat::BinaryV2 => writer.write_all(&[2]),
+        HashFormat::BinaryV3 { leaf_prefix, internal_prefix } => writer.write_all(&[3, leaf_prefix, internal_prefix]),
+    }
+}
+
+pub(crate) fn read_format<R: Read>(reader: &mut R) -> Result<HashFormat, SnapshotError> {
+    match read_array::<_, 1>(reader)?[0] {
+        0 => Ok(HashFormat::JsonV0),
+        1 => Ok(HashFormat::BinaryV1),
+        2 => Ok(HashFormat::BinaryV2),
+        3 => {
+            let [leaf_prefix, internal_prefix] = read_array(reader)?;
+            Ok(HashFormat::BinaryV3 { leaf_prefix, internal_prefix })
+        }
+        tag => Err(SnapshotError::Corrupted(format!("unknown hash format tag {}", tag))),
+    }
+}
+
+pub(crate) fn algorithm_tag(algorithm: Option<HashAlgorithm>) -> u8 {
+    match algorithm {
+        None => 0,
+        Some(HashAlgorithm::Sha256) => 1,
+        Some(HashAlgorithm::DoubleSha256) => 4,
+        #[cfg(feature = "blake3")]
+        Some(HashAlgorithm::Blake3) => 2,
+        #[cfg(feature = "keccak")]
+        Some(HashAlgorithm::Keccak256) => 3,
+    }
+}
+
+pub(crate) fn algorithm_from_tag(tag: u8) -> Result<Option<HashAlgorithm>, SnapshotError> {
+    match tag {
+        0 => Ok(None),
+        1 => Ok(Some(HashAlgorithm::Sha256)),
+        4 => Ok(Some(HashAlgorithm::DoubleSha256)),
+        #[cfg(feature = "blake3")]
+        2 => Ok(Some(HashAlgorithm::Blake3)),
+        #[cfg(feature = "keccak")]
+        3 => Ok(Some(HashAlgorithm::Keccak256)),
+        _ => Err(SnapshotError::Corrupted(format!(
+            "hash algorithm tag {} is unknown or not enabled in this build",
+            tag
+        ))),
+    }
+}
+
+impl<T, H> CryptoBinaryTree<T, H>
+where
+    T: TreeKey + Serialize + DeserializeOwned + Clone,
+    H: TreeHasher,
+{
+    /// Writes a self-describing snapshot of the tree to `path`.
+    ///
+    /// Layout: `SNAPSHOT_MAGIC`, `u16` version, `u8` hash format (plus the two
+    /// domain prefixes for `BinaryV3`), `u8` hash algorithm, `u8` flags, `u64` node count, the length-prefixed Merkle root, then one
+    /// record per node in pre-order (child flags, `i32` height, stored hash,
+    /// JSON payload).
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_snapshot(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the snapshot described in [`CryptoBinaryTree::save`] to any writer.
+    ///
+    /// Fails with `PendingHashes`, writing nothing, while lazy hashing has
+    /// hashes pending.
+    pub fn write_snapshot<W: Write>(&self, writer: &mut W) -> Result<(), SnapshotError> {
+        if self.has_pending_hashes() {
+            return Err(SnapshotError::PendingHashes);
+        }
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_be_bytes())?;
+        write_format(writer, self.hasher.format())?;
+        writer.write_all(&[algorithm_tag(self.hasher.algorithm())])?;
+        let flags = if self.hasher.salt().is_some() { SALTED } else { 0 };
+        writer.write_all(&[flags])?;
+        writer.write_all(&(self.len() as u64).to_be_bytes())?;
+        write_bytes(writer, self.merkle_root.as_bytes())?;
+        write_nodes(writer, &self.nodes, self.root)
+    }
+
+    /// Reads a snapshot from any reader, rehashing every node with `hasher`.
+    ///
+    /// Fails if the header is invalid, a record is malformed, or the
+    /// reconstructed Merkle root differs from the stored one.
+    pub fn read_snapshot<R: Read>(reader: &mut R, hasher: H) -> Result<Self, SnapshotError> {
+        let mut magic = [0u8; 6];
+        reader.read_exact(&mut magic)?;
+        if &magic == SEALED_MAGIC {
+            return Err(SnapshotError::Encrypted);
+        }
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = u16::from_be_bytes(read_array(reader)?);
+        if !(1..=SNAPSHOT_VERSION).contains(&version) {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let format = read_format(reader)?;
+        if format != hasher.format() {
+            return Err(SnapshotError::FormatMismatch {
+                stored: format,
+                expected: hasher.format(),
+            });
+        }
+        if version >= 2 {
+            let algorithm = algorithm_from_tag(read_array::<_, 1>(reader)?[0])?;
+            if algorithm != hasher.algorithm() {
+                return Err(SnapshotError::AlgorithmMismatch {
+                    stored: algorithm,
+                    expected: hasher.algorithm(),
+                });
+            }
+        }
+        if version >= 4 {
+            let flags = read_array::<_, 1>(reader)?[0];
+            if flags & !SALTED != 0 {
+                return Err(SnapshotError::Corrupted(format!("unknown header flags {:#04x}", flags)));
+            }
+            let salted = flags & SALTED != 0;
+            if salted != hasher.salt().is_some() {
+                return Err(SnapshotError::SaltMismatch { salted });
+            }
+        }
+        let size = u64::from_be_bytes(read_array(reader)?) as usize;
+        let stored_root = read_string(reader)?;
+
+        let mut nodes = Arena::new();
+        let root = if size == 0 {
+            None
+        } else {
+            Some(read_node(reader, &hasher, &mut nodes, 1)?)
+        };
+        if nodes.len() != size {
+            return Err(SnapshotError::Corrupted(format!("expected {} nodes, read {}", size, nodes.len())));
+        }
+
+        let mut tree = Self::with_hasher(hasher);
+        tree.root = root;
+        tree.nodes = nodes;
+        tree._intern_all();
+        tree._update_merkle_root();
+        if tree.merkle_root != stored_root {
+            return Err(SnapshotError::RootMismatch {
+                stored: stored_root,
+                computed: tree.merkle_root,
+            });
+        }
+        Ok(tree)
+    }
+
+    /// Loads a snapshot written by [`CryptoBinaryTree::save`], hashing with `hasher`.
+    pub fn load_with_hasher<P: AsRef<Path>>(path: P, hasher: H) -> Result<Self, SnapshotError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::read_snapshot(&mut reader, hasher)
+    }
+}
+
+impl<T> CryptoBinaryTree<T>
+where
+    T: TreeKey + Serialize + DeserializeOwned + Clone,
+{
+    /// Loads a SHA-256 snapshot written by [`CryptoBinaryTree::save`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::load_with_hasher(path, Sha256Hasher::default())
+    }
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u64).to_be_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// Writes one record per node, in pre-order.
+fn write_nodes<T: Serialize, W: Write>(writer: &mut W, nodes: &Arena<T>, root: Option<NodeId>) -> Result<(), SnapshotError> {
+    let mut stack: Vec<NodeId> = root.into_iter().collect();
+    while let Some(id) = stack.pop() {
+        let n = &nodes[id];
+        let mut flags = 0;
+        if n.left.is_some() {
+            flags |= HAS_LEFT;
+        }
+        if n.right.is_some() {
+            flags |= HAS_RIGHT;
+        }
+        let payload = serde_json::to_vec(&n.transaction).map_err(|e| SnapshotError::Corrupted(e.to_string()))?;
+
+        writer.write_all(&[flags])?;
+        writer.write_all(&n.height.to_be_bytes())?;
+        write_bytes(writer, n.hash.as_bytes())?;
+        write_bytes(writer, &payload)?;
+        stack.extend(n.right);
+        stack.extend(n.left);
+    }
+    Ok(())
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, SnapshotError> {
+    let len = u64::from_be_bytes(read_array(reader)?);
+    let mut buf = Vec::new();
+    reader.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(SnapshotError::Corrupted("truncated record".to_string()));
+    }
+    Ok(buf)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String, SnapshotError> {
+    String::from_utf8(read_bytes(reader)?).map_err(|e| SnapshotError::Corrupted(e.to_string()))
+}
+
+/// Reads the records of a subtree at `depth` into `nodes`, returning the id of its root.
+///
+/// Records nested deeper than any AVL tree can be are rejected before they
+/// exhaust the stack.
+fn read_node<T, H, R>(reader: &mut R, hasher: &H, nodes: &mut Arena<T>, depth: i32) -> Result<NodeId, SnapshotError>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    H: TreeHasher,
+    R: Read,
+{
+    if depth > MAX_TREE_HEIGHT {
+        return Err(SnapshotError::Corrupted(format!("records nested deeper than {}", MAX_TREE_HEIGHT)));
+    }
+    let flags = read_array::<_, 1>(reader)?[0];
+    let height = i32::from_be_bytes(read_array(reader)?);
+    let stored_hash = read_string(reader)?;
+    let payload = read_bytes(reader)?;
+    let transaction: T = serde_json::from_slice(&payload).map_err(|e| SnapshotError::Corrupted(e.to_string()))?;
+    let record = nodes.len() + 1;
+
+    let left = if flags & HAS_LEFT != 0 {
+        Some(read_node(reader, hasher, nodes, depth + 1)?)
+    } else {
+        None
+    };
+    let right = if flags & HAS_RIGHT != 0 {
+        Some(read_node(reader, hasher, nodes, depth + 1)?)
+    } else {
+        None
+    };
+
+    let size = 1 + nodes.size(left) + nodes.size(right);
+    let hash = crate::encoding::hash_node(hasher, &transaction, nodes.hash(left), nodes.hash(right), height, size)
+        .map_err(|e| SnapshotError::Corrupted(e.to_string()))?;
+    if hash != stored_hash {
+        return Err(SnapshotError::Corrupted(format!("hash mismatch at record {}", record)));
+    }
+    Ok(nodes.push(CryptoTreeNode {
+        transaction,
+        left,
+        right,
+        height,
+        size,
+        hash,
+        digest: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Transaction, TransactionTree};
+    use crate::test_util::{build_tree, temp_path};
+
+    #[test]
+    fn test_save_and_load() {
+        let tree = build_tree(75);
+        let path = temp_path("snapshot.bin");
+        tree.save(&path).unwrap();
+        let loaded = TransactionTree::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), 75);
+        assert_eq!(loaded.merkle_root(), tree.merkle_root());
+        assert!(loaded.verify_integrity());
+        assert!(loaded.search("tx_042").is_some());
+    }
+
+    #[test]
+    fn test_rejects_bad_header_and_tampering() {
+        let tree = build_tree(5);
+        let mut bytes = Vec::new();
+        tree.write_snapshot(&mut bytes).unwrap();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            TransactionTree::read_snapshot(&mut bad_magic.as_slice(), Sha256Hasher::new()),
+            Err(SnapshotError::BadMagic)
+        ));
+
+        // Change the last digit of the last payload amount
+        let mut tampered = bytes.clone();
+        let pos = tampered.windows(9).rposition(|w| w == b"\"amount\":").unwrap() + 9;
+        tampered[pos] = if tampered[pos] == b'9' { b'8' } else { b'9' };
+        assert!(TransactionTree::read_snapshot(&mut tampered.as_slice(), Sha256Hasher::new()).is_err());
+
+        let legacy = Sha256Hasher::with_format(HashFormat::JsonV0);
+        assert!(matches!(
+            TransactionTree::read_snapshot(&mut bytes.as_slice(), legacy),
+            Err(SnapshotError::FormatMismatch { .. })
+        ));
+        let swapped = Sha256Hasher::with_format(HashFormat::BinaryV3 {
+            leaf_prefix: 0x01,
+            internal_prefix: 0x00,
+        });
+        assert!(matches!(
+            TransactionTree::read_snapshot(&mut bytes.as_slice(), swapped),
+            Err(SnapshotError::FormatMismatch { .. })
+        ));
+
+        assert!(TransactionTree::read_snapshot(&mut &bytes[..bytes.len() - 3], Sha256Hasher::new()).is_err());
+    }
+
+    #[test]
+    fn test_records_hash_algorithm() {
+        let tree = build_tree(5);
+        let mut bytes = Vec::new();
+        tree.write_snapshot(&mut bytes).unwrap();
+        assert_eq!(bytes[8..11], [3, 0x00, 0x01]);
+        assert_eq!(bytes[11], algorithm_tag(Some(HashAlgorithm::Sha256)));
+        assert_eq!(bytes[12], 0);
+
+        let sha512 = crate::DigestHasher::<sha2::Sha512>::new();
+        assert!(matches!(
+            CryptoBinaryTree::<Transaction, _>::read_snapshot(&mut bytes.as_slice(), sha512),
+            Err(SnapshotError::AlgorithmMismatch {
+                stored: Some(HashAlgorithm::Sha256),
+                expected: None
+            })
+        ));
+
+        // Version 1 had no algorithm byte and at most `BinaryV2`
+        let v2_hasher = Sha256Hasher::with_format(HashFormat::BinaryV2);
+        let mut v2_tree = CryptoBinaryTree::with_hasher(v2_hasher.clone());
+        for tx in build_tree(5) {
+            v2_tree.insert(tx);
+        }
+        let mut v1 = Vec::new();
+        v2_tree.write_snapshot(&mut v1).unwrap();
+        v1[6..8].copy_from_slice(&1u16.to_be_bytes());
+        v1.drain(9..11);
+        let loaded = CryptoBinaryTree::<Transaction, _>::read_snapshot(&mut v1.as_slice(), v2_hasher).unwrap();
+        assert_eq!(loaded.merkle_root(), v2_tree.merkle_root());
+    }
+
+    #[test]
+    fn test_records_salt_flag() {
+        let hasher = Sha256Hasher::new().with_salt("tenant-a");
+        let mut tree = CryptoBinaryTree::with_hasher(hasher.clone());
+        for tx in build_tree(8) {
+            tree.insert(tx);
+        }
+        let mut bytes = Vec::new();
+        tree.write_snapshot(&mut bytes).unwrap();
+        assert_eq!(bytes[12], SALTED);
+        assert!(!bytes.windows(8).any(|w| w == b"tenant-a"));
+
+        let loaded = CryptoBinaryTree::<Transaction, _>::read_snapshot(&mut bytes.as_slice(), hasher).unwrap();
+        assert_eq!(loaded.merkle_root(), tree.merkle_root());
+        assert!(matches!(
+            TransactionTree::read_snapshot(&mut bytes.as_slice(), Sha256Hasher::new()),
+            Err(SnapshotError::SaltMismatch { salted: true })
+        ));
+        // A different salt passes the header check but not the root check
+        assert!(matches!(
+            TransactionTree::read_snapshot(&mut bytes.as_slice(), Sha256Hasher::new().with_salt("tenant-b")),
+            Err(SnapshotError::RootMismatch { .. }) | Err(SnapshotError::Corrupted(_))
+        ));
+
+        let mut plain = Vec::new();
+        build_tree(8).write_snapshot(&mut plain).unwrap();
+        assert!(matches!(
+            TransactionTree::read_snapshot(&mut plain.as_slice(), Sha256Hasher::new().with_salt("tenant-a")),
+            Err(SnapshotError::SaltMismatch { salted: false })
+        ));
+    }
+
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn test_keccak_snapshot() {
+        use crate::Keccak256Hasher;
+
+        let mut tree = CryptoBinaryTree::with_hasher(Keccak256Hasher::new());
+        for tx in build_tree(10) {
+            tree.insert(tx);
+        }
+        let mut bytes = Vec::new();
+        tree.write_snapshot(&mut bytes).unwrap();
+
+        let loaded =
+            CryptoBinaryTree::<Transaction, _>::read_snapshot(&mut bytes.as_slice(), Keccak256Hasher::new()).unwrap();
+        assert_eq!(loaded.merkle_root(), tree.merkle_root());
+        assert!(matches!(
+            TransactionTree::read_snapshot(&mut bytes.as_slice(), Sha256Hasher::new()),
+            Err(SnapshotError::AlgorithmMismatch {
+                stored: Some(HashAlgorithm::Keccak256),
+                expected: Some(HashAlgorithm::Sha256)
+            })
+        ));
+    }
+
+    #[test]
+    fn test_pending_hashes_are_not_written() {
+        let mut tree = build_tree(10);
+        tree.set_lazy_hashing(true);
+        tree.remove("tx_004");
+        let mut bytes = Vec::new();
+        assert!(matches!(tree.write_snapshot(&mut bytes), Err(SnapshotError::PendingHashes)));
+        assert!(bytes.is_empty());
+
+        tree.flush_hashes();
+        tree.write_snapshot(&mut bytes).unwrap();
+        let loaded = TransactionTree::read_snapshot(&mut &bytes[..], Sha256Hasher::default()).unwrap();
+        assert_eq!(loaded.merkle_root(), tree.merkle_root());
+    }
+
+    #[test]
+    fn test_empty_snapshot() {
+        let tree = TransactionTree::new();
+        let mut bytes = Vec::new();
+        tree.write_snapshot(&mut bytes).unwrap();
+        let loaded = TransactionTree::read_snapshot(&mut bytes.as_slice(), Sha256Hasher::new()).unwrap();
+        assert!(loaded.is_empty());
+        assert_eq!(loaded.merkle_root(), "0");
+    }
+
+    #[test]
+    fn test_rejects_deep_nesting() {
+        let mut bytes = Vec::new();
+        TransactionTree::new().write_snapshot(&mut bytes).unwrap();
+        // Claim many nodes, then chain left children far deeper than any AVL tree
+        let size_at = bytes.len() - 1 - 8 - 8;
+        bytes[size_at..size_at + 8].copy_from_slice(&100_000u64.to_be_bytes());
+        let payload = br#"{"id":"a","from":"A","to":"B","amount":1,"timestamp":null}"#;
+        for _ in 0..100_000 {
+            bytes.push(HAS_LEFT);
+            bytes.extend_from_slice(&1i32.to_be_bytes());
+            bytes.extend_from_slice(&0u64.to_be_bytes());
+            bytes.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+            bytes.extend_from_slice(payload);
+        }
+        assert!(matches!(
+            TransactionTree::read_snapshot(&mut bytes.as_slice(), Sha256Hasher::new()),
+            Err(SnapshotError::Corrupted(_))
+        ));
+    }
+}
@@ -0,0 +1,285 @@
+//! Sparse Merkle tree over a 256-bit key space.
+//!
+//! A [`SparseMerkleTree`] places each transaction at the leaf named by the
+//! hash of its id, in a complete binary tree of depth 256 whose other leaves
+//! are empty. Only the nodes above stored leaves are kept; every empty
+//! subtree has a fixed default hash. The shape depends only on the ids
+//! stored, not on the order they arrived in, and every proof has exactly 256
+//! steps: a [`SparseProof`] shows that an id holds a given transaction, or
+//! that it holds nothing.
+
+use alloc::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hasher::from_hex;
+use crate::prelude::*;
+use crate::{encode_canonical, Result, Sha256Hasher, Transaction, TreeHasher, TreeKey};
+
+/// Depth of the tree, the number of bits in a leaf's path
+pub const SPARSE_DEPTH: usize = 256;
+
+type Path = [u8; SPARSE_DEPTH / 8];
+
+/// Bit `k` of `path`, counting from the most significant bit of the first byte
+fn bit(path: &Path, k: usize) -> bool {
+    path[k / 8] >> (7 - k % 8) & 1 == 1
+}
+
+/// The first `SPARSE_DEPTH - height` bits of `path`, naming the node at `height` above it
+fn prefix(path: &Path, height: usize) -> Path {
+    let mut prefix = *path;
+    for k in SPARSE_DEPTH - height..SPARSE_DEPTH {
+        prefix[k / 8] &= !(1 << (7 - k % 8));
+    }
+    prefix
+}
+
+/// Leaf position of `key`: the first 256 bits of its hash
+fn key_path<K: Serialize + ?Sized, H: TreeHasher>(hasher: &H, key: &K) -> Result<Path> {
+    let digest = hasher.hash(&encode_canonical(key)?);
+    let bytes = from_hex(&digest).unwrap_or_else(|| digest.into_bytes());
+    let mut path = Path::default();
+    let n = bytes.len().min(path.len());
+    path[..n].copy_from_slice(&bytes[..n]);
+    Ok(path)
+}
+
+/// Hash of a stored leaf, which commits to its path as well as its payload
+fn leaf_hash<T: Serialize, H: TreeHasher>(hasher: &H, path: &Path, transaction: &T) -> Result<String> {
+    let mut data = vec![0];
+    data.extend_from_slice(path);
+    data.extend(encode_canonical(transaction)?);
+    Ok(hasher.hash(&data))
+}
+
+/// Hash of an inner node over its children's hashes, also used by the `mmr` module
+pub(crate) fn node_hash<H: TreeHasher>(hasher: &H, left: &str, right: &str) -> String {
+    let mut data = Vec::with_capacity(1 + left.len() + right.len());
+    data.push(1);
+    data.extend_from_slice(left.as_bytes());
+    data.extend_from_slice(right.as_bytes());
+    hasher.hash(&data)
+}
+
+/// Hashes of empty subtrees by height, from an empty leaf up to the empty root
+fn default_hashes<H: TreeHasher>(hasher: &H) -> Vec<String> {
+    let mut defaults = vec![hasher.hash(&[])];
+    for height in 0..SPARSE_DEPTH {
+        let below = &defaults[height];
+        defaults.push(node_hash(hasher, below, below));
+    }
+    defaults
+}
+
+/// Proof that a key holds a given transaction, or none, in a sparse tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SparseProof {
+    /// Hash of the sibling at every height, from the leaf's up to the root's
+    /// children; always `SPARSE_DEPTH` entries
+    pub siblings: Vec<String>,
+}
+
+impl SparseProof {
+    /// Checks that under `root`, `key` holds `transaction`, or with `None`
+    /// that it holds nothing.
+    pub fn verify<T, H>(&self, root: &str, key: &T::Key, transaction: Option<&T>, hasher: &H) -> bool
+    where
+        T: TreeKey + Serialize,
+        H: TreeHasher,
+    {
+        if self.siblings.len() != SPARSE_DEPTH || transaction.is_some_and(|tx| tx.key() != key) {
+            return false;
+        }
+        let Ok(path) = key_path(hasher, key) else {
+            return false;
+        };
+        let mut current = match transaction {
+            Some(tx) => match leaf_hash(hasher, &path, tx) {
+                Ok(hash) => hash,
+                Err(_) => return false,
+            },
+            None => hasher.hash(&[]),
+        };
+        for (height, sibling) in self.siblings.iter().enumerate() {
+            current = if bit(&path, SPARSE_DEPTH - 1 - height) {
+                node_hash(hasher, sibling, &current)
+            } else {
+                node_hash(hasher, &current, sibling)
+            };
+        }
+        current == root
+    }
+}
+
+/// A Merkle tree with one leaf for every possible 256-bit key hash
+///
+/// Inserts, removals and proofs each take `SPARSE_DEPTH` hashes or lookups.
+#[derive(Debug, Clone)]
+pub struct SparseMerkleTree<T: TreeKey = Transaction, H = Sha256Hasher> {
+    hasher: H,
+    leaves: BTreeMap<Path, T>,
+    /// Hashes of the nodes that are not empty, by height and prefix
+    nodes: BTreeMap<(usize, Path), String>,
+    defaults: Vec<String>,
+}
+
+impl<T: TreeKey + Serialize> SparseMerkleTree<T> {
+    /// Creates an empty tree hashed with SHA-256.
+    pub fn new() -> Self {
+        Self::with_hasher(Sha256Hasher::new())
+    }
+}
+
+impl<T: TreeKey + Serialize> Default for SparseMerkleTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: TreeKey + Serialize, H: TreeHasher> SparseMerkleTree<T, H> {
+    /// Creates an empty tree whose nodes are hashed with `hasher`.
+    pub fn with_hasher(hasher: H) -> Self {
+        SparseMerkleTree {
+            defaults: default_hashes(&hasher),
+            hasher,
+            leaves: BTreeMap::new(),
+            nodes: BTreeMap::new(),
+        }
+    }
+
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// The root hash; the same for the same set of transactions whatever
+    /// order they were inserted in
+    pub fn root(&self) -> &str {
+        self.nodes.get(&(SPARSE_DEPTH, Path::default())).unwrap_or(&self.defaults[SPARSE_DEPTH])
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// The transaction stored under `key`
+    pub fn get(&self, key: &T::Key) -> Option<&T> {
+        let path = key_path(&self.hasher, key).ok()?;
+        self.leaves.get(&path).filter(|tx| tx.key() == key)
+    }
+
+    /// Stores `transaction` under its id, returning the one it replaced.
+    ///
+    /// Fails with `SerializationFailed`, leaving the tree unchanged, if the
+    /// transaction cannot be encoded for hashing.
+    pub fn insert(&mut self, transaction: T) -> Result<Option<T>> {
+        let path = key_path(&self.hasher, transaction.key())?;
+        let hash = leaf_hash(&self.hasher, &path, &transaction)?;
+        self._set_leaf(&path, hash);
+        Ok(self.leaves.insert(path, transaction))
+    }
+
+    /// Empties the leaf of `key`, returning the transaction it held.
+    pub fn remove(&mut self, key: &T::Key) -> Option<T> {
+        let path = key_path(&self.hasher, key).ok()?;
+        if self.leaves.get(&path)?.key() != key {
+            return None;
+        }
+        self._set_leaf(&path, self.defaults[0].clone());
+        self.leaves.remove(&path)
+    }
+
+    /// Builds a proof of what `key` holds, checked with `SparseProof::verify`
+    /// against `get(key)`.
+    pub fn prove(&self, key: &T::Key) -> Result<SparseProof> {
+        let path = key_path(&self.hasher, key)?;
+        let siblings = (0..SPARSE_DEPTH).map(|height| self._sibling(&path, height).to_string()).collect();
+        Ok(SparseProof { siblings })
+    }
+
+    /// Stored transactions in the order of their key hashes
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.leaves.values()
+    }
+
+    /// Hash of the sibling of the node at `height` above `path`
+    fn _sibling(&self, path: &Path, height: usize) -> &str {
+        let mut sibling = prefix(path, height);
+        let k = SPARSE_DEPTH - 1 - height;
+        sibling[k / 8] ^= 1 << (7 - k % 8);
+        self.nodes.get(&(height, sibling)).unwrap_or(&self.defaults[height])
+    }
+
+    /// Sets the leaf at `path` to `hash` and rehashes every node above it.
+    fn _set_leaf(&mut self, path: &Path, hash: String) {
+        let mut current = hash;
+        for height in 0..=SPARSE_DEPTH {
+            let next = (height < SPARSE_DEPTH).then(|| {
+                let sibling = self._sibling(path, height);
+                if bit(path, SPARSE_DEPTH - 1 - height) {
+                    node_hash(&self.hasher, sibling, &current)
+                } else {
+                    node_hash(&self.hasher, &current, sibling)
+                }
+            });
+            let id = (height, prefix(path, height));
+            if current == self.defaults[height] {
+                self.nodes.remove(&id);
+            } else {
+                self.nodes.insert(id, current);
+            }
+            match next {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_sparse_proofs() {
+        let mut tree: SparseMerkleTree = SparseMerkleTree::new();
+        let empty = tree.root().to_string();
+        for i in 0..20 {
+            assert!(tree.insert(sample_tx(&format!("tx_{}", i), 10)).unwrap().is_none());
+        }
+        let mut reversed: SparseMerkleTree = SparseMerkleTree::new();
+        for i in (0..20).rev() {
+            reversed.insert(sample_tx(&format!("tx_{}", i), 10)).unwrap();
+        }
+        assert_eq!(tree.root(), reversed.root());
+
+        let hasher = Sha256Hasher::new();
+        let root = tree.root().to_string();
+        let key = "tx_7".to_string();
+        let proof = tree.prove(&key).unwrap();
+        assert_eq!(proof.siblings.len(), SPARSE_DEPTH);
+        assert!(proof.verify(&root, &key, tree.get(&key), &hasher));
+        assert!(!proof.verify(&root, &key, Some(&sample_tx("tx_7", 11)), &hasher));
+        assert!(!proof.verify::<Transaction, _>(&root, &key, None, &hasher));
+
+        let missing = "tx_99".to_string();
+        let proof = tree.prove(&missing).unwrap();
+        assert!(tree.get(&missing).is_none());
+        assert!(proof.verify::<Transaction, _>(&root, &missing, None, &hasher));
+        assert!(!proof.verify(&root, &missing, Some(&sample_tx("tx_99", 1)), &hasher));
+
+        assert_eq!(tree.insert(sample_tx("tx_7", 11)).unwrap().unwrap().amount, 10);
+        assert_ne!(tree.root(), root);
+        for i in 0..20 {
+            assert!(tree.remove(&format!("tx_{}", i)).is_some());
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), empty);
+        assert!(tree.nodes.is_empty());
+    }
+}
@@ -0,0 +1,346 @@
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::arena::Arena;
+use crate::prelude::*;
+use crate::{
+    CryptoBinaryTree, CryptoTreeError, CryptoTreeNode, NodeId, Result, Transaction, TreeHasher, TreeKey, MAX_TREE_HEIGHT,
+};
+
+/// Serializable image of a tree: the node arena with every stored hash and height.
+///
+/// This is what `CryptoBinaryTree` serializes to. Deserializing a
+/// `CryptoBinaryTree` directly always re-verifies it; go through
+/// `TreeState::into_tree` to choose whether to re-verify. States written
+/// before the arena, with children nested inside their parents, are still read.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "StateRepr<T>")]
+pub struct TreeState<T = Transaction> {
+    pub size: usize,
+    pub merkle_root: String,
+    pub root: Option<NodeId>,
+    pub nodes: Vec<CryptoTreeNode<T>>,
+}
+
+impl<T: TreeKey + Serialize + Clone> TreeState<T> {
+    /// Rebuilds a tree hashed with `hasher`.
+    ///
+    /// The node links must form a single tree, or this fails with
+    /// `MalformedState`. With `verify` set, every node hash is recomputed and
+    /// the node count and stored Merkle root must match; otherwise the stored
+    /// hashes are trusted.
+    pub fn into_tree<H: TreeHasher>(self, hasher: H, verify: bool) -> Result<CryptoBinaryTree<T, H>> {
+        let mut nodes = self.nodes;
+        fill_sizes(&mut nodes, self.root)?;
+        let mut tree = CryptoBinaryTree::with_hasher(hasher);
+        tree.root = self.root;
+        tree.nodes = Arena::from_vec(nodes);
+        tree._intern_all();
+        tree._update_merkle_root();
+
+        if verify {
+            if tree.len() != self.size {
+                return Err(CryptoTreeError::SizeMismatch {
+                    stored: self.size,
+                    found: tree.len(),
+                });
+            }
+            if tree.merkle_root != self.merkle_root {
+                return Err(CryptoTreeError::RootMismatch {
+                    stored: self.merkle_root,
+                    computed: tree.merkle_root,
+                });
+            }
+            tree.check_integrity()?;
+        }
+        Ok(tree)
+    }
+}
+
+/// Checks that the links under `root` reach every node exactly once, no
+/// deeper than any AVL tree can be, and recomputes every subtree size from them.
+///
+/// Stored sizes are never trusted; under `BinaryV2` and later the hash check
+/// then catches any node whose committed size disagrees with its shape.
+fn fill_sizes<T>(nodes: &mut [CryptoTreeNode<T>], root: Option<NodeId>) -> Result<()> {
+    let malformed = |reason: String| Err(CryptoTreeError::MalformedState(reason));
+    let mut linked = vec![false; nodes.len()];
+    let mut order = Vec::with_capacity(nodes.len());
+    let mut stack: Vec<NodeId> = root.into_iter().collect();
+    while let Some(id) = stack.pop() {
+        match linked.get_mut(id.index()) {
+            None => return malformed(format!("node {} does not exist", id.index())),
+            Some(true) => return malformed(format!("node {} is linked twice", id.index())),
+            Some(seen) => *seen = true,
+        }
+        order.push(id);
+        let n = &nodes[id.index()];
+        stack.extend(n.left);
+        stack.extend(n.right);
+    }
+    if order.len() != nodes.len() {
+        return malformed(format!("{} of {} nodes are unreachable", nodes.len() - order.len(), nodes.len()));
+    }
+    // Children come after their parent in `order`; later walks over the
+    // nodes recurse, so overly deep chains are refused here
+    let mut heights = vec![0; nodes.len()];
+    for id in order.into_iter().rev() {
+        let n = &nodes[id.index()];
+        let size = |child: Option<NodeId>| child.map_or(0, |c| nodes[c.index()].size);
+        let height = |child: Option<NodeId>| child.map_or(0, |c| heights[c.index()]);
+        let total = 1 + size(n.left) + size(n.right);
+        heights[id.index()] = 1 + height(n.left).max(height(n.right));
+        if heights[id.index()] > MAX_TREE_HEIGHT {
+            return malformed(format!("nodes nested deeper than {}", MAX_TREE_HEIGHT));
+        }
+        nodes[id.index()].size = total;
+    }
+    Ok(())
+}
+
+/// Wire form of `TreeState`: the arena, or the nested layout of older releases
+#[derive(Deserialize)]
+struct StateRepr<T> {
+    size: usize,
+    merkle_root: String,
+    root: Option<RootRepr<T>>,
+    #[serde(default = "Vec::new")]
+    nodes: Vec<CryptoTreeNode<T>>,
+}
+
+/// Root of a state: an arena id, or a whole nested tree.
+///
+/// Dispatched by hand rather than with `#[serde(untagged)]`, whose buffering
+/// cannot carry `u128` amounts.
+enum RootRepr<T> {
+    Id(NodeId),
+    Nested(Box<NestedNode<T>>),
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for RootRepr<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        struct RootVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for RootVisitor<T> {
+            type Value = RootRepr<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a node id or a nested node")
+            }
+
+            fn visit_u64<E: de::Error>(self, id: u64) -> core::result::Result<Self::Value, E> {
+                u32::try_from(id)
+                    .map(|id| RootRepr::Id(NodeId::new(id as usize)))
+                    .map_err(|_| E::custom("node id out of range"))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> core::result::Result<Self::Value, A::Error> {
+                NestedNode::deserialize(de::value::MapAccessDeserializer::new(map)).map(|n| RootRepr::Nested(Box::new(n)))
+            }
+        }
+
+        deserializer.deserialize_any(RootVisitor(PhantomData))
+    }
+}
+
+/// Node of a state written before the arena, holding its children inline
+#[derive(Deserialize)]
+struct NestedNode<T> {
+    transaction: T,
+    left: Option<Box<NestedNode<T>>>,
+    right: Option<Box<NestedNode<T>>>,
+    height: i32,
+    #[serde(default)]
+    size: usize,
+    hash: String,
+}
+
+impl<T> NestedNode<T> {
+    /// Moves this subtree into `nodes`, returning the id of its root.
+    fn flatten(self, nodes: &mut Vec<CryptoTreeNode<T>>) -> NodeId {
+        let left = self.left.map(|l| l.flatten(nodes));
+        let right = self.right.map(|r| r.flatten(nodes));
+        nodes.push(CryptoTreeNode {
+            transaction: self.transaction,
+            left,
+            right,
+            height: self.height,
+            size: self.size,
+            hash: self.hash,
+            digest: None,
+        });
+        NodeId::new(nodes.len() - 1)
+    }
+}
+
+impl<T> From<StateRepr<T>> for TreeState<T> {
+    fn from(repr: StateRepr<T>) -> Self {
+        let mut nodes = repr.nodes;
+        let root = match repr.root {
+            None => None,
+            Some(RootRepr::Id(id)) => Some(id),
+            Some(RootRepr::Nested(root)) => Some(root.flatten(&mut nodes)),
+        };
+        TreeState {
+            size: repr.size,
+            merkle_root: repr.merkle_root,
+            root,
+            nodes,
+        }
+    }
+}
+
+/// Borrowed counterpart of `TreeState` used when serializing
+#[derive(Serialize)]
+struct TreeStateRef<'a, T> {
+    size: usize,
+    merkle_root: &'a str,
+    root: Option<NodeId>,
+    nodes: NodesRef<'a, T>,
+}
+
+/// The nodes of an arena, serialized as a sequence in id order
+struct NodesRef<'a, T>(&'a Arena<T>);
+
+impl<T: Serialize> Serialize for NodesRef<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+impl<T: TreeKey + Serialize, H> Serialize for CryptoBinaryTree<T, H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        if self.has_pending_hashes() {
+            return Err(serde::ser::Error::custom("tree has pending hashes, call flush_hashes() first"));
+        }
+        TreeStateRef {
+            size: self.nodes.len(),
+            merkle_root: &self.merkle_root,
+            root: self.root,
+            nodes: NodesRef(&self.nodes),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T, H> Deserialize<'de> for CryptoBinaryTree<T, H>
+where
+    T: TreeKey + Serialize + Deserialize<'de> + Clone,
+    H: TreeHasher + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        TreeState::deserialize(deserializer)?
+            .into_tree(H::default(), true)
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionTree;
+    use crate::test_util::build_tree;
+
+    #[test]
+    fn test_json_round_trip() {
+        let tree = build_tree(40);
+        let json = serde_json::to_string(&tree).unwrap();
+        let restored: TransactionTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.len(), 40);
+        assert_eq!(restored.merkle_root(), tree.merkle_root());
+        assert!(restored.search("tx_017").is_some());
+        assert!(restored.verify_integrity());
+    }
+
+    #[test]
+    fn test_tampered_state_rejected() {
+        let tree = build_tree(10);
+        let json = serde_json::to_string(&tree).unwrap().replace("\"amount\":3,", "\"amount\":300,");
+        assert!(serde_json::from_str::<TransactionTree>(&json).is_err());
+
+        // Explicitly skipping verification trusts the stored hashes
+        let state: TreeState = serde_json::from_str(&json).unwrap();
+        let unverified = state.into_tree(crate::Sha256Hasher::new(), false).unwrap();
+        assert_eq!(unverified.merkle_root(), tree.merkle_root());
+        assert!(!unverified.verify_integrity());
+    }
+
+    #[test]
+    fn test_empty_round_trip() {
+        let tree = TransactionTree::new();
+        let json = serde_json::to_string(&tree).unwrap();
+        let restored: TransactionTree = serde_json::from_str(&json).unwrap();
+        assert!(restored.is_empty());
+        assert_eq!(restored.merkle_root(), "0");
+    }
+
+    /// Rebuilds the nested layout written before the arena
+    fn nest(nodes: &[serde_json::Value], id: &serde_json::Value) -> serde_json::Value {
+        let Some(id) = id.as_u64() else {
+            return serde_json::Value::Null;
+        };
+        let mut node = nodes[id as usize].clone();
+        for side in ["left", "right"] {
+            node[side] = nest(nodes, &node[side]);
+        }
+        node
+    }
+
+    #[test]
+    fn test_nested_state_still_loads() {
+        let tree = build_tree(25);
+        let mut state = serde_json::to_value(&tree).unwrap();
+        let nodes = state["nodes"].as_array().unwrap().clone();
+        state["root"] = nest(&nodes, &state["root"]);
+        state.as_object_mut().unwrap().remove("nodes");
+
+        let restored: TransactionTree = serde_json::from_value(state).unwrap();
+        assert_eq!(restored.len(), 25);
+        assert_eq!(restored.merkle_root(), tree.merkle_root());
+        assert!(restored.search("tx_013").is_some());
+    }
+
+    #[test]
+    fn test_malformed_links_rejected() {
+        let tree = build_tree(10);
+        let state = serde_json::to_value(&tree).unwrap();
+        let root = state["root"].as_u64().unwrap() as usize;
+
+        // A child pointing back at the root would make a cycle
+        let mut cyclic = state.clone();
+        cyclic["nodes"][root]["left"] = state["root"].clone();
+        let cyclic: TreeState = serde_json::from_value(cyclic).unwrap();
+        assert!(matches!(
+            cyclic.into_tree(crate::Sha256Hasher::new(), false),
+            Err(CryptoTreeError::MalformedState(_))
+        ));
+
+        let mut dangling = state.clone();
+        dangling["nodes"][root]["left"] = serde_json::json!(10);
+        assert!(serde_json::from_value::<TransactionTree>(dangling).is_err());
+
+        // A chain too deep for any AVL tree
+        let chain: Vec<_> = (0..100)
+            .map(|i| serde_json::json!({"transaction": tree.search("tx_001").unwrap(), "left": null,
+                "right": if i < 99 { serde_json::json!(i + 1) } else { serde_json::Value::Null },
+                "height": 1, "hash": "0"}))
+            .collect();
+        let deep: TreeState = serde_json::from_value(serde_json::json!({"size": 100, "merkle_root": "0", "root": 0, "nodes": chain})).unwrap();
+        assert!(matches!(
+            deep.into_tree(crate::Sha256Hasher::new(), false),
+            Err(CryptoTreeError::MalformedState(_))
+        ));
+
+        let mut orphaned = state;
+        orphaned["nodes"][root]["right"] = serde_json::Value::Null;
+        let orphaned: TreeState = serde_json::from_value(orphaned).unwrap();
+        assert!(matches!(
+            orphaned.into_tree(crate::Sha256Hasher::new(), false),
+            Err(CryptoTreeError::MalformedState(_))
+        ));
+    }
+}
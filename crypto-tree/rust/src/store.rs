@@ -0,0 +1,607 @@
+//! Trees kept in an asynchronous node store (`store` feature).
+//!
+//! [`AsyncCryptoTree`] keeps no nodes in memory. Each node is written once
+//! to a [`TreeStore`] under its own hash and never changed; an insert loads
+//! the nodes on its search path, writes new versions of those it changes and
+//! finally commits the new root. Every committed root therefore stays
+//! readable, and a crash between the writes and the commit leaves only
+//! unreferenced nodes behind. Roots, hashes and proofs are the same as those
+//! of a [`CryptoBinaryTree`](crate::CryptoBinaryTree) fed the same inserts.
+//!
+//! Store methods return futures, so the tree can sit on an async database or
+//! object store without blocking the runtime. [`MemoryStore`] keeps
+//! everything in a map, for tests and as a reference implementation.
+
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt;
+use core::future::Future;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::{
+    key_string, BatchResult, CryptoTreeError, Direction, ProofStep, Result, Sha256Hasher, Side, TreeHasher, TreeKey,
+};
+
+/// A node as written to a store, keyed by its hash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredNode<T> {
+    pub transaction: T,
+    pub height: i32,
+    pub size: usize,
+    /// Hash of the left child, which is stored under it
+    pub left: Option<String>,
+    /// Hash of the right child
+    pub right: Option<String>,
+}
+
+/// Asynchronous storage of content-addressed nodes and the current root
+///
+/// Nodes are immutable: `put_node` is only ever called with the hash the
+/// node's contents produce, so a store may skip writing a hash it already
+/// holds. Writes become part of the tree when `commit_root` names a root
+/// that reaches them; until then `get_node` must still return them, as a
+/// batch reads the nodes its earlier inserts wrote.
+pub trait TreeStore<T>: Send + Sync {
+    type Error: fmt::Display;
+
+    /// The node stored under `hash`, if any
+    fn get_node(&self, hash: &str) -> impl Future<Output = core::result::Result<Option<StoredNode<T>>, Self::Error>> + Send;
+
+    fn put_node(&self, hash: &str, node: &StoredNode<T>) -> impl Future<Output = core::result::Result<(), Self::Error>> + Send;
+
+    /// Makes `root` the current root, `None` for an empty tree.
+    fn commit_root(&self, root: Option<&str>) -> impl Future<Output = core::result::Result<(), Self::Error>> + Send;
+
+    /// The root last committed, `None` if none was or the tree is empty
+    fn load_root(&self) -> impl Future<Output = core::result::Result<Option<String>, Self::Error>> + Send;
+}
+
+fn storage_error(e: impl fmt::Display) -> CryptoTreeError {
+    CryptoTreeError::Storage(e.to_string())
+}
+
+/// A Merkle AVL tree whose nodes live in a [`TreeStore`]
+pub struct AsyncCryptoTree<T, S, H = Sha256Hasher> {
+    store: S,
+    hasher: H,
+    root: Option<String>,
+    len: usize,
+    _payload: core::marker::PhantomData<fn() -> T>,
+}
+
+impl<T, S, H> AsyncCryptoTree<T, S, H>
+where
+    T: TreeKey + Serialize + Clone + Send + Sync,
+    S: TreeStore<T>,
+    H: TreeHasher + Sync,
+{
+    /// Opens the tree at the root last committed to `store`.
+    ///
+    /// Fails with `Storage` if the store cannot be read, or the root it
+    /// names is missing.
+    pub async fn open(store: S, hasher: H) -> Result<Self> {
+        let root = store.load_root().await.map_err(storage_error)?;
+        let mut tree = AsyncCryptoTree {
+            store,
+            hasher,
+            root: None,
+            len: 0,
+            _payload: core::marker::PhantomData,
+        };
+        if let Some(root) = root {
+            tree.len = tree.load(&root).await?.size;
+            tree.root = Some(root);
+        }
+        Ok(tree)
+    }
+
+    /// Merkle root, `"0"` for an empty tree as with `CryptoBinaryTree`
+    pub fn merkle_root(&self) -> &str {
+        self.root.as_deref().unwrap_or("0")
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    async fn load(&self, hash: &str) -> Result<StoredNode<T>> {
+        self.store
+            .get_node(hash)
+            .await
+            .map_err(storage_error)?
+            .ok_or_else(|| CryptoTreeError::Storage(format!("node {} is missing", hash)))
+    }
+
+    /// Loads the nodes from the root towards `key`, returning the last one and
+    /// the path of `(node, hash, direction)` above it.
+    async fn descend<Q>(&self, key: &Q) -> Result<(Vec<(StoredNode<T>, String, Direction)>, Option<StoredNode<T>>)>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut path = Vec::new();
+        let mut current = self.root.clone();
+        while let Some(hash) = current {
+            let node = self.load(&hash).await?;
+            let direction = match key.cmp(node.transaction.key().borrow()) {
+                Ordering::Less => Direction::Left,
+                Ordering::Greater => Direction::Right,
+                Ordering::Equal => return Ok((path, Some(node))),
+            };
+            current = match direction {
+                Direction::Left => node.left.clone(),
+                Direction::Right => node.right.clone(),
+            };
+            path.push((node, hash, direction));
+        }
+        Ok((path, None))
+    }
+
+    /// Loads the transaction with the given id.
+    pub async fn search<Q>(&self, tx_id: &Q) -> Result<Option<T>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        Ok(self.descend(tx_id).await?.1.map(|n| n.transaction))
+    }
+
+    /// Builds the same proof as `CryptoBinaryTree::get_proof_of_inclusion`,
+    /// loading only the nodes on the search path.
+    pub async fn get_proof_of_inclusion<Q>(&self, tx_id: &Q) -> Result<Option<Vec<ProofStep<T>>>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (path, found) = self.descend(tx_id).await?;
+        let Some(target) = found else {
+            return Ok(None);
+        };
+        let mut proof: Vec<ProofStep<T>> = path
+            .into_iter()
+            .map(|(n, _, direction)| {
+                let (side, sibling) = match direction {
+                    Direction::Left => (Side::Right, n.right),
+                    Direction::Right => (Side::Left, n.left),
+                };
+                let sibling = sibling.unwrap_or_else(|| "0".to_string());
+                ProofStep::new(side, sibling, n.height, n.size, Some(n.transaction))
+            })
+            .collect();
+        if let Some(left) = target.left {
+            proof.push(ProofStep::new(Side::Left, left, target.height, target.size, None));
+        }
+        if let Some(right) = target.right {
+            proof.push(ProofStep::new(Side::Right, right, target.height, target.size, None));
+        }
+        Ok(Some(proof))
+    }
+
+    /// Inserts a transaction and commits the new root.
+    ///
+    /// Fails with `DuplicateId` if the id is stored, `SerializationFailed`
+    /// if the payload cannot be encoded, and `Storage` if the store fails. The
+    /// committed root only changes once every new node has been written.
+    pub async fn insert(&mut self, transaction: T) -> Result<()> {
+        let committed = (self.root.clone(), self.len);
+        self.place(transaction).await?;
+        self.commit(committed).await
+    }
+
+    /// Inserts many transactions under a single root commit.
+    ///
+    /// Duplicates (against the tree or earlier items) and payloads that
+    /// cannot be encoded are skipped and reported. Roots match
+    /// `CryptoBinaryTree::insert_batch`. A store that buffers node writes
+    /// until `commit_root` thus applies the whole batch atomically. If the
+    /// store fails, nothing is committed and the tree stays at its last root.
+    pub async fn insert_batch(&mut self, transactions: Vec<T>) -> Result<BatchResult> {
+        let committed = (self.root.clone(), self.len);
+        let mut result = BatchResult::default();
+        for transaction in transactions {
+            let id = key_string(transaction.key());
+            match self.place(transaction).await {
+                Ok(()) => result.inserted += 1,
+                Err(CryptoTreeError::DuplicateId(_)) => result.duplicates.push(id),
+                Err(e @ CryptoTreeError::SerializationFailed(_)) => result.failed.push((id, e)),
+                Err(e) => {
+                    (self.root, self.len) = committed;
+                    return Err(e);
+                }
+            }
+        }
+        if result.inserted > 0 {
+            self.commit(committed).await?;
+        }
+        Ok(result)
+    }
+
+    /// Commits the current root, or returns to `committed` if that fails.
+    async fn commit(&mut self, committed: (Option<String>, usize)) -> Result<()> {
+        if let Err(e) = self.store.commit_root(self.root.as_deref()).await {
+            (self.root, self.len) = committed;
+            return Err(storage_error(e));
+        }
+        Ok(())
+    }
+
+    /// Writes the nodes that placing `transaction` creates and moves the
+    /// root to the result, without committing it.
+    async fn place(&mut self, transaction: T) -> Result<()> {
+        let (path, found) = self.descend(transaction.key()).await?;
+        if found.is_some() {
+            return Err(CryptoTreeError::DuplicateId(key_string(transaction.key())));
+        }
+        let mut drafts = Drafts { nodes: Vec::new() };
+        let mut child = drafts.push(Draft::leaf(transaction));
+        for (node, hash, direction) in path.into_iter().rev() {
+            let parent = drafts.push(Draft::stored(node, hash));
+            *drafts.nodes[parent].child_mut(direction) = Link::Local(child);
+            child = self.rebalance(&mut drafts, parent).await?;
+        }
+
+        let mut writes = Vec::new();
+        let root = drafts.seal(child, &self.hasher, &mut writes)?;
+        for (hash, node) in &writes {
+            self.store.put_node(hash, node).await.map_err(storage_error)?;
+        }
+        self.root = Some(root);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Turns a stored child link of `id` into a draft, loading the node.
+    async fn expand(&self, drafts: &mut Drafts<T>, id: usize, direction: Direction) -> Result<()> {
+        if let Link::Stored(hash) = drafts.nodes[id].child(direction) {
+            let hash = hash.clone();
+            let node = self.load(&hash).await?;
+            let child = drafts.push(Draft::stored(node, hash));
+            *drafts.nodes[id].child_mut(direction) = Link::Local(child);
+        }
+        Ok(())
+    }
+
+    /// Loads the children of `id` and of its child in `direction`, if any.
+    async fn expand_heavy(&self, drafts: &mut Drafts<T>, id: usize, direction: Direction) -> Result<usize> {
+        let Link::Local(child) = *drafts.nodes[id].child(direction) else {
+            unreachable!("a heavy side has a child");
+        };
+        self.expand(drafts, child, Direction::Left).await?;
+        self.expand(drafts, child, Direction::Right).await?;
+        Ok(child)
+    }
+
+    /// Restores height, size and balance of a draft whose subtree changed,
+    /// loading whatever the rotations need, and returns the subtree root.
+    async fn rebalance(&self, drafts: &mut Drafts<T>, id: usize) -> Result<usize> {
+        self.expand(drafts, id, Direction::Left).await?;
+        self.expand(drafts, id, Direction::Right).await?;
+        drafts.update_stats(id);
+        let balance = drafts.balance_factor(id);
+        if balance > 1 {
+            let left = self.expand_heavy(drafts, id, Direction::Left).await?;
+            if drafts.balance_factor(left) < 0 {
+                self.expand_heavy(drafts, left, Direction::Right).await?;
+                drafts.nodes[id].left = Link::Local(drafts.rotate(left, Direction::Left));
+            }
+            return Ok(drafts.rotate(id, Direction::Right));
+        }
+        if balance < -1 {
+            let right = self.expand_heavy(drafts, id, Direction::Right).await?;
+            if drafts.balance_factor(right) > 0 {
+                self.expand_heavy(drafts, right, Direction::Left).await?;
+                drafts.nodes[id].right = Link::Local(drafts.rotate(right, Direction::Right));
+            }
+            return Ok(drafts.rotate(id, Direction::Left));
+        }
+        Ok(id)
+    }
+}
+
+/// A child of a draft: nothing, a node left as stored, or another draft
+#[derive(Debug)]
+enum Link {
+    Empty,
+    Stored(String),
+    Local(usize),
+}
+
+/// A node loaded or created during an insert; `hash` is `None` once changed
+#[derive(Debug)]
+struct Draft<T> {
+    transaction: T,
+    height: i32,
+    size: usize,
+    left: Link,
+    right: Link,
+    hash: Option<String>,
+}
+
+impl<T> Draft<T> {
+    fn leaf(transaction: T) -> Self {
+        Draft {
+            transaction,
+            height: 1,
+            size: 1,
+            left: Link::Empty,
+            right: Link::Empty,
+            hash: None,
+        }
+    }
+
+    fn stored(node: StoredNode<T>, hash: String) -> Self {
+        let link = |child: Option<String>| child.map_or(Link::Empty, Link::Stored);
+        Draft {
+            transaction: node.transaction,
+            height: node.height,
+            size: node.size,
+            left: link(node.left),
+            right: link(node.right),
+            hash: Some(hash),
+        }
+    }
+
+    fn child(&self, direction: Direction) -> &Link {
+        match direction {
+            Direction::Left => &self.left,
+            Direction::Right => &self.right,
+        }
+    }
+
+    fn child_mut(&mut self, direction: Direction) -> &mut Link {
+        self.hash = None;
+        match direction {
+            Direction::Left => &mut self.left,
+            Direction::Right => &mut self.right,
+        }
+    }
+}
+
+/// The drafts of one insert
+struct Drafts<T> {
+    nodes: Vec<Draft<T>>,
+}
+
+impl<T: Serialize> Drafts<T> {
+    fn push(&mut self, draft: Draft<T>) -> usize {
+        self.nodes.push(draft);
+        self.nodes.len() - 1
+    }
+
+    /// Height and size of a loaded or empty link
+    fn stats(&self, link: &Link) -> (i32, usize) {
+        match link {
+            Link::Empty => (0, 0),
+            Link::Local(id) => (self.nodes[*id].height, self.nodes[*id].size),
+            Link::Stored(_) => unreachable!("links are loaded before their height is needed"),
+        }
+    }
+
+    fn update_stats(&mut self, id: usize) {
+        let ((left_height, left_size), (right_height, right_size)) =
+            (self.stats(&self.nodes[id].left), self.stats(&self.nodes[id].right));
+        let n = &mut self.nodes[id];
+        n.height = 1 + left_height.max(right_height);
+        n.size = 1 + left_size + right_size;
+        n.hash = None;
+    }
+
+    fn balance_factor(&self, id: usize) -> i32 {
+        self.stats(&self.nodes[id].left).0 - self.stats(&self.nodes[id].right).0
+    }
+
+    /// Rotates `z` towards `direction`, returning the new subtree root.
+    fn rotate(&mut self, z: usize, direction: Direction) -> usize {
+        let (up, down) = match direction {
+            Direction::Left => (Direction::Right, Direction::Left),
+            Direction::Right => (Direction::Left, Direction::Right),
+        };
+        let Link::Local(y) = core::mem::replace(self.nodes[z].child_mut(up), Link::Empty) else {
+            unreachable!("a rotated node has a loaded child on the rising side");
+        };
+        let inner = core::mem::replace(self.nodes[y].child_mut(down), Link::Local(z));
+        *self.nodes[z].child_mut(up) = inner;
+        self.update_stats(z);
+        self.update_stats(y);
+        y
+    }
+
+    /// Hashes every changed draft below `id`, collecting them as writes, and
+    /// returns the hash of `id`.
+    fn seal<H: TreeHasher>(&mut self, id: usize, hasher: &H, writes: &mut Vec<(String, StoredNode<T>)>) -> Result<String>
+    where
+        T: Clone,
+    {
+        if let Some(hash) = &self.nodes[id].hash {
+            return Ok(hash.clone());
+        }
+        let mut child_hash = |s: &mut Self, direction| match *s.nodes[id].child(direction) {
+            Link::Empty => Ok(None),
+            Link::Stored(ref hash) => Ok(Some(hash.clone())),
+            Link::Local(child) => s.seal(child, hasher, writes).map(Some),
+        };
+        let left = child_hash(self, Direction::Left)?;
+        let right = child_hash(self, Direction::Right)?;
+        let n = &self.nodes[id];
+        let hash = crate::encoding::hash_node(hasher, &n.transaction, left.as_deref(), right.as_deref(), n.height, n.size)?;
+        writes.push((
+            hash.clone(),
+            StoredNode {
+                transaction: n.transaction.clone(),
+                height: n.height,
+                size: n.size,
+                left,
+                right,
+            },
+        ));
+        self.nodes[id].hash = Some(hash.clone());
+        Ok(hash)
+    }
+}
+
+/// Contents of a [`MemoryStore`]
+#[derive(Debug)]
+struct Memory<T> {
+    nodes: BTreeMap<String, StoredNode<T>>,
+    root: Option<String>,
+}
+
+/// A [`TreeStore`] that keeps every node in memory
+#[derive(Debug)]
+pub struct MemoryStore<T> {
+    inner: Mutex<Memory<T>>,
+}
+
+impl<T> Default for MemoryStore<T> {
+    fn default() -> Self {
+        MemoryStore {
+            inner: Mutex::new(Memory {
+                nodes: BTreeMap::new(),
+                root: None,
+            }),
+        }
+    }
+}
+
+impl<T> MemoryStore<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of distinct nodes written, including those of earlier roots
+    pub fn node_count(&self) -> usize {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner).nodes.len()
+    }
+}
+
+impl<T: Clone + Send + Sync> TreeStore<T> for MemoryStore<T> {
+    type Error = core::convert::Infallible;
+
+    async fn get_node(&self, hash: &str) -> core::result::Result<Option<StoredNode<T>>, Self::Error> {
+        Ok(self.inner.lock().unwrap_or_else(PoisonError::into_inner).nodes.get(hash).cloned())
+    }
+
+    async fn put_node(&self, hash: &str, node: &StoredNode<T>) -> core::result::Result<(), Self::Error> {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.nodes.entry(hash.to_string()).or_insert_with(|| node.clone());
+        Ok(())
+    }
+
+    async fn commit_root(&self, root: Option<&str>) -> core::result::Result<(), Self::Error> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner).root = root.map(str::to_string);
+        Ok(())
+    }
+
+    async fn load_root(&self) -> core::result::Result<Option<String>, Self::Error> {
+        Ok(self.inner.lock().unwrap_or_else(PoisonError::into_inner).root.clone())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll};
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+
+    use super::*;
+    use crate::{verify_proof, CryptoBinaryTree, Transaction};
+    use crate::test_util::sample_tx;
+
+    /// Wakes a test thread parked in [`block_on`]
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Runs a future on the current thread, parking it while the future
+    /// waits, as on a sled flush.
+    pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Arc::new(Unpark(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_in_memory_tree() {
+        let mut tree = block_on(AsyncCryptoTree::open(MemoryStore::new(), Sha256Hasher::new())).unwrap();
+        let mut reference = CryptoBinaryTree::new();
+        assert_eq!(tree.merkle_root(), reference.merkle_root());
+        // Ids arrive in an order that exercises all four rotation cases
+        for i in 0..200 {
+            let id = format!("tx_{:03}", (i * 37) % 200);
+            block_on(tree.insert(sample_tx(&id, 10))).unwrap();
+            reference.insert(sample_tx(&id, 10));
+            assert_eq!(tree.merkle_root(), reference.merkle_root());
+        }
+        assert_eq!(tree.len(), 200);
+
+        let tx = block_on(tree.search("tx_042")).unwrap().unwrap();
+        let proof = block_on(tree.get_proof_of_inclusion("tx_042")).unwrap().unwrap();
+        let expected = reference.get_proof_of_inclusion("tx_042").unwrap();
+        assert_eq!(serde_json::to_string(&proof).unwrap(), serde_json::to_string(&expected).unwrap());
+        assert!(verify_proof(tree.merkle_root(), &tx, &proof));
+        assert!(block_on(tree.search("tx_999")).unwrap().is_none());
+        assert!(matches!(
+            block_on(tree.insert(sample_tx("tx_042", 10))),
+            Err(CryptoTreeError::DuplicateId(_))
+        ));
+    }
+
+    #[test]
+    fn test_batch_commits_once() {
+        let mut tree = block_on(AsyncCryptoTree::open(MemoryStore::new(), Sha256Hasher::new())).unwrap();
+        block_on(tree.insert(sample_tx("tx_05", 10))).unwrap();
+        let batch: Vec<_> = ["tx_01", "tx_09", "tx_05", "tx_03", "tx_01", "tx_07"].map(|id| sample_tx(id, 10)).into();
+        let result = block_on(tree.insert_batch(batch.clone())).unwrap();
+        assert_eq!(result.inserted, 4);
+        assert_eq!(result.duplicates, ["tx_05", "tx_01"]);
+
+        let mut reference = CryptoBinaryTree::new();
+        reference.insert(sample_tx("tx_05", 10));
+        reference.insert_batch(batch);
+        assert_eq!(tree.merkle_root(), reference.merkle_root());
+        assert_eq!(block_on(tree.store().load_root()).unwrap().as_deref(), Some(tree.merkle_root()));
+        assert_eq!(tree.len(), 5);
+    }
+
+    #[test]
+    fn test_reopen_and_old_roots() {
+        let mut tree = block_on(AsyncCryptoTree::open(MemoryStore::new(), Sha256Hasher::new())).unwrap();
+        for i in 0..10 {
+            block_on(tree.insert(sample_tx(&format!("tx_{}", i), 10))).unwrap();
+        }
+        let old_root = tree.merkle_root().to_string();
+        block_on(tree.insert(sample_tx("tx_new", 10))).unwrap();
+
+        let reopened = block_on(AsyncCryptoTree::<Transaction, _>::open(tree.store, Sha256Hasher::new())).unwrap();
+        assert_eq!(reopened.len(), 11);
+        assert_eq!(reopened.merkle_root(), tree.root.as_deref().unwrap());
+        // Nodes of the earlier version are still stored
+        let old = block_on(reopened.store().get_node(&old_root)).unwrap().unwrap();
+        assert_eq!(old.size, 10);
+    }
+}
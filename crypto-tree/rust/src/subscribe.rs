@@ -0,0 +1,191 @@
+//! Change feeds for code that mirrors a tree.
+//!
+//! Unlike a [`TreeObserver`](crate::TreeObserver), which sees ids and
+//! counters, a subscriber receives the transactions themselves: every
+//! insert, update and removal as a [`ChangeEvent`], followed by the new
+//! Merkle root once it is known, so indexers and gateways can follow the
+//! tree without polling it.
+
+use alloc::sync::Arc;
+use core::fmt;
+
+use crate::prelude::*;
+use crate::{CryptoBinaryTree, TreeKey};
+
+/// A change delivered to subscribers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent<T: TreeKey> {
+    /// A transaction was inserted
+    Inserted(T),
+    /// A stored transaction was replaced by this one
+    Updated(T),
+    /// The transaction with this id was removed
+    Removed(T::Key),
+    /// The Merkle root changed to this one. With lazy hashing it is sent
+    /// once `flush_hashes` computes it.
+    NewRoot(String),
+}
+
+/// Receives the [`ChangeEvent`]s of the trees it is subscribed to
+pub trait ChangeSubscriber<T: TreeKey>: Send + Sync {
+    /// Delivers `event`, returning `false` once the subscriber has gone
+    /// away; it is then dropped.
+    fn send(&self, event: ChangeEvent<T>) -> bool;
+}
+
+#[cfg(feature = "std")]
+impl<T: TreeKey + Send> ChangeSubscriber<T> for std::sync::mpsc::Sender<ChangeEvent<T>>
+where
+    T::Key: Send,
+{
+    fn send(&self, event: ChangeEvent<T>) -> bool {
+        std::sync::mpsc::Sender::send(self, event).is_ok()
+    }
+}
+
+/// The subscribers of a tree; a clone of the tree starts without any
+pub(crate) struct Subscribers<T: TreeKey> {
+    list: Vec<Arc<dyn ChangeSubscriber<T>>>,
+    /// Root last sent as `NewRoot`
+    root: String,
+}
+
+impl<T: TreeKey> Subscribers<T> {
+    pub(crate) fn new() -> Self {
+        Subscribers {
+            list: Vec::new(),
+            root: String::new(),
+        }
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        !self.list.is_empty()
+    }
+}
+
+impl<T: TreeKey + Clone> Subscribers<T> {
+    pub(crate) fn send(&mut self, event: ChangeEvent<T>) {
+        if let [subscriber] = self.list.as_slice() {
+            if !subscriber.send(event) {
+                self.list.clear();
+            }
+            return;
+        }
+        self.list.retain(|s| s.send(event.clone()));
+    }
+
+    /// Sends `root` as `NewRoot` unless it is pending or was the last one sent.
+    pub(crate) fn send_root(&mut self, root: &str) {
+        if self.is_active() && !root.is_empty() && root != self.root {
+            self.root = root.to_string();
+            self.send(ChangeEvent::NewRoot(self.root.clone()));
+        }
+    }
+}
+
+/// Subscribers follow one tree: changes to a clone must not reach them as
+/// if the original had changed.
+impl<T: TreeKey> Clone for Subscribers<T> {
+    fn clone(&self) -> Self {
+        Subscribers::new()
+    }
+}
+
+impl<T: TreeKey> fmt::Debug for Subscribers<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Subscribers({})", self.list.len())
+    }
+}
+
+impl<T: TreeKey, H> CryptoBinaryTree<T, H> {
+    /// Feeds every later change of the tree to `subscriber`. Clones of the
+    /// tree start without subscribers.
+    ///
+    /// `insert`, `insert_batch`, `merge` and `upsert` send `Inserted`,
+    /// `update` sends `Updated`, and `remove` and `retain` send `Removed`,
+    /// each followed by `NewRoot`. Changes that replace the nodes wholesale,
+    /// such as `clear`, `apply_delta` or `rollback_to`, only send `NewRoot`.
+    pub fn add_subscriber(&mut self, subscriber: Arc<dyn ChangeSubscriber<T>>) {
+        self.subscribers.root = self.merkle_root.clone();
+        self.subscribers.list.push(subscriber);
+    }
+
+    /// Subscribes a channel to the tree's changes, see `add_subscriber`.
+    ///
+    /// Events queue up until received; dropping the receiver unsubscribes.
+    #[cfg(feature = "std")]
+    pub fn subscribe(&mut self) -> std::sync::mpsc::Receiver<ChangeEvent<T>>
+    where
+        T: Send + 'static,
+        T::Key: Send,
+    {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.add_subscriber(Arc::new(sender));
+        receiver
+    }
+
+    /// Removes every subscriber.
+    pub fn clear_subscribers(&mut self) {
+        self.subscribers.list.clear();
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::CryptoBinaryTree;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_subscriber_sees_changes() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.insert(sample_tx("tx_a", 1));
+        let events = tree.subscribe();
+
+        tree.insert(sample_tx("tx_b", 2));
+        let after_insert = tree.merkle_root().to_string();
+        assert!(!tree.insert(sample_tx("tx_b", 3)));
+        tree.update("tx_a", |tx| tx.amount = 10).unwrap();
+        tree.remove("tx_b");
+        let root = tree.merkle_root().to_string();
+
+        let received: Vec<_> = events.try_iter().collect();
+        assert_eq!(received.len(), 6);
+        assert_eq!(received[0], ChangeEvent::Inserted(sample_tx("tx_b", 2)));
+        assert_eq!(received[1], ChangeEvent::NewRoot(after_insert));
+        assert_eq!(received[2], ChangeEvent::Updated(sample_tx("tx_a", 10)));
+        assert_eq!(received[4], ChangeEvent::Removed("tx_b".to_string()));
+        assert_eq!(received[5], ChangeEvent::NewRoot(root));
+
+        // Lazy hashing sends one root per flush
+        tree.set_lazy_hashing(true);
+        tree.insert_batch(vec![sample_tx("tx_c", 1), sample_tx("tx_d", 1)]);
+        tree.insert(sample_tx("tx_e", 1));
+        let root = tree.flush_hashes().to_string();
+        let received: Vec<_> = events.try_iter().collect();
+        assert_eq!(received.len(), 4);
+        assert_eq!(received[3], ChangeEvent::NewRoot(root));
+
+        drop(events);
+        tree.insert(sample_tx("tx_f", 1));
+        assert!(!tree.subscribers.is_active());
+    }
+
+    #[test]
+    fn test_clone_does_not_feed_original_subscribers() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.insert(sample_tx("tx_a", 1));
+        let events = tree.subscribe();
+
+        let mut copy = tree.clone();
+        copy.insert(sample_tx("only_in_copy", 1));
+        copy.update("tx_a", |tx| tx.amount = 2).unwrap();
+        copy.remove("tx_a");
+        assert!(events.try_recv().is_err());
+        assert!(!copy.subscribers.is_active());
+
+        // The original still feeds its own subscriber
+        tree.insert(sample_tx("tx_b", 1));
+        assert_eq!(events.try_recv().unwrap(), ChangeEvent::Inserted(sample_tx("tx_b", 1)));
+    }
+}
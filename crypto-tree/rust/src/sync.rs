@@ -0,0 +1,464 @@
+//! Anti-entropy reconciliation between two replicas of a tree.
+//!
+//! Each message carries the sender's Merkle root, so replicas already in
+//! sync finish after one round trip. Otherwise, since AVL subtrees depend on
+//! the order transactions arrived in, two replicas holding the same data
+//! rarely share subtree hashes. Every node therefore also caches a digest of
+//! its subtree that depends only on the transactions in it, and the replicas
+//! compare fingerprints of key ranges taken from O(log n) of those digests: a
+//! range that matches is done, a small one is exchanged whole, and a large
+//! one is split into sub-ranges at the sender's quantile keys and compared
+//! again. Each round is one [`SyncMessage`], so any transport that can carry
+//! the messages back and forth will do, and only the differing transactions
+//! cross it. The digests are computed on the first sync and afterwards only
+//! along the paths that changed.
+//!
+//! Keys both replicas hold with different contents are settled the same way
+//! on both sides: the version whose canonical encoding has the greater
+//! SHA-256 hash wins. A session ends once both replicas hold the same
+//! transactions; it never reshapes either tree, so their Merkle roots match
+//! only if their shapes do. Replicas that need equal roots call
+//! [`CryptoBinaryTree::rebuild_balanced`] afterwards.
+//!
+//! # Threat model
+//!
+//! A range fingerprint is an additive multiset hash: the lane-wise sum of
+//! the SHA-256 hashes of the range's transactions. That is what lets it be
+//! cached per subtree and taken in O(log n), and it tells apart sets that
+//! differ by accident with overwhelming probability. It is not collision
+//! resistant against a peer that chooses transactions: sums can be matched
+//! with generalized birthday attacks, so a malicious replica can make a
+//! range it holds differently look in sync. It cannot slip in anything
+//! beyond what it sends, and everything it sends goes through the
+//! validator, policies and ledger rules, but it can keep transactions from
+//! being exchanged. Sync with replicas you trust, or confirm the outcome
+//! with a collision-resistant check such as equal roots after both sides
+//! call `rebuild_balanced`.
+
+use core::cmp::{Ordering, Reverse};
+use core::ops::{Add, Sub};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::prelude::*;
+use crate::{
+    balanced_root, encode_canonical, Arena, AuditOp, CryptoBinaryTree, CryptoTreeNode, MergePolicy, NodeId, Result,
+    Sha256Hasher, TreeHasher, TreeKey,
+};
+
+/// Ranges holding at most this many transactions are exchanged whole
+const SYNC_LEAF_ITEMS: usize = 16;
+/// Number of sub-ranges a larger range that differs is split into
+const SYNC_FANOUT: usize = 16;
+
+/// The keys from `start` up to but excluding `end`; `None` leaves that side open
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRange<K> {
+    pub start: Option<K>,
+    pub end: Option<K>,
+}
+
+impl<K> KeyRange<K> {
+    /// The range of every key
+    pub fn full() -> Self {
+        KeyRange { start: None, end: None }
+    }
+}
+
+/// What one replica holds in a key range
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RangeFingerprint<K> {
+    pub range: KeyRange<K>,
+    /// Number of transactions in the range
+    pub count: u64,
+    /// Hex digest of the range's transactions, independent of the tree's shape
+    pub hash: String,
+}
+
+/// One round of a sync session, sent by [`CryptoBinaryTree::sync_start`] or
+/// [`CryptoBinaryTree::sync_step`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "T: Serialize",
+    deserialize = "T: Deserialize<'de>, T::Key: Deserialize<'de>"
+))]
+pub struct SyncMessage<T: TreeKey> {
+    /// The sender's Merkle root when it sent the message
+    pub root: String,
+    /// Ranges for the receiver to compare with its own
+    pub ranges: Vec<RangeFingerprint<T::Key>>,
+    /// Ranges whose transactions the sender asks for
+    pub wanted: Vec<KeyRange<T::Key>>,
+    /// Transactions for the receiver to merge
+    pub transactions: Vec<T>,
+}
+
+impl<T: TreeKey> SyncMessage<T> {
+    /// Returns `true` if the message only announces the end of the session
+    pub fn is_done(&self) -> bool {
+        self.ranges.is_empty() && self.wanted.is_empty() && self.transactions.is_empty()
+    }
+}
+
+/// Lane-wise wrapping sum of the SHA-256 hashes of canonical payload
+/// encodings: the same for the same transactions however they are stored,
+/// and cheap to add and subtract, but not collision resistant; see the
+/// module's threat model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SetDigest([u64; 4]);
+
+impl SetDigest {
+    fn of<T: Serialize>(transaction: &T) -> Self {
+        let bytes = encode_canonical(transaction).expect("stored transactions encode");
+        let hash = Sha256::digest(bytes);
+        let mut lanes = [0u64; 4];
+        for (lane, chunk) in lanes.iter_mut().zip(hash.chunks_exact(8)) {
+            *lane = u64::from_be_bytes(chunk.try_into().expect("chunks are 8 bytes"));
+        }
+        SetDigest(lanes)
+    }
+
+    fn to_hex(self) -> String {
+        self.0.iter().map(|lane| format!("{:016x}", lane)).collect()
+    }
+}
+
+impl Add for SetDigest {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        SetDigest(core::array::from_fn(|i| self.0[i].wrapping_add(other.0[i])))
+    }
+}
+
+impl Sub for SetDigest {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        SetDigest(core::array::from_fn(|i| self.0[i].wrapping_sub(other.0[i])))
+    }
+}
+
+/// Sort order for conflicting versions, the same on every replica
+fn conflict_rank<T: Serialize>(transaction: &T) -> Option<String> {
+    encode_canonical(transaction).ok().map(|bytes| Sha256Hasher::default().hash(&bytes))
+}
+
+impl<T: TreeKey + Serialize + Clone + PartialEq, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Opens a sync session, returning the first message for the other replica.
+    pub fn sync_start(&mut self) -> Result<SyncMessage<T>> {
+        Ok(SyncMessage {
+            root: self.flush_hashes().to_string(),
+            ranges: vec![self._fingerprint(KeyRange::full())],
+            wanted: Vec::new(),
+            transactions: Vec::new(),
+        })
+    }
+
+    /// Handles a message from the other replica, returning the reply to send
+    /// it, or `None` once the session is over.
+    ///
+    /// The transactions the message carries are merged in as by `merge`, so
+    /// one the validator, a policy or the ledger rules reject fails the step
+    /// and leaves the tree unchanged. If the message holds a key more than
+    /// once, the version that would win a conflict is taken. A reply that
+    /// [`is_done`](SyncMessage::is_done) ends the session on both sides; by
+    /// then the replicas hold the same transactions, though in trees whose
+    /// shapes, and so roots, may differ. Both replicas must use the same
+    /// hasher.
+    pub fn sync_step(&mut self, message: SyncMessage<T>) -> Result<Option<SyncMessage<T>>> {
+        if message.is_done() {
+            return Ok(None);
+        }
+
+        let mut received = message.transactions;
+        received.sort_by_cached_key(|tx| (tx.key().clone(), Reverse(conflict_rank(tx))));
+        received.dedup_by(|later, earlier| later.key() == earlier.key());
+        if !received.is_empty() {
+            let other = CryptoBinaryTree::<T>::from_sorted(received.clone())?;
+            let resolve = |ours: &T, theirs: T| if conflict_rank(&theirs) > conflict_rank(ours) { theirs } else { ours.clone() };
+            self.merge(other, MergePolicy::Custom(Box::new(resolve)))?;
+        }
+
+        let root = self.flush_hashes().to_string();
+        let mut reply = SyncMessage { root, ranges: Vec::new(), wanted: Vec::new(), transactions: Vec::new() };
+        if reply.root == message.root {
+            // Same hasher and same root: the replicas already hold the same transactions
+            return Ok(Some(reply));
+        }
+        for range in &message.wanted {
+            // Leave out what the sender just sent and still holds
+            let (lo, hi) = self._range_bounds(range);
+            let unchanged = |tx: &T| received.binary_search_by(|r| r.key().cmp(tx.key())).is_ok_and(|i| received[i] == *tx);
+            reply.transactions.extend(self._range_items(lo, hi).filter(|tx| !unchanged(tx)).cloned());
+        }
+        for theirs in message.ranges {
+            let (lo, hi) = self._range_bounds(&theirs.range);
+            let ours = self._fingerprint(theirs.range.clone());
+            if ours.count == theirs.count && ours.hash == theirs.hash {
+                continue;
+            }
+            if hi - lo <= SYNC_LEAF_ITEMS {
+                reply.transactions.extend(self._range_items(lo, hi).cloned());
+                reply.wanted.push(theirs.range);
+            } else {
+                reply.ranges.extend(self._split_range(theirs.range, lo, hi));
+            }
+        }
+        Ok(Some(reply))
+    }
+
+    /// Rebuilds the tree into the balanced shape of its contents, in O(n).
+    ///
+    /// Trees holding the same transactions then have the same root whatever
+    /// order the transactions arrived in, as with `from_sorted`.
+    pub fn rebuild_balanced(&mut self) {
+        let before = self.flush_hashes().to_string();
+        let mut nodes: Vec<CryptoTreeNode<T>> = self.iter().cloned().map(CryptoTreeNode::unhashed).collect();
+        Self::_build_balanced(&mut nodes, 0, &self.hasher).expect("stored transactions encode");
+        self.root = balanced_root(nodes.len()).map(NodeId::new);
+        self.nodes = Arena::from_vec(nodes);
+        self._intern_all();
+        self._reindex();
+        self._update_merkle_root();
+        if self.merkle_root != before {
+            self._audit(AuditOp::Rebuild, Vec::new());
+        }
+    }
+
+    /// Positions in key order of the first transaction in `range` and of the first after it
+    fn _range_bounds(&self, range: &KeyRange<T::Key>) -> (usize, usize) {
+        let lo = range.start.as_ref().map_or(0, |start| self.rank(start));
+        let hi = range.end.as_ref().map_or(self.len(), |end| self.rank(end));
+        (lo, hi.max(lo))
+    }
+
+    fn _range_items(&self, lo: usize, hi: usize) -> impl Iterator<Item = &T> + '_ {
+        (lo..hi).filter_map(|k| self.select(k))
+    }
+
+    /// Digest of the subtree at `id`, computing and caching the missing ones below it
+    fn _subtree_digest(nodes: &mut Arena<T>, id: Option<NodeId>) -> SetDigest {
+        let Some(id) = id else {
+            return SetDigest::default();
+        };
+        if let Some(digest) = nodes[id].digest {
+            return digest;
+        }
+        let (left, right) = (nodes[id].left, nodes[id].right);
+        let digest = Self::_subtree_digest(nodes, left) + Self::_subtree_digest(nodes, right) + SetDigest::of(&nodes[id].transaction);
+        nodes[id].digest = Some(digest);
+        digest
+    }
+
+    /// Digest of the transactions with keys below `bound`, in O(log n) once cached
+    fn _digest_below(&mut self, bound: &T::Key) -> SetDigest {
+        let mut digest = SetDigest::default();
+        let mut current = self.root;
+        while let Some(id) = current {
+            let n = &self.nodes[id];
+            let (left, right) = (n.left, n.right);
+            if n.cmp_key(bound) == Ordering::Greater {
+                digest = digest + Self::_subtree_digest(&mut self.nodes, Some(id)) - Self::_subtree_digest(&mut self.nodes, right);
+                current = right;
+            } else {
+                current = left;
+            }
+        }
+        digest
+    }
+
+    fn _fingerprint(&mut self, range: KeyRange<T::Key>) -> RangeFingerprint<T::Key> {
+        let (lo, hi) = self._range_bounds(&range);
+        let digest = if lo == hi {
+            SetDigest::default()
+        } else {
+            let below_end = match &range.end {
+                Some(end) => self._digest_below(end),
+                None => Self::_subtree_digest(&mut self.nodes, self.root),
+            };
+            let below_start = range.start.as_ref().map_or(SetDigest::default(), |start| self._digest_below(start));
+            below_end - below_start
+        };
+        RangeFingerprint {
+            range,
+            count: (hi - lo) as u64,
+            hash: digest.to_hex(),
+        }
+    }
+
+    /// Fingerprints of up to `SYNC_FANOUT` sub-ranges of `range`, which holds positions `lo..hi`
+    fn _split_range(&mut self, range: KeyRange<T::Key>, lo: usize, hi: usize) -> Vec<RangeFingerprint<T::Key>> {
+        let splits: Vec<T::Key> = (1..SYNC_FANOUT)
+            .filter_map(|j| self.select(lo + (hi - lo) * j / SYNC_FANOUT))
+            .map(|split| split.key().clone())
+            .collect();
+        let mut parts = Vec::with_capacity(SYNC_FANOUT);
+        let mut start = range.start;
+        for split in splits {
+            let end = Some(split.clone());
+            parts.push(self._fingerprint(KeyRange { start: start.replace(split), end }));
+        }
+        parts.push(self._fingerprint(KeyRange { start, end: range.end }));
+        parts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Transaction;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_replicas_converge() {
+        let mut a = CryptoBinaryTree::new();
+        let mut b = CryptoBinaryTree::new();
+        for i in 0..1000 {
+            a.insert(sample_tx(&format!("tx_{:04}", i), 10));
+        }
+        for i in (0..1000).rev() {
+            b.insert(sample_tx(&format!("tx_{:04}", i), 10));
+        }
+        a.insert(sample_tx("tx_a_only", 1));
+        b.insert(sample_tx("tx_b_only", 2));
+        b.remove("tx_0500");
+        a.update("tx_0042", |tx| tx.amount = 42).unwrap();
+        b.update("tx_0042", |tx| tx.amount = 43).unwrap();
+
+        let mut message = Some(a.sync_start().unwrap());
+        let (mut sent, mut rounds) = (0, 0);
+        let mut turn_a = false;
+        while let Some(m) = message {
+            sent += m.transactions.len();
+            rounds += 1;
+            message = if turn_a { a.sync_step(m) } else { b.sync_step(m) }.unwrap();
+            turn_a = !turn_a;
+        }
+
+        assert_eq!(a.len(), 1002);
+        assert!(a.iter().eq(b.iter()));
+        assert!(a.search("tx_0500").is_some() && b.search("tx_a_only").is_some());
+        assert_eq!(a.search("tx_0042"), b.search("tx_0042"));
+        assert!(sent < 100, "sent {} transactions", sent);
+        assert!(rounds < 10);
+
+        // Already in sync: one round trip, and neither tree is reshaped
+        let (root_a, root_b) = (a.merkle_root().to_string(), b.merkle_root().to_string());
+        let reply = b.sync_step(a.sync_start().unwrap()).unwrap().unwrap();
+        assert!(reply.is_done());
+        assert!(a.sync_step(reply).unwrap().is_none());
+        assert_eq!((a.merkle_root(), b.merkle_root()), (root_a.as_str(), root_b.as_str()));
+
+        a.rebuild_balanced();
+        b.rebuild_balanced();
+        assert_eq!(a.merkle_root(), b.merkle_root());
+    }
+
+    #[test]
+    fn test_sync_leaves_shapes_alone() {
+        let txs: Vec<Transaction> = (0..100).map(|i| sample_tx(&format!("tx_{:03}", i), 10)).collect();
+        let mut a = CryptoBinaryTree::new();
+        let mut b = CryptoBinaryTree::new();
+        for tx in &txs {
+            a.insert(tx.clone());
+        }
+        for tx in txs.iter().rev() {
+            b.insert(tx.clone());
+        }
+        assert_ne!(a.merkle_root(), b.merkle_root());
+
+        // The contents match at the first comparison; the roots still differ
+        let reply = b.sync_step(a.sync_start().unwrap()).unwrap().unwrap();
+        assert!(reply.is_done());
+        assert!(a.sync_step(reply).unwrap().is_none());
+        assert_ne!(a.merkle_root(), b.merkle_root());
+
+        a.rebuild_balanced();
+        b.rebuild_balanced();
+        let balanced: CryptoBinaryTree = CryptoBinaryTree::from_sorted(txs).unwrap();
+        assert_eq!(a.merkle_root(), balanced.merkle_root());
+        assert_eq!(b.merkle_root(), balanced.merkle_root());
+        assert!(a.structurally_equal(&balanced));
+    }
+
+    #[test]
+    fn test_fingerprints_follow_mutations() {
+        let mut tree = CryptoBinaryTree::new();
+        for i in 0..200 {
+            tree.insert(sample_tx(&format!("tx_{:03}", (i * 37) % 200), 10));
+        }
+        let range = |start: &str, end: &str| KeyRange { start: Some(start.to_string()), end: Some(end.to_string()) };
+        let fingerprints = |tree: &mut CryptoBinaryTree| {
+            [KeyRange::full(), range("tx_050", "tx_150"), range("tx_100", "tx_101"), range("tx_150", "tx_050")]
+                .map(|r| tree._fingerprint(r))
+        };
+        fingerprints(&mut tree);
+
+        tree.remove("tx_120");
+        tree.update("tx_060", |tx| tx.amount = 11).unwrap();
+        tree.set_lazy_hashing(true);
+        tree.insert(sample_tx("tx_999", 1));
+        tree.remove("tx_001");
+        tree.flush_hashes();
+        let mut fresh: CryptoBinaryTree = CryptoBinaryTree::from_sorted(tree.iter().cloned().collect()).unwrap();
+        let (cached, expected) = (fingerprints(&mut tree), fingerprints(&mut fresh));
+        assert_eq!(cached, expected);
+        assert_eq!(expected[1].count, 99);
+        assert_eq!(expected[3].count, 0);
+    }
+
+    #[test]
+    fn test_set_digest_threat_model() {
+        let txs: Vec<Transaction> = (0..50).map(|i| sample_tx(&format!("tx_{:02}", i), 10)).collect();
+        let sum = |txs: &[Transaction]| txs.iter().map(SetDigest::of).fold(SetDigest::default(), |acc, d| acc + d);
+
+        // Independent of order and of the tree's shape
+        let mut shuffled = txs.clone();
+        shuffled.reverse();
+        shuffled.swap(3, 40);
+        assert_eq!(sum(&txs), sum(&shuffled));
+        let mut tree = CryptoBinaryTree::new();
+        for tx in &shuffled {
+            tree.insert(tx.clone());
+        }
+        assert_eq!(tree._fingerprint(KeyRange::full()).hash, sum(&txs).to_hex());
+
+        // Any single change shows
+        let mut changed = txs.clone();
+        changed[7].amount = 11;
+        assert_ne!(sum(&txs), sum(&changed));
+        assert_ne!(sum(&txs), sum(&txs[1..]));
+
+        // But the digest is linear: a set's digest is fixed by its members'
+        // digests alone, so a peer choosing transactions only needs some
+        // whose digests add up to another set's to fake a match
+        let (left, right) = txs.split_at(20);
+        assert_eq!(sum(left) + sum(right), sum(&txs));
+        assert_eq!(sum(&txs) - SetDigest::of(&txs[7]) + SetDigest::of(&changed[7]), sum(&changed));
+    }
+
+    #[test]
+    fn test_received_batches_are_checked() {
+        use crate::{CryptoTreeError, LedgerRules};
+
+        let mut tree = CryptoBinaryTree::new();
+        tree.insert(sample_tx("tx_1", 10));
+        let message = |transactions| SyncMessage { root: "peer".to_string(), ranges: Vec::new(), wanted: Vec::new(), transactions };
+
+        // A key sent twice is settled as a conflict would be
+        let versions = [sample_tx("tx_2", 1), sample_tx("tx_2", 2), sample_tx("tx_2", 3)];
+        let winner = versions.iter().max_by_key(|tx| conflict_rank(*tx)).unwrap().clone();
+        tree.sync_step(message(versions.to_vec())).unwrap();
+        assert_eq!(tree.search("tx_2"), Some(&winner));
+
+        let mut tree = CryptoBinaryTree::new();
+        tree.set_ledger_rules(LedgerRules::new().with_exempt("mint"));
+        tree.insert(Transaction { from: "mint".into(), to: "Alice".into(), ..sample_tx("tx_1", 10) });
+        let root = tree.merkle_root().to_string();
+        let overdraft = Transaction { from: "Alice".into(), ..sample_tx("tx_2", 11) };
+        assert!(matches!(tree.sync_step(message(vec![overdraft])), Err(CryptoTreeError::InsufficientBalance { .. })));
+        assert_eq!(tree.merkle_root(), root);
+    }
+}
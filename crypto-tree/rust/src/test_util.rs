@@ -0,0 +1,45 @@
+//! Fixtures shared by the unit tests.
+
+use crate::prelude::*;
+use crate::Transaction;
+#[cfg(feature = "tree")]
+use crate::TransactionTree;
+
+/// A payment of `amount` from Alice to Bob
+pub(crate) fn sample_tx(id: &str, amount: u128) -> Transaction {
+    Transaction {
+        id: id.to_string(),
+        from: "Alice".into(),
+        to: "Bob".into(),
+        amount,
+        ..Default::default()
+    }
+}
+
+/// A tree of `n` payments `tx_001`, `tx_002`, ..., whose amounts and
+/// timestamps grow with the index
+#[cfg(feature = "tree")]
+pub(crate) fn build_tree(n: u64) -> TransactionTree {
+    let mut tree = TransactionTree::new();
+    for i in 1..=n {
+        tree.insert(Transaction {
+            timestamp: Some(1640995200 + i),
+            ..sample_tx(&format!("tx_{:03}", i), u128::from(i))
+        });
+    }
+    tree
+}
+
+/// A path named after `name` in a scratch directory, with nothing at it.
+#[cfg(all(feature = "std", feature = "tree"))]
+pub(crate) fn temp_path(name: &str) -> std::path::PathBuf {
+    // WASI has no temp dir or process ids; the runner preopens the package root
+    let path = if cfg!(target_os = "wasi") {
+        std::path::PathBuf::from(format!("target/crypto_tree_{}", name))
+    } else {
+        std::env::temp_dir().join(format!("crypto_tree_{}_{}", name, std::process::id()))
+    };
+    let _ = std::fs::remove_dir_all(&path);
+    let _ = std::fs::remove_file(&path);
+    path
+}
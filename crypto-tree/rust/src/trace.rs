@@ -0,0 +1,119 @@
+//! `tracing` spans and events (`tracing` feature).
+//!
+//! With the feature on, inserts, updates, removals, rebalancing and hash
+//! flushes run inside `TRACE`-level spans, so a subscriber can time them.
+//! [`TracingObserver`] is a [`TreeObserver`] that turns every [`TreeEvent`]
+//! into a `tracing` event with target `crypto_tree`, so rotations, rehashing,
+//! proofs and integrity checks show up in whatever subscriber the application
+//! already installs. Routine operations are logged at `TRACE`, bulk work and
+//! refused changes at `DEBUG`, and failed integrity checks at `ERROR`.
+
+use tracing::{debug, error, trace};
+
+use crate::{TreeEvent, TreeObserver};
+
+/// Target of every event, e.g. for `RUST_LOG=crypto_tree=debug`
+pub const TRACING_TARGET: &str = "crypto_tree";
+
+/// Emits the events of the trees it is installed on through `tracing`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingObserver;
+
+impl TracingObserver {
+    pub fn new() -> Self {
+        TracingObserver
+    }
+}
+
+impl TreeObserver for TracingObserver {
+    fn on_event(&self, event: &TreeEvent<'_>) {
+        match *event {
+            TreeEvent::Inserted { id, rotations, hashes } => trace!(target: TRACING_TARGET, id, rotations, hashes, "insert"),
+            TreeEvent::InsertRejected { id, error } => debug!(target: TRACING_TARGET, id, %error, "insert rejected"),
+            TreeEvent::BatchInserted {
+                inserted,
+                duplicates,
+                failed,
+                rotations,
+            } => debug!(target: TRACING_TARGET, inserted, duplicates, failed, rotations, "batch insert"),
+            TreeEvent::Removed { id, rotations, hashes } => trace!(target: TRACING_TARGET, id, rotations, hashes, "remove"),
+            TreeEvent::Updated { id, hashes, error: None } => trace!(target: TRACING_TARGET, id, hashes, "update"),
+            TreeEvent::Updated {
+                id,
+                error: Some(error),
+                ..
+            } => debug!(target: TRACING_TARGET, id, %error, "update rejected"),
+            TreeEvent::Flushed { hashes } => debug!(target: TRACING_TARGET, hashes, "hashes flushed"),
+            TreeEvent::ProofGenerated { id, steps } => trace!(target: TRACING_TARGET, id, steps, "proof generated"),
+            TreeEvent::IntegrityChecked { nodes, corrupted: None } => debug!(target: TRACING_TARGET, nodes, "integrity verified"),
+            TreeEvent::IntegrityChecked {
+                nodes,
+                corrupted: Some(id),
+            } => error!(target: TRACING_TARGET, nodes, corrupted = id, "integrity check failed"),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Level, Metadata, Subscriber};
+
+    use super::*;
+    use crate::test_util::sample_tx;
+    use crate::CryptoBinaryTree;
+
+    /// Records the level of every event and the name of every span
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Level>>>, Arc<Mutex<Vec<&'static str>>>);
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.1.lock().unwrap().push(span.metadata().name());
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            assert_eq!(event.metadata().target(), TRACING_TARGET);
+            self.0.lock().unwrap().push(*event.metadata().level());
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_events_reach_subscriber() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let mut tree = CryptoBinaryTree::new();
+            tree.set_observer(Arc::new(TracingObserver::new()));
+            let tx = sample_tx("tx_1", 10);
+            tree.insert(tx.clone());
+            tree.insert(tx);
+            assert!(tree.verify_integrity());
+        });
+        assert_eq!(*recorder.0.lock().unwrap(), [Level::TRACE, Level::DEBUG, Level::DEBUG]);
+    }
+
+    #[test]
+    fn test_operations_open_spans() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let mut tree = CryptoBinaryTree::new();
+            tree.set_lazy_hashing(true);
+            tree.insert(sample_tx("tx_1", 10));
+            tree.insert(sample_tx("tx_2", 10));
+            tree.flush_hashes();
+        });
+        let spans = recorder.1.lock().unwrap();
+        assert_eq!(spans.iter().filter(|&&name| name == "try_insert").count(), 2);
+        assert!(spans.contains(&"rebalance"));
+        assert!(spans.contains(&"flush_hashes"));
+    }
+}
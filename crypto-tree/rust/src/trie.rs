@@ -0,0 +1,467 @@
+//! Merkle Patricia trie keyed by transaction id.
+//!
+//! A [`PatriciaTrie`] splits each id into hex nibbles and stores it along a
+//! path of branch nodes with sixteen children, collapsing runs of nodes with
+//! a single child into extension and leaf nodes, as Ethereum's state trie
+//! does. Long ids that share a prefix store it once. Every node is hashed
+//! over the canonical encoding of its [`TrieProofNode`] form, so the root
+//! commits to the whole set and depends only on which transactions it holds,
+//! and a [`TrieProof`] is the list of nodes from the root to an id.
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::{encode_canonical, key_string, Result, Sha256Hasher, Transaction, TreeHasher, TreeKey};
+
+/// Hex digits of the bytes of `key` as rendered by `key_string`
+fn nibbles<K: Serialize + ?Sized>(key: &K) -> Vec<u8> {
+    key_string(key).bytes().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+fn hex_path(nibbles: &[u8]) -> String {
+    nibbles.iter().map(|&n| char::from_digit(u32::from(n), 16).expect("nibbles are below 16")).collect()
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+/// A trie node as hashed and as carried in proofs; paths are hex nibbles and
+/// children are hashes, `"0"` for none
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrieProofNode<T> {
+    Leaf { path: String, value: T },
+    Extension { path: String, child: String },
+    Branch { children: Vec<String>, value: Option<T> },
+}
+
+impl<T: Serialize> TrieProofNode<T> {
+    /// Hashes the canonical encoding of the node with `hasher`.
+    pub fn hash<H: TreeHasher>(&self, hasher: &H) -> Result<String> {
+        Ok(hasher.hash(&encode_canonical(self)?))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Kind<T> {
+    Leaf { path: Vec<u8>, value: T },
+    Extension { path: Vec<u8>, child: Box<TrieNode<T>> },
+    Branch { children: [Option<Box<TrieNode<T>>>; 16], value: Option<T> },
+}
+
+#[derive(Debug, Clone)]
+struct TrieNode<T> {
+    kind: Kind<T>,
+    hash: String,
+}
+
+impl<T: Serialize> TrieNode<T> {
+    /// Wraps `kind` and hashes it; its values were checked to encode.
+    fn new<H: TreeHasher>(kind: Kind<T>, hasher: &H) -> Box<Self> {
+        let mut node = TrieNode { kind, hash: String::new() };
+        node.hash = node.encoded().hash(hasher).expect("trie values are checked to encode");
+        Box::new(node)
+    }
+
+    fn encoded(&self) -> TrieProofNode<&T> {
+        match &self.kind {
+            Kind::Leaf { path, value } => TrieProofNode::Leaf { path: hex_path(path), value },
+            Kind::Extension { path, child } => TrieProofNode::Extension {
+                path: hex_path(path),
+                child: child.hash.clone(),
+            },
+            Kind::Branch { children, value } => TrieProofNode::Branch {
+                children: children.iter().map(|c| c.as_ref().map_or("0", |c| &c.hash).to_string()).collect(),
+                value: value.as_ref(),
+            },
+        }
+    }
+
+    /// Stores `value` at `path` below `node`, returning the new node and the
+    /// value replaced.
+    fn insert<H: TreeHasher>(node: Option<Box<Self>>, path: &[u8], value: T, hasher: &H) -> (Box<Self>, Option<T>) {
+        let Some(node) = node else {
+            return (Self::new(Kind::Leaf { path: path.to_vec(), value }, hasher), None);
+        };
+        match node.kind {
+            Kind::Leaf { path: leaf_path, value: old } => {
+                if leaf_path == path {
+                    return (Self::new(Kind::Leaf { path: leaf_path, value }, hasher), Some(old));
+                }
+                let common = common_prefix(&leaf_path, path);
+                let mut branch = Self::_branch();
+                Self::_place(&mut branch, &leaf_path[common..], old, hasher);
+                Self::_place(&mut branch, &path[common..], value, hasher);
+                (Self::_extend(&path[..common], Self::new(branch, hasher), hasher), None)
+            }
+            Kind::Extension { path: ext_path, child } => {
+                let common = common_prefix(&ext_path, path);
+                if common == ext_path.len() {
+                    let (child, replaced) = Self::insert(Some(child), &path[common..], value, hasher);
+                    return (Self::new(Kind::Extension { path: ext_path, child }, hasher), replaced);
+                }
+                let mut branch = Self::_branch();
+                if let Kind::Branch { children, .. } = &mut branch {
+                    children[usize::from(ext_path[common])] = Some(Self::_extend(&ext_path[common + 1..], child, hasher));
+                }
+                Self::_place(&mut branch, &path[common..], value, hasher);
+                (Self::_extend(&path[..common], Self::new(branch, hasher), hasher), None)
+            }
+            Kind::Branch { mut children, value: mut stored } => {
+                let replaced = match path.split_first() {
+                    None => stored.replace(value),
+                    Some((&n, rest)) => {
+                        let (child, replaced) = Self::insert(children[usize::from(n)].take(), rest, value, hasher);
+                        children[usize::from(n)] = Some(child);
+                        replaced
+                    }
+                };
+                (Self::new(Kind::Branch { children, value: stored }, hasher), replaced)
+            }
+        }
+    }
+
+    /// Takes the value at `path` out from below `node`, returning what is
+    /// left of the node and the value.
+    fn remove<H: TreeHasher>(node: Self, path: &[u8], hasher: &H) -> (Option<Box<Self>>, Option<T>) {
+        match node.kind {
+            Kind::Leaf { path: leaf_path, value } if leaf_path == path => (None, Some(value)),
+            Kind::Extension { path: ext_path, child } if path.starts_with(&ext_path) => {
+                match Self::remove(*child, &path[ext_path.len()..], hasher) {
+                    (child, None) => (
+                        child.map(|child| Self::new(Kind::Extension { path: ext_path, child }, hasher)),
+                        None,
+                    ),
+                    (child, removed) => (child.map(|child| Self::_extend(&ext_path, child, hasher)), removed),
+                }
+            }
+            Kind::Branch { mut children, mut value } => {
+                let removed = match path.split_first() {
+                    None => value.take(),
+                    Some((&n, rest)) => match children[usize::from(n)].take() {
+                        Some(child) => {
+                            let (child, removed) = Self::remove(*child, rest, hasher);
+                            children[usize::from(n)] = child;
+                            removed
+                        }
+                        None => None,
+                    },
+                };
+                if removed.is_none() {
+                    return (Some(Self::new(Kind::Branch { children, value }, hasher)), None);
+                }
+                (Self::_collapse(children, value, hasher), removed)
+            }
+            kind => (
+                Some(Box::new(TrieNode {
+                    kind,
+                    hash: node.hash,
+                })),
+                None,
+            ),
+        }
+    }
+
+    fn _branch() -> Kind<T> {
+        Kind::Branch {
+            children: Default::default(),
+            value: None,
+        }
+    }
+
+    /// Puts `value` at `path` below an empty slot of `branch`.
+    fn _place<H: TreeHasher>(branch: &mut Kind<T>, path: &[u8], value: T, hasher: &H) {
+        let Kind::Branch { children, value: stored } = branch else {
+            unreachable!("values are only placed in branches");
+        };
+        match path.split_first() {
+            None => *stored = Some(value),
+            Some((&n, rest)) => children[usize::from(n)] = Some(Self::new(Kind::Leaf { path: rest.to_vec(), value }, hasher)),
+        }
+    }
+
+    /// Prefixes `node` with `path`, merging it into a leaf or extension below.
+    fn _extend<H: TreeHasher>(path: &[u8], node: Box<Self>, hasher: &H) -> Box<Self> {
+        if path.is_empty() {
+            return node;
+        }
+        let kind = match node.kind {
+            Kind::Leaf { path: rest, value } => Kind::Leaf {
+                path: [path, &rest].concat(),
+                value,
+            },
+            Kind::Extension { path: rest, child } => Kind::Extension {
+                path: [path, &rest].concat(),
+                child,
+            },
+            kind => Kind::Extension {
+                path: path.to_vec(),
+                child: Box::new(TrieNode { kind, hash: node.hash }),
+            },
+        };
+        Self::new(kind, hasher)
+    }
+
+    /// Rebuilds a branch an entry was removed from, merging it into its only
+    /// remaining entry if it has just one.
+    fn _collapse<H: TreeHasher>(mut children: [Option<Box<Self>>; 16], value: Option<T>, hasher: &H) -> Option<Box<Self>> {
+        let mut remaining = children.iter().enumerate().filter(|(_, c)| c.is_some()).map(|(n, _)| n);
+        match (remaining.next(), remaining.next(), value) {
+            (None, _, None) => None,
+            (None, _, Some(value)) => Some(Self::new(Kind::Leaf { path: Vec::new(), value }, hasher)),
+            (Some(n), None, None) => {
+                let child = children[n].take().expect("child is present");
+                Some(Self::_extend(&[n as u8], child, hasher))
+            }
+            (_, _, value) => Some(Self::new(Kind::Branch { children, value }, hasher)),
+        }
+    }
+}
+
+/// Proof that an id holds a transaction in a trie
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrieProof<T> {
+    /// Nodes from the root down to the one holding the transaction
+    pub nodes: Vec<TrieProofNode<T>>,
+}
+
+impl<T: TreeKey + Serialize + PartialEq> TrieProof<T> {
+    /// Checks that under `root`, `key` holds `transaction`.
+    pub fn verify<H: TreeHasher>(&self, root: &str, key: &T::Key, transaction: &T, hasher: &H) -> bool {
+        if transaction.key() != key {
+            return false;
+        }
+        let path = nibbles(key);
+        let mut rest = &path[..];
+        let mut expected = root;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.hash(hasher).ok().as_deref() != Some(expected) {
+                return false;
+            }
+            let last = i + 1 == self.nodes.len();
+            match node {
+                TrieProofNode::Leaf { path, value } => return last && hex_path(rest) == *path && value == transaction,
+                TrieProofNode::Extension { path, child } => {
+                    let Some(remaining) = rest.get(path.len()..).filter(|_| hex_path(&rest[..path.len()]) == *path) else {
+                        return false;
+                    };
+                    rest = remaining;
+                    expected = child;
+                }
+                TrieProofNode::Branch { children, value } => match rest.split_first() {
+                    None => return last && value.as_ref() == Some(transaction),
+                    Some((&n, remaining)) => {
+                        rest = remaining;
+                        expected = children.get(usize::from(n)).map_or("0", String::as_str);
+                    }
+                },
+            }
+        }
+        false
+    }
+}
+
+/// A hex-nibble Merkle Patricia trie of transactions keyed by id
+///
+/// Each insert or removal rehashes the nodes on the id's path, at most one
+/// per nibble of the id.
+#[derive(Debug, Clone)]
+pub struct PatriciaTrie<T: TreeKey = Transaction, H = Sha256Hasher> {
+    hasher: H,
+    root: Option<Box<TrieNode<T>>>,
+    len: usize,
+}
+
+impl<T: TreeKey + Serialize> PatriciaTrie<T> {
+    /// Creates an empty trie hashed with SHA-256.
+    pub fn new() -> Self {
+        Self::with_hasher(Sha256Hasher::new())
+    }
+}
+
+impl<T: TreeKey + Serialize> Default for PatriciaTrie<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: TreeKey + Serialize, H: TreeHasher> PatriciaTrie<T, H> {
+    /// Creates an empty trie whose nodes are hashed with `hasher`.
+    pub fn with_hasher(hasher: H) -> Self {
+        PatriciaTrie { hasher, root: None, len: 0 }
+    }
+
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// The root hash, `"0"` while the trie is empty
+    pub fn root(&self) -> &str {
+        self.root.as_ref().map_or("0", |r| &r.hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Stores `transaction` under its id, returning the one it replaced.
+    ///
+    /// Fails with `SerializationFailed`, leaving the trie unchanged, if the
+    /// transaction cannot be encoded for hashing.
+    pub fn insert(&mut self, transaction: T) -> Result<Option<T>> {
+        encode_canonical(&transaction)?;
+        let path = nibbles(transaction.key());
+        let (root, replaced) = TrieNode::insert(self.root.take(), &path, transaction, &self.hasher);
+        self.root = Some(root);
+        if replaced.is_none() {
+            self.len += 1;
+        }
+        Ok(replaced)
+    }
+
+    /// Removes the transaction with id `key`, returning it if it was stored.
+    pub fn remove(&mut self, key: &T::Key) -> Option<T> {
+        // A miss leaves every node, and so its hash, as it was
+        self._path(key)?;
+        let (root, removed) = TrieNode::remove(*self.root.take()?, &nibbles(key), &self.hasher);
+        self.root = root;
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// The nodes on the path to `key`, ending at the one holding its
+    /// transaction, or `None` if it is not stored
+    fn _path(&self, key: &T::Key) -> Option<Vec<&TrieNode<T>>> {
+        let path = nibbles(key);
+        let mut rest = &path[..];
+        let mut nodes = Vec::new();
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            nodes.push(node);
+            current = match &node.kind {
+                Kind::Leaf { path, .. } => return (path[..] == *rest).then_some(nodes),
+                Kind::Extension { path, child } => {
+                    rest = rest.strip_prefix(&path[..])?;
+                    Some(child)
+                }
+                Kind::Branch { children, value } => match rest.split_first() {
+                    None => return value.is_some().then_some(nodes),
+                    Some((&n, remaining)) => {
+                        rest = remaining;
+                        children[usize::from(n)].as_deref()
+                    }
+                },
+            };
+        }
+        None
+    }
+
+    /// The transaction stored under `key`
+    pub fn get(&self, key: &T::Key) -> Option<&T> {
+        match &self._path(key)?.last()?.kind {
+            Kind::Leaf { value, .. } => Some(value),
+            Kind::Branch { value, .. } => value.as_ref(),
+            Kind::Extension { .. } => None,
+        }
+    }
+
+    /// Builds a proof that `key` is stored, checked with `TrieProof::verify`.
+    pub fn prove(&self, key: &T::Key) -> Option<TrieProof<T>>
+    where
+        T: Clone,
+    {
+        let nodes = self._path(key)?;
+        let nodes = nodes
+            .into_iter()
+            .map(|node| match node.encoded() {
+                TrieProofNode::Leaf { path, value } => TrieProofNode::Leaf { path, value: value.clone() },
+                TrieProofNode::Extension { path, child } => TrieProofNode::Extension { path, child },
+                TrieProofNode::Branch { children, value } => TrieProofNode::Branch {
+                    children,
+                    value: value.cloned(),
+                },
+            })
+            .collect();
+        Some(TrieProof { nodes })
+    }
+
+    /// Transactions in the byte order of their ids
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        let mut stack: Vec<&TrieNode<T>> = self.root.as_deref().into_iter().collect();
+        core::iter::from_fn(move || {
+            while let Some(node) = stack.pop() {
+                match &node.kind {
+                    Kind::Leaf { value, .. } => return Some(value),
+                    Kind::Extension { child, .. } => stack.push(child),
+                    Kind::Branch { children, value } => {
+                        stack.extend(children.iter().rev().flatten().map(|c| &**c));
+                        if let Some(value) = value {
+                            return Some(value);
+                        }
+                    }
+                }
+            }
+            None
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_trie_roots_and_proofs() {
+        let ids: Vec<String> = (0..40)
+            .map(|i| format!("settlement/2024/eu-west/batch-{}", i))
+            .chain(["tx_1", "tx_10", "tx_100", "tx_2"].map(String::from))
+            .collect();
+        let mut trie: PatriciaTrie = PatriciaTrie::new();
+        let mut roots = vec![trie.root().to_string()];
+        for id in &ids {
+            assert!(trie.insert(sample_tx(id, 10)).unwrap().is_none());
+            roots.push(trie.root().to_string());
+        }
+        let mut reversed: PatriciaTrie = PatriciaTrie::new();
+        for id in ids.iter().rev() {
+            reversed.insert(sample_tx(id, 10)).unwrap();
+        }
+        assert_eq!(trie.root(), reversed.root());
+        assert_eq!(trie.len(), ids.len());
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert!(trie.iter().map(|tx| &tx.id).eq(sorted.iter()));
+
+        let hasher = Sha256Hasher::new();
+        let root = trie.root().to_string();
+        for id in &ids {
+            let proof = trie.prove(id).unwrap();
+            assert!(proof.verify(&root, id, &sample_tx(id, 10), &hasher), "{}", id);
+            assert!(!proof.verify(&root, id, &sample_tx(id, 11), &hasher));
+        }
+        let proof = trie.prove(&"tx_10".to_string()).unwrap();
+        assert!(!proof.verify(&root, &"tx_1".to_string(), &sample_tx("tx_1", 10), &hasher));
+        assert!(trie.get(&"tx_3".to_string()).is_none());
+        assert!(trie.prove(&"tx_".to_string()).is_none());
+
+        assert_eq!(trie.insert(sample_tx("tx_1", 5)).unwrap().unwrap().amount, 10);
+        assert_ne!(trie.root(), root);
+        trie.insert(sample_tx("tx_1", 10)).unwrap();
+        assert_eq!(trie.root(), root);
+
+        // Removing in reverse order retraces every earlier root
+        for (i, id) in ids.iter().enumerate().rev() {
+            assert!(trie.remove(id).is_some());
+            assert!(trie.remove(id).is_none());
+            assert_eq!(trie.root(), roots[i], "after removing {}", id);
+        }
+        assert!(trie.is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use alloc::sync::Arc;
+use core::fmt;
+
+use serde::Serialize;
+
+use crate::{key_string, CryptoBinaryTree, CryptoTreeError, Result, TreeHasher, TreeKey, ValidationError};
+
+type CheckFn<T> = dyn Fn(&T) -> core::result::Result<(), ValidationError> + Send + Sync;
+
+/// Application-supplied check run before a transaction enters the tree
+pub(crate) struct Validator<T> {
+    check: Arc<CheckFn<T>>,
+}
+
+impl<T> Validator<T> {
+    pub(crate) fn validate(&self, transaction: &T) -> Result<()>
+    where
+        T: TreeKey,
+    {
+        (self.check)(transaction).map_err(|reason| CryptoTreeError::Rejected {
+            id: key_string(transaction.key()),
+            reason,
+        })
+    }
+}
+
+// Clones share the same check
+impl<T> Clone for Validator<T> {
+    fn clone(&self) -> Self {
+        Self {
+            check: Arc::clone(&self.check),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Validator<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Validator")
+    }
+}
+
+type PolicyFn<T> = dyn Fn(&T) -> Result<()> + Send + Sync;
+
+/// Built-in admission check switched on by a tree mode such as `require_signatures`
+pub(crate) struct Policy<T> {
+    name: &'static str,
+    check: Arc<PolicyFn<T>>,
+}
+
+impl<T> Clone for Policy<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            check: Arc::clone(&self.check),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Policy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Enables a named policy, replacing any earlier policy of the same name.
+    pub(crate) fn _set_policy(&mut self, name: &'static str, check: impl Fn(&T) -> Result<()> + Send + Sync + 'static) {
+        let policy = Policy {
+            name,
+            check: Arc::new(check),
+        };
+        match self.policies.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = policy,
+            None => self.policies.push(policy),
+        }
+    }
+
+    /// Disables the named policy, if enabled.
+    pub(crate) fn _remove_policy(&mut self, name: &'static str) {
+        self.policies.retain(|p| p.name != name);
+    }
+
+    /// Runs the enabled policies and then the validator against `transaction`.
+    pub(crate) fn _admit(&self, transaction: &T) -> Result<()> {
+        for policy in &self.policies {
+            (policy.check)(transaction)?;
+        }
+        match &self.validator {
+            Some(validator) => validator.validate(transaction),
+            None => Ok(()),
+        }
+    }
+
+    /// Installs a check run on every transaction before it is inserted or updated.
+    ///
+    /// Transactions the validator rejects fail with `Rejected` and leave the
+    /// tree untouched. Replaces any previous validator; transactions already
+    /// stored are not re-checked.
+    pub fn set_validator(&mut self, f: impl Fn(&T) -> core::result::Result<(), ValidationError> + Send + Sync + 'static) {
+        self.validator = Some(Validator { check: Arc::new(f) });
+    }
+
+    /// Removes the validator installed with `set_validator`.
+    pub fn clear_validator(&mut self) {
+        self.validator = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ValidationError;
+    use crate::{CryptoBinaryTree, CryptoTreeError, Transaction};
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_validator_rejects_before_insert() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.set_validator(|tx: &Transaction| {
+            if tx.amount == 0 {
+                return Err(ValidationError::new("amount must be non-zero"));
+            }
+            Ok(())
+        });
+
+        tree.try_insert(sample_tx("tx_1", 5)).unwrap();
+        let root = tree.merkle_root().to_string();
+        match tree.try_insert(sample_tx("tx_2", 0)) {
+            Err(CryptoTreeError::Rejected { id, reason }) => {
+                assert_eq!(id, "tx_2");
+                assert_eq!(reason.message(), "amount must be non-zero");
+            }
+            other => panic!("expected rejection, got {:?}", other),
+        }
+        assert_eq!(tree.merkle_root(), root);
+
+        let result = tree.insert_batch(vec![sample_tx("tx_3", 0), sample_tx("tx_4", 1)]);
+        assert_eq!(result.inserted, 1);
+        assert_eq!(result.failed[0].0, "tx_3");
+
+        assert!(tree.update("tx_1", |tx| tx.amount = 0).is_err());
+        assert_eq!(tree.search("tx_1").unwrap().amount, 5);
+
+        tree.clear_validator();
+        assert!(tree.insert(sample_tx("tx_2", 0)));
+    }
+
+    #[test]
+    fn test_validator_is_shared_by_clones() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.set_validator(|tx: &Transaction| {
+            tx.id.starts_with("tx_").then_some(()).ok_or_else(|| ValidationError::new("bad id"))
+        });
+        let mut copy = tree.clone();
+        assert!(!copy.insert(sample_tx("other", 1)));
+        assert!(copy.insert(sample_tx("tx_1", 1)));
+    }
+}
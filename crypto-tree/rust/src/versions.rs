@@ -0,0 +1,228 @@
+//! Reads against earlier versions of a tree.
+//!
+//! Node pages are copy-on-write, so keeping a version costs one shared
+//! reference to the arena; the writes after it copy the pages they touch,
+//! about `log n` pages of 64 nodes per write. [`CryptoBinaryTree::retain_versions`]
+//! keeps the last few versions this way, [`CryptoBinaryTree::view_at`]
+//! reads any of them by its Merkle root and [`CryptoBinaryTree::rollback_to`]
+//! or [`CryptoBinaryTree::undo`] make one current again.
+
+use alloc::collections::VecDeque;
+
+use serde::Serialize;
+
+use crate::arena::Arena;
+use crate::frozen::TreeSnapshot;
+use crate::prelude::*;
+use crate::{AuditOp, CryptoBinaryTree, CryptoTreeError, NodeId, Result, Sha256Hasher, Transaction, TreeHasher, TreeKey};
+
+/// A read-only view of the tree as it was when a given root was current
+///
+/// It is a [`TreeSnapshot`] of that version: `search`, `iter`, `traverse`
+/// and `get_proof_of_inclusion` answer exactly as the tree did then, and its
+/// proofs verify against that root.
+pub type TreeView<T = Transaction, H = Sha256Hasher> = TreeSnapshot<T, H>;
+
+/// The versions kept for `view_at`, oldest first
+#[derive(Debug, Clone)]
+pub(crate) struct Versions<T> {
+    keep: usize,
+    retained: VecDeque<Version<T>>,
+}
+
+#[derive(Debug, Clone)]
+struct Version<T> {
+    merkle_root: String,
+    root: Option<NodeId>,
+    nodes: Arena<T>,
+}
+
+impl<T> Versions<T> {
+    /// Keeps the version at `merkle_root` unless its hashes are pending or
+    /// it is the latest one already, dropping the oldest beyond `keep`.
+    pub(crate) fn record(&mut self, merkle_root: &str, root: Option<NodeId>, nodes: &Arena<T>) {
+        if merkle_root.is_empty() || self.retained.back().is_some_and(|v| v.merkle_root == merkle_root) {
+            return;
+        }
+        if self.retained.len() == self.keep {
+            self.retained.pop_front();
+        }
+        self.retained.push_back(Version {
+            merkle_root: merkle_root.to_string(),
+            root,
+            nodes: nodes.clone(),
+        });
+    }
+}
+
+impl<T: TreeKey, H> CryptoBinaryTree<T, H> {
+    /// Keeps the last `keep` versions of the tree, including the current
+    /// one, readable with `view_at`; 0 drops them all and stops keeping any.
+    ///
+    /// A version is taken whenever the root changes, or with lazy hashing
+    /// when `flush_hashes` computes it. Each write then copies the node
+    /// pages on its path instead of changing them in place, and a kept
+    /// version holds on to pages no later version shares.
+    pub fn retain_versions(&mut self, keep: usize) {
+        if keep == 0 {
+            self.versions = None;
+            return;
+        }
+        let versions = self.versions.get_or_insert_with(|| Versions {
+            keep,
+            retained: VecDeque::new(),
+        });
+        versions.keep = keep;
+        while versions.retained.len() > keep {
+            versions.retained.pop_front();
+        }
+        versions.record(&self.merkle_root, self.root, &self.nodes);
+    }
+
+    /// Merkle roots of the kept versions, oldest first
+    pub fn retained_roots(&self) -> impl Iterator<Item = &str> + '_ {
+        self.versions.iter().flat_map(|v| v.retained.iter().map(|v| v.merkle_root.as_str()))
+    }
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher + Clone> CryptoBinaryTree<T, H> {
+    /// The tree as it was when its Merkle root was `root_hash`, in O(1).
+    ///
+    /// Finds the current version and those kept with `retain_versions`;
+    /// `None` for any other root. If a root recurs, the latest version with
+    /// it is returned, which holds the same transactions as any earlier one.
+    pub fn view_at(&self, root_hash: &str) -> Option<TreeView<T, H>> {
+        if root_hash == self.merkle_root && !self.has_pending_hashes() {
+            return Some(self.snapshot());
+        }
+        let version = self.versions.as_ref()?.retained.iter().rev().find(|v| v.merkle_root == root_hash)?;
+        Some(self._frozen(version.root, version.nodes.clone(), version.merkle_root.clone()))
+    }
+}
+
+impl<T: TreeKey + Serialize + Clone, H: TreeHasher> CryptoBinaryTree<T, H> {
+    /// Returns the tree to the version `n` changes of the root back and
+    /// returns its root. `undo(0)` only flushes pending hashes.
+    ///
+    /// Only versions kept with `retain_versions` can be reached; asking for
+    /// more fails with `UnknownVersion` and changes nothing.
+    pub fn undo(&mut self, n: usize) -> Result<String> {
+        self.flush_hashes();
+        let retained = self.versions.as_ref().map_or(0, |v| v.retained.len());
+        let Some(target) = retained.checked_sub(n + 1) else {
+            return Err(CryptoTreeError::UnknownVersion(format!("{} versions back", n)));
+        };
+        self._restore_version(target);
+        Ok(self.merkle_root.clone())
+    }
+
+    /// Returns the tree to the latest kept version whose root is `root_hash`.
+    ///
+    /// The versions after it are dropped, so a later `undo` continues from
+    /// there. Fails with `UnknownVersion` if no kept version has that root.
+    /// Like `apply_delta` this bypasses the validator and policies; the index,
+    /// if enabled, is rebuilt.
+    pub fn rollback_to(&mut self, root_hash: &str) -> Result<String> {
+        self.flush_hashes();
+        let target = self
+            .versions
+            .as_ref()
+            .and_then(|v| v.retained.iter().rposition(|v| v.merkle_root == root_hash))
+            .ok_or_else(|| CryptoTreeError::UnknownVersion(root_hash.to_string()))?;
+        self._restore_version(target);
+        Ok(self.merkle_root.clone())
+    }
+
+    /// Makes kept version `i` current and drops those after it.
+    fn _restore_version(&mut self, i: usize) {
+        let Some(versions) = self.versions.as_mut() else { return };
+        versions.retained.truncate(i + 1);
+        let version = &versions.retained[i];
+        if version.merkle_root == self.merkle_root {
+            return;
+        }
+        self.root = version.root;
+        self.nodes = version.nodes.clone();
+        self._intern_all();
+        self._reindex();
+        self._update_merkle_root();
+        self._audit(AuditOp::Rollback, Vec::new());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{verify_proof, CryptoBinaryTree, CryptoTreeError};
+    use crate::test_util::sample_tx;
+
+    #[test]
+    fn test_view_at_earlier_roots() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.retain_versions(4);
+        let mut roots = vec![tree.merkle_root().to_string()];
+        for i in 0..200 {
+            tree.insert(sample_tx(&format!("tx_{:03}", i), 10));
+        }
+        roots.push(tree.merkle_root().to_string());
+        tree.update("tx_100", |tx| tx.amount = 99).unwrap();
+        roots.push(tree.merkle_root().to_string());
+        tree.remove("tx_050");
+        roots.push(tree.merkle_root().to_string());
+        tree.clear();
+
+        let view = tree.view_at(&roots[1]).unwrap();
+        assert_eq!(view.merkle_root(), roots[1]);
+        assert_eq!(view.len(), 200);
+        assert_eq!(view.search("tx_100").unwrap().amount, 10);
+        assert!(view.verify_integrity() && view.verify_invariants());
+        let proof = view.get_proof_of_inclusion("tx_050").unwrap();
+        assert!(verify_proof(&roots[1], view.search("tx_050").unwrap(), &proof));
+
+        let view = tree.view_at(&roots[3]).unwrap();
+        assert_eq!(view.search("tx_100").unwrap().amount, 99);
+        assert!(view.search("tx_050").is_none());
+        assert_eq!(view.iter().len(), 199);
+        assert!(view.iter().map(|tx| &tx.id).is_sorted());
+
+        // The empty tree is current again; the first empty version was dropped
+        assert_eq!(tree.retained_roots().count(), 4);
+        assert!(tree.view_at(&roots[0]).unwrap().is_empty());
+        assert!(tree.view_at("not a root").is_none());
+        tree.retain_versions(0);
+        assert!(tree.view_at(&roots[1]).is_none());
+    }
+
+    #[test]
+    fn test_undo_and_rollback() {
+        let mut tree = CryptoBinaryTree::new();
+        tree.enable_index();
+        for i in 0..20 {
+            tree.insert(sample_tx(&format!("tx_{:02}", i), 10));
+        }
+        tree.retain_versions(16);
+        let good = tree.merkle_root().to_string();
+        // A bad import, then a correction made on top of it
+        for i in 20..30 {
+            tree.insert(sample_tx(&format!("tx_{:02}", i), 1_000));
+        }
+        tree.update("tx_25", |tx| tx.amount = 5).unwrap();
+
+        let root = tree.undo(1).unwrap();
+        assert_eq!(tree.search("tx_25").unwrap().amount, 1_000);
+        assert_eq!(root, tree.merkle_root());
+        assert!(matches!(tree.undo(100), Err(CryptoTreeError::UnknownVersion(_))));
+        assert_eq!(tree.merkle_root(), root);
+
+        assert_eq!(tree.rollback_to(&good).unwrap(), good);
+        assert_eq!(tree.len(), 20);
+        assert!(tree.search("tx_25").is_none());
+        assert_eq!(tree.transactions_from("Alice").len(), 20);
+        assert!(tree.verify_integrity() && tree.verify_invariants());
+        assert!(tree.undo(1).is_err(), "versions after the rollback target were dropped");
+        assert!(matches!(tree.rollback_to(&root), Err(CryptoTreeError::UnknownVersion(_))));
+
+        // Writes after a rollback keep being versioned
+        tree.insert(sample_tx("tx_new", 1));
+        assert_eq!(tree.undo(1).unwrap(), good);
+    }
+}
@@ -0,0 +1,642 @@
+//! Write-ahead log of tree operations.
+//!
+//! A [`LoggedTree`] appends every successful insert, removal and update to a
+//! log in a directory before returning, and replays the log when reopened,
+//! so a crash loses no acknowledged operation and the tree, root included,
+//! comes back exactly. The log is split into numbered segment files,
+//! `<seq>.wal`, rotated once they pass a size limit;
+//! [`compact_to_snapshot`](LoggedTree::compact_to_snapshot) folds them into a
+//! `<seq>-<root>.snap` snapshot that later replays start from, and the
+//! `checkpoint` module keeps several such snapshots around.
+//!
+//! Each record is framed as a `u32` length and the CRC-32 of the payload,
+//! both little-endian, then the JSON payload. A torn record at the end of
+//! the newest segment, as left by a crash mid-append, is cut off on open;
+//! damage anywhere else fails with [`WalError::Corrupted`]. A log opened with
+//! [`open_encrypted`](LoggedTree::open_encrypted) seals each payload with the
+//! caller's [`AtRestCipher`] before framing it, and writes its snapshots
+//! encrypted too.
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::checkpoint::{Checkpoint, CheckpointPolicy};
+use crate::encrypt::Cipher;
+use crate::{key_string, AtRestCipher, CryptoBinaryTree, CryptoTreeError, Result, Sha256Hasher, SnapshotError, Transaction, TreeHasher, TreeKey};
+
+const SEGMENT_EXT: &str = "wal";
+const SNAPSHOT_EXT: &str = "snap";
+
+/// Error raised while writing or replaying a write-ahead log
+#[derive(Debug)]
+pub enum WalError {
+    Io(io::Error),
+    /// A record of `segment` starting at byte `offset` is damaged or does not
+    /// apply to the tree replayed so far
+    Corrupted { segment: u64, offset: u64, reason: String },
+    /// The snapshot replay starts from could not be read
+    Snapshot(SnapshotError),
+    /// No retained checkpoint or logged operation produced this root
+    UnknownRoot(String),
+}
+
+impl fmt::Display for WalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalError::Io(e) => write!(f, "write-ahead log I/O error: {}", e),
+            WalError::Corrupted { segment, offset, reason } => {
+                write!(f, "corrupted write-ahead log segment {} at byte {}: {}", segment, offset, reason)
+            }
+            WalError::Snapshot(e) => write!(f, "write-ahead log snapshot: {}", e),
+            WalError::UnknownRoot(root) => write!(f, "root {} is not in the retained log", root),
+        }
+    }
+}
+
+impl std::error::Error for WalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WalError::Io(e) => Some(e),
+            WalError::Snapshot(e) => Some(e),
+            WalError::Corrupted { .. } | WalError::UnknownRoot(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for WalError {
+    fn from(e: io::Error) -> Self {
+        WalError::Io(e)
+    }
+}
+
+/// How a [`LoggedTree`] writes its log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalOptions {
+    /// Size after which the next record starts a new segment
+    pub max_segment_bytes: u64,
+    /// Sync every record to disk before returning; without it a crash of the
+    /// machine, as opposed to the process, may lose the latest records
+    pub sync: bool,
+    /// When to checkpoint automatically and how many checkpoints to keep
+    pub checkpoints: CheckpointPolicy,
+}
+
+impl Default for WalOptions {
+    fn default() -> Self {
+        WalOptions {
+            max_segment_bytes: 64 << 20,
+            sync: true,
+            checkpoints: CheckpointPolicy::default(),
+        }
+    }
+}
+
+/// One logged operation; written borrowed and read owned
+#[derive(Serialize, Deserialize)]
+enum Record<T, K> {
+    Insert(T),
+    Remove(K),
+    /// The transaction as stored after the update
+    Update(T),
+}
+
+/// CRC-32 (IEEE) lookup table
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes
+        .iter()
+        .fold(!0u32, |crc, &b| CRC_TABLE[((crc ^ u32::from(b)) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+/// A tree whose operations are recorded in a write-ahead log
+///
+/// Only the operations go to the log; index, validator, policies and lazy
+/// hashing are not restored on replay, and a replayed operation that the
+/// reopened tree rejects fails the open as `Corrupted`. Each operation is
+/// checked, then its record written (and synced, with `WalOptions::sync`),
+/// and only then applied, so one whose append fails leaves the tree as the
+/// log has it.
+#[derive(Debug)]
+pub struct LoggedTree<T: TreeKey = Transaction, H = Sha256Hasher> {
+    pub(crate) tree: CryptoBinaryTree<T, H>,
+    pub(crate) dir: PathBuf,
+    pub(crate) options: WalOptions,
+    /// Number of the segment being appended to, its file and bytes written
+    pub(crate) segment: u64,
+    file: File,
+    written: u64,
+    /// Operations logged since the last checkpoint, and when it was taken
+    pub(crate) pending_ops: u64,
+    pub(crate) checkpointed: Option<Instant>,
+    /// Seals records and snapshots, if the log is encrypted
+    pub(crate) cipher: Option<Cipher>,
+}
+
+fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", seq, SEGMENT_EXT))
+}
+
+/// Snapshots are named after the segment that follows them and their root
+pub(crate) fn snapshot_path(dir: &Path, checkpoint: &Checkpoint) -> PathBuf {
+    dir.join(format!("{:020}-{}.{}", checkpoint.seq, checkpoint.root, SNAPSHOT_EXT))
+}
+
+/// Segments and snapshots in `dir`, each by ascending sequence number
+pub(crate) fn list(dir: &Path) -> io::Result<(Vec<u64>, Vec<Checkpoint>)> {
+    let (mut segments, mut snapshots) = (Vec::new(), Vec::new());
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(SEGMENT_EXT) => segments.extend(stem.parse::<u64>().ok()),
+            Some(SNAPSHOT_EXT) => {
+                if let Some((seq, root)) = stem.split_once('-') {
+                    snapshots.extend(seq.parse().ok().map(|seq| Checkpoint {
+                        seq,
+                        root: root.to_string(),
+                    }));
+                }
+            }
+            _ => {}
+        }
+    }
+    segments.sort_unstable();
+    snapshots.sort_unstable_by_key(|c| c.seq);
+    Ok((segments, snapshots))
+}
+
+impl<T> LoggedTree<T>
+where
+    T: TreeKey + Serialize + DeserializeOwned + Clone,
+    T::Key: DeserializeOwned,
+{
+    /// Opens or creates the SHA-256 tree logged in `dir`.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        Self::open_with(dir, Sha256Hasher::default(), WalOptions::default())
+    }
+}
+
+impl<T, H> LoggedTree<T, H>
+where
+    T: TreeKey + Serialize + DeserializeOwned + Clone,
+    T::Key: DeserializeOwned,
+    H: TreeHasher,
+{
+    /// Opens or creates the tree logged in `dir`, replaying the newest
+    /// snapshot and every segment written after it.
+    ///
+    /// New records go to a fresh segment. Fails with `CryptoTreeError::Wal` if
+    /// the log cannot be read or is damaged.
+    pub fn open_with<P: AsRef<Path>>(dir: P, hasher: H, options: WalOptions) -> Result<Self> {
+        Self::_open(dir.as_ref(), hasher, options, None)
+    }
+
+    /// Like [`open_with`](Self::open_with), for a log whose records and
+    /// snapshots are encrypted with `cipher`.
+    ///
+    /// A log must always be opened with the cipher it was written with;
+    /// records the cipher cannot decrypt fail the open as `Corrupted`.
+    pub fn open_encrypted<P: AsRef<Path>>(dir: P, hasher: H, options: WalOptions, cipher: Arc<dyn AtRestCipher>) -> Result<Self> {
+        Self::_open(dir.as_ref(), hasher, options, Some(Cipher(cipher)))
+    }
+
+    fn _open(dir: &Path, hasher: H, options: WalOptions, cipher: Option<Cipher>) -> Result<Self> {
+        let dir = dir.to_path_buf();
+        fs::create_dir_all(&dir).map_err(WalError::from)?;
+        let (tree, segment) = restore(&dir, hasher, true, cipher.as_ref().map(|c| &*c.0))?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(segment_path(&dir, segment))
+            .map_err(WalError::from)?;
+        Ok(LoggedTree {
+            tree,
+            dir,
+            segment,
+            file,
+            written: 0,
+            pending_ops: 0,
+            checkpointed: options.checkpoints.every.map(|_| Instant::now()),
+            options,
+            cipher,
+        })
+    }
+
+    /// The tree as of the last logged operation
+    pub fn tree(&self) -> &CryptoBinaryTree<T, H> {
+        &self.tree
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(crate) fn _cipher(&self) -> Option<&dyn AtRestCipher> {
+        self.cipher.as_ref().map(|c| &*c.0)
+    }
+
+    /// Inserts a transaction as `CryptoBinaryTree::try_insert` does, logging it first.
+    pub fn try_insert(&mut self, transaction: T) -> Result<()> {
+        self.tree._check_insert(&transaction)?;
+        self.append(&Record::<&T, &T::Key>::Insert(&transaction))?;
+        self.tree.try_insert(transaction)?;
+        self.logged()
+    }
+
+    /// Logs a removal and removes the transaction; `Ok(None)` if the id is
+    /// not stored, which is not logged.
+    pub fn remove<Q>(&mut self, tx_id: &Q) -> Result<Option<T>>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + Serialize + ?Sized,
+    {
+        let Some(stored) = self.tree.search(tx_id) else {
+            return Ok(None);
+        };
+        let key = stored.key().clone();
+        self.append(&Record::<&T, &T::Key>::Remove(&key))?;
+        let removed = self.tree.remove(tx_id);
+        self.logged()?;
+        Ok(removed)
+    }
+
+    /// Updates a transaction as `CryptoBinaryTree::update` does, logging the
+    /// result first, and returns the new Merkle root.
+    pub fn update<Q>(&mut self, tx_id: &Q, f: impl FnOnce(&mut T)) -> Result<String>
+    where
+        T::Key: Borrow<Q>,
+        Q: Ord + Serialize + ?Sized,
+    {
+        let mut updated = self.tree.search(tx_id).ok_or_else(|| CryptoTreeError::NotFound(key_string(tx_id)))?.clone();
+        f(&mut updated);
+        self.tree._check_update(tx_id, &updated)?;
+        self.append(&Record::<&T, &T::Key>::Update(&updated))?;
+        let root = self.tree.update(tx_id, move |stored| *stored = updated)?;
+        self.logged()?;
+        Ok(root)
+    }
+
+    /// Writes one framed record, syncing it with `WalOptions::sync`. A record
+    /// that fails partway is cut off again, so the next one follows the last
+    /// complete record.
+    fn append<K: Serialize>(&mut self, record: &Record<&T, K>) -> Result<()> {
+        let frame = frame(record, self._cipher())?;
+        let written = self.file.write_all(&frame).and_then(|()| if self.options.sync { self.file.sync_data() } else { Ok(()) });
+        if let Err(e) = written {
+            let _ = self.file.set_len(self.written);
+            return Err(WalError::from(e).into());
+        }
+        self.written += frame.len() as u64;
+        Ok(())
+    }
+
+    /// Counts an applied operation, rotating to a new segment past the size
+    /// limit and checkpointing when due.
+    fn logged(&mut self) -> Result<()> {
+        if self.written >= self.options.max_segment_bytes {
+            self.rotate()?;
+        }
+        self.pending_ops += 1;
+        if self._checkpoint_due() {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// Closes the current segment and starts the next one.
+    pub fn rotate(&mut self) -> Result<()> {
+        let next = self.segment + 1;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(segment_path(&self.dir, next))
+            .map_err(WalError::from)?;
+        self.segment = next;
+        self.written = 0;
+        Ok(())
+    }
+
+    /// Writes a snapshot of the tree and deletes the segments and older
+    /// snapshots it replaces.
+    ///
+    /// The snapshot is named after the segment that follows it and only
+    /// appears once complete, so a crash at any point leaves a log that
+    /// replays to the same tree.
+    pub fn compact_to_snapshot(&mut self) -> Result<()> {
+        self._write_checkpoint()?;
+        self._prune(1)
+    }
+
+    /// Snapshots the tree as of the end of the current segment, which is
+    /// closed, and returns the checkpoint.
+    pub(crate) fn _write_checkpoint(&mut self) -> Result<Checkpoint> {
+        self.rotate()?;
+        let checkpoint = Checkpoint {
+            seq: self.segment,
+            root: self.tree.merkle_root().to_string(),
+        };
+        let path = snapshot_path(&self.dir, &checkpoint);
+        let partial = path.with_extension("partial");
+        match self._cipher() {
+            Some(cipher) => self.tree.save_encrypted(&partial, cipher)?,
+            None => self.tree.save(&partial)?,
+        }
+        File::open(&partial).and_then(|f| f.sync_all()).map_err(WalError::from)?;
+        fs::rename(&partial, &path).map_err(WalError::from)?;
+        self.pending_ops = 0;
+        self.checkpointed = self.options.checkpoints.every.map(|_| Instant::now());
+        Ok(checkpoint)
+    }
+
+    /// Deletes all but the newest `keep` snapshots, and the segments that
+    /// only the deleted ones needed.
+    pub(crate) fn _prune(&self, keep: usize) -> Result<()> {
+        let (segments, snapshots) = list(&self.dir).map_err(WalError::from)?;
+        let Some(oldest) = snapshots.len().checked_sub(keep.max(1)).map(|i| snapshots[i].seq) else {
+            return Ok(());
+        };
+        for seq in segments.into_iter().filter(|&seq| seq < oldest) {
+            fs::remove_file(segment_path(&self.dir, seq)).map_err(WalError::from)?;
+        }
+        for checkpoint in snapshots.iter().filter(|c| c.seq < oldest) {
+            fs::remove_file(snapshot_path(&self.dir, checkpoint)).map_err(WalError::from)?;
+        }
+        Ok(())
+    }
+}
+
+/// Loads the newest snapshot in `dir` and replays the segments after it,
+/// returning the tree and the number of the next segment. With `repair`, a
+/// torn record at the end of the log is truncated away.
+pub(crate) fn restore<T, H>(
+    dir: &Path,
+    hasher: H,
+    repair: bool,
+    cipher: Option<&dyn AtRestCipher>,
+) -> Result<(CryptoBinaryTree<T, H>, u64)>
+where
+    T: TreeKey + Serialize + DeserializeOwned + Clone,
+    T::Key: DeserializeOwned,
+    H: TreeHasher,
+{
+    let (segments, snapshots) = list(dir).map_err(WalError::from)?;
+    let start = snapshots.last().map_or(0, |c| c.seq);
+    let mut tree = match snapshots.last() {
+        Some(checkpoint) => load_checkpoint(dir, checkpoint, hasher, cipher)?,
+        None => CryptoBinaryTree::with_hasher(hasher),
+    };
+    let replayed: Vec<u64> = segments.into_iter().filter(|&seq| seq >= start).collect();
+    for (i, &seq) in replayed.iter().enumerate() {
+        replay(&mut tree, dir, seq, i + 1 == replayed.len(), repair, cipher, &mut |_| false)?;
+    }
+    Ok((tree, replayed.last().map_or(start, |seq| seq + 1)))
+}
+
+pub(crate) fn load_checkpoint<T, H>(
+    dir: &Path,
+    checkpoint: &Checkpoint,
+    hasher: H,
+    cipher: Option<&dyn AtRestCipher>,
+) -> Result<CryptoBinaryTree<T, H>>
+where
+    T: TreeKey + Serialize + DeserializeOwned + Clone,
+    H: TreeHasher,
+{
+    let path = snapshot_path(dir, checkpoint);
+    let loaded = match cipher {
+        Some(cipher) => CryptoBinaryTree::load_encrypted_with_hasher(path, hasher, cipher),
+        None => CryptoBinaryTree::load_with_hasher(path, hasher),
+    };
+    Ok(loaded.map_err(WalError::Snapshot)?)
+}
+
+/// Encodes `record`, sealed with `cipher` if given, with its length and checksum.
+fn frame<T: Serialize, K: Serialize>(record: &Record<T, K>, cipher: Option<&dyn AtRestCipher>) -> Result<Vec<u8>> {
+    let mut payload = serde_json::to_vec(record).map_err(|e| CryptoTreeError::SerializationFailed(e.to_string()))?;
+    if let Some(cipher) = cipher {
+        payload = cipher.seal(&payload);
+    }
+    let mut frame = Vec::with_capacity(8 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32(&payload).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// Applies the records of segment `seq` to `tree` until `stop` holds after
+/// one, returning whether it did. In the newest segment a torn final record
+/// ends the replay instead of failing it, and is truncated away with `repair`.
+pub(crate) fn replay<T, H>(
+    tree: &mut CryptoBinaryTree<T, H>,
+    dir: &Path,
+    seq: u64,
+    newest: bool,
+    repair: bool,
+    cipher: Option<&dyn AtRestCipher>,
+    stop: &mut dyn FnMut(&CryptoBinaryTree<T, H>) -> bool,
+) -> Result<bool>
+where
+    T: TreeKey + Serialize + DeserializeOwned + Clone,
+    T::Key: DeserializeOwned,
+    H: TreeHasher,
+{
+    let path = segment_path(dir, seq);
+    let bytes = fs::read(&path).map_err(WalError::from)?;
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let corrupted = |reason: String| WalError::Corrupted {
+            segment: seq,
+            offset: offset as u64,
+            reason,
+        };
+        let rest = &bytes[offset..];
+        let record = rest.get(..8).and_then(|header| {
+            let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+            let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+            rest.get(8..8 + len).filter(|payload| crc32(payload) == crc)
+        });
+        let Some(payload) = record else {
+            // Only the record being written when the process died may be damaged
+            if newest && damaged_tail(rest) {
+                if repair {
+                    let file = OpenOptions::new().write(true).open(&path).map_err(WalError::from)?;
+                    file.set_len(offset as u64).map_err(WalError::from)?;
+                }
+                return Ok(false);
+            }
+            return Err(corrupted("bad length or checksum".into()).into());
+        };
+        let opened;
+        let json = match cipher {
+            Some(cipher) => {
+                opened = cipher.open(payload).ok_or_else(|| corrupted("record could not be decrypted".into()))?;
+                &opened
+            }
+            None => payload,
+        };
+        let record: Record<T, T::Key> =
+            serde_json::from_slice(json).map_err(|e| corrupted(format!("undecodable record: {}", e)))?;
+        let applied = match record {
+            Record::Insert(tx) => tree.try_insert(tx),
+            Record::Remove(key) => match tree.remove(&key) {
+                Some(_) => Ok(()),
+                None => Err(CryptoTreeError::NotFound(crate::key_string(&key))),
+            },
+            Record::Update(tx) => {
+                let key = tx.key().clone();
+                tree.update(&key, |stored| *stored = tx).map(|_| ())
+            }
+        };
+        applied.map_err(|e| corrupted(format!("record does not apply: {}", e)))?;
+        offset += 8 + payload.len();
+        if stop(tree) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Whether `rest` could be one record cut short or garbled by a torn write:
+/// its header, if complete, claims at least everything that is left.
+fn damaged_tail(rest: &[u8]) -> bool {
+    match rest.get(..4) {
+        Some(len) => 8 + u32::from_le_bytes(len.try_into().unwrap()) as usize >= rest.len(),
+        None => true,
+    }
+}
+
+impl From<WalError> for CryptoTreeError {
+    fn from(e: WalError) -> Self {
+        CryptoTreeError::Wal(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{sample_tx, temp_path};
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_replay_restores_tree() {
+        let dir = temp_path("wal_replay");
+        let options = WalOptions {
+            max_segment_bytes: 512,
+            sync: false,
+            ..Default::default()
+        };
+        let mut log: LoggedTree = LoggedTree::open_with(&dir, Sha256Hasher::new(), options).unwrap();
+        for i in 0..20 {
+            log.try_insert(sample_tx(&format!("tx_{:02}", i), 10)).unwrap();
+        }
+        assert!(log.try_insert(sample_tx("tx_03", 1)).is_err());
+        log.remove("tx_05").unwrap().unwrap();
+        assert!(log.remove("tx_05").unwrap().is_none());
+        log.update("tx_07", |tx| tx.amount = 70).unwrap();
+        let root = log.tree().merkle_root().to_string();
+        drop(log);
+        assert!(list(&dir).unwrap().0.len() > 2, "small segments rotate");
+
+        // A crash mid-append leaves a torn record behind
+        let (segments, _) = list(&dir).unwrap();
+        let mut last = OpenOptions::new().append(true).open(segment_path(&dir, *segments.last().unwrap())).unwrap();
+        last.write_all(&[200, 0, 0, 0, 1, 2]).unwrap();
+
+        let log: LoggedTree = LoggedTree::open(&dir).unwrap();
+        assert_eq!(log.tree().merkle_root(), root);
+        assert_eq!(log.tree().len(), 19);
+        assert_eq!(log.tree().search("tx_07").unwrap().amount, 70);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compact_and_corruption() {
+        let dir = temp_path("wal_compact");
+        let mut log: LoggedTree = LoggedTree::open(&dir).unwrap();
+        for i in 0..10 {
+            log.try_insert(sample_tx(&format!("tx_{}", i), 10)).unwrap();
+        }
+        log.compact_to_snapshot().unwrap();
+        log.try_insert(sample_tx("tx_after", 1)).unwrap();
+        let root = log.tree().merkle_root().to_string();
+        drop(log);
+        let (segments, snapshots) = list(&dir).unwrap();
+        assert_eq!((segments.len(), snapshots.len()), (1, 1));
+
+        let log: LoggedTree = LoggedTree::open(&dir).unwrap();
+        assert_eq!(log.tree().merkle_root(), root);
+        let segment = segment_path(&dir, log.segment - 1);
+        drop(log);
+
+        // Flipping a payload byte of a complete record is not a torn write
+        let mut bytes = fs::read(&segment).unwrap();
+        bytes[10] ^= 1;
+        bytes.extend_from_slice(&bytes.clone());
+        fs::write(&segment, bytes).unwrap();
+        assert!(matches!(
+            LoggedTree::<Transaction>::open(&dir),
+            Err(CryptoTreeError::Wal(WalError::Corrupted { offset: 0, .. }))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_append_leaves_tree_unchanged() {
+        let dir = temp_path("wal_failed_append");
+        let mut log: LoggedTree = LoggedTree::open(&dir).unwrap();
+        log.try_insert(sample_tx("tx_1", 10)).unwrap();
+        log.try_insert(sample_tx("tx_2", 20)).unwrap();
+        let root = log.tree().merkle_root().to_string();
+
+        // A handle that cannot be written to makes every append fail
+        let path = segment_path(&dir, log.segment);
+        let writable = std::mem::replace(&mut log.file, File::open(&path).unwrap());
+        assert!(matches!(log.try_insert(sample_tx("tx_3", 30)), Err(CryptoTreeError::Wal(WalError::Io(_)))));
+        assert!(log.remove("tx_1").is_err());
+        assert!(log.update("tx_2", |tx| tx.amount = 21).is_err());
+        assert_eq!(log.tree().len(), 2);
+        assert_eq!(log.tree().merkle_root(), root);
+        assert_eq!(log.tree().search("tx_2").unwrap().amount, 20);
+
+        // Rejected operations are not logged either
+        log.file = writable;
+        assert!(log.try_insert(sample_tx("tx_1", 1)).is_err());
+        assert!(log.update("tx_2", |tx| tx.id = "tx_9".to_string()).is_err());
+        log.update("tx_2", |tx| tx.amount = 22).unwrap();
+        let root = log.tree().merkle_root().to_string();
+        drop(log);
+
+        let log: LoggedTree = LoggedTree::open(&dir).unwrap();
+        assert_eq!(log.tree().merkle_root(), root);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
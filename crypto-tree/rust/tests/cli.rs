@@ -0,0 +1,109 @@
+#![cfg(feature = "cli")]
+
+use std::path::PathBuf;
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+struct Workdir(PathBuf);
+
+impl Workdir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("crypto_tree_cli_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Workdir(dir)
+    }
+
+    fn cmd(&self) -> Command {
+        let mut cmd = Command::cargo_bin("crypto-tree").unwrap();
+        cmd.current_dir(&self.0);
+        cmd
+    }
+}
+
+impl Drop for Workdir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+const NDJSON: &str = r#"{"id":"tx_2","from":"A","to":"B","amount":20,"timestamp":null}
+{"id":"tx_1","from":"A","to":"B","amount":10,"timestamp":null}
+
+{"id":"tx_3","from":"B","to":"C","amount":5,"timestamp":1700000000}
+"#;
+
+#[test]
+fn test_insert_search_and_root() {
+    let dir = Workdir::new("insert");
+    dir.cmd().arg("insert").write_stdin(NDJSON).assert().success().stdout("inserted 3, duplicates 0\n");
+    dir.cmd()
+        .args(["insert", "-"])
+        .write_stdin(r#"[{"id":"tx_1","from":"A","to":"B","amount":10,"timestamp":null}]"#)
+        .assert()
+        .success()
+        .stdout("inserted 0, duplicates 1\n");
+
+    dir.cmd().args(["search", "tx_3"]).assert().success().stdout(predicate::str::contains(r#""to":"C""#));
+    dir.cmd().args(["search", "nope"]).assert().code(2).stderr(predicate::str::contains("not found"));
+    dir.cmd().arg("root").assert().success().stdout(predicate::str::is_match("^[0-9a-f]{64}\n$").unwrap());
+    dir.cmd().arg("verify").assert().success().stdout(predicate::str::starts_with("ok: 3 transactions"));
+}
+
+#[test]
+fn test_proof_round_trip() {
+    let dir = Workdir::new("proof");
+    dir.cmd().arg("insert").write_stdin(NDJSON).assert().success();
+    let root = String::from_utf8(dir.cmd().arg("root").output().unwrap().stdout).unwrap();
+    let proof = dir.cmd().args(["proof", "tx_2"]).output().unwrap().stdout;
+
+    dir.cmd()
+        .args(["verify-proof", "--root", root.trim()])
+        .write_stdin(proof.clone())
+        .assert()
+        .success()
+        .stdout("valid\n");
+    dir.cmd()
+        .args(["verify-proof", "--root", &"0".repeat(64)])
+        .write_stdin(proof)
+        .assert()
+        .code(1)
+        .stdout("invalid\n");
+}
+
+#[test]
+fn test_export_import() {
+    let dir = Workdir::new("export");
+    dir.cmd().arg("insert").write_stdin(NDJSON).assert().success();
+    let root = dir.cmd().arg("root").output().unwrap().stdout;
+    dir.cmd().args(["export", "dump.ndjson"]).assert().success();
+
+    dir.cmd()
+        .args(["--tree", "copy.snap", "import", "dump.ndjson"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("imported 3"));
+    dir.cmd().args(["--tree", "copy.snap", "root"]).assert().success().stdout(root);
+}
+
+#[test]
+fn test_verify_detects_corruption() {
+    let dir = Workdir::new("verify");
+    dir.cmd().arg("insert").write_stdin(NDJSON).assert().success();
+    let path = dir.0.join("crypto-tree.snap");
+    let mut bytes = std::fs::read(&path).unwrap();
+    // Change tx_2's amount from 20 to 21 without touching its stored hash
+    let at = bytes.windows(11).position(|w| w == b"\"amount\":20").unwrap();
+    bytes[at + 10] = b'1';
+    std::fs::write(&path, bytes).unwrap();
+    dir.cmd().arg("verify").assert().code(1).stdout(predicate::str::starts_with("corrupted"));
+}
+
+#[test]
+fn test_usage_errors() {
+    let dir = Workdir::new("usage");
+    dir.cmd().assert().code(2).stderr(predicate::str::contains("missing command"));
+    dir.cmd().arg("frobnicate").assert().code(2).stderr(predicate::str::contains("unknown command"));
+    dir.cmd().arg("--help").assert().success().stdout(predicate::str::contains("verify-proof"));
+    dir.cmd().arg("root").assert().code(2);
+}
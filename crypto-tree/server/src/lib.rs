@@ -0,0 +1,257 @@
+//! HTTP API for a shared [`TransactionTree`].
+//!
+//! | Route | |
+//! |---|---|
+//! | `POST /transactions` | insert a JSON transaction, `201` with the new root |
+//! | `GET /transactions/{id}` | the stored transaction |
+//! | `GET /proof/{id}` | a self-contained [`Proof`](crypto_tree::Proof) |
+//! | `GET /root` | Merkle root and size |
+//! | `GET /verify` | recompute every hash |
+//! | `GET /metrics` | operation counters in the Prometheus text format |
+//!
+//! Errors are returned as `{"error": "..."}` with a matching status code.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use crypto_tree::{
+    CryptoTreeError, Transaction, TransactionTree, TreeMetrics, TreeObserver, TreeReader, TreeSnapshot, TreeWriter,
+};
+use serde_json::{json, Value};
+
+/// A tree shared between request handlers.
+///
+/// Lookups never lock: they load the latest published [`TreeSnapshot`] and
+/// run concurrently with each other and with writes. Inserts take the writer
+/// lock, and readers see them once the write returns. Handlers never hold
+/// the lock across an `.await`. The tree reports its operations to the
+/// [`TreeMetrics`] served at `/metrics`.
+#[derive(Clone)]
+pub struct SharedTree {
+    writer: Arc<Mutex<TreeWriter>>,
+    reader: TreeReader,
+    metrics: Arc<TreeMetrics>,
+}
+
+impl Default for SharedTree {
+    fn default() -> Self {
+        Self::new(TransactionTree::new())
+    }
+}
+
+impl SharedTree {
+    /// Shares `tree`, replacing its observer with the server's metrics.
+    pub fn new(mut tree: TransactionTree) -> Self {
+        let metrics = Arc::new(TreeMetrics::new());
+        tree.set_observer(metrics.clone());
+        let writer = TreeWriter::new(tree);
+        SharedTree {
+            reader: writer.reader(),
+            writer: Arc::new(Mutex::new(writer)),
+            metrics,
+        }
+    }
+
+    /// Reports the tree's operations to `observer` as well as to the metrics.
+    pub fn observe(&self, observer: impl TreeObserver + 'static) {
+        let observer = Arc::new((self.metrics.clone(), observer));
+        self.write(|tree| tree.set_observer(observer));
+    }
+
+    pub fn metrics(&self) -> &TreeMetrics {
+        &self.metrics
+    }
+
+    /// The tree as of the last completed write, without locking
+    pub fn read(&self) -> Arc<TreeSnapshot> {
+        self.reader.load()
+    }
+
+    /// Runs `f` on the tree under the writer lock and publishes the result.
+    ///
+    /// A panic in another handler cannot leave the tree half-updated, so a
+    /// poisoned lock is simply taken over.
+    pub fn write<R>(&self, f: impl FnOnce(&mut TransactionTree) -> R) -> R {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner).write(f)
+    }
+}
+
+/// An error response with a JSON body
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(id: &str) -> Self {
+        ApiError::new(StatusCode::NOT_FOUND, format!("transaction {} not found", id))
+    }
+}
+
+impl From<CryptoTreeError> for ApiError {
+    fn from(e: CryptoTreeError) -> Self {
+        let status = match e {
+            CryptoTreeError::DuplicateId(_) => StatusCode::CONFLICT,
+            CryptoTreeError::NotFound(_) => StatusCode::NOT_FOUND,
+            CryptoTreeError::SerializationFailed(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+        ApiError::new(status, e.to_string())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(e: JsonRejection) -> Self {
+        ApiError::new(e.status(), e.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// Builds the router serving `tree`.
+pub fn router(tree: SharedTree) -> Router {
+    Router::new()
+        .route("/transactions", post(insert))
+        .route("/transactions/{id}", get(search))
+        .route("/proof/{id}", get(proof))
+        .route("/root", get(root))
+        .route("/verify", get(verify))
+        .route("/metrics", get(metrics))
+        .with_state(tree)
+}
+
+async fn insert(
+    State(tree): State<SharedTree>,
+    body: Result<Json<Transaction>, JsonRejection>,
+) -> ApiResult<(StatusCode, Json<Value>)> {
+    let Json(tx) = body?;
+    let id = tx.id.clone();
+    let root = tree.write(|tree| tree.try_insert(tx).map(|()| tree.merkle_root().to_string()))?;
+    Ok((StatusCode::CREATED, Json(json!({ "id": id, "root": root }))))
+}
+
+async fn search(State(tree): State<SharedTree>, Path(id): Path<String>) -> ApiResult<Json<Transaction>> {
+    let tree = tree.read();
+    let tx = tree.search(id.as_str()).ok_or_else(|| ApiError::not_found(&id))?;
+    Ok(Json(tx.clone()))
+}
+
+async fn proof(State(tree): State<SharedTree>, Path(id): Path<String>) -> ApiResult<Json<Value>> {
+    let proof = tree.read().get_proof(id.as_str()).ok_or_else(|| ApiError::not_found(&id))?;
+    let body = serde_json::to_value(proof).map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(body))
+}
+
+async fn root(State(tree): State<SharedTree>) -> Json<Value> {
+    let tree = tree.read();
+    Json(json!({ "root": tree.merkle_root(), "size": tree.len() }))
+}
+
+async fn verify(State(tree): State<SharedTree>) -> ApiResult<Json<Value>> {
+    let tree = tree.read();
+    tree.check_integrity()
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(json!({ "ok": true, "root": tree.merkle_root(), "size": tree.len() })))
+}
+
+async fn metrics(State(tree): State<SharedTree>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        tree.metrics().render_prometheus(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    async fn call(app: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_insert_search_proof() {
+        let tree = SharedTree::default();
+        let app = router(tree.clone());
+        let tx = r#"{"id":"tx_1","from":"A","to":"B","amount":10,"timestamp":null}"#;
+
+        let (status, body) = call(&app, "POST", "/transactions", tx).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["root"], tree.read().merkle_root());
+
+        let (status, body) = call(&app, "POST", "/transactions", tx).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body["error"].as_str().unwrap().contains("duplicate"));
+
+        let (status, body) = call(&app, "GET", "/transactions/tx_1", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["amount"], 10);
+
+        let (status, body) = call(&app, "GET", "/proof/tx_1", "").await;
+        assert_eq!(status, StatusCode::OK);
+        let proof: crypto_tree::Proof = serde_json::from_value(body).unwrap();
+        assert!(proof.verify());
+
+        let (status, body) = call(&app, "GET", "/verify", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["size"], 1);
+
+        let request = Request::builder().uri("/metrics").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(text.contains("crypto_tree_inserts_total 1\n"));
+        assert!(text.contains("crypto_tree_duplicates_rejected_total 1\n"));
+        assert!(text.contains("crypto_tree_proofs_generated_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_json_errors() {
+        let app = router(SharedTree::default());
+
+        let (status, body) = call(&app, "GET", "/transactions/missing", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body["error"].is_string());
+
+        let (status, body) = call(&app, "POST", "/transactions", "{not json").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].is_string());
+
+        let (status, body) = call(&app, "GET", "/root", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["root"], "0");
+    }
+}
@@ -0,0 +1,61 @@
+//! Serves a tree over HTTP.
+//!
+//! Usage: `crypto-tree-server [SNAPSHOT]`. The listen address is taken from
+//! `CRYPTO_TREE_ADDR` (default `127.0.0.1:8080`). When a snapshot path is
+//! given the tree is loaded from it at startup and written back on Ctrl-C.
+//! Logs go to stderr, filtered by `RUST_LOG` (default `info`).
+
+use std::process::ExitCode;
+
+use crypto_tree::{TracingObserver, TransactionTree};
+use crypto_tree_server::{router, SharedTree};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with_writer(std::io::stderr)
+        .init();
+
+    let snapshot = std::env::args().nth(1);
+    let tree = match &snapshot {
+        Some(path) if std::path::Path::new(path).exists() => match TransactionTree::load(path) {
+            Ok(tree) => tree,
+            Err(e) => {
+                error!(path = %path, error = %e, "cannot load snapshot");
+                return ExitCode::FAILURE;
+            }
+        },
+        _ => TransactionTree::new(),
+    };
+    let tree = SharedTree::new(tree);
+    tree.observe(TracingObserver::new());
+
+    let addr = std::env::var("CRYPTO_TREE_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(addr = %addr, error = %e, "cannot listen");
+            return ExitCode::FAILURE;
+        }
+    };
+    info!(addr = %addr, "listening");
+
+    let shutdown = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    if let Err(e) = axum::serve(listener, router(tree.clone())).with_graceful_shutdown(shutdown).await {
+        error!(error = %e, "server failed");
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(path) = snapshot {
+        if let Err(e) = tree.read().save(&path) {
+            error!(path = %path, error = %e, "cannot save snapshot");
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
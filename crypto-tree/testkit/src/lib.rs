@@ -0,0 +1,150 @@
+//! Test support for the `crypto-tree` Merkle AVL tree.
+//!
+//! [`Op`] is one step of a random workload, applied both to a tree and to a
+//! plain `BTreeMap` model so the two can be compared, and [`check_shape`]
+//! checks the invariants its hashes cannot show. Generators live behind
+//! features: `proptest` adds the strategies in [`strategy`], `arbitrary`
+//! implements `arbitrary::Arbitrary` for fuzzing.
+
+use std::collections::BTreeMap;
+
+use crypto_tree::{CryptoBinaryTree, Transaction, TreeHasher};
+
+#[cfg(feature = "arbitrary")]
+mod unstructured;
+#[cfg(feature = "arbitrary")]
+pub use unstructured::ArbitraryTransaction;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+
+/// Reference model of a transaction tree: its contents in key order
+pub type Model = BTreeMap<String, Transaction>;
+
+/// One mutation of a transaction tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Insert(Transaction),
+    Remove(String),
+    /// Sets the amount of a stored transaction
+    Update { id: String, amount: u128 },
+    Upsert(Transaction),
+    PopFirst,
+    PopLast,
+}
+
+impl Op {
+    /// Applies the operation to `tree`, ignoring whether it succeeded.
+    pub fn apply<H: TreeHasher>(&self, tree: &mut CryptoBinaryTree<Transaction, H>) {
+        match self {
+            Op::Insert(tx) => {
+                tree.insert(tx.clone());
+            }
+            Op::Remove(id) => {
+                tree.remove(id.as_str());
+            }
+            Op::Update { id, amount } => {
+                let _ = tree.update(id.as_str(), |tx| tx.amount = *amount);
+            }
+            Op::Upsert(tx) => {
+                let _ = tree.upsert(tx.clone());
+            }
+            Op::PopFirst => {
+                tree.pop_first();
+            }
+            Op::PopLast => {
+                tree.pop_last();
+            }
+        }
+    }
+
+    /// Applies the operation to `model` the way a tree without rules or validators would.
+    pub fn apply_model(&self, model: &mut Model) {
+        match self {
+            Op::Insert(tx) => {
+                model.entry(tx.id.clone()).or_insert_with(|| tx.clone());
+            }
+            Op::Remove(id) => {
+                model.remove(id);
+            }
+            Op::Update { id, amount } => {
+                if let Some(tx) = model.get_mut(id) {
+                    tx.amount = *amount;
+                }
+            }
+            Op::Upsert(tx) => {
+                model.insert(tx.id.clone(), tx.clone());
+            }
+            Op::PopFirst => {
+                model.pop_first();
+            }
+            Op::PopLast => {
+                model.pop_last();
+            }
+        }
+    }
+}
+
+/// Transactions of `tree` in key order
+pub fn contents<H: TreeHasher>(tree: &CryptoBinaryTree<Transaction, H>) -> Vec<Transaction> {
+    let mut out = Vec::with_capacity(tree.len());
+    tree.traverse(crypto_tree::TraversalOrder::InOrder, |n| out.push(n.transaction.clone()));
+    out
+}
+
+/// A payment of `amount` from Alice to Bob
+pub fn sample_tx(id: &str, amount: u128) -> Transaction {
+    Transaction {
+        id: id.to_string(),
+        from: "Alice".into(),
+        to: "Bob".into(),
+        amount,
+        ..Default::default()
+    }
+}
+
+/// A path named after `name` in the temp dir, with nothing at it
+pub fn temp_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("crypto_tree_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Checks key order, heights, balance factors and subtree sizes of every node.
+///
+/// Wraps `check_invariants`, also comparing the node count with `len`, and
+/// describes the first violation found.
+pub fn check_shape<H: TreeHasher>(tree: &CryptoBinaryTree<Transaction, H>) -> Result<(), String> {
+    tree.check_invariants().map_err(|e| e.to_string())?;
+    let size = tree.root_id().and_then(|id| tree.node(id)).map_or(0, |n| n.size);
+    if size != tree.len() {
+        return Err(format!("tree reports {} transactions but holds {}", tree.len(), size));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ops_match_model() {
+        let ops = [
+            Op::Insert(sample_tx("b", 1)),
+            Op::Insert(sample_tx("a", 1)),
+            Op::Insert(sample_tx("c", 1)),
+            Op::Update { id: "a".to_string(), amount: 5 },
+            Op::Remove("b".to_string()),
+            Op::Upsert(sample_tx("d", 1)),
+            Op::PopLast,
+        ];
+        let mut tree = CryptoBinaryTree::new();
+        let mut model = Model::new();
+        for op in &ops {
+            op.apply(&mut tree);
+            op.apply_model(&mut model);
+            check_shape(&tree).unwrap();
+        }
+        assert_eq!(contents(&tree), model.into_values().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,66 @@
+//! proptest strategies for transactions and operation sequences (`proptest` feature).
+//!
+//! Ids come from a small space, so random operations keep hitting stored keys
+//! and exercise duplicates, updates and removals rather than only inserts.
+
+use proptest::collection::{btree_set, vec};
+use proptest::prelude::*;
+
+use crypto_tree::Transaction;
+
+use crate::Op;
+
+/// Ids `tx_000` to `tx_511`
+pub fn id() -> impl Strategy<Value = String> {
+    (0u16..512).prop_map(|i| format!("tx_{:03}", i))
+}
+
+/// A few recurring addresses, or occasionally a random one
+pub fn address() -> impl Strategy<Value = String> {
+    prop_oneof![
+        4 => prop::sample::select(vec!["Alice", "Bob", "Carol", "Dave"]).prop_map(str::to_string),
+        1 => "[a-z0-9]{1,16}",
+    ]
+}
+
+/// Transactions with ids drawn from `id`, any amount and an optional timestamp and attachment
+pub fn transaction_with_id(id: impl Strategy<Value = String>) -> impl Strategy<Value = Transaction> {
+    (id, address(), address(), any::<u128>(), any::<Option<u64>>(), vec(any::<u8>(), 0..32)).prop_map(
+        |(id, from, to, amount, timestamp, data)| Transaction {
+            id,
+            from: from.into(),
+            to: to.into(),
+            amount,
+            timestamp,
+            data,
+            ..Default::default()
+        },
+    )
+}
+
+pub fn transaction() -> impl Strategy<Value = Transaction> {
+    transaction_with_id(id())
+}
+
+/// Up to `max_len` transactions with distinct ids, in key order
+pub fn transaction_set(max_len: usize) -> impl Strategy<Value = Vec<Transaction>> {
+    btree_set(id(), 0..max_len)
+        .prop_flat_map(|ids| ids.into_iter().map(|id| transaction_with_id(Just(id))).collect::<Vec<_>>())
+}
+
+/// One operation, mostly inserts so trees grow
+pub fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        6 => transaction().prop_map(Op::Insert),
+        3 => id().prop_map(Op::Remove),
+        1 => (id(), any::<u128>()).prop_map(|(id, amount)| Op::Update { id, amount }),
+        1 => transaction().prop_map(Op::Upsert),
+        1 => Just(Op::PopFirst),
+        1 => Just(Op::PopLast),
+    ]
+}
+
+/// Sequences of up to `max_len` operations
+pub fn ops(max_len: usize) -> impl Strategy<Value = Vec<Op>> {
+    vec(op(), 0..max_len)
+}
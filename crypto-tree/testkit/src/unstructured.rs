@@ -0,0 +1,58 @@
+//! `arbitrary::Arbitrary` for operations and transactions (`arbitrary` feature).
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crypto_tree::Transaction;
+
+use crate::Op;
+
+/// A `Transaction` built from unstructured bytes, e.g. fuzzer input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArbitraryTransaction(pub Transaction);
+
+/// Ids from a small space, like `strategy::id`, so operations collide
+fn id(u: &mut Unstructured) -> Result<String> {
+    Ok(format!("tx_{:03}", u.int_in_range(0u16..=511)?))
+}
+
+fn address(u: &mut Unstructured) -> Result<String> {
+    if u.ratio(4, 5)? {
+        Ok(u.choose(&["Alice", "Bob", "Carol", "Dave"])?.to_string())
+    } else {
+        String::arbitrary(u)
+    }
+}
+
+fn transaction(u: &mut Unstructured) -> Result<Transaction> {
+    Ok(Transaction {
+        id: id(u)?,
+        from: address(u)?.into(),
+        to: address(u)?.into(),
+        amount: u128::arbitrary(u)?,
+        timestamp: Option::<u64>::arbitrary(u)?,
+        data: Vec::<u8>::arbitrary(u)?,
+        ..Default::default()
+    })
+}
+
+impl<'a> Arbitrary<'a> for ArbitraryTransaction {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        transaction(u).map(ArbitraryTransaction)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Op {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0u8..=12)? {
+            0..=5 => Op::Insert(transaction(u)?),
+            6..=8 => Op::Remove(id(u)?),
+            9 => Op::Update {
+                id: id(u)?,
+                amount: u128::arbitrary(u)?,
+            },
+            10 => Op::Upsert(transaction(u)?),
+            11 => Op::PopFirst,
+            _ => Op::PopLast,
+        })
+    }
+}
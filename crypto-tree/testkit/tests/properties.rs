@@ -0,0 +1,116 @@
+//! Properties of the tree under random workloads (`cargo test --features proptest`).
+
+use crypto_tree::{CryptoBinaryTree, LoggedTree, Sha256Hasher, Transaction, WalOptions};
+use crypto_tree_testkit::strategy::{ops, transaction_set};
+use crypto_tree_testkit::{check_shape, contents, temp_path, Model, Op};
+use proptest::prelude::*;
+
+/// Applies `op` through the operations a `LoggedTree` offers, ignoring
+/// whether it succeeded.
+fn apply_logged(op: &Op, log: &mut LoggedTree) {
+    let first = |log: &LoggedTree| log.tree().iter().next().map(|tx| tx.id.clone());
+    let last = |log: &LoggedTree| log.tree().iter().last().map(|tx| tx.id.clone());
+    match op {
+        Op::Insert(tx) => {
+            let _ = log.try_insert(tx.clone());
+        }
+        Op::Remove(id) => {
+            let _ = log.remove(id.as_str());
+        }
+        Op::Update { id, amount } => {
+            let _ = log.update(id.as_str(), |tx| tx.amount = *amount);
+        }
+        Op::Upsert(tx) => {
+            if log.tree().search(tx.id.as_str()).is_some() {
+                let _ = log.update(tx.id.as_str(), |stored| *stored = tx.clone());
+            } else {
+                let _ = log.try_insert(tx.clone());
+            }
+        }
+        Op::PopFirst => {
+            if let Some(id) = first(log) {
+                let _ = log.remove(id.as_str());
+            }
+        }
+        Op::PopLast => {
+            if let Some(id) = last(log) {
+                let _ = log.remove(id.as_str());
+            }
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    /// Every operation leaves a balanced search tree holding what the model holds.
+    #[test]
+    fn shape_and_contents_follow_model(ops in ops(200)) {
+        let mut tree = CryptoBinaryTree::new();
+        let mut model = Model::new();
+        for op in &ops {
+            op.apply(&mut tree);
+            op.apply_model(&mut model);
+            if let Err(violation) = check_shape(&tree) {
+                return Err(TestCaseError::fail(format!("after {:?}: {}", op, violation)));
+            }
+        }
+        prop_assert_eq!(contents(&tree), model.into_values().collect::<Vec<_>>());
+        prop_assert!(tree.check_integrity().is_ok());
+    }
+
+    /// Lazy hashing ends at the same root as eager hashing.
+    #[test]
+    fn lazy_hashing_matches_eager(ops in ops(200)) {
+        let mut eager = CryptoBinaryTree::new();
+        let mut lazy = CryptoBinaryTree::new();
+        lazy.set_lazy_hashing(true);
+        for op in &ops {
+            op.apply(&mut eager);
+            op.apply(&mut lazy);
+        }
+        prop_assert_eq!(lazy.flush_hashes(), eager.merkle_root());
+    }
+
+    /// Reopening a write-ahead log, from a snapshot plus the records after
+    /// it, restores the tree the logged operations built, root included.
+    #[test]
+    fn wal_replay_restores_live_root(ops in ops(200), snapshot_at in 0usize..200) {
+        let dir = temp_path("properties_wal");
+        let options = WalOptions { max_segment_bytes: 4 << 10, sync: false, ..WalOptions::default() };
+        let mut live = CryptoBinaryTree::new();
+        let mut log: LoggedTree = LoggedTree::open_with(&dir, Sha256Hasher::new(), options).unwrap();
+        for (i, op) in ops.iter().enumerate() {
+            if i == snapshot_at {
+                log.compact_to_snapshot().unwrap();
+            }
+            op.apply(&mut live);
+            apply_logged(op, &mut log);
+        }
+        prop_assert_eq!(log.tree().merkle_root(), live.merkle_root());
+        drop(log);
+
+        let reopened: LoggedTree = LoggedTree::open_with(&dir, Sha256Hasher::new(), options).unwrap();
+        prop_assert_eq!(reopened.tree().merkle_root(), live.merkle_root());
+        prop_assert_eq!(contents(reopened.tree()), contents(&live));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// AVL shape depends on insertion order, but the set does not: any order
+    /// holds the same transactions and rebuilds to the balanced tree of the set.
+    #[test]
+    fn insertion_order_only_changes_shape(
+        (set, shuffled) in transaction_set(300).prop_flat_map(|set| (Just(set.clone()), Just(set).prop_shuffle()))
+    ) {
+        let mut tree = CryptoBinaryTree::new();
+        for tx in shuffled {
+            prop_assert!(tree.insert(tx));
+        }
+        prop_assert!(check_shape(&tree).is_ok());
+        prop_assert_eq!(contents(&tree), set.clone());
+
+        let canonical = CryptoBinaryTree::from_sorted(set).unwrap();
+        let rebuilt = CryptoBinaryTree::from_sorted(tree.into_iter().collect::<Vec<Transaction>>()).unwrap();
+        prop_assert_eq!(rebuilt.merkle_root(), canonical.merkle_root());
+    }
+}
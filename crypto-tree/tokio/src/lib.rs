@@ -0,0 +1,91 @@
+//! Async streams of the changes made to a `crypto-tree` tree.
+//!
+//! [`SubscribeExt::subscribe_stream`] subscribes a tokio channel to a tree
+//! and returns its receiving end as a [`Stream`] of [`ChangeEvent`]s, so a
+//! websocket gateway or indexer task can `.next().await` on the tree's
+//! inserts, updates, removals and new roots.
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::sync::Arc;
+
+use crypto_tree::{ChangeEvent, ChangeSubscriber, CryptoBinaryTree, TreeKey};
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+/// Feeds a tree's changes into an unbounded tokio channel
+struct ChannelSubscriber<T: TreeKey>(mpsc::UnboundedSender<ChangeEvent<T>>);
+
+impl<T: TreeKey + Send> ChangeSubscriber<T> for ChannelSubscriber<T>
+where
+    T::Key: Send,
+{
+    fn send(&self, event: ChangeEvent<T>) -> bool {
+        self.0.send(event).is_ok()
+    }
+}
+
+/// The changes of a tree, in the order they were made
+///
+/// The stream ends once the tree is dropped; clones of the tree do not feed
+/// it. Dropping the stream unsubscribes on the tree's next change.
+#[derive(Debug)]
+pub struct ChangeStream<T: TreeKey> {
+    receiver: mpsc::UnboundedReceiver<ChangeEvent<T>>,
+}
+
+impl<T: TreeKey> Stream for ChangeStream<T> {
+    type Item = ChangeEvent<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChangeEvent<T>>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Adds [`subscribe_stream`](SubscribeExt::subscribe_stream) to trees
+pub trait SubscribeExt<T: TreeKey> {
+    /// Subscribes to the tree's changes as an async stream.
+    ///
+    /// The channel is unbounded, so the tree never waits for the stream; a
+    /// consumer that falls behind buffers the events it has not read.
+    fn subscribe_stream(&mut self) -> ChangeStream<T>;
+}
+
+impl<T, H> SubscribeExt<T> for CryptoBinaryTree<T, H>
+where
+    T: TreeKey + Send + 'static,
+    T::Key: Send,
+{
+    fn subscribe_stream(&mut self) -> ChangeStream<T> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.add_subscriber(Arc::new(ChannelSubscriber(sender)));
+        ChangeStream { receiver }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::future::poll_fn;
+
+    use crypto_tree_testkit::sample_tx;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stream_yields_changes() {
+        let mut tree: CryptoBinaryTree = CryptoBinaryTree::new();
+        let mut changes = tree.subscribe_stream();
+        tree.insert(sample_tx("tx_a", 10));
+        tree.remove("tx_a");
+        drop(tree);
+
+        let mut events = Vec::new();
+        while let Some(event) = poll_fn(|cx| Pin::new(&mut changes).poll_next(cx)).await {
+            events.push(event);
+        }
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], ChangeEvent::Inserted(sample_tx("tx_a", 10)));
+        assert_eq!(events[2], ChangeEvent::Removed("tx_a".to_string()));
+        assert_eq!(events[3], ChangeEvent::NewRoot("0".to_string()));
+    }
+}
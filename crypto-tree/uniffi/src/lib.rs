@@ -0,0 +1,289 @@
+//! UniFFI bindings for Swift and Kotlin.
+//!
+//! Records mirror the core types with mobile-friendly field types: amounts
+//! are decimal strings, since neither language has a native 128-bit integer,
+//! and metadata values are JSON text. `CryptoTree` is shared by reference
+//! across threads, so the tree lives behind a mutex.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crypto_tree::{CryptoBinaryTree, Side};
+
+uniffi::setup_scaffolding!();
+
+/// Errors thrown to Swift and Kotlin callers
+#[derive(Debug, uniffi::Error)]
+pub enum CryptoTreeError {
+    /// A transaction or proof could not be converted to the core type
+    InvalidInput { reason: String },
+}
+
+impl fmt::Display for CryptoTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoTreeError::InvalidInput { reason } => write!(f, "invalid input: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CryptoTreeError {}
+
+fn invalid(reason: String) -> CryptoTreeError {
+    CryptoTreeError::InvalidInput { reason }
+}
+
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct Transaction {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+    /// Unsigned decimal integer, up to 128 bits
+    pub amount: String,
+    pub timestamp: Option<u64>,
+    /// Values are JSON text, e.g. `"\"memo\""` or `"42"`
+    pub metadata: HashMap<String, String>,
+    pub data: Vec<u8>,
+    pub signature: Option<String>,
+    pub public_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum ProofSide {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct ProofStep {
+    pub side: ProofSide,
+    pub hash: String,
+    pub height: i32,
+    pub size: u64,
+    /// Set for ancestors on the search path, `None` for the target node
+    pub transaction: Option<Transaction>,
+}
+
+/// Inclusion proof bundled with its transaction, root and tree height
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct Proof {
+    pub transaction: Transaction,
+    pub root: String,
+    pub height: i32,
+    pub steps: Vec<ProofStep>,
+}
+
+impl From<crypto_tree::Transaction> for Transaction {
+    fn from(tx: crypto_tree::Transaction) -> Self {
+        Transaction {
+            id: tx.id,
+            from: tx.from.into(),
+            to: tx.to.into(),
+            amount: tx.amount.to_string(),
+            timestamp: tx.timestamp,
+            metadata: tx.metadata.into_iter().map(|(k, v)| (k, v.to_string())).collect(),
+            data: tx.data,
+            signature: tx.signature,
+            public_key: tx.public_key,
+        }
+    }
+}
+
+impl TryFrom<Transaction> for crypto_tree::Transaction {
+    type Error = CryptoTreeError;
+
+    fn try_from(tx: Transaction) -> Result<Self, CryptoTreeError> {
+        let amount = tx
+            .amount
+            .parse()
+            .map_err(|_| invalid(format!("transaction {}: amount {:?} is not an unsigned integer", tx.id, tx.amount)))?;
+        let metadata = tx
+            .metadata
+            .into_iter()
+            .map(|(k, v)| {
+                serde_json::from_str(&v)
+                    .map(|v| (k.clone(), v))
+                    .map_err(|e| invalid(format!("transaction {}: metadata {}: {}", tx.id, k, e)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(crypto_tree::Transaction {
+            id: tx.id,
+            from: tx.from.into(),
+            to: tx.to.into(),
+            amount,
+            timestamp: tx.timestamp,
+            metadata,
+            data: tx.data,
+            signature: tx.signature,
+            public_key: tx.public_key,
+        })
+    }
+}
+
+impl From<crypto_tree::ProofStep> for ProofStep {
+    fn from(step: crypto_tree::ProofStep) -> Self {
+        ProofStep {
+            side: match step.side {
+                Side::Left => ProofSide::Left,
+                Side::Right => ProofSide::Right,
+            },
+            hash: step.hash,
+            height: step.height,
+            size: step.size as u64,
+            transaction: step.transaction.map(Into::into),
+        }
+    }
+}
+
+impl TryFrom<ProofStep> for crypto_tree::ProofStep {
+    type Error = CryptoTreeError;
+
+    fn try_from(step: ProofStep) -> Result<Self, CryptoTreeError> {
+        let size = usize::try_from(step.size).map_err(|_| invalid(format!("proof step size {} is too large", step.size)))?;
+        Ok(crypto_tree::ProofStep {
+            side: match step.side {
+                ProofSide::Left => Side::Left,
+                ProofSide::Right => Side::Right,
+            },
+            hash: step.hash,
+            height: step.height,
+            size,
+            transaction: step.transaction.map(TryInto::try_into).transpose()?,
+        })
+    }
+}
+
+impl From<crypto_tree::Proof> for Proof {
+    fn from(proof: crypto_tree::Proof) -> Self {
+        Proof {
+            transaction: proof.transaction.into(),
+            root: proof.root,
+            height: proof.height,
+            steps: proof.steps.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TryFrom<Proof> for crypto_tree::Proof {
+    type Error = CryptoTreeError;
+
+    fn try_from(proof: Proof) -> Result<Self, CryptoTreeError> {
+        Ok(crypto_tree::Proof {
+            transaction: proof.transaction.try_into()?,
+            root: proof.root,
+            height: proof.height,
+            steps: proof.steps.into_iter().map(TryInto::try_into).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Checks `proof` against a trusted Merkle root, without holding the tree.
+///
+/// Returns `false` for a proof under a different root or one that cannot be
+/// converted, e.g. because of a malformed amount.
+#[uniffi::export]
+pub fn verify_proof(root: String, proof: Proof) -> bool {
+    if proof.root != root {
+        return false;
+    }
+    crypto_tree::Proof::try_from(proof).is_ok_and(|proof| proof.verify())
+}
+
+#[derive(uniffi::Object)]
+pub struct CryptoTree {
+    tree: Mutex<CryptoBinaryTree>,
+}
+
+impl CryptoTree {
+    /// Locks the tree, recovering it if another caller panicked while holding the lock.
+    fn tree(&self) -> MutexGuard<'_, CryptoBinaryTree> {
+        self.tree.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[uniffi::export]
+impl CryptoTree {
+    #[uniffi::constructor]
+    pub fn new() -> Self {
+        Self {
+            tree: Mutex::new(CryptoBinaryTree::new()),
+        }
+    }
+
+    /// Inserts a transaction, returning `false` if its id is already stored.
+    pub fn insert(&self, transaction: Transaction) -> Result<bool, CryptoTreeError> {
+        let transaction = transaction.try_into()?;
+        Ok(self.tree().insert(transaction))
+    }
+
+    pub fn search(&self, id: String) -> Option<Transaction> {
+        self.tree().search(&id).cloned().map(Into::into)
+    }
+
+    /// Inclusion proof for `id`, or `None` if it is not stored.
+    pub fn get_proof(&self, id: String) -> Option<Proof> {
+        self.tree().get_proof(&id).map(Into::into)
+    }
+
+    pub fn merkle_root(&self) -> String {
+        self.tree().merkle_root().to_string()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.tree().len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree().is_empty()
+    }
+}
+
+impl Default for CryptoTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The testkit payment with every optional field set and the largest amount
+    fn sample_tx(id: &str) -> Transaction {
+        let mut tx = crypto_tree_testkit::sample_tx(id, u128::MAX);
+        tx.timestamp = Some(1_700_000_000);
+        tx.metadata.insert("memo".to_string(), "rent".into());
+        tx.data = vec![0xca, 0xfe];
+        tx.into()
+    }
+
+    #[test]
+    fn test_insert_search_verify() {
+        let tree = CryptoTree::new();
+        for i in 1..=10 {
+            assert!(tree.insert(sample_tx(&format!("tx_{:02}", i))).unwrap());
+        }
+        assert!(!tree.insert(sample_tx("tx_03")).unwrap());
+        assert_eq!(tree.len(), 10);
+        assert_eq!(tree.search("tx_03".to_string()), Some(sample_tx("tx_03")));
+
+        let root = tree.merkle_root();
+        let proof = tree.get_proof("tx_07".to_string()).unwrap();
+        assert!(verify_proof(root.clone(), proof.clone()));
+        assert!(!verify_proof("0".to_string(), proof.clone()));
+
+        let mut forged = proof;
+        forged.transaction.amount = "1".to_string();
+        assert!(!verify_proof(root, forged));
+    }
+
+    #[test]
+    fn test_rejects_malformed_amount() {
+        let tree = CryptoTree::new();
+        let mut tx = sample_tx("tx_01");
+        tx.amount = "-5".to_string();
+        assert!(matches!(tree.insert(tx), Err(CryptoTreeError::InvalidInput { .. })));
+        assert!(tree.is_empty());
+    }
+}
@@ -1,6 +1,87 @@
-use crypto_tree::{CryptoBinaryTree, Transaction, ProofStep};
+use crypto_tree::{CryptoBinaryTree, Transaction, ProofStep, Sha256Hasher};
+use js_sys::{Array, Object};
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{DeserializeOwned, Deserializer, IntoDeserializer, Visitor};
+use serde::Serialize;
 use wasm_bindgen::prelude::*;
-use serde_wasm_bindgen::to_value;  // <-- Add this import
+use wasm_bindgen::JsCast;
+use serde_wasm_bindgen::Serializer;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_TYPES: &str = r#"
+/** A payment transaction, as returned by `search` and accepted by `insert_tx` */
+export interface Transaction {
+    id: string;
+    from: string;
+    to: string;
+    /** Smallest token unit. Returned as a `bigint`; a `number` up to
+     * `Number.MAX_SAFE_INTEGER` is accepted too */
+    amount: bigint | number;
+    /** Unix seconds, returned as a `bigint` like `amount` */
+    timestamp?: bigint | number;
+    metadata?: Record<string, unknown>;
+    /** Hex-encoded attachment */
+    data?: string;
+    signature?: string;
+    public_key?: string;
+}
+
+/** One step of an inclusion proof, ordered from the root down */
+export interface ProofStep {
+    side: "l" | "r";
+    /** Sibling subtree hash, `"0"` when empty */
+    hash: string;
+    height: number;
+    /** Returned as a `bigint` */
+    size: bigint | number;
+    /** Present for ancestors, absent for the target node's own children */
+    transaction?: Transaction;
+}
+
+/** Summary returned by `stats` */
+export interface TreeStats {
+    size: number;
+    height: number;
+    root: string;
+    /** Edges on the longest root-to-leaf path, 0 for an empty tree */
+    max_depth: number;
+}
+
+/** A self-contained inclusion proof */
+export interface Proof {
+    transaction: Transaction;
+    root: string;
+    height: number;
+    steps: ProofStep[];
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "Transaction")]
+    pub type JsTransaction;
+
+    #[wasm_bindgen(typescript_type = "Transaction[]")]
+    pub type JsTransactions;
+
+    #[wasm_bindgen(typescript_type = "ProofStep[]")]
+    pub type JsProofSteps;
+
+    #[wasm_bindgen(typescript_type = "Proof")]
+    pub type JsProof;
+
+    #[wasm_bindgen(typescript_type = "TreeStats")]
+    pub type JsTreeStats;
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct TreeStats<'a> {
+    // 32 bits, so `to_js` gives plain numbers rather than BigInts
+    size: u32,
+    height: i32,
+    root: &'a str,
+    max_depth: u32,
+}
 
 #[wasm_bindgen]
 pub struct CryptoTreeWasm {
@@ -20,26 +101,104 @@ impl CryptoTreeWasm {
     pub fn insert(&mut self, id: &str, from: &str, to: &str, amount: u64, timestamp: Option<u64>) -> bool {
         let tx = Transaction {
             id: id.to_string(),
-            from: from.to_string(),
-            to: to.to_string(),
-            amount,
+            from: from.into(),
+            to: to.into(),
+            amount: amount.into(),
             timestamp,
+            ..Default::default()
         };
         self.tree.insert(tx)
     }
 
+    /// Inserts a `{id, from, to, amount, timestamp}` object.
+    ///
+    /// Returns `false` for a duplicate id and throws if the object is malformed.
+    #[wasm_bindgen]
+    pub fn insert_tx(&mut self, tx: JsTransaction) -> Result<bool, JsError> {
+        let tx: Transaction = from_js(tx.into()).map_err(|e| JsError::new(&format!("invalid transaction: {}", e)))?;
+        Ok(self.tree.insert(tx))
+    }
+
+    /// Removes a transaction, returning it or `undefined` if the id is unknown.
     #[wasm_bindgen]
-    pub fn search(&self, id: &str) -> Option<JsValue> {
-        self.tree.search(id).map(|tx| {
-            to_value(&tx).unwrap()  // <-- Now this works
-        })
+    pub fn remove(&mut self, id: &str) -> Result<Option<JsTransaction>, JsError> {
+        self.tree.remove(id).map(|tx| to_js(&tx).map(JsCast::unchecked_into)).transpose()
+    }
+
+    /// Removes every transaction.
+    #[wasm_bindgen]
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Replaces the transaction stored under `id` with `tx`, returning the new Merkle root.
+    ///
+    /// Throws if `tx` is malformed, `id` is unknown or `tx` carries a different id.
+    #[wasm_bindgen]
+    pub fn update(&mut self, id: &str, tx: JsTransaction) -> Result<String, JsError> {
+        let tx: Transaction = from_js(tx.into()).map_err(|e| JsError::new(&format!("invalid transaction: {}", e)))?;
+        Ok(self.tree.update(id, |stored| *stored = tx)?)
+    }
+
+    #[wasm_bindgen]
+    pub fn search(&self, id: &str) -> Result<Option<JsTransaction>, JsError> {
+        self.tree.search(id).map(|tx| to_js(tx).map(JsCast::unchecked_into)).transpose()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_proof_of_inclusion(&self, id: &str) -> Result<Option<JsProofSteps>, JsError> {
+        self.tree
+            .get_proof_of_inclusion(id)
+            .map(|proof| to_js(&proof).map(JsCast::unchecked_into))
+            .transpose()
+    }
+
+    /// Like `get_proof_of_inclusion`, bundled with the transaction, root and height.
+    #[wasm_bindgen]
+    pub fn get_proof(&self, id: &str) -> Result<Option<JsProof>, JsError> {
+        self.tree.get_proof(id).map(|proof| to_js(&proof).map(JsCast::unchecked_into)).transpose()
+    }
+
+    /// Checks a proof from `get_proof_of_inclusion` against a Merkle root,
+    /// without holding the tree. Malformed inputs yield `false`.
+    #[wasm_bindgen]
+    pub fn verify_proof(root: &str, transaction_js: JsTransaction, proof_js: JsProofSteps) -> bool {
+        let (Ok(transaction), Ok(proof)) = (
+            from_js::<Transaction>(transaction_js.into()),
+            from_js::<Vec<ProofStep>>(proof_js.into()),
+        ) else {
+            return false;
+        };
+        crypto_tree::verify_proof(root, &transaction, &proof)
     }
 
+    /// Returns up to `limit` transactions in id order, skipping the first `offset`.
     #[wasm_bindgen]
-    pub fn get_proof_of_inclusion(&self, id: &str) -> Option<JsValue> {
-        self.tree.get_proof_of_inclusion(id).map(|proof| {
-            to_value(&proof).unwrap()  // <-- Now this works
-        })
+    pub fn list(&self, offset: usize, limit: usize) -> Result<JsTransactions, JsError> {
+        Ok(to_js(&self.page(offset, limit))?.unchecked_into())
+    }
+
+    /// Returns the transactions with ids in `start_id..=end_id`, in id order.
+    #[wasm_bindgen]
+    pub fn list_range(&self, start_id: &str, end_id: &str) -> Result<JsTransactions, JsError> {
+        Ok(to_js(&self.id_range(start_id, end_id))?.unchecked_into())
+    }
+
+    /// Serializes the tree as a snapshot, e.g. to keep it in IndexedDB.
+    #[wasm_bindgen]
+    pub fn export_state(&self) -> Result<Vec<u8>, JsError> {
+        let mut bytes = Vec::new();
+        self.tree.write_snapshot(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Restores a tree from `export_state` output, rehashing every node.
+    ///
+    /// Throws if the bytes are not a valid snapshot or their Merkle root does not check out.
+    #[wasm_bindgen]
+    pub fn from_state(bytes: &[u8]) -> Result<CryptoTreeWasm, JsError> {
+        let tree = CryptoBinaryTree::read_snapshot(&mut &bytes[..], Sha256Hasher::default())?;
+        Ok(Self { tree })
     }
 
     #[wasm_bindgen]
@@ -56,9 +215,399 @@ impl CryptoTreeWasm {
     pub fn len(&self) -> usize {
         self.tree.len()
     }
+
+    #[wasm_bindgen]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Number of levels in the tree, 0 when empty.
+    #[wasm_bindgen]
+    pub fn height(&self) -> i32 {
+        self.tree.height()
+    }
+
+    /// Size, height, root and maximum depth in one call.
+    #[wasm_bindgen]
+    pub fn stats(&self) -> Result<JsTreeStats, JsError> {
+        Ok(to_js(&self.tree_stats())?.unchecked_into())
+    }
+}
+
+impl CryptoTreeWasm {
+    fn page(&self, offset: usize, limit: usize) -> Vec<&Transaction> {
+        let end = offset.saturating_add(limit).min(self.tree.len());
+        (offset..end).filter_map(|k| self.tree.select(k)).collect()
+    }
+
+    fn id_range(&self, start_id: &str, end_id: &str) -> Vec<&Transaction> {
+        (self.tree.rank(start_id)..)
+            .map_while(|k| self.tree.select(k))
+            .take_while(|tx| tx.id.as_str() <= end_id)
+            .collect()
+    }
+
+    fn tree_stats(&self) -> TreeStats<'_> {
+        TreeStats {
+            size: u32::try_from(self.tree.len()).unwrap_or(u32::MAX),
+            height: self.tree.height(),
+            root: self.tree.merkle_root(),
+            max_depth: u32::try_from(self.tree.height() - 1).unwrap_or(0),
+        }
+    }
+}
+
+impl Default for CryptoTreeWasm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts a value for JS, writing maps as plain objects so they can be
+/// passed back in, and 64-bit integers such as amounts past
+/// `Number.MAX_SAFE_INTEGER` as `BigInt`s.
+fn to_js<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, JsError> {
+    let serializer = Serializer::new()
+        .serialize_maps_as_objects(true)
+        .serialize_large_number_types_as_bigints(true);
+    value
+        .serialize(&serializer)
+        .map_err(|e| JsError::new(&format!("could not convert result for JS: {}", e)))
+}
+
+/// Logs panics to the browser console instead of an opaque `unreachable` trap.
+#[cfg(feature = "panic-hook")]
+#[wasm_bindgen(start)]
+fn install_panic_hook() {
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = console)]
+        fn error(message: String);
+    }
+
+    std::panic::set_hook(Box::new(|info| error(info.to_string())));
+}
+
+/// Reads a value passed in from JS.
+fn from_js<T: DeserializeOwned>(value: JsValue) -> Result<T, String> {
+    T::deserialize(JsInput(value)).map_err(|e| e.to_string())
+}
+
+/// A JS value read as serde-wasm-bindgen reads it, except that a `u128`,
+/// such as a transaction amount, may be a plain number as well as a `BigInt`.
+///
+/// serde-wasm-bindgen reads `u128` from a `BigInt` only, and its
+/// `deserialize_any` stops at `u64`, so objects and arrays are walked here to
+/// give nested transactions the same treatment.
+struct JsInput(JsValue);
+
+impl JsInput {
+    fn plain(self) -> serde_wasm_bindgen::Deserializer {
+        self.0.into()
+    }
+}
+
+macro_rules! forward_to_plain {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error> {
+                self.plain().$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for JsInput {
+    type Error = serde_wasm_bindgen::Error;
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.0.is_bigint() {
+            self.plain().deserialize_u128(visitor)
+        } else {
+            // Safe integers only, as for `u64`
+            self.plain().deserialize_u64(visitor)
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.0.is_null() || self.0.is_undefined() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0.dyn_into::<Array>() {
+            Ok(array) => visitor.visit_seq(SeqDeserializer::new(array.iter().map(JsInput))),
+            Err(value) => JsInput(value).plain().deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if !self.0.is_object() || Array::is_array(&self.0) {
+            return self.plain().deserialize_struct(name, fields, visitor);
+        }
+        // Fields set to `undefined` count as missing, as in JSON.stringify
+        let entries = Object::entries(self.0.unchecked_ref());
+        let present = entries.iter().filter_map(|entry| {
+            let pair: Array = entry.unchecked_into();
+            let value = pair.get(1);
+            (!value.is_undefined()).then(|| (pair.get(0).as_string().unwrap_or_default(), JsInput(value)))
+        });
+        visitor.visit_map(MapDeserializer::new(present))
+    }
+
+    forward_to_plain! {
+        deserialize_any()
+        deserialize_bool()
+        deserialize_i8()
+        deserialize_i16()
+        deserialize_i32()
+        deserialize_i64()
+        deserialize_i128()
+        deserialize_u8()
+        deserialize_u16()
+        deserialize_u32()
+        deserialize_u64()
+        deserialize_f32()
+        deserialize_f64()
+        deserialize_char()
+        deserialize_str()
+        deserialize_string()
+        deserialize_bytes()
+        deserialize_byte_buf()
+        deserialize_unit()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_map()
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_identifier()
+        deserialize_ignored_any()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, serde_wasm_bindgen::Error> for JsInput {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
 }
 
 #[wasm_bindgen]
 pub fn greet() -> String {
     "Hello from CryptoTree WASM!".to_string()
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `n` transactions inserted through the positional `insert`
+    fn wasm_tree(n: u64) -> CryptoTreeWasm {
+        let mut tree = CryptoTreeWasm::new();
+        for i in 1..=n {
+            assert!(tree.insert(&format!("tx_{:03}", i), "Alice", "Bob", i, Some(1640995200 + i)));
+        }
+        tree
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let tree = wasm_tree(50);
+        let restored = CryptoTreeWasm::from_state(&tree.export_state().unwrap()).unwrap();
+        assert_eq!(restored.merkle_root(), tree.merkle_root());
+        assert_eq!(restored.len(), 50);
+        assert!(restored.verify_integrity());
+
+        let empty = CryptoTreeWasm::from_state(&CryptoTreeWasm::new().export_state().unwrap()).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_remove_unknown_and_clear() {
+        let mut tree = wasm_tree(10);
+        let root = tree.merkle_root();
+        assert!(tree.remove("tx_999").unwrap().is_none());
+        assert_eq!((tree.len(), tree.merkle_root()), (10, root));
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.merkle_root(), CryptoTreeWasm::new().merkle_root());
+        assert!(tree.insert("tx_001", "Alice", "Bob", 1, None));
+    }
+
+    fn ids(page: Vec<&Transaction>) -> Vec<&str> {
+        page.into_iter().map(|tx| tx.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_pages() {
+        let tree = wasm_tree(25);
+        assert_eq!(ids(tree.page(0, 3)), ["tx_001", "tx_002", "tx_003"]);
+        assert_eq!(ids(tree.page(23, 10)), ["tx_024", "tx_025"]);
+        assert!(tree.page(25, 10).is_empty());
+        assert!(tree.page(usize::MAX, usize::MAX).is_empty());
+        let all: Vec<_> = (0..25).step_by(10).flat_map(|offset| tree.page(offset, 10)).collect();
+        assert_eq!(all.len(), 25);
+
+        // Bounds need not be stored ids, and both ends are included
+        assert_eq!(ids(tree.id_range("tx_010", "tx_012")), ["tx_010", "tx_011", "tx_012"]);
+        assert_eq!(ids(tree.id_range("tx_0235", "zzz")), ["tx_024", "tx_025"]);
+        assert!(tree.id_range("tx_012", "tx_010").is_empty());
+        assert!(CryptoTreeWasm::new().id_range("", "zzz").is_empty());
+    }
+
+    #[test]
+    fn test_stats() {
+        let empty = CryptoTreeWasm::new();
+        assert!(empty.is_empty());
+        assert_eq!(empty.height(), 0);
+        assert_eq!(empty.tree_stats(), TreeStats { size: 0, height: 0, root: "0", max_depth: 0 });
+
+        // 7 sorted inserts settle into a perfect tree of 3 levels
+        let tree = wasm_tree(7);
+        assert!(!tree.is_empty());
+        let root = tree.merkle_root();
+        assert_eq!(tree.tree_stats(), TreeStats { size: 7, height: 3, root: &root, max_depth: 2 });
+    }
+}
+
+/// The conversions from and to JS values, which only run on wasm32:
+/// `wasm-pack test --node`
+#[cfg(all(test, target_arch = "wasm32"))]
+mod js_tests {
+    use crypto_tree_testkit::sample_tx;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    fn tx_js(id: &str, amount: u128) -> JsTransaction {
+        to_js(&sample_tx(id, amount)).unwrap().unchecked_into()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_insert_tx() {
+        let mut tree = CryptoTreeWasm::new();
+        assert!(tree.insert_tx(tx_js("tx_1", 10)).unwrap());
+        assert!(!tree.insert_tx(tx_js("tx_1", 10)).unwrap());
+        assert_eq!(tree.tree.search("tx_1"), Some(&sample_tx("tx_1", 10)));
+
+        // Amounts past 2^53 arrive as BigInt
+        let big = u128::from(u64::MAX) + 1;
+        assert!(tree.insert_tx(tx_js("tx_2", big)).unwrap());
+        assert_eq!(tree.tree.search("tx_2").unwrap().amount, big);
+
+        // Hand-written objects may give the amount as a plain number
+        let with_amount = |amount: f64| {
+            let tx = to_js(&sample_tx("tx_3", 7)).unwrap();
+            js_sys::Reflect::set(&tx, &"amount".into(), &JsValue::from_f64(amount)).unwrap();
+            tx.unchecked_into::<JsTransaction>()
+        };
+        assert!(tree.insert_tx(with_amount(1.5)).is_err());
+        assert!(tree.insert_tx(with_amount(-7.0)).is_err());
+        assert!(tree.insert_tx(with_amount(7.0)).unwrap());
+        assert_eq!(tree.tree.search("tx_3"), Some(&sample_tx("tx_3", 7)));
+
+        let missing_fields = to_js(&serde_json::json!({ "id": "tx_4" })).unwrap();
+        assert!(tree.insert_tx(missing_fields.unchecked_into()).is_err());
+        assert!(tree.insert_tx(JsValue::from_str("tx_4").unchecked_into()).is_err());
+        assert_eq!(tree.len(), 3);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_verify_proof_reads_nested_transactions() {
+        let mut tree = CryptoTreeWasm::new();
+        // Ancestor steps carry whole transactions, with amounts as numbers and BigInts
+        let amount = |i: u128| if i.is_multiple_of(2) { i } else { u128::from(u64::MAX) + i };
+        for i in 1..=7 {
+            tree.insert_tx(tx_js(&format!("tx_{}", i), amount(i))).unwrap();
+        }
+        let steps = tree.get_proof_of_inclusion("tx_1").unwrap().unwrap();
+        let root = tree.merkle_root();
+        assert!(CryptoTreeWasm::verify_proof(&root, tx_js("tx_1", amount(1)), steps.clone().unchecked_into()));
+        assert!(!CryptoTreeWasm::verify_proof(&root, tx_js("tx_1", 1), steps));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_from_state_rejects_damage() {
+        let mut tree = CryptoTreeWasm::new();
+        tree.insert_tx(tx_js("tx_1", 10)).unwrap();
+        let bytes = tree.export_state().unwrap();
+        assert!(CryptoTreeWasm::from_state(&bytes[..bytes.len() - 1]).is_err());
+        assert!(CryptoTreeWasm::from_state(b"not a snapshot").is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_remove_and_update() {
+        let mut tree = CryptoTreeWasm::new();
+        tree.insert_tx(tx_js("tx_1", 10)).unwrap();
+        tree.insert_tx(tx_js("tx_2", 20)).unwrap();
+
+        let root = tree.update("tx_1", tx_js("tx_1", 15)).unwrap();
+        assert_eq!(root, tree.merkle_root());
+        assert_eq!(tree.tree.search("tx_1").unwrap().amount, 15);
+        // Unknown ids and changed ids throw and leave the tree alone
+        assert!(tree.update("tx_3", tx_js("tx_3", 1)).is_err());
+        assert!(tree.update("tx_1", tx_js("tx_9", 1)).is_err());
+        assert!(tree.update("tx_1", JsValue::NULL.unchecked_into()).is_err());
+        assert_eq!(tree.merkle_root(), root);
+
+        let removed = tree.remove("tx_1").unwrap().unwrap();
+        assert_eq!(from_js::<Transaction>(removed.into()).unwrap(), sample_tx("tx_1", 15));
+        assert!(tree.remove("tx_1").unwrap().is_none());
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_unsafe_integers_come_back_as_bigints() {
+        let big = (1u128 << 53) + 1;
+        let mut tree = CryptoTreeWasm::new();
+        tree.insert_tx(tx_js("tx_1", big)).unwrap();
+        tree.insert_tx(tx_js("tx_2", 10)).unwrap();
+
+        let found: JsValue = tree.search("tx_1").unwrap().unwrap().into();
+        let amount = js_sys::Reflect::get(&found, &"amount".into()).unwrap();
+        assert!(amount.is_bigint());
+        assert_eq!(u128::try_from(amount).unwrap(), big);
+        assert_eq!(from_js::<Transaction>(found).unwrap(), sample_tx("tx_1", big));
+
+        let page: Vec<Transaction> = from_js(tree.list(0, 2).unwrap().into()).unwrap();
+        assert_eq!(page, [sample_tx("tx_1", big), sample_tx("tx_2", 10)]);
+        let proof: crypto_tree::Proof = from_js(tree.get_proof("tx_1").unwrap().unwrap().into()).unwrap();
+        assert!(proof.verify());
+        let removed = tree.remove("tx_1").unwrap().unwrap();
+        assert_eq!(from_js::<Transaction>(removed.into()).unwrap(), sample_tx("tx_1", big));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_list_returns_transactions() {
+        let mut tree = CryptoTreeWasm::new();
+        for i in 1..=5 {
+            tree.insert_tx(tx_js(&format!("tx_{}", i), 10)).unwrap();
+        }
+        let page: Vec<Transaction> = from_js(tree.list(1, 2).unwrap().into()).unwrap();
+        assert_eq!(page, [sample_tx("tx_2", 10), sample_tx("tx_3", 10)]);
+        let range: Vec<Transaction> = from_js(tree.list_range("tx_4", "tx_9").unwrap().into()).unwrap();
+        assert_eq!(range, [sample_tx("tx_4", 10), sample_tx("tx_5", 10)]);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_stats_object() {
+        let mut tree = CryptoTreeWasm::new();
+        tree.insert_tx(tx_js("tx_1", 10)).unwrap();
+        let stats: serde_json::Value = from_js(tree.stats().unwrap().into()).unwrap();
+        let expected = serde_json::json!({ "size": 1, "height": 1, "root": tree.merkle_root(), "max_depth": 0 });
+        assert_eq!(stats, expected);
+    }
+}